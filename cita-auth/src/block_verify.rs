@@ -12,13 +12,14 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use crate::handler::verify_base_quota_required;
+use crate::handler::{signer_to_address, verify_base_quota_required};
+use crate::transaction_verify::Error;
 use cita_types::traits::LowerHex;
 use cita_types::Address;
-use crypto::{pubkey_to_address, PubKey};
 use libproto::blockchain::AccountGasLimit;
 use libproto::blockchain::SignedTransaction;
 use std::collections::HashMap;
+use util::BLOCKLIMIT;
 
 pub struct BlockVerify<'a> {
     pub transactions: &'a Vec<SignedTransaction>,
@@ -44,7 +45,8 @@ impl<'a> BlockVerify<'a> {
         let transactions = self.transactions();
         for tx in transactions {
             let quota = tx.get_transaction_with_sig().get_transaction().get_quota();
-            let signer = pubkey_to_address(&PubKey::from(tx.get_signer()));
+            let signer =
+                signer_to_address(tx.get_transaction_with_sig().get_crypto(), tx.get_signer());
 
             if block_quota_limit < quota {
                 return false;
@@ -73,23 +75,59 @@ impl<'a> BlockVerify<'a> {
         true
     }
 
-    pub fn verify_version() -> bool {
-        unimplemented!();
+    /// All transactions must carry the current chain version.
+    pub fn verify_version(&self, version: u32) -> Result<(), Error> {
+        for tx in self.transactions() {
+            let tx_version = tx
+                .get_transaction_with_sig()
+                .get_transaction()
+                .get_version();
+            if tx_version != version {
+                info!(
+                    "invalid version: tx_version-{}, chain_version-{}",
+                    tx_version, version
+                );
+                return Err(Error::InvalidVersion);
+            }
+        }
+        Ok(())
     }
 
-    pub fn verify_valid_until_block() -> bool {
-        unimplemented!();
+    /// The block is proposed at `height`, so every transaction must still be valid there
+    /// and not be valid for more than `BLOCKLIMIT` blocks.
+    pub fn verify_valid_until_block(&self, height: u64) -> Result<(), Error> {
+        for tx in self.transactions() {
+            let valid_until_block = tx
+                .get_transaction_with_sig()
+                .get_transaction()
+                .get_valid_until_block();
+            if valid_until_block < height || valid_until_block >= height + BLOCKLIMIT {
+                return Err(Error::InvalidUntilBlock);
+            }
+        }
+        Ok(())
     }
 
-    pub fn verify_emergency_intervention() -> bool {
-        unimplemented!()
+    /// The admin address is only set while emergency intervention is on,
+    /// and then only the admin may send transactions.
+    pub fn verify_emergency_intervention(&self, admin: Option<Address>) -> Result<(), Error> {
+        if let Some(admin) = admin {
+            let forbidden = self.transactions().iter().any(|tx| {
+                signer_to_address(tx.get_transaction_with_sig().get_crypto(), tx.get_signer())
+                    != admin
+            });
+            if forbidden {
+                return Err(Error::Forbidden);
+            }
+        }
+        Ok(())
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crypto::{CreateKey, KeyPair};
+    use crypto::{pubkey_to_address, CreateKey, KeyPair};
     use libproto::Transaction;
 
     #[test]
@@ -131,4 +169,75 @@ mod tests {
             .insert(address.lower_hex(), 500);
         assert_eq!(block.verify_quota(10000, &account_quota_limit, true), false);
     }
+
+    fn signed_tx(keypair: &KeyPair, version: u32, valid_until_block: u64) -> SignedTransaction {
+        let mut raw_tx = Transaction::new();
+        raw_tx.set_version(version);
+        raw_tx.set_valid_until_block(valid_until_block);
+        raw_tx.sign(*keypair.privkey())
+    }
+
+    #[test]
+    fn test_verify_version() {
+        let keypair = KeyPair::gen_keypair();
+        let transactions = vec![signed_tx(&keypair, 2, 200)];
+        let block = BlockVerify {
+            transactions: &transactions,
+        };
+        assert_eq!(block.verify_version(2), Ok(()));
+
+        let transactions = vec![signed_tx(&keypair, 2, 200), signed_tx(&keypair, 1, 200)];
+        let block = BlockVerify {
+            transactions: &transactions,
+        };
+        assert_eq!(block.verify_version(2), Err(Error::InvalidVersion));
+    }
+
+    #[test]
+    fn test_verify_valid_until_block() {
+        let keypair = KeyPair::gen_keypair();
+        let transactions = vec![signed_tx(&keypair, 2, 200)];
+        let block = BlockVerify {
+            transactions: &transactions,
+        };
+
+        assert_eq!(block.verify_valid_until_block(200), Ok(()));
+        assert_eq!(block.verify_valid_until_block(201 - BLOCKLIMIT), Ok(()));
+        // expired
+        assert_eq!(
+            block.verify_valid_until_block(201),
+            Err(Error::InvalidUntilBlock)
+        );
+        // too far in the future
+        assert_eq!(
+            block.verify_valid_until_block(200 - BLOCKLIMIT),
+            Err(Error::InvalidUntilBlock)
+        );
+    }
+
+    #[test]
+    fn test_verify_emergency_intervention() {
+        let admin = KeyPair::gen_keypair();
+        let other = KeyPair::gen_keypair();
+        let admin_address = pubkey_to_address(admin.pubkey());
+        let transactions = vec![signed_tx(&admin, 2, 200)];
+        let block = BlockVerify {
+            transactions: &transactions,
+        };
+        assert_eq!(block.verify_emergency_intervention(None), Ok(()));
+        assert_eq!(
+            block.verify_emergency_intervention(Some(admin_address)),
+            Ok(())
+        );
+
+        let transactions = vec![signed_tx(&admin, 2, 200), signed_tx(&other, 2, 200)];
+        let block = BlockVerify {
+            transactions: &transactions,
+        };
+        assert_eq!(block.verify_emergency_intervention(None), Ok(()));
+        assert_eq!(
+            block.verify_emergency_intervention(Some(admin_address)),
+            Err(Error::Forbidden)
+        );
+    }
 }
@@ -19,6 +19,8 @@ pub struct Config {
     pub tx_verify_thread_num: usize,
     pub tx_verify_cache_size: usize,
     pub tx_pool_limit: usize,
+    #[serde(default)]
+    pub account_pool_limit: usize,
     pub wal_enable: bool,
     pub prof_start: u64,
     pub prof_duration: u64,
@@ -43,6 +45,7 @@ mod tests {
         tx_verify_thread_num = 4
         tx_verify_cache_size = 100000
         tx_pool_limit = 50000
+        account_pool_limit = 1000
         wal_enable = true
         prof_start = 0
         prof_duration = 0
@@ -58,6 +61,7 @@ mod tests {
         assert_eq!(4, value.tx_verify_thread_num);
         assert_eq!(100000, value.tx_verify_cache_size);
         assert_eq!(50000, value.tx_pool_limit);
+        assert_eq!(1000, value.account_pool_limit);
         assert_eq!(true, value.wal_enable);
         assert_eq!(0, value.prof_start);
         assert_eq!(0, value.prof_duration);
@@ -13,6 +13,8 @@
 // limitations under the License.
 
 use crate::handler::SysConfigInfo;
+use crate::pool::{PendingTransaction, Pool, PoolStatus};
+use crate::transaction_verify::Error;
 use crate::txwal::TxWal;
 use cita_types::traits::LowerHex;
 use cita_types::{Address, H256};
@@ -25,20 +27,21 @@ use std::cell::RefCell;
 use std::collections::HashSet;
 use std::convert::Into;
 use std::thread;
-use tx_pool;
 
 pub struct Dispatcher {
-    txs_pool: RefCell<tx_pool::Pool>,
+    txs_pool: RefCell<Pool>,
     wal: TxWal,
     wal_enable: bool,
+    account_limit: usize,
 }
 
 impl Dispatcher {
-    pub fn new(wal_enable: bool) -> Self {
+    pub fn new(wal_enable: bool, account_limit: usize) -> Self {
         let mut dispatch = Dispatcher {
-            txs_pool: RefCell::new(tx_pool::Pool::new(0)),
+            txs_pool: RefCell::new(Pool::new(0, account_limit)),
             wal: TxWal::new("/txwal"),
             wal_enable,
+            account_limit,
         };
 
         // restore tx data from wal to txs_pool
@@ -56,7 +59,7 @@ impl Dispatcher {
 
     /// Clean transaction pool and regenerate an pool cache db
     pub fn clear_txs_pool(&mut self, package_limit: usize) {
-        self.txs_pool = RefCell::new(tx_pool::Pool::new(package_limit));
+        self.txs_pool = RefCell::new(Pool::new(package_limit, self.account_limit));
         self.wal.regenerate("/txwal");
     }
 
@@ -64,6 +67,29 @@ impl Dispatcher {
         self.txs_pool.borrow().len()
     }
 
+    /// Number of accounts with transactions in pool
+    pub fn tx_pool_account_len(&self) -> usize {
+        self.txs_pool.borrow().account_len()
+    }
+
+    pub fn pool_status(&self) -> PoolStatus {
+        self.txs_pool.borrow().status()
+    }
+
+    /// Summaries of the first `limit` transactions to be packaged, all if `limit` is 0
+    pub fn pending_transactions(&self, limit: usize) -> Vec<PendingTransaction> {
+        self.txs_pool.borrow().pending(limit)
+    }
+
+    pub fn pending_transactions_by_account(&self, address: &Address) -> Vec<PendingTransaction> {
+        self.txs_pool.borrow().account_pending(address)
+    }
+
+    /// Transactions of an account in pool, in package order
+    pub fn get_account_txs(&self, address: &Address) -> Vec<SignedTransaction> {
+        self.txs_pool.borrow().account_txs(address)
+    }
+
     /// package a block with new transactions,
     /// send to cita-bft
     pub fn proposal_tx_list(
@@ -119,21 +145,25 @@ impl Dispatcher {
             .unwrap();
     }
 
-    pub fn add_tx_to_pool(&self, tx: &SignedTransaction) -> bool {
+    pub fn add_tx_to_pool(&self, tx: &SignedTransaction) -> Result<(), Error> {
         trace!("add tx {} to pool", tx.get_tx_hash().lower_hex());
         let txs_pool = &mut self.txs_pool.borrow_mut();
-        let success = txs_pool.enqueue(tx.clone());
-        if self.wal_enable {
-            if success {
-                self.wal.write(tx);
-            } else {
+        match txs_pool.enqueue(tx.clone()) {
+            Ok(()) => {
+                if self.wal_enable {
+                    self.wal.write(tx);
+                }
+                Ok(())
+            }
+            Err(e) => {
                 warn!(
-                    "the transaction {} is already exist",
-                    tx.get_tx_hash().lower_hex()
+                    "the transaction {} is not added to pool: {}",
+                    tx.get_tx_hash().lower_hex(),
+                    e
                 );
+                Err(e)
             }
         }
-        success
     }
 
     // TODO: Wal shoud be inside pool
@@ -143,7 +173,7 @@ impl Dispatcher {
             .into_iter()
             .filter(|tx| {
                 trace!("add txs {} to pool", tx.get_tx_hash().lower_hex());
-                txs_pool.enqueue(tx.clone())
+                txs_pool.enqueue(tx.clone()).is_ok()
             })
             .collect();
         if self.wal_enable {
@@ -172,15 +202,21 @@ impl Dispatcher {
         admin_address: &Option<Address>,
         version: u32,
     ) -> Vec<SignedTransaction> {
-        let txs_pool = &mut self.txs_pool.borrow_mut();
-        txs_pool.package(
+        let (txs, invalid) = self.txs_pool.borrow_mut().package(
             height,
             block_quota_limit,
             account_quota_limit,
             check_quota,
             *admin_address,
             version,
-        )
+        );
+        if self.wal_enable && !invalid.is_empty() {
+            let mut wal = self.wal.clone();
+            thread::spawn(move || {
+                wal.delete_with_hashes(&invalid);
+            });
+        }
+        txs
     }
 
     pub fn del_txs_from_pool_with_hash(&self, txs: &HashSet<H256>) {
@@ -200,11 +236,16 @@ impl Dispatcher {
     // This function will be called in Dispatcher::new().
     pub fn read_tx_from_wal(&mut self) -> usize {
         let txs = self.wal.read_all();
-        let len = txs.len();
         let mut pool = self.txs_pool.borrow_mut();
+        let mut dropped = Vec::new();
         for tx in txs {
-            pool.enqueue(tx);
+            let hash = H256::from_slice(tx.get_tx_hash());
+            if pool.enqueue(tx).is_err() {
+                dropped.push(hash);
+            }
         }
-        len
+        // the pool config may have changed since these were written
+        self.wal.delete_with_hashes(&dropped);
+        pool.len()
     }
 }
@@ -16,18 +16,20 @@ use crate::block_txn::{BlockTxnMessage, BlockTxnReq};
 use crate::block_verify::BlockVerify;
 use crate::dispatcher::Dispatcher;
 use crate::history::HistoryHeights;
+use crate::metrics;
 use crate::transaction_verify::Error;
+use cita_rpc_ext as ext;
 use cita_types::traits::LowerHex;
 use cita_types::{clean_0x, Address, H256, U256};
 use crypto::{pubkey_to_address, PubKey, Sign, Signature, SIGNATURE_BYTES_LEN};
 use error::ErrorCode;
-use jsonrpc_types::rpc_types::TxResponse;
+use jsonrpc_types::rpc_types::{Data20, TxResponse};
 use libproto::auth::{Miscellaneous, MiscellaneousReq};
 use libproto::blockchain::{AccountGasLimit, SignedTransaction, Transaction};
 use libproto::router::{MsgType, RoutingKey, SubModules};
 use libproto::snapshot::{Cmd, Resp, SnapshotReq, SnapshotResp};
 use libproto::{
-    BlackList, BlockTxHashes, BlockTxHashesReq, BlockTxn, Crypto, GetBlockTxn, Message,
+    BlackList, BlockTxHashes, BlockTxHashesReq, BlockTxn, Crypto, GetBlockTxn, Message, MsgClass,
     OperateType, Origin, Request, Response, UnverifiedTransaction, VerifyBlockReq, VerifyTxReq,
 };
 use libproto::{TryFrom, TryInto};
@@ -49,23 +51,34 @@ const G_TX_DATA_NON_ZERO: usize = 68;
 const G_TRANSACTION: usize = 21000;
 // Paid for contract create
 const G_CREATE: usize = 32000;
+// Reports of an address which auth lets pass before forbidding it
+const BLACK_LIST_CREDIT: i8 = 3;
+// Credit of a forbidden address
+const FORBIDDEN_CREDIT: i8 = -1;
 
 // verify signature
 pub fn verify_tx_sig(crypto: Crypto, hash: &H256, sig_bytes: &[u8]) -> Result<Vec<u8>, ()> {
-    if sig_bytes.len() != SIGNATURE_BYTES_LEN {
-        return Err(());
-    }
-
-    let sig = Signature::from(sig_bytes);
     match crypto {
-        Crypto::DEFAULT => sig
-            .recover(&hash)
+        Crypto::DEFAULT => {
+            if sig_bytes.len() != SIGNATURE_BYTES_LEN {
+                return Err(());
+            }
+            Signature::from(sig_bytes)
+                .recover(&hash)
+                .map(|pubkey| pubkey.to_vec())
+                .map_err(|_| ())
+        }
+        Crypto::RESERVED => reserved_crypto::recover(sig_bytes, &hash.0)
             .map(|pubkey| pubkey.to_vec())
             .map_err(|_| ()),
-        _ => {
-            warn!("Unexpected crypto");
-            Err(())
-        }
+    }
+}
+
+/// Address of the signer, as the crypto of its transaction derives it.
+pub fn signer_to_address(crypto: Crypto, signer: &[u8]) -> Address {
+    match crypto {
+        Crypto::DEFAULT => pubkey_to_address(&PubKey::from_slice(signer)),
+        Crypto::RESERVED => Address::from_slice(&reserved_crypto::pubkey_to_address(signer)),
     }
 }
 
@@ -161,12 +174,12 @@ impl MsgHandler {
         self.cache.put(tx_hash, option_pubkey);
     }
 
-    pub fn verify_tx_quota(&self, quota: u64, signer: &[u8]) -> bool {
+    pub fn verify_tx_quota(&self, quota: u64, crypto: Crypto, signer: &[u8]) -> bool {
         if quota > self.config_info.block_quota_limit {
             return false;
         }
         if self.config_info.check_quota {
-            let addr = pubkey_to_address(&PubKey::from(signer));
+            let addr = signer_to_address(crypto, signer);
             let mut quota_limit = self
                 .config_info
                 .account_quota_limit
@@ -234,7 +247,7 @@ impl MsgHandler {
     fn verify_black_list(&self, req: &VerifyTxReq) -> Result<(), Error> {
         if let Some(credit) = self
             .black_list_cache
-            .get(&pubkey_to_address(&PubKey::from_slice(req.get_signer())))
+            .get(&signer_to_address(req.get_crypto(), req.get_signer()))
         {
             if *credit < 0 {
                 Err(Error::Forbidden)
@@ -304,7 +317,7 @@ impl MsgHandler {
         if self
             .config_info
             .admin_address
-            .map(|admin| pubkey_to_address(&PubKey::from_slice(req.get_signer())) != admin)
+            .map(|admin| signer_to_address(req.get_crypto(), req.get_signer()) != admin)
             .unwrap_or_else(|| false)
         {
             return Err(Error::Forbidden);
@@ -328,13 +341,30 @@ impl MsgHandler {
             }
         }
 
-        if !self.verify_tx_quota(req.get_quota(), req.get_signer()) {
+        if !self.verify_tx_quota(req.get_quota(), req.get_crypto(), req.get_signer()) {
             return Err(Error::QuotaNotEnough);
         }
 
         Ok(())
     }
 
+    // verify version, valid_until_block, emergency intervention and quota of a proposal
+    fn verify_block(&self, height: u64, block: &BlockVerify) -> Result<(), Error> {
+        if let Some(version) = self.config_info.version {
+            block.verify_version(version)?;
+        }
+        block.verify_valid_until_block(height)?;
+        block.verify_emergency_intervention(self.config_info.admin_address)?;
+        if !block.verify_quota(
+            self.config_info.block_quota_limit,
+            &self.config_info.account_quota_limit,
+            self.config_info.check_quota,
+        ) {
+            return Err(Error::QuotaNotEnough);
+        }
+        Ok(())
+    }
+
     fn publish_tx_failed_result(&self, request_id: Vec<u8>, ret: &Error) {
         let result = format!("{:?}", ret);
         let mut response = Response::new();
@@ -406,6 +436,9 @@ impl MsgHandler {
     }
 
     fn daily_task(&mut self) {
+        metrics::TX_POOL_SIZE.set(self.dispatcher.tx_pool_len() as i64);
+        metrics::TX_POOL_ACCOUNTS.set(self.dispatcher.tx_pool_account_len() as i64);
+
         if self.is_need_proposal_new_block && self.is_ready() {
             self.dispatcher.proposal_tx_list(
                 (self.history_heights.next_height() - 1) as usize, // todo fix bft
@@ -460,6 +493,21 @@ impl MsgHandler {
                         error!("Can not get black list from message {:?}.", msg);
                     }
                 }
+                routing_key!(Executor >> RawBytes) => {
+                    let data = msg.take_raw_bytes().unwrap_or_default();
+                    // Responses of the executor to jsonrpc travel on the same key, skip them.
+                    match ext::Message::from_bytes(&data) {
+                        Ok(ext::Message::ManualBlackList(list)) => {
+                            self.deal_manual_black_list(&list)
+                        }
+                        Ok(_) => {}
+                        Err(e) => error!("Can not get message from raw bytes: {:?}.", e),
+                    }
+                }
+                routing_key!(Jsonrpc >> RawBytes) => {
+                    let data = msg.take_raw_bytes().unwrap_or_default();
+                    self.reply_ext_request(&data);
+                }
                 routing_key!(Net >> Request) | routing_key!(Jsonrpc >> RequestNewTxBatch) => {
                     if let Some(newtx_req) = msg.take_request() {
                         let is_local = rounting_key.is_sub_module(SubModules::Jsonrpc);
@@ -596,6 +644,50 @@ impl MsgHandler {
         self.history_hashes.entry(height).or_insert(tx_hashes_h256);
     }
 
+    /// Serve the methods of `cita_rpc_ext` for auth, the others are for chain.
+    fn reply_ext_request(&self, data: &[u8]) {
+        let req = match ext::Message::from_bytes(data) {
+            Ok(ext::Message::Request(req)) => req,
+            Ok(_) => return,
+            Err(e) => {
+                warn!("Invalid extension request: {:?}.", e);
+                return;
+            }
+        };
+        if req.service() != Some(ext::Service::Auth) {
+            return;
+        }
+
+        let result = match req.method.as_str() {
+            ext::GET_POOL_STATUS => {
+                Ok(serde_json::to_value(self.dispatcher.pool_status()).unwrap())
+            }
+            ext::GET_PENDING_TRANSACTIONS => req.param::<Option<usize>>(0).map(|limit| {
+                let txs = self.dispatcher.pending_transactions(limit.unwrap_or(0));
+                serde_json::to_value(txs).unwrap()
+            }),
+            ext::GET_PENDING_TRANSACTIONS_BY_ACCOUNT => req.param::<Data20>(0).map(|address| {
+                let address = Address::from_slice(address.as_ref());
+                let txs = self.dispatcher.pending_transactions_by_account(&address);
+                serde_json::to_value(txs).unwrap()
+            }),
+            method => Err(ext::Error::new(
+                ErrorCode::query_error(),
+                format!("{} is not served by auth", method),
+            )),
+        };
+        let resp = ext::Message::Response(ext::Response::new(req.id, result));
+        let msg = Message::init(OperateType::Single, 0, MsgClass::RawBytes(resp.to_bytes()));
+        self.tx_pub
+            .send((
+                routing_key!(Auth >> RawBytes).into(),
+                msg.try_into().unwrap(),
+            ))
+            .unwrap();
+    }
+
+    /// Each report of an address after the first takes one from its credit,
+    /// it is forbidden when the credit runs out.
     fn deal_black_list(&mut self, black_list: &BlackList) {
         black_list
             .get_clear_list()
@@ -621,11 +713,27 @@ impl MsgHandler {
                             *e -= 1;
                         }
                     })
-                    .or_insert(3);
+                    .or_insert(BLACK_LIST_CREDIT);
                 debug!("Current black list is {:?}", self.black_list_cache);
             });
     }
 
+    /// Addresses added by an admin are forbidden at once, and allowed again when removed.
+    fn deal_manual_black_list(&mut self, list: &ext::ManualBlackList) {
+        let parse = |address: &String| {
+            Address::from_str(clean_0x(address))
+                .map_err(|e| error!("Invalid address {} in black list: {:?}.", address, e))
+                .ok()
+        };
+        for address in list.allow.iter().filter_map(parse) {
+            self.black_list_cache.remove(&address);
+        }
+        for address in list.forbid.iter().filter_map(parse) {
+            self.black_list_cache.insert(address, FORBIDDEN_CREDIT);
+        }
+        debug!("Current black list is {:?}", self.black_list_cache);
+    }
+
     #[allow(unknown_lints, clippy::cognitive_complexity)] // TODO clippy
     fn deal_request(&mut self, is_local: bool, newtx_req: Request) {
         if newtx_req.has_batch_req() {
@@ -647,6 +755,7 @@ impl MsgHandler {
 
             if self.is_flow_control(batch_new_tx.len()) {
                 trace!("flow control ...");
+                metrics::FLOW_CONTROL_REJECTIONS.inc_by(batch_new_tx.len() as i64);
                 if is_local {
                     for tx_req in batch_new_tx.iter() {
                         let request_id = tx_req.get_request_id().to_vec();
@@ -751,15 +860,20 @@ impl MsgHandler {
                     signed_tx.set_signer(req.get_signer().to_vec());
                     signed_tx.set_tx_hash(tx_hash.to_vec());
                     let request_id = tx_req.get_request_id().to_vec();
-                    if self.dispatcher.add_tx_to_pool(&signed_tx) {
-                        if is_local {
-                            self.publish_tx_success_result(request_id, tx_hash);
+                    match self.dispatcher.add_tx_to_pool(&signed_tx) {
+                        Ok(()) => {
+                            if is_local {
+                                self.publish_tx_success_result(request_id, tx_hash);
+                            }
+                            // new tx need forward to other nodes
+                            self.forward_request(tx_req.clone());
+                        }
+                        // dup or too many txs of the sender in pool
+                        Err(e) => {
+                            if is_local {
+                                self.publish_tx_failed_result(request_id, &e);
+                            }
                         }
-                        // new tx need forward to other nodes
-                        self.forward_request(tx_req.clone());
-                    } else if is_local {
-                        // dup with transaction in tx pool
-                        self.publish_tx_failed_result(request_id, &Error::Dup);
                     }
                 });
         } else if newtx_req.has_un_tx() {
@@ -774,6 +888,7 @@ impl MsgHandler {
             }
             if self.is_flow_control(1) {
                 trace!("flow control ...");
+                metrics::FLOW_CONTROL_REJECTIONS.inc();
                 if is_local {
                     self.publish_tx_failed_result(request_id, &Error::Busy);
                 }
@@ -836,15 +951,20 @@ impl MsgHandler {
             signed_tx.set_transaction_with_sig(newtx_req.get_un_tx().clone());
             signed_tx.set_signer(req.get_signer().to_vec());
             signed_tx.set_tx_hash(tx_hash.to_vec());
-            if self.dispatcher.add_tx_to_pool(&signed_tx) {
-                if is_local {
-                    self.publish_tx_success_result(request_id, tx_hash);
+            match self.dispatcher.add_tx_to_pool(&signed_tx) {
+                Ok(()) => {
+                    if is_local {
+                        self.publish_tx_success_result(request_id, tx_hash);
+                    }
+                    // new tx need forward to other nodes
+                    self.forward_request(newtx_req);
+                }
+                // dup or too many txs of the sender in pool
+                Err(e) => {
+                    if is_local {
+                        self.publish_tx_failed_result(request_id, &e);
+                    }
                 }
-                // new tx need forward to other nodes
-                self.forward_request(newtx_req);
-            } else if is_local {
-                // dup with transaction in tx pool
-                self.publish_tx_failed_result(request_id, &Error::Dup);
             }
         }
     }
@@ -968,11 +1088,25 @@ impl MsgHandler {
         if missing_hashes.is_empty() {
             // TODO: Refactor
             let transactions = self.dispatcher.get_txs(&tx_hashes);
+            let height = verify_block_req.get_block().get_header().get_height();
 
-            if let Err(err) = verify_block_req.check_txs(&transactions[..]) {
-                error!("verify_block_req check txs failed {:?}", err);
-            }
-            let resp = verify_block_req.reply(Ok(transactions));
+            let resp = match self.verify_block(
+                height,
+                &BlockVerify {
+                    transactions: &transactions,
+                },
+            ) {
+                Ok(()) => {
+                    if let Err(err) = verify_block_req.check_txs(&transactions[..]) {
+                        error!("verify_block_req check txs failed {:?}", err);
+                    }
+                    verify_block_req.reply(Ok(transactions))
+                }
+                Err(err) => {
+                    warn!("proposal at height {} rejected: {}", height, err);
+                    verify_block_req.reply(Err(()))
+                }
+            };
 
             let msg = Message::init(OperateType::Single, 0, resp.into());
             self.tx_pub
@@ -1026,26 +1160,25 @@ impl MsgHandler {
                 return;
             }
 
-            let result = {
-                let block = BlockVerify {
-                    transactions: &transactions,
-                };
-
-                block.verify_quota(
-                    self.config_info.block_quota_limit,
-                    &self.config_info.account_quota_limit,
-                    self.config_info.check_quota,
-                )
-            };
+            let height = verify_block_req.get_block().get_header().get_height();
 
             // TODO: Refactor
-            let resp = if result {
-                if let Err(err) = verify_block_req.check_txs(&transactions[..]) {
-                    error!("verify_block_req check txs failed {:?}", err);
+            let resp = match self.verify_block(
+                height,
+                &BlockVerify {
+                    transactions: &transactions,
+                },
+            ) {
+                Ok(()) => {
+                    if let Err(err) = verify_block_req.check_txs(&transactions[..]) {
+                        error!("verify_block_req check txs failed {:?}", err);
+                    }
+                    verify_block_req.reply(Ok(transactions))
+                }
+                Err(err) => {
+                    warn!("proposal at height {} rejected: {}", height, err);
+                    verify_block_req.reply(Err(()))
                 }
-                verify_block_req.reply(Ok(transactions))
-            } else {
-                verify_block_req.reply(Err(()))
             };
             let msg = Message::init(OperateType::Single, 0, resp.into());
             self.tx_pub
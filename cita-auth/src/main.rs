@@ -27,7 +27,9 @@
 //!     | auth  | Consensus | VerifyBlockReq    |
 //!     | auth  | Chain     | BlockTxHashes     |
 //!     | auth  | Executor  | BlackList         |
+//!     | auth  | Executor  | RawBytes          |
 //!     | auth  | Jsonrpc   | RequestNewTxBatch |
+//!     | auth  | Jsonrpc   | RawBytes          |
 //!     | auth  | Net       | Request           |
 //!     | auth  | Snapshot  | SnapshotReq       |
 //!     | auth  | Executor  | Miscellaneous     |
@@ -41,6 +43,7 @@
 //!     | auth  | Auth      | Chain     | BlockTxHashesReq |
 //!     | auth  | Auth      | Consensus | VerifyBlockResp  |
 //!     | auth  | Auth      | Jsonrpc   | Response         |
+//!     | auth  | Auth      | Jsonrpc   | RawBytes         |
 //!     | auth  | Auth      | Net       | Request          |
 //!     | auth  | Auth      | Consensus | BlockTxs         |
 //!     | auth  | Auth      | Snapshot  | SnapshotResp     |
@@ -59,13 +62,14 @@
 //! - [`handle module`]
 //!
 //! [`Dispatcher`]: ./dispatcher/struct.Dispatcher.html
-//! [`Pool`]: ../tx_pool/pool/struct.Pool.html
+//! [`Pool`]: ./pool/struct.Pool.html
 //! [`TxWal`]: ./txwal/struct.TxWal.html
 //! [`Verifier`]: ./verifier/struct.Verifier.html
 //! [`handle module`]: ./handler/index.html
 //!
 
 extern crate cita_crypto as crypto;
+extern crate cita_reserved_crypto as reserved_crypto;
 #[macro_use]
 extern crate libproto;
 #[macro_use]
@@ -100,6 +104,8 @@ pub mod config;
 pub mod dispatcher;
 pub mod handler;
 pub mod history;
+pub mod metrics;
+pub mod pool;
 mod transaction_verify;
 pub mod txwal;
 
@@ -132,7 +138,8 @@ fn main() {
         .about("CITA Block Chain Node powered by Rust")
         .args_from_usage(
             "-c, --config=[FILE] 'Sets a custom config file'
-                          -s, --stdout 'Log to console'",
+                          -s, --stdout 'Log to console'
+                          -m, --metrics=[ADDR] 'Serves Prometheus metrics on ADDR'",
         )
         .get_matches();
 
@@ -140,6 +147,10 @@ fn main() {
     micro_service_init!("cita-auth", "CITA:auth", stdout);
     info!("Version: {}", get_build_info_str(true));
 
+    if let Some(addr) = matches.value_of("metrics") {
+        cita_metrics::start_server(addr).expect("Start metrics server failed");
+    }
+
     let config_path = matches.value_of("config").unwrap_or("auth.toml");
 
     let config = Config::new(config_path);
@@ -150,6 +161,7 @@ fn main() {
     let tx_verify_cache_size = config.tx_verify_cache_size;
     let tx_pool_limit = config.tx_pool_limit;
     let wal_enable = config.wal_enable;
+    let account_pool_limit = config.account_pool_limit;
 
     // start profiler
     let flag_prof_start = config.prof_start;
@@ -169,7 +181,9 @@ fn main() {
             Consensus >> VerifyBlockReq,
             Chain >> BlockTxHashes,
             Executor >> BlackList,
+            Executor >> RawBytes,
             Jsonrpc >> RequestNewTxBatch,
+            Jsonrpc >> RawBytes,
             Net >> Request,
             Snapshot >> SnapshotReq,
             Executor >> Miscellaneous,
@@ -189,7 +203,7 @@ fn main() {
         batch_forward.run();
     });
 
-    let dispatcher = Dispatcher::new(wal_enable);
+    let dispatcher = Dispatcher::new(wal_enable, account_pool_limit);
 
     // handle message from MQ
     let mut msg_handler = MsgHandler::new(
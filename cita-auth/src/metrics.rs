@@ -0,0 +1,33 @@
+// Copyright Rivtower Technologies LLC.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Metrics of auth, served on `/metrics`.
+
+use lazy_static::lazy_static;
+use prometheus::{register_int_counter, register_int_gauge, IntCounter, IntGauge};
+
+lazy_static! {
+    pub static ref TX_POOL_SIZE: IntGauge =
+        register_int_gauge!("auth_tx_pool_size", "Transactions in pool").unwrap();
+    pub static ref TX_POOL_ACCOUNTS: IntGauge = register_int_gauge!(
+        "auth_tx_pool_accounts",
+        "Accounts with transactions in pool"
+    )
+    .unwrap();
+    pub static ref FLOW_CONTROL_REJECTIONS: IntCounter = register_int_counter!(
+        "auth_flow_control_rejections_total",
+        "Transactions rejected because the pool is full"
+    )
+    .unwrap();
+}
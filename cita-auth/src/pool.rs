@@ -0,0 +1,394 @@
+// Copyright Rivtower Technologies LLC.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Transaction pool.
+//!
+//! Pending transactions are packaged in arrival order, and indexed by sender so that
+//! a sender may hold at most `account_limit` of them.
+//!
+//! The quota price is set for the whole chain by the `PriceManagement` contract, a
+//! transaction does not offer a price of its own, so there is no price to order by
+//! or to replace a pending transaction with.
+
+use crate::handler::signer_to_address;
+use crate::transaction_verify::Error;
+use cita_types::traits::LowerHex;
+use cita_types::{Address, H256};
+use jsonrpc_types::rpc_types::Quantity;
+use libproto::blockchain::{AccountGasLimit, SignedTransaction};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use util::BLOCKLIMIT;
+
+/// Pool size, for `getPoolStatus`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PoolStatus {
+    pub pending: usize,
+    pub accounts: usize,
+    pub account_limit: usize,
+}
+
+/// Summary of a pending transaction, for `getPendingTransactions`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PendingTransaction {
+    pub hash: H256,
+    pub from: Address,
+    pub nonce: String,
+    pub quota: Quantity,
+    pub valid_until_block: Quantity,
+}
+
+impl PendingTransaction {
+    fn new(hash: H256, pool_tx: &PoolTx) -> Self {
+        let tx = pool_tx.tx.get_transaction_with_sig().get_transaction();
+        PendingTransaction {
+            hash,
+            from: pool_tx.sender,
+            nonce: tx.get_nonce().to_owned(),
+            quota: Quantity::from(tx.get_quota()),
+            valid_until_block: Quantity::from(tx.get_valid_until_block()),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct PoolTx {
+    tx: SignedTransaction,
+    sender: Address,
+    seq: u64,
+}
+
+#[derive(Debug)]
+pub struct Pool {
+    package_limit: usize,
+    account_limit: usize,
+    seq: u64,
+    txs: HashMap<H256, PoolTx>,
+    // arrival seq -> tx hash
+    queue: BTreeMap<u64, H256>,
+    // sender -> tx hashes
+    accounts: HashMap<Address, HashSet<H256>>,
+}
+
+impl Pool {
+    pub fn new(package_limit: usize, account_limit: usize) -> Self {
+        Pool {
+            package_limit,
+            account_limit,
+            seq: 0,
+            txs: HashMap::new(),
+            queue: BTreeMap::new(),
+            accounts: HashMap::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.txs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.txs.is_empty()
+    }
+
+    /// Number of senders with pending transactions.
+    pub fn account_len(&self) -> usize {
+        self.accounts.len()
+    }
+
+    pub fn status(&self) -> PoolStatus {
+        PoolStatus {
+            pending: self.len(),
+            accounts: self.account_len(),
+            account_limit: self.account_limit,
+        }
+    }
+
+    pub fn get(&self, hash: &H256) -> Option<&SignedTransaction> {
+        self.txs.get(hash).map(|pool_tx| &pool_tx.tx)
+    }
+
+    /// Add a transaction to the pool.
+    pub fn enqueue(&mut self, tx: SignedTransaction) -> Result<(), Error> {
+        let hash = H256::from_slice(tx.get_tx_hash());
+        if self.txs.contains_key(&hash) {
+            return Err(Error::Dup);
+        }
+
+        let sender = signer_to_address(tx.get_transaction_with_sig().get_crypto(), tx.get_signer());
+        let pending = self.accounts.get(&sender).map_or(0, HashSet::len);
+        if self.account_limit != 0 && pending >= self.account_limit {
+            return Err(Error::AccountBusy);
+        }
+
+        self.seq += 1;
+        self.queue.insert(self.seq, hash);
+        self.accounts
+            .entry(sender)
+            .or_insert_with(HashSet::new)
+            .insert(hash);
+        self.txs.insert(
+            hash,
+            PoolTx {
+                tx,
+                sender,
+                seq: self.seq,
+            },
+        );
+        Ok(())
+    }
+
+    /// Remove transactions which have been included in a block.
+    pub fn update_with_hash(&mut self, hashes: &HashSet<H256>) {
+        for hash in hashes {
+            self.remove(hash);
+        }
+    }
+
+    /// Pending transactions of an account, in package order.
+    pub fn account_txs(&self, address: &Address) -> Vec<SignedTransaction> {
+        self.account_pool_txs(address)
+            .into_iter()
+            .map(|(_, pool_tx)| pool_tx.tx.clone())
+            .collect()
+    }
+
+    /// The first `limit` pending transactions in package order, all of them if `limit` is 0.
+    pub fn pending(&self, limit: usize) -> Vec<PendingTransaction> {
+        let limit = if limit == 0 { self.len() } else { limit };
+        self.queue
+            .values()
+            .take(limit)
+            .map(|hash| PendingTransaction::new(*hash, &self.txs[hash]))
+            .collect()
+    }
+
+    pub fn account_pending(&self, address: &Address) -> Vec<PendingTransaction> {
+        self.account_pool_txs(address)
+            .into_iter()
+            .map(|(hash, pool_tx)| PendingTransaction::new(hash, pool_tx))
+            .collect()
+    }
+
+    fn account_pool_txs(&self, address: &Address) -> Vec<(H256, &PoolTx)> {
+        let mut txs: Vec<(H256, &PoolTx)> = self
+            .accounts
+            .get(address)
+            .map(|hashes| hashes.iter().map(|hash| (*hash, &self.txs[hash])).collect())
+            .unwrap_or_default();
+        txs.sort_by_key(|(_, pool_tx)| pool_tx.seq);
+        txs
+    }
+
+    /// Take transactions for the block at `height`, without removing them.
+    ///
+    /// Transactions which can no longer be included are dropped from the pool,
+    /// their hashes are returned along with the package.
+    pub fn package(
+        &mut self,
+        height: u64,
+        block_quota_limit: u64,
+        account_quota_limit: AccountGasLimit,
+        check_quota: bool,
+        admin_address: Option<Address>,
+        version: u32,
+    ) -> (Vec<SignedTransaction>, Vec<H256>) {
+        let mut package = Vec::new();
+        let mut invalid = Vec::new();
+        let mut block_quota_left = block_quota_limit;
+        let mut account_quota_used: HashMap<Address, u64> = HashMap::new();
+        let common_quota_limit = account_quota_limit.get_common_quota_limit();
+        let specific_quota_limit = account_quota_limit.get_specific_quota_limit();
+
+        for hash in self.queue.values() {
+            let pool_tx = &self.txs[hash];
+            let tx = pool_tx.tx.get_transaction_with_sig().get_transaction();
+
+            let valid_until_block = tx.get_valid_until_block();
+            if valid_until_block < height
+                || valid_until_block >= height + BLOCKLIMIT
+                || tx.get_version() != version
+            {
+                invalid.push(*hash);
+                continue;
+            }
+
+            if admin_address.map_or(false, |admin| admin != pool_tx.sender) {
+                continue;
+            }
+
+            let quota = tx.get_quota();
+            if quota > block_quota_left {
+                continue;
+            }
+            if check_quota {
+                let used = account_quota_used.entry(pool_tx.sender).or_insert(0);
+                let limit = specific_quota_limit
+                    .get(&pool_tx.sender.lower_hex())
+                    .cloned()
+                    .unwrap_or(common_quota_limit);
+                if *used + quota > limit {
+                    continue;
+                }
+                *used += quota;
+            }
+
+            block_quota_left -= quota;
+            package.push(pool_tx.tx.clone());
+            if self.package_limit != 0 && package.len() >= self.package_limit {
+                break;
+            }
+        }
+
+        for hash in &invalid {
+            self.remove(hash);
+        }
+        (package, invalid)
+    }
+
+    fn remove(&mut self, hash: &H256) -> Option<SignedTransaction> {
+        let pool_tx = self.txs.remove(hash)?;
+        self.queue.remove(&pool_tx.seq);
+        let is_empty = match self.accounts.get_mut(&pool_tx.sender) {
+            Some(hashes) => {
+                hashes.remove(hash);
+                hashes.is_empty()
+            }
+            None => false,
+        };
+        if is_empty {
+            self.accounts.remove(&pool_tx.sender);
+        }
+        Some(pool_tx.tx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crypto::{CreateKey, KeyPair, PrivKey};
+    use libproto::blockchain::Transaction;
+    use serde_json::json;
+
+    fn tx(privkey: &PrivKey, nonce: &str, quota: u64) -> SignedTransaction {
+        let mut raw_tx = Transaction::new();
+        raw_tx.set_nonce(nonce.to_owned());
+        raw_tx.set_quota(quota);
+        raw_tx.set_valid_until_block(99);
+        raw_tx.sign(*privkey)
+    }
+
+    fn package(pool: &mut Pool, block_quota_limit: u64) -> Vec<SignedTransaction> {
+        pool.package(1, block_quota_limit, AccountGasLimit::new(), false, None, 0)
+            .0
+    }
+
+    #[test]
+    fn test_fifo() {
+        let keypair = KeyPair::gen_keypair();
+        let mut pool = Pool::new(0, 0);
+        let tx1 = tx(keypair.privkey(), "1", 100);
+        let tx2 = tx(keypair.privkey(), "2", 300);
+        let tx3 = tx(keypair.privkey(), "3", 200);
+        for t in &[&tx1, &tx2, &tx3] {
+            assert_eq!(pool.enqueue((*t).clone()), Ok(()));
+        }
+        assert_eq!(pool.enqueue(tx1.clone()), Err(Error::Dup));
+
+        assert_eq!(
+            package(&mut pool, 1000),
+            vec![tx1.clone(), tx2, tx3.clone()]
+        );
+        // tx2 does not fit, the smaller tx3 still does
+        assert_eq!(package(&mut pool, 300), vec![tx1, tx3]);
+    }
+
+    #[test]
+    fn test_same_nonce() {
+        let keypair = KeyPair::gen_keypair();
+        let mut pool = Pool::new(0, 0);
+        let tx1 = tx(keypair.privkey(), "1", 200);
+        let tx2 = tx(keypair.privkey(), "1", 300);
+        pool.enqueue(tx1.clone()).unwrap();
+        // Nothing to outbid with, so it is just another transaction.
+        pool.enqueue(tx2.clone()).unwrap();
+        assert_eq!(pool.len(), 2);
+        assert_eq!(pool.account_txs(&keypair.address()), vec![tx1, tx2]);
+    }
+
+    #[test]
+    fn test_account_limit() {
+        let (a, b) = (KeyPair::gen_keypair(), KeyPair::gen_keypair());
+        let mut pool = Pool::new(0, 2);
+        pool.enqueue(tx(a.privkey(), "1", 100)).unwrap();
+        pool.enqueue(tx(a.privkey(), "2", 100)).unwrap();
+        assert_eq!(
+            pool.enqueue(tx(a.privkey(), "3", 100)),
+            Err(Error::AccountBusy)
+        );
+        assert_eq!(pool.enqueue(tx(b.privkey(), "1", 100)), Ok(()));
+
+        let hashes = pool
+            .account_txs(&a.address())
+            .iter()
+            .map(|tx| H256::from_slice(tx.get_tx_hash()))
+            .collect();
+        pool.update_with_hash(&hashes);
+        assert_eq!(pool.len(), 1);
+        assert_eq!(pool.account_len(), 1);
+        assert_eq!(pool.enqueue(tx(a.privkey(), "3", 100)), Ok(()));
+    }
+
+    #[test]
+    fn test_drop_invalid() {
+        let keypair = KeyPair::gen_keypair();
+        let mut pool = Pool::new(0, 0);
+        let tx1 = tx(keypair.privkey(), "1", 100);
+        pool.enqueue(tx1.clone()).unwrap();
+        let (txs, invalid) = pool.package(100, 1000, AccountGasLimit::new(), false, None, 0);
+        assert!(txs.is_empty());
+        assert_eq!(invalid, vec![H256::from_slice(tx1.get_tx_hash())]);
+        assert!(pool.is_empty());
+    }
+
+    #[test]
+    fn test_pending() {
+        let (a, b) = (KeyPair::gen_keypair(), KeyPair::gen_keypair());
+        let mut pool = Pool::new(0, 16);
+        let tx1 = tx(a.privkey(), "1", 100);
+        let tx2 = tx(b.privkey(), "1", 200);
+        pool.enqueue(tx1.clone()).unwrap();
+        pool.enqueue(tx2.clone()).unwrap();
+
+        let status = serde_json::to_value(pool.status()).unwrap();
+        assert_eq!(
+            status,
+            json!({"pending": 2, "accounts": 2, "accountLimit": 16})
+        );
+
+        let pending = pool.pending(1);
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].hash, H256::from_slice(tx1.get_tx_hash()));
+        assert_eq!(pool.pending(0).len(), 2);
+
+        let pending = pool.account_pending(&a.address());
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].from, a.address());
+        let value = serde_json::to_value(&pending[0]).unwrap();
+        assert_eq!(value["nonce"], json!("1"));
+        assert_eq!(value["quota"], json!("0x64"));
+        assert_eq!(value["validUntilBlock"], json!("0x63"));
+        assert!(pool.account_pending(&Address::zero()).is_empty());
+    }
+}
@@ -28,6 +28,7 @@ pub enum Error {
     Forbidden,
     InvalidValue,
     InvalidVersion,
+    AccountBusy,
 }
 
 impl fmt::Display for Error {
@@ -45,6 +46,7 @@ impl fmt::Display for Error {
             Forbidden => write!(f, "Forbidden"),
             InvalidValue => write!(f, "InvalidValue"),
             InvalidVersion => write!(f, "InvalidVersion"),
+            AccountBusy => write!(f, "AccountBusy"),
         }
     }
 }
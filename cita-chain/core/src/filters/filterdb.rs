@@ -13,9 +13,50 @@
 // limitations under the License.
 
 use jsonrpc_types::rpc_types::Filter;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::time::SystemTime;
 
+/// What jsonrpc puts between the request id and the client it appends,
+/// the request id is `<uuid>client:<client>`.
+pub const CLIENT_TAG: &[u8] = b"client:";
+
+/// The client that sent a request, the bytes after `CLIENT_TAG` in the request id.
+/// Requests without it are all counted as one anonymous client.
+pub fn client_of(request_id: &[u8]) -> &[u8] {
+    request_id
+        .windows(CLIENT_TAG.len())
+        .rposition(|window| window == CLIENT_TAG)
+        .map(|pos| &request_id[pos + CLIENT_TAG.len()..])
+        .unwrap_or(&[])
+}
+
+/// A filter as saved in the database, one record per filter
+#[derive(Serialize, Deserialize)]
+struct InstalledFilter {
+    client: Vec<u8>,
+    /// Timestamp of the last poll, the filter expires a lifetime after it
+    last_update: u64,
+    /// The next block to report
+    block: u64,
+    logs: Option<Filter>,
+}
+
+/// The ids of the saved filters
+#[derive(Serialize, Deserialize)]
+struct FilterIndex {
+    next_available_id: usize,
+    ids: Vec<usize>,
+}
+
+/// Filters changed since the last `take_unsaved`
+#[derive(Default)]
+pub struct UnsavedFilters {
+    /// The encoded index, if filters were installed or removed
+    pub index: Option<Vec<u8>>,
+    /// The encoded filters, None for the removed ones
+    pub filters: Vec<(usize, Option<Vec<u8>>)>,
+}
+
 // TODO Refactor:
 // * use generic data type
 // * use one hashmap: use tuple type
@@ -84,6 +125,14 @@ pub struct FilterDB {
     logs_filter: LogsFilter,
     /// lifetime of fileter id
     lifetime: u32,
+    /// The client which installed the filter
+    clients: HashMap<usize, Vec<u8>>,
+    /// Most filters one client may install, 0 means no limit
+    max_filters_per_client: usize,
+    /// Filters changed or removed since the last save
+    unsaved: HashSet<usize>,
+    /// Whether filters were installed or removed since the last save
+    index_unsaved: bool,
 }
 
 impl FilterDB {
@@ -106,14 +155,35 @@ impl FilterDB {
         self.prune();
         let id = self.next_available_id;
         self.next_available_id = self.next_available_id.wrapping_add(1);
+        self.index_unsaved = true;
         id
     }
 
+    /// Generate a new fresh id for the client.
+    /// Return None if the client already has too many filters installed.
+    pub fn gen_client_id(&mut self, client: &[u8]) -> Option<usize> {
+        self.prune();
+        if self.max_filters_per_client != 0
+            && self.clients.values().filter(|c| c[..] == *client).count()
+                >= self.max_filters_per_client
+        {
+            return None;
+        }
+        let id = self.gen_id();
+        self.clients.insert(id, client.to_vec());
+        Some(id)
+    }
+
+    pub fn set_max_filters_per_client(&mut self, max: usize) {
+        self.max_filters_per_client = max;
+    }
+
     /// Generate a new normal filter
     pub fn gen_logs_filter(&mut self, id: usize, filter: Filter) {
         let now = now();
         self.last_update.insert(id, now);
         self.logs_filter.insert(id, filter);
+        self.unsaved.insert(id);
     }
 
     /// Generate a new filter for block
@@ -121,6 +191,7 @@ impl FilterDB {
         let now = now();
         self.last_update.insert(id, now);
         self.block_filter.insert(id, filter);
+        self.unsaved.insert(id);
     }
 
     /// Uninstall the filter id
@@ -133,6 +204,10 @@ impl FilterDB {
             if self.is_logs_filter(id) {
                 self.logs_filter.remove(id);
             }
+            self.last_update.remove(&id);
+            self.clients.remove(&id);
+            self.unsaved.insert(id);
+            self.index_unsaved = true;
 
             true
         } else {
@@ -145,10 +220,14 @@ impl FilterDB {
     pub fn prune(&mut self) {
         let now = now();
         for (id, time) in self.last_update.clone().iter() {
-            if (now - *time) >= self.lifetime.into() {
-                trace!("Prune filter, time: {:?}", (now - *time));
+            if now.saturating_sub(*time) >= self.lifetime.into() {
+                trace!("Prune filter, time: {:?}", now.saturating_sub(*time));
                 self.block_filter.remove(*id);
                 self.logs_filter.remove(*id);
+                self.last_update.remove(id);
+                self.clients.remove(id);
+                self.unsaved.insert(*id);
+                self.index_unsaved = true;
             }
         }
     }
@@ -188,6 +267,68 @@ impl FilterDB {
     pub fn is_filter(&self, id: usize) -> bool {
         self.logs_filter.is_filter(id) || self.block_filter.is_filter(id)
     }
+
+    /// Encode the filters changed since the last call, to be saved in the database
+    pub fn take_unsaved(&mut self) -> UnsavedFilters {
+        let mut unsaved = UnsavedFilters::default();
+        if self.index_unsaved {
+            let index = FilterIndex {
+                next_available_id: self.next_available_id,
+                ids: self.block_filter.data.keys().cloned().collect(),
+            };
+            unsaved.index = Some(serde_json::to_vec(&index).unwrap());
+            self.index_unsaved = false;
+        }
+        for id in self.unsaved.drain() {
+            let filter = self.block_filter.get(id).map(|block| InstalledFilter {
+                client: self.clients.get(&id).cloned().unwrap_or_default(),
+                last_update: self.last_update.get(&id).cloned().unwrap_or_else(now),
+                block: *block,
+                logs: self.logs_filter.get(id).cloned(),
+            });
+            unsaved.filters.push((
+                id,
+                filter.map(|filter| serde_json::to_vec(&filter).unwrap()),
+            ));
+        }
+        unsaved
+    }
+
+    /// Restore the filters saved by `take_unsaved`, dropping the expired ones.
+    /// `get_filter` reads the saved record of a filter id.
+    pub fn load<F>(&mut self, index: &[u8], get_filter: F)
+    where
+        F: Fn(usize) -> Option<Vec<u8>>,
+    {
+        let index: FilterIndex = match serde_json::from_slice(index) {
+            Ok(index) => index,
+            Err(e) => {
+                warn!("Drop the saved filters which can not be decoded: {:?}", e);
+                return;
+            }
+        };
+        self.next_available_id = index.next_available_id;
+        for id in index.ids {
+            let filter = get_filter(id)
+                .and_then(|bytes| serde_json::from_slice::<InstalledFilter>(&bytes).ok());
+            let filter = match filter {
+                Some(filter) => filter,
+                None => {
+                    warn!("Drop the saved filter {} which can not be read", id);
+                    self.unsaved.insert(id);
+                    self.index_unsaved = true;
+                    continue;
+                }
+            };
+            self.last_update.insert(id, filter.last_update);
+            self.clients.insert(id, filter.client);
+            self.block_filter.insert(id, filter.block);
+            if let Some(logs) = filter.logs {
+                self.logs_filter.insert(id, logs);
+            }
+        }
+        self.prune();
+    }
 }
 
 /// Generate the now time
@@ -200,9 +341,10 @@ fn now() -> u64 {
 
 #[cfg(test)]
 mod tests {
-    use super::FilterDB;
+    use super::{client_of, FilterDB};
     use jsonrpc_types::rpc_types::BlockNumber;
     use jsonrpc_types::rpc_types::Filter;
+    use std::collections::HashMap;
 
     #[test]
     fn test_gen_id() {
@@ -260,4 +402,95 @@ mod tests {
         assert_eq!(filterdb.get_block_filter(id), None);
         assert_eq!(filterdb.is_filter(id), false);
     }
+
+    #[test]
+    fn test_client_quota() {
+        let mut filterdb = FilterDB::new();
+        filterdb.set_max_filters_per_client(2);
+        let ids = (0..2)
+            .map(|_| filterdb.gen_client_id(b"127.0.0.1").unwrap())
+            .collect::<Vec<_>>();
+        for id in ids.iter() {
+            filterdb.gen_block_filter(*id, 0);
+        }
+        assert_eq!(filterdb.gen_client_id(b"127.0.0.1"), None);
+        // Other clients are not limited by it.
+        assert!(filterdb.gen_client_id(b"127.0.0.2").is_some());
+        // Uninstall frees the quota.
+        assert!(filterdb.uninstall(ids[0]));
+        assert!(filterdb.gen_client_id(b"127.0.0.1").is_some());
+    }
+
+    fn save(filterdb: &mut FilterDB, store: &mut HashMap<usize, Vec<u8>>) -> Option<Vec<u8>> {
+        let unsaved = filterdb.take_unsaved();
+        for (id, filter) in unsaved.filters {
+            match filter {
+                Some(bytes) => store.insert(id, bytes),
+                None => store.remove(&id),
+            };
+        }
+        unsaved.index
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let mut store = HashMap::new();
+        let mut filterdb = FilterDB::new();
+        let logs_id = filterdb.gen_client_id(b"127.0.0.1").unwrap();
+        let filter = Filter::new(BlockNumber::earliest(), BlockNumber::earliest(), None, None);
+        filterdb.gen_logs_filter(logs_id, filter.clone());
+        filterdb.gen_block_filter(logs_id, 7);
+        let block_id = filterdb.gen_client_id(b"127.0.0.1").unwrap();
+        filterdb.gen_block_filter(block_id, 9);
+        let index = save(&mut filterdb, &mut store).unwrap();
+
+        // A poll only saves the polled filter.
+        filterdb.gen_block_filter(block_id, 10);
+        let unsaved = filterdb.take_unsaved();
+        assert!(unsaved.index.is_none());
+        assert_eq!(unsaved.filters.len(), 1);
+        assert_eq!(unsaved.filters[0].0, block_id);
+        store.insert(block_id, unsaved.filters[0].1.clone().unwrap());
+
+        let mut loaded = FilterDB::new();
+        loaded.load(&index, |id| store.get(&id).cloned());
+        assert_eq!(*loaded.get_logs_filter(logs_id).unwrap(), filter);
+        assert_eq!(*loaded.get_block_filter(logs_id).unwrap(), 7);
+        assert!(loaded.is_block_filter(block_id));
+        assert_eq!(*loaded.get_block_filter(block_id).unwrap(), 10);
+        assert_eq!(loaded.gen_id(), block_id + 1);
+        loaded.set_max_filters_per_client(2);
+        assert_eq!(loaded.gen_client_id(b"127.0.0.1"), None);
+
+        // Uninstalled filters are removed from the store.
+        assert!(filterdb.uninstall(logs_id));
+        let index = save(&mut filterdb, &mut store).unwrap();
+        assert!(!store.contains_key(&logs_id));
+        let mut loaded = FilterDB::new();
+        loaded.load(&index, |id| store.get(&id).cloned());
+        assert!(!loaded.is_filter(logs_id));
+        assert!(loaded.is_filter(block_id));
+
+        // Expired filters are not restored, and are removed on the next save.
+        let mut expired = FilterDB::new();
+        expired.set_lifetime(0);
+        expired.load(&index, |id| store.get(&id).cloned());
+        assert!(!expired.is_filter(block_id));
+        assert!(save(&mut expired, &mut store).is_some());
+        assert!(store.is_empty());
+    }
+
+    #[test]
+    fn test_client_of() {
+        // The request id of jsonrpc's `tag_client`: a v4 uuid, the tag and the client IP.
+        let mut request_id = vec![0x63; 16];
+        request_id.extend_from_slice(b"client:127.0.0.1");
+        assert_eq!(client_of(&request_id), b"127.0.0.1");
+        // A uuid which happens to hold the tag.
+        let mut request_id = b"client:client:xx".to_vec();
+        request_id.extend_from_slice(b"client:::1");
+        assert_eq!(client_of(&request_id), b"::1");
+        assert_eq!(client_of(&[0x63; 16]), b"");
+        assert_eq!(client_of(b"client:"), b"");
+    }
 }
@@ -26,12 +26,13 @@ use jsonrpc_types::rpc_types::{Filter, FilterChanges, Log};
 ///     * uninstallFilter
 /// *Not include `getLogs`*.
 pub trait RpcFilter {
-    // Create a new filter and return the filter id
+    // Create a new filter for the client and return the filter id,
+    // None if the client has installed too many filters.
     // https://docs.citahub.com/zh-CN/cita/rpc-guide/rpc#newfilter
-    fn new_filter(&self, filter: Filter) -> usize;
+    fn new_filter(&self, client: &[u8], filter: Filter) -> Option<usize>;
     // Create a new filter that can listen the new block.
     // https://docs.citahub.com/zh-CN/cita/rpc-guide/rpc#newblockfilter
-    fn new_block_filter(&self) -> usize;
+    fn new_block_filter(&self, client: &[u8]) -> Option<usize>;
     // Get the logs for the filter with the given id since last time it was called.
     // https://docs.citahub.com/zh-CN/cita/rpc-guide/rpc#getfilterchanges
     fn get_filter_changes(&self, id: usize) -> Option<FilterChanges>;
@@ -47,8 +48,12 @@ pub trait RpcFilter {
 trait FilterHelper {
     // Get the block filter with the given id
     fn get_block_filter(&self, id: usize) -> BlockNumber;
-    // Get logs with given filter
-    fn get_logs_with_filter(&self, filter: Filter, block_filter: BlockNumber) -> Vec<Log>;
+    // Get logs with given filter from the block filter on, and the block to continue from
+    fn get_logs_with_filter(
+        &self,
+        filter: Filter,
+        block_filter: BlockNumber,
+    ) -> (Vec<Log>, BlockNumber);
 }
 
 impl FilterHelper for Chain {
@@ -64,42 +69,61 @@ impl FilterHelper for Chain {
         block_filter
     }
 
-    fn get_logs_with_filter(&self, filter: Filter, block_filter: BlockNumber) -> Vec<Log> {
+    /// One call covers at most `max_logs_block_range` blocks and `max_logs_count` logs,
+    /// or the limit of the filter, in ascending order. The rest is left to the next call,
+    /// which starts from the block returned.
+    fn get_logs_with_filter(
+        &self,
+        filter: Filter,
+        block_filter: BlockNumber,
+    ) -> (Vec<Log>, BlockNumber) {
         let mut filter: FilterType = filter.into();
+        let span = self.max_logs_block_range.max(1) - 1;
+        let to = self
+            .get_current_height()
+            .min(block_filter.saturating_add(span));
         filter.from_block = BlockTag::Height(block_filter);
-        filter.to_block = BlockTag::Tag(Tag::Latest);
-        let limit = filter.limit;
-        split_logs(
-            self.get_logs(&filter).into_iter().map(Into::into).collect(),
-            limit,
-        )
+        filter.to_block = BlockTag::Height(to);
+        let count = filter
+            .limit
+            .map_or(self.max_logs_count, |limit| limit.min(self.max_logs_count));
+
+        let (mut logs, next_block) = match self.get_logs_page(&filter, None, count) {
+            Ok((logs, None)) => (logs, (to + 1).max(block_filter)),
+            // The block of the next log is left to the next call as a whole, unless it
+            // has more logs than one call returns alone.
+            Ok((logs, Some(next))) if next.block_number == block_filter => (logs, block_filter + 1),
+            Ok((logs, Some(next))) => (logs, next.block_number),
+            Err(err) => {
+                warn!("get logs of filter from {} error: {:?}", block_filter, err);
+                (Vec::new(), block_filter)
+            }
+        };
+        logs.retain(|log| log.block_number < next_block);
+        (logs.into_iter().map(Into::into).collect(), next_block)
     }
 }
 
 impl RpcFilter for Chain {
-    fn new_filter(&self, filter: Filter) -> usize {
+    fn new_filter(&self, client: &[u8], filter: Filter) -> Option<usize> {
         let filterdb = self.filter_db();
-        let id = filterdb.try_lock().unwrap().gen_id();
+        let mut filterdb = filterdb.try_lock().unwrap();
+        let id = filterdb.gen_client_id(client)?;
         let block_number = self.get_current_height();
-        filterdb.try_lock().unwrap().gen_logs_filter(id, filter);
-        filterdb
-            .try_lock()
-            .unwrap()
-            .gen_block_filter(id, block_number);
-        drop(filterdb);
-        id
+        filterdb.gen_logs_filter(id, filter);
+        filterdb.gen_block_filter(id, block_number);
+        self.save_filters(&mut filterdb);
+        Some(id)
     }
 
-    fn new_block_filter(&self) -> usize {
+    fn new_block_filter(&self, client: &[u8]) -> Option<usize> {
         let filterdb = self.filter_db();
+        let mut filterdb = filterdb.try_lock().unwrap();
         let block_number = self.get_current_height();
-        let id = filterdb.try_lock().unwrap().gen_id();
-        filterdb
-            .try_lock()
-            .unwrap()
-            .gen_block_filter(id, block_number);
-        drop(filterdb);
-        id
+        let id = filterdb.gen_client_id(client)?;
+        filterdb.gen_block_filter(id, block_number);
+        self.save_filters(&mut filterdb);
+        Some(id)
     }
 
     fn get_filter_changes(&self, id: usize) -> Option<FilterChanges> {
@@ -114,11 +138,12 @@ impl RpcFilter for Chain {
         }
 
         // Check the logs
+        let mut next_block = current_number + 1;
         if let Some(filter) = filterdb.try_lock().unwrap().get_logs_filter(id) {
             trace!("Into filter changes: logs");
-            changes = Some(FilterChanges::Logs(
-                self.get_logs_with_filter(filter.clone(), block_filter),
-            ));
+            let (logs, next) = self.get_logs_with_filter(filter.clone(), block_filter);
+            next_block = next;
+            changes = Some(FilterChanges::Logs(logs));
         };
 
         // Check the block
@@ -134,12 +159,11 @@ impl RpcFilter for Chain {
             ));
         };
 
-        // Update the block filter: use the current number
-        filterdb
-            .try_lock()
-            .unwrap()
-            .gen_block_filter(id, current_number + 1);
-        drop(filterdb);
+        // Update the block filter: use the next block, the one after the current
+        // unless the logs are left for the next time
+        let mut filterdb = filterdb.try_lock().unwrap();
+        filterdb.gen_block_filter(id, next_block);
+        self.save_filters(&mut filterdb);
         changes
     }
 
@@ -147,7 +171,7 @@ impl RpcFilter for Chain {
         let filterdb = self.filter_db();
         let block_filter = self.get_block_filter(id);
         let logs = match filterdb.try_lock().unwrap().get_logs_filter(id) {
-            Some(filter) => Some(self.get_logs_with_filter(filter.clone(), block_filter)),
+            Some(filter) => Some(self.get_logs_with_filter(filter.clone(), block_filter).0),
             _ => None,
         };
         drop(filterdb);
@@ -156,17 +180,11 @@ impl RpcFilter for Chain {
 
     fn uninstall_filter(&self, id: usize) -> bool {
         let filterdb = self.filter_db();
-        let uninstall_ok = filterdb.try_lock().unwrap().uninstall(id);
-        drop(filterdb);
+        let mut filterdb = filterdb.try_lock().unwrap();
+        let uninstall_ok = filterdb.uninstall(id);
+        if uninstall_ok {
+            self.save_filters(&mut filterdb);
+        }
         uninstall_ok
     }
 }
-
-/// Split the logs with the limit filter.
-fn split_logs(mut logs: Vec<Log>, limit: Option<usize>) -> Vec<Log> {
-    let len = logs.len();
-    match limit {
-        Some(limit) if len >= limit => logs.split_off(len - limit),
-        _ => logs,
-    }
-}
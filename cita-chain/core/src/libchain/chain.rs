@@ -41,15 +41,28 @@ use std::sync::Arc;
 use util::{Mutex, RwLock};
 
 use crate::db_indexes::{
-    BlockNumber2Body, BlockNumber2Header, CurrentHash, CurrentHeight, CurrentProof,
-    Hash2BlockNumber, Hash2BlockReceipts, Hash2TransactionIndex, LogGroupPosition,
+    AddressTransactionCount, AddressTransactions, BlockNumber2Body, BlockNumber2Header,
+    CurrentHash, CurrentHeight, CurrentProof, Hash2BlockNumber, Hash2BlockReceipts,
+    Hash2TransactionIndex, InstalledFilter, InstalledFilters, LogGroupPosition,
 };
 
 use crate::types::block::{Block, BlockBody, OpenBlock};
 use crate::types::{
-    block_number::BlockTag, block_number::Tag, block_number::TransactionHash,
-    block_receipts::BlockReceipts, filter::Filter, log::LocalizedLog, log::Log,
-    transaction::Action, transaction::SignedTransaction, transaction_index::TransactionIndex,
+    address_index::{self, AddressTransaction},
+    block_number::height_at_timestamp,
+    block_number::BlockTag,
+    block_number::Tag,
+    block_number::TransactionHash,
+    block_receipts::BlockReceipts,
+    filter::check_block_range,
+    filter::Filter,
+    filter::LogCursor,
+    filter::LogsError,
+    log::LocalizedLog,
+    log::Log,
+    transaction::Action,
+    transaction::SignedTransaction,
+    transaction_index::TransactionIndex,
 };
 use cita_types::traits::LowerHex;
 use cita_types::{Address, Bloom as LogBloom, H256, U256};
@@ -252,11 +265,41 @@ pub enum BlockSource {
 #[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
 pub struct Config {
     pub prooftype: u8,
+    /// Index the transactions of every sender and recipient address
+    #[serde(default)]
+    pub address_index: bool,
+    /// Most blocks a `getLogs` query may span
+    #[serde(default = "default_max_logs_block_range")]
+    pub max_logs_block_range: u64,
+    /// Most logs a `getLogs` query may return
+    #[serde(default = "default_max_logs_count")]
+    pub max_logs_count: usize,
+    /// Most filters one client may install, 0 means no limit
+    #[serde(default = "default_max_filters_per_client")]
+    pub max_filters_per_client: usize,
+}
+
+fn default_max_logs_block_range() -> u64 {
+    100_000
+}
+
+fn default_max_logs_count() -> usize {
+    10_000
+}
+
+fn default_max_filters_per_client() -> usize {
+    64
 }
 
 impl Config {
     pub fn default() -> Self {
-        Config { prooftype: 2 }
+        Config {
+            prooftype: 2,
+            address_index: false,
+            max_logs_block_range: default_max_logs_block_range(),
+            max_logs_count: default_max_logs_count(),
+            max_filters_per_client: default_max_filters_per_client(),
+        }
     }
 
     pub fn new(path: &str) -> Self {
@@ -308,6 +351,11 @@ pub struct Chain {
     pub filterdb: Arc<Mutex<FilterDB>>,
     /// Proof type
     pub prooftype: u8,
+    /// Whether the transactions of addresses are indexed
+    pub address_index: bool,
+    /// Limits of `getLogs` queries
+    pub max_logs_block_range: u64,
+    pub max_logs_count: usize,
     // snapshot flag
     pub is_snapshot: RwLock<bool>,
     admin_address: RwLock<Option<Address>>,
@@ -366,6 +414,12 @@ pub fn contract_address(address: &Address, nonce: &U256) -> Address {
     From::from(stream.out().crypt_hash())
 }
 
+fn encode_address_transaction_page(entries: &[AddressTransaction]) -> Vec<u8> {
+    let mut stream = RlpStream::new();
+    stream.append_list(entries);
+    stream.out()
+}
+
 impl Chain {
     pub fn init_chain(db: Arc<RocksDB>, chain_config: Config) -> Chain {
         info!("chain config: {:?}", chain_config);
@@ -388,6 +442,21 @@ impl Chain {
             max_store_height, current_height
         );
 
+        let mut filterdb = FilterDB::new();
+        filterdb.set_max_filters_per_client(chain_config.max_filters_per_client);
+        if let Ok(Some(index)) = db.get(
+            Some(cita_db::DataCategory::Extra),
+            &InstalledFilters.get_index(),
+        ) {
+            filterdb.load(&index, |id| {
+                db.get(
+                    Some(cita_db::DataCategory::Extra),
+                    &InstalledFilter(id as u64).get_index(),
+                )
+                .unwrap_or(None)
+            });
+        }
+
         let chain = Chain {
             blooms_config,
             current_header: RwLock::new(header.clone()),
@@ -395,7 +464,7 @@ impl Chain {
             max_store_height,
             block_map: RwLock::new(BTreeMap::new()),
             db,
-            filterdb: Arc::new(Mutex::new(FilterDB::new())),
+            filterdb: Arc::new(Mutex::new(filterdb)),
             nodes: RwLock::new(Vec::new()),
             validators: RwLock::new(Vec::new()),
             // need to be cautious here
@@ -405,6 +474,9 @@ impl Chain {
             account_quota_limit: RwLock::new(ProtoAccountGasLimit::new()),
             check_quota: AtomicBool::new(false),
             prooftype: chain_config.prooftype,
+            address_index: chain_config.address_index,
+            max_logs_block_range: chain_config.max_logs_block_range,
+            max_logs_count: chain_config.max_logs_count,
             proof_map: RwLock::new(BTreeMap::new()),
             is_snapshot: RwLock::new(false),
             admin_address: RwLock::new(None),
@@ -424,7 +496,7 @@ impl Chain {
     }
 
     /// Get block number by BlockTag
-    fn block_number(&self, tag: BlockTag) -> Option<BlockNumber> {
+    pub fn block_number(&self, tag: BlockTag) -> Option<BlockNumber> {
         match tag {
             BlockTag::Height(number) => Some(number),
             BlockTag::Hash(hash) => self.block_height_by_hash(hash),
@@ -513,81 +585,298 @@ impl Chain {
                 .collect()
         };
 
+        // Everything in Extra is written in one batch, and the current hash last, so a
+        // failed write leaves the chain at the previous block instead of half indexed.
+        let mut keys = Vec::new();
+        let mut values = Vec::new();
+
         // Save hash -> receipts
-        if !info.get_receipts().is_empty() {
-            let receipts: Vec<Receipt> = info
-                .get_receipts()
-                .iter()
-                .map(|r| Receipt::from(r.get_receipt().clone()))
-                .collect();
+        let receipts: Vec<Receipt> = info
+            .get_receipts()
+            .iter()
+            .map(|r| Receipt::from(r.get_receipt().clone()))
+            .collect();
+        if self.address_index {
+            self.save_address_transactions(number, block, &receipts, &mut keys, &mut values);
+        }
+        if !receipts.is_empty() {
             let block_receipts = BlockReceipts::new(receipts);
-            let hash_key = Hash2BlockReceipts(header_hash).get_index();
-            let _ = self.db.insert(
-                Some(cita_db::DataCategory::Extra),
-                hash_key,
-                rlp::encode(&block_receipts).into_vec(),
-            );
+            keys.push(Hash2BlockReceipts(header_hash).get_index());
+            values.push(rlp::encode(&block_receipts).into_vec());
         }
 
         // Save block transaction indexes
-        if !block_transaction_indexes.is_empty() {
-            for (k, v) in block_transaction_indexes.iter() {
-                let hash_key = Hash2TransactionIndex(*k).get_index();
-                let _ = self.db.insert(
-                    Some(cita_db::DataCategory::Extra),
-                    hash_key,
-                    rlp::encode(v).into_vec(),
-                );
-            }
+        for (k, v) in block_transaction_indexes.iter() {
+            keys.push(Hash2TransactionIndex(*k).get_index());
+            values.push(rlp::encode(v).into_vec());
         }
 
         // Save number -> header
         trace!("Save ExecutedResult's header: {:?}", header);
         let number_key = BlockNumber2Header(number).get_index();
-        let _ = self.db.insert(
+        if let Err(e) = self.db.insert(
             Some(cita_db::DataCategory::Headers),
             number_key,
             rlp::encode(&header).into_vec(),
-        );
+        ) {
+            error!("Save header of block {} failed: {:?}", number, e);
+            return;
+        }
 
         // Save Body
         let mheight = self.get_max_store_height();
         if mheight < number || (number == 0 && mheight == 0) {
             let number_key = BlockNumber2Body(number).get_index();
-            let _ = self.db.insert(
+            if let Err(e) = self.db.insert(
                 Some(cita_db::DataCategory::Bodies),
                 number_key,
                 rlp::encode(block.body()).into_vec(),
-            );
+            ) {
+                error!("Save body of block {} failed: {:?}", number, e);
+                return;
+            }
         }
 
         // Save hash -> blockNumber
-        let hash_key = Hash2BlockNumber(header_hash).get_index();
-        let _ = self.db.insert(
-            Some(cita_db::DataCategory::Extra),
-            hash_key,
-            rlp::encode(&number).into_vec(),
-        );
+        keys.push(Hash2BlockNumber(header_hash).get_index());
+        values.push(rlp::encode(&number).into_vec());
 
         // Save blocks blooms
         for (k, v) in blocks_blooms.iter() {
-            let _ = self.db.insert(
-                Some(cita_db::DataCategory::Extra),
-                k.get_index(),
-                rlp::encode(v).into_vec(),
-            );
+            keys.push(k.get_index());
+            values.push(rlp::encode(v).into_vec());
         }
 
         // Save current hash
-        let _ = self.db.insert(
-            Some(cita_db::DataCategory::Extra),
+        keys.push(CurrentHash.get_index());
+        values.push(rlp::encode(&header_hash).into_vec());
+
+        if let Err(e) = self
+            .db
+            .insert_batch(Some(cita_db::DataCategory::Extra), keys, values)
+        {
+            error!("Save block {} failed: {:?}", number, e);
+            return;
+        }
+
+        *self.current_header.write() = header;
+        self.current_height.store(number as usize, Ordering::SeqCst);
+        self.clean_proof_with_height(number);
+    }
+
+    /// Continue from a block whose state is synced from other nodes by executor.
+    ///
+    /// Only the header of the block is saved, the blocks before it are left out.
+    pub fn set_synced_header(&self, header: &[u8], ctx_pub: &Sender<(String, Vec<u8>)>) {
+        let header: Header = match UntrustedRlp::new(header).as_val() {
+            Ok(header) => header,
+            Err(e) => {
+                warn!("Invalid synced header: {:?}", e);
+                return;
+            }
+        };
+        let number = header.number();
+        if number <= self.get_current_height() {
+            return;
+        }
+        let header_hash = header.hash().unwrap();
+        info!("Continue from the synced state of block {}", number);
+
+        // Save number -> header
+        let number_key = BlockNumber2Header(number).get_index();
+        if let Err(e) = self.db.insert(
+            Some(cita_db::DataCategory::Headers),
+            number_key,
+            rlp::encode(&header).into_vec(),
+        ) {
+            error!("Save header of block {} failed: {:?}", number, e);
+            return;
+        }
+
+        // Save hash -> blockNumber, then current height and hash
+        let keys = vec![
+            Hash2BlockNumber(header_hash).get_index(),
+            CurrentHeight.get_index(),
             CurrentHash.get_index(),
+        ];
+        let values = vec![
+            rlp::encode(&number).into_vec(),
+            rlp::encode(&number).into_vec(),
             rlp::encode(&header_hash).into_vec(),
-        );
+        ];
+        if let Err(e) = self
+            .db
+            .insert_batch(Some(cita_db::DataCategory::Extra), keys, values)
+        {
+            error!("Save block {} failed: {:?}", number, e);
+            return;
+        }
 
         *self.current_header.write() = header;
         self.current_height.store(number as usize, Ordering::SeqCst);
-        self.clean_proof_with_height(number);
+        // Also sets the max store height to the synced block
+        self.clear_block_map();
+        self.broadcast_current_status(ctx_pub);
+    }
+
+    /// Append the transactions of the block to the index of their senders, recipients
+    /// and created contracts, pushing the writes to `keys` and `values`.
+    fn save_address_transactions(
+        &self,
+        number: BlockNumber,
+        block: &OpenBlock,
+        receipts: &[Receipt],
+        keys: &mut Vec<Vec<u8>>,
+        values: &mut Vec<Vec<u8>>,
+    ) {
+        let mut transactions: BTreeMap<Address, Vec<AddressTransaction>> = BTreeMap::new();
+        for (index, tx) in block.body().transactions().iter().enumerate() {
+            let entry = AddressTransaction {
+                block_number: number,
+                index,
+                hash: tx.hash(),
+            };
+            let sender = *tx.sender();
+            let recipient = match *tx.action() {
+                Action::Call(recipient) => Some(recipient),
+                Action::Create => receipts
+                    .get(index)
+                    .filter(|receipt| receipt.error.is_none())
+                    .map(|receipt| contract_address(&sender, &receipt.account_nonce)),
+                _ => None,
+            };
+            if let Some(recipient) = recipient.filter(|recipient| *recipient != sender) {
+                transactions
+                    .entry(recipient)
+                    .or_insert_with(Vec::new)
+                    .push(entry.clone());
+            }
+            transactions
+                .entry(sender)
+                .or_insert_with(Vec::new)
+                .push(entry);
+        }
+
+        for (address, entries) in transactions {
+            let mut count = self.address_transaction_count(&address);
+            // The block may be saved again, e.g. after a restart.
+            let indexed = count
+                .checked_sub(1)
+                .and_then(|last| self.address_transaction(&address, last))
+                .map_or(false, |last| last.block_number >= number);
+            if indexed {
+                continue;
+            }
+
+            let (mut page_number, _) = address_index::page_of(count);
+            let mut page = self.address_transaction_page(&address, page_number);
+            for entry in entries {
+                let (next_page_number, _) = address_index::page_of(count);
+                if next_page_number != page_number {
+                    keys.push(AddressTransactions(address, page_number).get_index());
+                    values.push(encode_address_transaction_page(&page));
+                    page_number = next_page_number;
+                    page = Vec::new();
+                }
+                page.push(entry);
+                count += 1;
+            }
+            keys.push(AddressTransactions(address, page_number).get_index());
+            values.push(encode_address_transaction_page(&page));
+            keys.push(AddressTransactionCount(address).get_index());
+            values.push(rlp::encode(&count).into_vec());
+        }
+    }
+
+    fn address_transaction_count(&self, address: &Address) -> u64 {
+        self.db
+            .get(
+                Some(cita_db::DataCategory::Extra),
+                &AddressTransactionCount(*address).get_index(),
+            )
+            .unwrap_or(None)
+            .map(|count| decode::<u64>(&count))
+            .unwrap_or(0)
+    }
+
+    fn address_transaction_page(&self, address: &Address, page: u64) -> Vec<AddressTransaction> {
+        self.db
+            .get(
+                Some(cita_db::DataCategory::Extra),
+                &AddressTransactions(*address, page).get_index(),
+            )
+            .unwrap_or(None)
+            .map(|page| UntrustedRlp::new(&page).as_list().unwrap_or_default())
+            .unwrap_or_default()
+    }
+
+    fn address_transaction(&self, address: &Address, position: u64) -> Option<AddressTransaction> {
+        let (page, offset) = address_index::page_of(position);
+        self.address_transaction_page(address, page)
+            .into_iter()
+            .nth(offset)
+    }
+
+    /// Get the transactions sent or received by `address` in the blocks `from..=to`.
+    ///
+    /// Start at `cursor` if given, which is the position returned by a previous call,
+    /// and return at most `limit` transactions, with the cursor of the next one if
+    /// there may be more. Only available when the address index is enabled.
+    pub fn transactions_by_address(
+        &self,
+        address: &Address,
+        from: BlockNumber,
+        to: BlockNumber,
+        cursor: Option<u64>,
+        limit: usize,
+    ) -> (Vec<AddressTransaction>, Option<u64>) {
+        let count = self.address_transaction_count(address);
+        // A cursor below `from` would walk the older transactions one by one.
+        let first = address_index::lower_bound(count, from, |position| {
+            self.address_transaction(address, position)
+                .map(|entry| entry.block_number)
+        });
+        let mut position = cursor.map_or(first, |cursor| cursor.max(first));
+
+        let mut transactions = Vec::new();
+        let mut page: Option<(u64, Vec<AddressTransaction>)> = None;
+        while position < count && transactions.len() < limit {
+            let (page_number, offset) = address_index::page_of(position);
+            if page.as_ref().map(|(number, _)| *number) != Some(page_number) {
+                page = Some((
+                    page_number,
+                    self.address_transaction_page(address, page_number),
+                ));
+            }
+            let entry = match page.as_ref().and_then(|(_, entries)| entries.get(offset)) {
+                Some(entry) => entry.clone(),
+                None => break,
+            };
+            if entry.block_number > to {
+                return (transactions, None);
+            }
+            transactions.push(entry);
+            position += 1;
+        }
+
+        let next = if position < count {
+            Some(position)
+        } else {
+            None
+        };
+        (transactions, next)
+    }
+
+    /// The block of the transaction, and the RLP list of the transactions of the block
+    /// up to and including it, which are replayed to trace it.
+    pub fn trace_position(&self, hash: TransactionHash) -> Option<(BlockNumber, Vec<u8>)> {
+        let index = self.transaction_index(hash)?;
+        let number = self.block_height_by_hash(index.block_hash)?;
+        let body = self.block_body(BlockTag::Height(number))?;
+        let transactions = body.transactions().get(..=index.index)?;
+        let mut stream = RlpStream::new();
+        stream.append_list(transactions);
+        Some((number, stream.out()))
     }
 
     pub fn broadcast_current_status(&self, ctx_pub: &Sender<(String, Vec<u8>)>) {
@@ -626,10 +915,11 @@ impl Chain {
 
         // Duplicated block
         if number <= self.get_current_height() {
+            // The body of a block whose state is synced is not saved
             let tx_hashes = self
                 .block_body_by_height(self.get_current_height())
-                .unwrap()
-                .transaction_hashes();
+                .map(|body| body.transaction_hashes())
+                .unwrap_or_default();
             self.delivery_block_tx_hashes(self.get_current_height(), &tx_hashes, &ctx_pub);
             self.broadcast_current_status(&ctx_pub);
             return;
@@ -741,6 +1031,14 @@ impl Chain {
             .and_then(|h| self.block_header_by_height(h))
     }
 
+    /// Get the height of the last block at or before `timestamp`
+    pub fn block_height_by_timestamp(&self, timestamp: u64) -> Option<BlockNumber> {
+        height_at_timestamp(timestamp, self.get_current_height(), |height| {
+            self.block_header_by_height(height)
+                .map(|header| header.timestamp())
+        })
+    }
+
     fn block_header_by_height(&self, number: BlockNumber) -> Option<Header> {
         let number_key = BlockNumber2Header(number).get_index();
         self.db
@@ -1193,6 +1491,92 @@ impl Chain {
         self.logs(blocks, |entry| filter.matches(entry), filter.limit)
     }
 
+    /// Get the logs for a `getLogs` query, within the configured limits.
+    ///
+    /// With a `limit` in the filter, the latest logs up to it are returned as
+    /// `get_logs` does. Without one, the query fails if it matches more logs
+    /// than allowed, instead of being truncated silently.
+    pub fn get_logs_checked(&self, filter: &Filter) -> Result<Vec<LocalizedLog>, LogsError> {
+        let (from, to) = self.logs_range(filter)?;
+        match filter.limit {
+            Some(limit) => {
+                let blocks = self.blocks_with_filter(filter, from, to);
+                let limit = limit.min(self.max_logs_count);
+                Ok(self.logs(blocks, |entry| filter.matches(entry), Some(limit)))
+            }
+            None => match self.get_logs_page(filter, None, self.max_logs_count)? {
+                (logs, None) => Ok(logs),
+                (_, Some(_)) => Err(LogsError::TooManyLogs(self.max_logs_count)),
+            },
+        }
+    }
+
+    /// Get at most `count` logs of the query in ascending order, starting at `cursor`.
+    ///
+    /// Returns the cursor of the next log too if there are more, to continue with.
+    pub fn get_logs_page(
+        &self,
+        filter: &Filter,
+        cursor: Option<LogCursor>,
+        count: usize,
+    ) -> Result<(Vec<LocalizedLog>, Option<LogCursor>), LogsError> {
+        let (from, to) = self.logs_range(filter)?;
+        let from = cursor.map_or(from, |cursor| cursor.block_number.max(from));
+        let mut blocks = self.blocks_with_filter(filter, from, to);
+        blocks.sort();
+
+        let mut logs = Vec::new();
+        for number in blocks {
+            for log in self.logs(vec![number], |entry| filter.matches(entry), None) {
+                let skipped = cursor.map_or(false, |cursor| {
+                    cursor.block_number == number && log.log_index < cursor.log_index
+                });
+                if skipped {
+                    continue;
+                }
+                if logs.len() == count {
+                    let next = LogCursor {
+                        block_number: number,
+                        log_index: log.log_index,
+                    };
+                    return Ok((logs, Some(next)));
+                }
+                logs.push(log);
+            }
+        }
+        Ok((logs, None))
+    }
+
+    /// The block numbers of the query range, checked against the configured span.
+    fn logs_range(&self, filter: &Filter) -> Result<(BlockNumber, BlockNumber), LogsError> {
+        let from = self
+            .block_number(filter.from_block)
+            .ok_or(LogsError::UnknownBlock)?;
+        let to = self
+            .block_number(filter.to_block)
+            .ok_or(LogsError::UnknownBlock)?
+            .min(self.get_pending_height());
+        check_block_range(from, to, self.max_logs_block_range)?;
+        Ok((from, to))
+    }
+
+    /// Numbers of the blocks in `from..=to` which may have logs matching the filter.
+    fn blocks_with_filter(
+        &self,
+        filter: &Filter,
+        from: BlockNumber,
+        to: BlockNumber,
+    ) -> Vec<BlockNumber> {
+        filter
+            .zip_blooms()
+            .iter()
+            .flat_map(|bloom| self.blocks_with_bloom(bloom, from, to))
+            // remove duplicate elements
+            .collect::<HashSet<u64>>()
+            .into_iter()
+            .collect()
+    }
+
     /// Delivery block tx hashes to auth
     pub fn delivery_block_tx_hashes(
         &self,
@@ -1397,6 +1781,44 @@ impl Chain {
         Arc::clone(&self.filterdb)
     }
 
+    /// Save the filters changed since the last save, so they survive a restart
+    pub fn save_filters(&self, filterdb: &mut FilterDB) {
+        let unsaved = filterdb.take_unsaved();
+        let mut keys = Vec::new();
+        let mut values = Vec::new();
+        let mut removed = Vec::new();
+        if let Some(index) = unsaved.index {
+            keys.push(InstalledFilters.get_index());
+            values.push(index);
+        }
+        for (id, filter) in unsaved.filters {
+            let key = InstalledFilter(id as u64).get_index();
+            match filter {
+                Some(value) => {
+                    keys.push(key);
+                    values.push(value);
+                }
+                None => removed.push(key),
+            }
+        }
+        if !keys.is_empty() {
+            if let Err(e) = self
+                .db
+                .insert_batch(Some(cita_db::DataCategory::Extra), keys, values)
+            {
+                warn!("Save filters failed: {:?}", e);
+            }
+        }
+        if !removed.is_empty() {
+            if let Err(e) = self
+                .db
+                .remove_batch(Some(cita_db::DataCategory::Extra), &removed)
+            {
+                warn!("Remove filters failed: {:?}", e);
+            }
+        }
+    }
+
     /// clear sync block
     pub fn clear_block_map(&self) {
         let mut block_map = self.block_map.write();
@@ -12,10 +12,13 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use crate::metrics;
+use cita_metrics::duration_to_seconds;
 use core::libchain::chain::Chain;
 use libproto::executor::ExecutedResult;
 use pubsub::channel::Sender;
 use std::sync::Arc;
+use std::time::Instant;
 
 /// Processing blocks and transaction storage
 #[derive(Clone)]
@@ -34,7 +37,16 @@ impl BlockProcessor {
     }
 
     pub fn set_executed_result(&self, ret: &ExecutedResult) {
+        let height = self.chain.get_current_height();
+        let now = Instant::now();
         self.chain.set_executed_result(ret, &self.ctx_pub);
+
+        // Only count the results which commit a new block
+        let new_height = self.chain.get_current_height();
+        if new_height > height {
+            metrics::BLOCK_COMMIT_SECONDS.observe(duration_to_seconds(now.elapsed()));
+            metrics::BLOCK_HEIGHT.set(new_height as i64);
+        }
     }
 
     pub fn reset_max_store_height(&self) {
@@ -17,27 +17,32 @@ use std::mem;
 use std::sync::atomic::Ordering;
 use std::sync::Arc;
 
-use cita_types::H256;
+use cita_rpc_ext::{self as ext, Service};
+use cita_types::{clean_0x, H256};
+use core::filters::filterdb;
 use core::filters::rpc_filter::RpcFilter as FilterMethod;
 use core::libchain::chain::{BlockInQueue, Chain};
 use error::ErrorCode;
 use jsonrpc_types::rpc_types::{
-    BlockNumber as RpcBlockNumber, BlockParamsByHash, BlockParamsByNumber, Filter as RpcFilter,
+    BlockParamsByHash, BlockParamsByNumber, Data, Data20, Data32, Filter as RpcFilter,
     Log as RpcLog, Receipt as RpcReceipt, RpcBlock,
 };
 use libproto::router::{MsgType, RoutingKey, SubModules};
 use libproto::{
     request, response, Block as ProtobufBlock, BlockTxHashes, BlockTxHashesReq, BlockWithProof,
-    ExecutedResult, Message, OperateType, ProofType, Request_oneof_req as Request, SyncRequest,
-    SyncResponse, TryFrom, TryInto,
+    ExecutedResult, Message, MsgClass, OperateType, ProofType, Request_oneof_req as Request,
+    SyncRequest, SyncResponse, TryFrom, TryInto,
 };
 use proof::BftProof;
 use pubsub::channel::Sender;
-use serde_json;
+use rustc_hex::FromHex;
+use serde_json::{self, Value};
 
+use crate::types::address_index::ADDRESS_INDEX_PAGE_SIZE;
 use crate::types::block::OpenBlock;
-use crate::types::block_number::BlockTag;
-use crate::types::filter::Filter;
+use crate::types::block_number::{BlockTag, Tag};
+use crate::types::filter::{Filter, LogCursor};
+use crate::types::transaction_index::TracePosition;
 
 /// Message forwarding and query data
 #[derive(Clone)]
@@ -71,12 +76,22 @@ impl Forward {
                 self.reply_request(req, msg_bytes.to_vec());
             }
 
+            routing_key!(Jsonrpc >> RawBytes) => {
+                let data = msg.take_raw_bytes().unwrap();
+                self.reply_ext_request(&data, msg_bytes.to_vec());
+            }
+
             //send to block_processor to operate
             routing_key!(Executor >> ExecutedResult) => {
                 let info = msg.take_executed_result().unwrap();
                 self.write_sender.send(info).unwrap();
             }
 
+            routing_key!(Executor >> SyncResponse) => {
+                let data = msg.take_raw_bytes().unwrap_or_default();
+                self.deal_state_synced(&data);
+            }
+
             routing_key!(Executor >> StateSignal) => {
                 if let Some(state_signal) = msg.take_state_signal() {
                     let specified_height = state_signal.get_height();
@@ -114,7 +129,9 @@ impl Forward {
 
     fn reply_request(&self, mut req: request::Request, imsg: Vec<u8>) {
         let mut response = response::Response::new();
-        response.set_request_id(req.take_request_id());
+        let request_id = req.take_request_id();
+        let client = filterdb::client_of(&request_id).to_vec();
+        response.set_request_id(request_id);
         match req.req.unwrap() {
             // TODO: should check the result, parse it first!
             Request::block_number(_) => {
@@ -216,9 +233,16 @@ impl Forward {
                     response.set_error_msg(format!("{:?}", err));
                 }) {
                     let filter: Filter = rpc_filter.into();
-                    let logs = self.chain.get_logs(&filter);
-                    let rpc_logs: Vec<RpcLog> = logs.into_iter().map(Into::into).collect();
-                    response.set_logs(serde_json::to_string(&rpc_logs).unwrap());
+                    match self.chain.get_logs_checked(&filter) {
+                        Ok(logs) => {
+                            let rpc_logs: Vec<RpcLog> = logs.into_iter().map(Into::into).collect();
+                            response.set_logs(serde_json::to_string(&rpc_logs).unwrap());
+                        }
+                        Err(err) => {
+                            response.set_code(ErrorCode::query_error());
+                            response.set_error_msg(err.to_string());
+                        }
+                    }
                 };
             }
 
@@ -283,13 +307,22 @@ impl Forward {
                 let new_filter: RpcFilter =
                     serde_json::from_str(&new_filter).expect("Invalid param");
                 trace!("new_filter {:?}", new_filter);
-                response.set_filter_id(self.chain.new_filter(new_filter) as u64);
+                match self.chain.new_filter(&client, new_filter) {
+                    Some(id) => response.set_filter_id(id as u64),
+                    None => {
+                        response.set_code(ErrorCode::query_error());
+                        response.set_error_msg("too many filters installed".to_owned());
+                    }
+                }
             }
 
-            Request::new_block_filter(_) => {
-                let block_filter = self.chain.new_block_filter();
-                response.set_filter_id(block_filter as u64);
-            }
+            Request::new_block_filter(_) => match self.chain.new_block_filter(&client) {
+                Some(id) => response.set_filter_id(id as u64),
+                None => {
+                    response.set_code(ErrorCode::query_error());
+                    response.set_error_msg("too many filters installed".to_owned());
+                }
+            },
 
             Request::uninstall_filter(filter_id) => {
                 trace!("uninstall_filter's id is {:?}", filter_id);
@@ -323,9 +356,9 @@ impl Forward {
             }
 
             Request::block_header_height(block_height) => {
-                let block_height: RpcBlockNumber =
+                let block_tag: BlockTag =
                     serde_json::from_str(&block_height).expect("Invalid param");
-                match self.chain.get_block_header_bytes(block_height.into()) {
+                match self.chain.get_block_header_bytes(block_tag) {
                     Some(block_header_bytes) => {
                         response.set_block_header(block_header_bytes);
                     }
@@ -360,6 +393,139 @@ impl Forward {
             .unwrap();
     }
 
+    fn reply_ext_request(&self, data: &[u8], imsg: Vec<u8>) {
+        let req = match ext::Message::from_bytes(data) {
+            Ok(ext::Message::Request(req)) => req,
+            Ok(_) => return,
+            Err(err) => {
+                warn!("invalid extension request: {:?}", err);
+                return;
+            }
+        };
+        match req.service() {
+            Some(Service::Chain) => {}
+            Some(Service::Executor) => {
+                trace!("{} request from jsonrpc", req.method);
+                self.ctx_pub
+                    .send((routing_key!(Chain >> RawBytes).into(), imsg))
+                    .unwrap();
+                return;
+            }
+            _ => return,
+        }
+
+        let result = match req.method.as_str() {
+            ext::GET_LOGS => self.logs_page(&req),
+            ext::GET_TRANSACTIONS_BY_ADDRESS => self.address_transactions(&req),
+            ext::GET_BLOCK_NUMBER_BY_TIMESTAMP => req.param(0).map(|timestamp| {
+                serde_json::json!(self.chain.block_height_by_timestamp(timestamp))
+            }),
+            ext::TRACE_TRANSACTION => match self.trace_request(&req) {
+                Ok(trace_req) => {
+                    trace!("{} request from jsonrpc", req.method);
+                    return self.publish_ext(ext::Message::Request(trace_req));
+                }
+                Err(err) => Err(err),
+            },
+            method => Err(ext::Error::new(
+                ErrorCode::query_error(),
+                format!("{} is not served by chain", method),
+            )),
+        };
+        self.publish_ext(ext::Message::Response(ext::Response::new(req.id, result)));
+    }
+
+    fn publish_ext(&self, ext_msg: ext::Message) {
+        let msg = Message::init(
+            OperateType::Single,
+            0,
+            MsgClass::RawBytes(ext_msg.to_bytes()),
+        );
+        self.ctx_pub
+            .send((
+                routing_key!(Chain >> RawBytes).into(),
+                msg.try_into().unwrap(),
+            ))
+            .unwrap();
+    }
+
+    /// The `traceTransaction` request for the executor, with the position of the transaction.
+    fn trace_request(&self, req: &ext::Request) -> Result<ext::Request, ext::Error> {
+        let hash: H256 = req.param(0)?;
+        let (block_number, transactions) = self.chain.trace_position(hash).ok_or_else(|| {
+            ext::Error::new(
+                ErrorCode::query_error(),
+                format!("transaction {:?} is not found", hash),
+            )
+        })?;
+        let position = TracePosition {
+            block_number,
+            transactions: Data::from(transactions),
+        };
+        let mut trace_req = req.clone();
+        trace_req
+            .params
+            .push(serde_json::to_value(position).unwrap());
+        Ok(trace_req)
+    }
+
+    /// A page of `getLogs` starting at the cursor, and the cursor of the next page.
+    fn logs_page(&self, req: &ext::Request) -> Result<Value, ext::Error> {
+        let rpc_filter: RpcFilter = req.param(0)?;
+        let cursor: Option<LogCursor> = req.param(1)?;
+        let filter: Filter = rpc_filter.into();
+        let count = filter.limit.map_or(self.chain.max_logs_count, |limit| {
+            limit.min(self.chain.max_logs_count)
+        });
+        let (logs, next_cursor) = self
+            .chain
+            .get_logs_page(&filter, cursor, count)
+            .map_err(|err| ext::Error::new(ErrorCode::query_error(), err))?;
+        let rpc_logs: Vec<RpcLog> = logs.into_iter().map(Into::into).collect();
+        Ok(serde_json::json!({"logs": rpc_logs, "nextCursor": next_cursor}))
+    }
+
+    /// A page of the transactions of an address, and the cursor of the next page.
+    fn address_transactions(&self, req: &ext::Request) -> Result<Value, ext::Error> {
+        if !self.chain.address_index {
+            return Err(ext::Error::new(
+                ErrorCode::query_error(),
+                "address index is disabled",
+            ));
+        }
+        let address: Data20 = req.param(0)?;
+        let from: Option<BlockTag> = req.param(1)?;
+        let to: Option<BlockTag> = req.param(2)?;
+        let cursor: Option<u64> = req.param(3)?;
+        let limit: Option<usize> = req.param(4)?;
+
+        let block_number = |tag: Option<BlockTag>, default: Tag| {
+            self.chain
+                .block_number(tag.unwrap_or(BlockTag::Tag(default)))
+                .ok_or_else(|| ext::Error::new(ErrorCode::query_error(), "unknown block"))
+        };
+        let from = block_number(from, Tag::Earliest)?;
+        let to = block_number(to, Tag::Latest)?;
+        let limit = limit.map_or(ADDRESS_INDEX_PAGE_SIZE as usize, |limit| {
+            limit.min(ADDRESS_INDEX_PAGE_SIZE as usize)
+        });
+
+        let (transactions, next_cursor) =
+            self.chain
+                .transactions_by_address(&address.into(), from, to, cursor, limit);
+        let transactions: Vec<Value> = transactions
+            .into_iter()
+            .map(|tx| {
+                serde_json::json!({
+                    "blockNumber": tx.block_number,
+                    "index": tx.index,
+                    "hash": Data32::from(tx.hash),
+                })
+            })
+            .collect();
+        Ok(serde_json::json!({"transactions": transactions, "nextCursor": next_cursor}))
+    }
+
     // Consensus block enqueue
     fn consensus_block_enqueue(&self, proof_blk: BlockWithProof) {
         let current_height = self.chain.get_current_height() as usize;
@@ -396,6 +562,16 @@ impl Forward {
         }
     }
 
+    /// Continue from the block whose state executor synced from other nodes.
+    fn deal_state_synced(&self, data: &[u8]) {
+        if let Ok(ext::Message::StateSynced(header)) = ext::Message::from_bytes(data) {
+            match clean_0x(&header).from_hex() {
+                Ok(header) => self.chain.set_synced_header(&header, &self.ctx_pub),
+                Err(e) => warn!("invalid synced header {}: {:?}", header, e),
+            }
+        }
+    }
+
     fn reply_syn_req(&self, sync_req: SyncRequest, origin: u32) {
         let mut sync_req = sync_req;
         let heights = sync_req.take_heights();
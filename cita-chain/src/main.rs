@@ -26,10 +26,12 @@
 //!     | chain   | Net         | SyncRequest      |
 //!     | chain   | Consensus   | BlockWithProof   |
 //!     | chain   | Jsonrpc     | Request          |
+//!     | chain   | Jsonrpc     | RawBytes         |
 //!     | chain   | Auth        | BlockTxHashesReq |
 //!     | chain   | Executor    | ExecutedResult   |
 //!     | chain   | Snapshot    | SnapshotReq      |
 //!     | chain   | Executor    | StateSignal      |
+//!     | chain   | Executor    | RawBytes         |
 //!
 //! 2. Publish channel
 //!
@@ -40,6 +42,8 @@
 //!     | chain | Chain     | Executor      | Request       |
 //!     | chain | Chain     | Executor      | StateSignal   |
 //!     | chain | Chain     | Jsonrpc       | Response      |
+//!     | chain | Chain     | Jsonrpc       | RawBytes      |
+//!     | chain | Chain     | Executor      | RawBytes      |
 //!     | chain | Chain     | Net           | SyncResponse  |
 //!     | chain | Chain     | Snapshot      | SnapshotResp  |
 //!     | chain | Chain     | Executor      | LocalSync     |
@@ -73,6 +77,7 @@ extern crate util;
 
 mod block_processor;
 mod forward;
+mod metrics;
 
 use std::sync::Arc;
 use std::thread;
@@ -99,7 +104,8 @@ fn main() {
         .about("CITA Block Chain Node powered by Rust")
         .args_from_usage(
             "-c, --config=[FILE] 'Sets a chain config file'
-                          -s, --stdout 'Log to console'",
+                          -s, --stdout 'Log to console'
+                          -m, --metrics=[ADDR] 'Serves Prometheus metrics on ADDR'",
         )
         .get_matches();
 
@@ -107,6 +113,10 @@ fn main() {
     micro_service_init!("cita-chain", "CITA:chain", stdout);
     info!("Version: {}", get_build_info_str(true));
 
+    if let Some(addr) = matches.value_of("metrics") {
+        cita_metrics::start_server(addr).expect("Start metrics server failed");
+    }
+
     let config_path = matches.value_of("config").unwrap_or("chain.toml");
 
     let (tx, rx) = channel::unbounded();
@@ -118,9 +128,11 @@ fn main() {
             Net >> SyncRequest,
             Consensus >> BlockWithProof,
             Jsonrpc >> Request,
+            Jsonrpc >> RawBytes,
             Auth >> BlockTxHashesReq,
             Executor >> ExecutedResult,
             Executor >> StateSignal,
+            Executor >> SyncResponse,
             Snapshot >> SnapshotReq,
         ]),
         tx,
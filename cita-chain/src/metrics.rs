@@ -0,0 +1,28 @@
+// Copyright Rivtower Technologies LLC.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Metrics of chain, served on `/metrics`.
+
+use lazy_static::lazy_static;
+use prometheus::{register_histogram, register_int_gauge, Histogram, IntGauge};
+
+lazy_static! {
+    pub static ref BLOCK_HEIGHT: IntGauge =
+        register_int_gauge!("chain_block_height", "Height of the latest committed block").unwrap();
+    pub static ref BLOCK_COMMIT_SECONDS: Histogram = register_histogram!(
+        "chain_block_commit_seconds",
+        "Time to commit a block once its executed result arrives"
+    )
+    .unwrap();
+}
@@ -0,0 +1,116 @@
+// Copyright Rivtower Technologies LLC.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Index from an address to the transactions it sent or received.
+//!
+//! The transactions of an address are numbered in the order they are indexed, and
+//! stored in pages of `ADDRESS_INDEX_PAGE_SIZE` entries, so a range of them is read
+//! without scanning the blocks. The position of an entry is its cursor.
+
+use crate::block_number::BlockNumber;
+use cita_types::H256;
+use rlp::{Decodable, DecoderError, Encodable, RlpStream, UntrustedRlp};
+
+pub const ADDRESS_INDEX_PAGE_SIZE: u64 = 128;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AddressTransaction {
+    pub block_number: BlockNumber,
+    /// Index of the transaction in its block
+    pub index: usize,
+    pub hash: H256,
+}
+
+impl Decodable for AddressTransaction {
+    fn decode(rlp: &UntrustedRlp) -> Result<Self, DecoderError> {
+        Ok(AddressTransaction {
+            block_number: rlp.val_at(0)?,
+            index: rlp.val_at(1)?,
+            hash: rlp.val_at(2)?,
+        })
+    }
+}
+
+impl Encodable for AddressTransaction {
+    fn rlp_append(&self, s: &mut RlpStream) {
+        s.begin_list(3);
+        s.append(&self.block_number);
+        s.append(&self.index);
+        s.append(&self.hash);
+    }
+}
+
+/// The page holding the entry at `position`, and where the entry is in it.
+pub fn page_of(position: u64) -> (u64, usize) {
+    (
+        position / ADDRESS_INDEX_PAGE_SIZE,
+        (position % ADDRESS_INDEX_PAGE_SIZE) as usize,
+    )
+}
+
+/// Find the position of the first entry at or after `block_number` among `count` entries.
+///
+/// Entries are indexed in block order, so it is a binary search over `block_number_at`,
+/// which gives the block number of the entry at a position.
+pub fn lower_bound<F>(count: u64, block_number: BlockNumber, block_number_at: F) -> u64
+where
+    F: Fn(u64) -> Option<BlockNumber>,
+{
+    let (mut low, mut high) = (0, count);
+    while low < high {
+        let mid = low + (high - low) / 2;
+        match block_number_at(mid) {
+            Some(number) if number < block_number => low = mid + 1,
+            _ => high = mid,
+        }
+    }
+    low
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{lower_bound, page_of, AddressTransaction, ADDRESS_INDEX_PAGE_SIZE};
+    use cita_types::H256;
+    use rlp;
+
+    #[test]
+    fn test_encode_and_decode_address_transaction() {
+        let tx = AddressTransaction {
+            block_number: 10,
+            index: 3,
+            hash: H256::from(0x55aa),
+        };
+        let decoded: AddressTransaction = rlp::decode(&rlp::encode(&tx).into_vec());
+        assert_eq!(decoded, tx);
+    }
+
+    #[test]
+    fn test_page_of() {
+        assert_eq!(page_of(0), (0, 0));
+        assert_eq!(page_of(ADDRESS_INDEX_PAGE_SIZE - 1), (0, 127));
+        assert_eq!(page_of(ADDRESS_INDEX_PAGE_SIZE), (1, 0));
+    }
+
+    #[test]
+    fn test_lower_bound() {
+        // Two entries in each of the blocks 0, 2, 4, ... 18.
+        let block_number_at = |position| Some(position / 2 * 2);
+        assert_eq!(lower_bound(20, 0, block_number_at), 0);
+        assert_eq!(lower_bound(20, 3, block_number_at), 4);
+        assert_eq!(lower_bound(20, 4, block_number_at), 4);
+        assert_eq!(lower_bound(20, 18, block_number_at), 18);
+        assert_eq!(lower_bound(20, 19, block_number_at), 20);
+        assert_eq!(lower_bound(0, 0, block_number_at), 0);
+    }
+}
@@ -12,8 +12,11 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use cita_types::H256;
+use cita_types::{H256, U256};
 use jsonrpc_types::rpc_types::{BlockNumber as RpcBlockNumber, BlockTag as RpcBlockTag};
+use serde::de::{Error, IntoDeserializer};
+use serde::{Deserialize, Deserializer};
+use std::str::FromStr;
 
 pub type TransactionHash = H256;
 pub type BlockNumber = u64;
@@ -42,3 +45,122 @@ impl From<RpcBlockNumber> for BlockTag {
         }
     }
 }
+
+/// Find the last block at or before `timestamp` among the heights `0..=latest`.
+///
+/// Block timestamps never decrease, so it is a binary search over `timestamp_of`,
+/// which gives the timestamp of a height. `None` if even the genesis block is later,
+/// or if a header in the way is missing.
+pub fn height_at_timestamp<F>(
+    timestamp: u64,
+    latest: BlockNumber,
+    timestamp_of: F,
+) -> Option<BlockNumber>
+where
+    F: Fn(BlockNumber) -> Option<u64>,
+{
+    if timestamp_of(0)? > timestamp {
+        return None;
+    }
+    let (mut low, mut high) = (0, latest);
+    while low < high {
+        let mid = high - (high - low) / 2;
+        if timestamp_of(mid)? <= timestamp {
+            low = mid;
+        } else {
+            high = mid - 1;
+        }
+    }
+    Some(low)
+}
+
+/// Accept a 32 bytes block hash besides everything `RpcBlockNumber` accepts,
+/// so the RPC requests can pin a query to a block by its hash.
+///
+/// jsonrpc checks the block id as a `RpcBlockNumber`, a 256 bits quantity, and passes it
+/// on without its leading zeros. So a hash is told apart by its value, not its length:
+/// a quantity which does not fit in a `u64` is a hash. Only a hash with 24 leading zero
+/// bytes would be taken as a height.
+impl<'de> Deserialize<'de> for BlockTag {
+    fn deserialize<D>(deserializer: D) -> Result<BlockTag, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let id = String::deserialize(deserializer)?;
+        if id.starts_with("0x") || id.starts_with("0X") {
+            let value = &id[2..];
+            if value.is_empty() || value.len() > 64 {
+                return Err(D::Error::custom(format!("invalid block id {}", id)));
+            }
+            let value = U256::from_str(value)
+                .map_err(|_| D::Error::custom(format!("invalid block id {}", id)))?;
+            return Ok(if value > U256::from(u64::max_value()) {
+                BlockTag::Hash(H256::from(value))
+            } else {
+                BlockTag::Height(value.low_u64())
+            });
+        }
+        RpcBlockNumber::deserialize(id.into_deserializer()).map(Into::into)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{height_at_timestamp, BlockTag, Tag};
+    use cita_types::traits::LowerHex;
+    use cita_types::H256;
+    use jsonrpc_types::rpc_types::BlockNumber as RpcBlockNumber;
+    use serde_json;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_deserialize_block_tag() {
+        let tag: BlockTag = serde_json::from_str("\"latest\"").unwrap();
+        assert_eq!(tag, BlockTag::Tag(Tag::Latest));
+        let tag: BlockTag = serde_json::from_str("\"0x10\"").unwrap();
+        assert_eq!(tag, BlockTag::Height(16));
+
+        let hash =
+            H256::from_str("7cabfb7709b29c16d9e876e876c9988d03f9c3414e1d3ff77ec1de2d0ee59f66")
+                .unwrap();
+        let tag: BlockTag = serde_json::from_str(&format!("\"0x{}\"", hash.lower_hex())).unwrap();
+        assert_eq!(tag, BlockTag::Hash(hash));
+
+        let bad_hash = format!("\"0x{}\"", "z".repeat(64));
+        assert!(serde_json::from_str::<BlockTag>(&bad_hash).is_err());
+        let long_hash = format!("\"0x{}\"", "1".repeat(65));
+        assert!(serde_json::from_str::<BlockTag>(&long_hash).is_err());
+        // A zero-padded height is still a height.
+        let tag: BlockTag = serde_json::from_str(&format!("\"0x{:064x}\"", 16)).unwrap();
+        assert_eq!(tag, BlockTag::Height(16));
+    }
+
+    #[test]
+    fn test_block_hash_through_jsonrpc() {
+        let hash =
+            H256::from_str("00000fd5ac0b7e5d2f6b3e4b1a0e1c9f5a7d3e2b1c0a9f8e7d6c5b4a39281706")
+                .unwrap();
+        let param = format!("\"0x{}\"", hash.lower_hex());
+        // What jsonrpc checks the param as, and passes on to chain and executor.
+        let rpc_block_id: RpcBlockNumber = serde_json::from_str(&param).unwrap();
+        let forwarded = serde_json::to_string(&rpc_block_id).unwrap();
+        let tag: BlockTag = serde_json::from_str(&forwarded).unwrap();
+        assert_eq!(tag, BlockTag::Hash(hash));
+        let tag: BlockTag = serde_json::from_str(&param).unwrap();
+        assert_eq!(tag, BlockTag::Hash(hash));
+    }
+    #[test]
+    fn test_height_at_timestamp() {
+        // Heights 0..=10 at 1000, 1003, 1006, ...
+        let timestamp_of = |height| Some(1000 + height * 3);
+        assert_eq!(height_at_timestamp(999, 10, timestamp_of), None);
+        assert_eq!(height_at_timestamp(1000, 10, timestamp_of), Some(0));
+        assert_eq!(height_at_timestamp(1004, 10, timestamp_of), Some(1));
+        assert_eq!(height_at_timestamp(1006, 10, timestamp_of), Some(2));
+        assert_eq!(height_at_timestamp(1030, 10, timestamp_of), Some(10));
+        assert_eq!(height_at_timestamp(5000, 10, timestamp_of), Some(10));
+
+        let missing = |height| if height > 5 { None } else { Some(1000) };
+        assert_eq!(height_at_timestamp(5000, 10, missing), None);
+    }
+}
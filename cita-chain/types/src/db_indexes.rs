@@ -14,7 +14,7 @@
 
 use crate::block_number::BlockNumber;
 use bloomchain::group::GroupPosition;
-use cita_types::{H256, H264};
+use cita_types::{Address, H256, H264};
 
 const TRANSACTION_INDEX: u8 = 0;
 const BLOCKRECEIPTS_INDEX: u8 = 1;
@@ -22,6 +22,11 @@ const BLOCKSBLOOMS_INDEX: u8 = 2;
 const BLOCKHASH_INDEX: u8 = 3;
 const BLOCKHEADHASH_INDEX: u8 = 4;
 const BLOCKBODYHASH_INDEX: u8 = 5;
+const STATEJOURNAL_INDEX: u8 = 6;
+const STATENODEREFCOUNT_INDEX: u8 = 7;
+const ADDRESSTRANSACTIONCOUNT_INDEX: u8 = 8;
+const ADDRESSTRANSACTIONS_INDEX: u8 = 9;
+const INSTALLEDFILTER_INDEX: u8 = 10;
 
 pub trait DBIndex {
     fn get_index(&self) -> Vec<u8>;
@@ -51,6 +56,54 @@ impl DBIndex for CurrentHeight {
     }
 }
 
+pub struct EarliestStateHeight;
+
+impl DBIndex for EarliestStateHeight {
+    fn get_index(&self) -> Vec<u8> {
+        H256::from("7cabfb7709b29c16d9e876e876c9988d03f9c3414e1d3ff77ec1de2d0ee59f69").to_vec()
+    }
+}
+
+/// The root of the state the reference counts of the state nodes start from.
+pub struct TrackedStateRoot;
+
+impl DBIndex for TrackedStateRoot {
+    fn get_index(&self) -> Vec<u8> {
+        H256::from("7cabfb7709b29c16d9e876e876c9988d03f9c3414e1d3ff77ec1de2d0ee59f6c").to_vec()
+    }
+}
+
+pub struct ManualBlackList;
+
+impl DBIndex for ManualBlackList {
+    fn get_index(&self) -> Vec<u8> {
+        H256::from("7cabfb7709b29c16d9e876e876c9988d03f9c3414e1d3ff77ec1de2d0ee59f6a").to_vec()
+    }
+}
+
+/// The ids of the installed filters.
+pub struct InstalledFilters;
+
+impl DBIndex for InstalledFilters {
+    fn get_index(&self) -> Vec<u8> {
+        H256::from("7cabfb7709b29c16d9e876e876c9988d03f9c3414e1d3ff77ec1de2d0ee59f6b").to_vec()
+    }
+}
+
+/// An installed filter, by its id.
+pub struct InstalledFilter(pub u64);
+
+impl DBIndex for InstalledFilter {
+    fn get_index(&self) -> Vec<u8> {
+        let mut result = Vec::with_capacity(9);
+        result.push(INSTALLEDFILTER_INDEX);
+        for i in (0..8).rev() {
+            result.push((self.0 >> (i * 8)) as u8);
+        }
+        result
+    }
+}
+
 pub struct Hash2Header(pub H256);
 
 impl DBIndex for Hash2Header {
@@ -147,6 +200,62 @@ impl DBIndex for Hash2BlockReceipts {
     }
 }
 
+pub struct BlockNumber2StateJournal(pub BlockNumber);
+
+impl DBIndex for BlockNumber2StateJournal {
+    fn get_index(&self) -> Vec<u8> {
+        let mut result = [0u8; 9];
+        result[0] = STATEJOURNAL_INDEX as u8;
+        result[1] = (self.0 >> 56) as u8;
+        result[2] = (self.0 >> 48) as u8;
+        result[3] = (self.0 >> 40) as u8;
+        result[4] = (self.0 >> 32) as u8;
+        result[5] = (self.0 >> 24) as u8;
+        result[6] = (self.0 >> 16) as u8;
+        result[7] = (self.0 >> 8) as u8;
+        result[8] = self.0 as u8;
+        result.to_vec()
+    }
+}
+
+pub struct StateNodeRefCount(pub H256);
+
+impl DBIndex for StateNodeRefCount {
+    fn get_index(&self) -> Vec<u8> {
+        let mut result = H264::default();
+        result[0] = STATENODEREFCOUNT_INDEX as u8;
+        (*result)[1..].clone_from_slice(&self.0);
+        result.to_vec()
+    }
+}
+
+/// Number of transactions indexed for an address.
+pub struct AddressTransactionCount(pub Address);
+
+impl DBIndex for AddressTransactionCount {
+    fn get_index(&self) -> Vec<u8> {
+        let mut result = Vec::with_capacity(21);
+        result.push(ADDRESSTRANSACTIONCOUNT_INDEX);
+        result.extend_from_slice(&self.0);
+        result
+    }
+}
+
+/// A page of the transactions indexed for an address.
+pub struct AddressTransactions(pub Address, pub u64);
+
+impl DBIndex for AddressTransactions {
+    fn get_index(&self) -> Vec<u8> {
+        let mut result = Vec::with_capacity(29);
+        result.push(ADDRESSTRANSACTIONS_INDEX);
+        result.extend_from_slice(&self.0);
+        for i in (0..8).rev() {
+            result.push((self.1 >> (i * 8)) as u8);
+        }
+        result
+    }
+}
+
 #[derive(Debug, PartialEq, Eq, Hash, Clone)]
 pub struct LogGroupPosition(GroupPosition);
 
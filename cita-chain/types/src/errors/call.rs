@@ -19,8 +19,11 @@ use std::fmt;
 pub enum CallError {
     /// Couldn't find the transaction in the chain.
     TransactionNotFound,
-    /// Couldn't find requested block's state in the chain.
-    StatePruned,
+    /// Couldn't find the requested block in the chain.
+    BlockNotFound,
+    /// The requested block's state has been pruned, only the states since the given height
+    /// are kept.
+    StatePruned(u64),
     /// Couldn't find an amount of gas that didn't result in an exception.
     Exceptional,
     /// Corrupt state.
@@ -35,7 +38,12 @@ impl fmt::Display for CallError {
 
         let msg = match *self {
             TransactionNotFound => "Transaction couldn't be found in the chain".into(),
-            StatePruned => "Couldn't find the transaction block's state in the chain".into(),
+            BlockNotFound => "Couldn't find the requested block in the chain".into(),
+            StatePruned(earliest) => format!(
+                "State pruned, only the states of blocks since {} are kept, \
+                 as configured by state_history of executor",
+                earliest
+            ),
             Exceptional => "An exception happened in the execution".into(),
             StateCorrupt => "Stored state found to be corrupted.".into(),
             Execution(ref e) => format!("{}", e),
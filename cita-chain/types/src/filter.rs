@@ -12,13 +12,14 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use crate::block_number::BlockTag;
 #[cfg(test)]
 use crate::block_number::Tag;
+use crate::block_number::{BlockNumber, BlockTag};
 use crate::log::Log;
 use cita_types::traits::BloomTools;
 use cita_types::{Address, Bloom, H256};
 use jsonrpc_types::rpc_types::{Filter as RpcFilter, FilterAddress, Topic, VariadicValue};
+use std::fmt;
 
 /// Address Filter.
 #[derive(Debug, PartialEq, Clone)]
@@ -192,6 +193,55 @@ impl Filter {
     }
 }
 
+/// Where a truncated log query continues: the block, and the index of the next log in it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LogCursor {
+    pub block_number: BlockNumber,
+    pub log_index: usize,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum LogsError {
+    /// `fromBlock` or `toBlock` is not found.
+    UnknownBlock,
+    /// The query spans more blocks than allowed.
+    RangeTooLarge(u64),
+    /// The query matches more logs than allowed.
+    TooManyLogs(usize),
+}
+
+impl fmt::Display for LogsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LogsError::UnknownBlock => write!(f, "unknown block in the log query range"),
+            LogsError::RangeTooLarge(max) => write!(
+                f,
+                "log query range is larger than {} blocks, narrow fromBlock and toBlock",
+                max
+            ),
+            LogsError::TooManyLogs(max) => write!(
+                f,
+                "log query returns more than {} logs, narrow the range or page it with a cursor",
+                max
+            ),
+        }
+    }
+}
+
+/// Check that the blocks `from..=to` are within `max_range` blocks.
+pub fn check_block_range(
+    from: BlockNumber,
+    to: BlockNumber,
+    max_range: u64,
+) -> Result<(), LogsError> {
+    if to >= from && to - from >= max_range {
+        Err(LogsError::RangeTooLarge(max_range))
+    } else {
+        Ok(())
+    }
+}
+
 impl From<RpcFilter> for Filter {
     fn from(v: RpcFilter) -> Filter {
         Filter {
@@ -206,7 +256,9 @@ impl From<RpcFilter> for Filter {
 
 #[cfg(test)]
 mod tests {
-    use crate::filter::{AddressFilter, Filter, TopicFilter};
+    use crate::filter::{
+        check_block_range, AddressFilter, Filter, LogCursor, LogsError, TopicFilter,
+    };
     use crate::log::Log;
     use cita_types::{Address, Bloom, H256};
 
@@ -378,4 +430,25 @@ mod tests {
         assert_eq!(filter.addresses.matches(&entry1), true);
         assert_eq!(filter.addresses.matches(&entry2), false);
     }
+    #[test]
+    fn test_check_block_range() {
+        assert_eq!(check_block_range(0, 99, 100), Ok(()));
+        assert_eq!(
+            check_block_range(0, 100, 100),
+            Err(LogsError::RangeTooLarge(100))
+        );
+        assert_eq!(check_block_range(100, 0, 100), Ok(()));
+        assert_eq!(check_block_range(7, 7, 1), Ok(()));
+    }
+
+    #[test]
+    fn test_log_cursor_json() {
+        let cursor = LogCursor {
+            block_number: 10,
+            log_index: 2,
+        };
+        let json = serde_json::to_string(&cursor).unwrap();
+        assert_eq!(json, r#"{"blockNumber":10,"logIndex":2}"#);
+        assert_eq!(serde_json::from_str::<LogCursor>(&json).unwrap(), cursor);
+    }
 }
@@ -13,6 +13,7 @@
 // limitations under the License.
 
 extern crate cita_crypto as crypto;
+extern crate cita_reserved_crypto as reserved_crypto;
 #[macro_use]
 extern crate serde_derive;
 #[macro_use]
@@ -24,6 +25,7 @@ extern crate cita_database as cita_db;
 pub extern crate bloomchain;
 
 pub type Bytes = Vec<u8>;
+pub mod address_index;
 pub mod block;
 pub mod block_number;
 pub mod block_receipts;
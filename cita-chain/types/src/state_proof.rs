@@ -1,6 +1,17 @@
 use super::Bytes;
-use cita_types::{Address, H256};
+use cita_trie::{MemoryDB, PatriciaTrie, Trie};
+use cita_types::{Address, H256, U256};
+use hasher::{Hasher, HasherKeccak};
+use jsonrpc_types::rpc_types::{Data, Quantity};
 use rlp::{self, Decodable, DecoderError, Encodable, RlpStream, UntrustedRlp};
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+use std::sync::Arc;
+
+/// Positions in an RLP encoded account: `[nonce, balance, storage_root, code_hash, ..]`.
+const ACCOUNT_NONCE_INDEX: usize = 0;
+const ACCOUNT_BALANCE_INDEX: usize = 1;
+const ACCOUNT_STORAGE_ROOT_INDEX: usize = 2;
+const ACCOUNT_CODE_HASH_INDEX: usize = 3;
 
 #[derive(Default, Debug, Clone)]
 pub struct StateProof {
@@ -36,23 +47,40 @@ impl StateProof {
         rlp::decode(bytes)
     }
 
-    // FixMe: The implementation should be finished while fixing cross chain
-    pub fn verify(&self, _state_root: H256) -> Option<H256> {
-        // let state = State();
-        // state.verify_proof();
-
-        // let trie = PatriciaTrie::new(db, codec);
-        // trie.verify_proof();
-        // trie.verify_proof();
+    pub fn new(
+        address: Address,
+        account_proof: Vec<Bytes>,
+        key: H256,
+        value_proof: Vec<Bytes>,
+    ) -> Self {
+        StateProof {
+            address,
+            account_proof,
+            key,
+            value_proof,
+        }
+    }
 
-        // trie::triedb::verify_value_proof(
-        //     &self.address,
-        //     state_root,
-        //     &self.account_proof,
-        //     Account::from_rlp,
-        // )
-        // .and_then(|a| a.verify_value_proof(&self.key, &self.value_proof))
-        None
+    /// Verify the proof against `state_root` and return the proven storage value.
+    ///
+    /// The account proof is walked from the state root to the account, then the value
+    /// proof is walked from the account's storage root to the key. Returns `None` if
+    /// either proof is invalid. A proven absence of the account or the key yields zero.
+    pub fn verify(&self, state_root: H256) -> Option<H256> {
+        let account = match verify_trie_proof(state_root, &self.address, &self.account_proof)? {
+            Some(account) => account,
+            None => return Some(H256::zero()),
+        };
+        let storage_root: H256 = UntrustedRlp::new(&account)
+            .val_at(ACCOUNT_STORAGE_ROOT_INDEX)
+            .ok()?;
+        match verify_trie_proof(storage_root, &self.key, &self.value_proof)? {
+            Some(value) => UntrustedRlp::new(&value)
+                .as_val::<U256>()
+                .ok()
+                .map(H256::from),
+            None => Some(H256::zero()),
+        }
     }
 
     pub fn address(&self) -> &Address {
@@ -67,16 +95,219 @@ impl StateProof {
         &self.key
     }
 
+    pub fn value_proof(&self) -> &Vec<Bytes> {
+        &self.value_proof
+    }
+
     #[cfg(test)]
     pub fn set_address(&mut self, new_address: Address) {
         self.address = new_address;
     }
 }
 
+/// Proof of an account and some of its storage keys, in the shape of EIP-1186 `eth_getProof`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AccountProof {
+    pub address: Address,
+    pub balance: U256,
+    pub nonce: U256,
+    pub code_hash: H256,
+    pub storage_hash: H256,
+    pub account_proof: Vec<Bytes>,
+    pub storage_proof: Vec<StorageProof>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct StorageProof {
+    pub key: H256,
+    pub value: U256,
+    pub proof: Vec<Bytes>,
+}
+
+impl AccountProof {
+    /// Build the proof from the raw trie proofs of the account and of each storage key.
+    ///
+    /// The account and values are read by walking the proofs from `state_root`, so
+    /// `None` means a proof is invalid. An absent account is reported as an empty
+    /// one and an absent key as zero, the proofs show their absence.
+    pub fn new(
+        address: Address,
+        state_root: H256,
+        account_proof: Vec<Bytes>,
+        storage_proofs: Vec<(H256, Vec<Bytes>)>,
+    ) -> Option<Self> {
+        let (nonce, balance, storage_hash, code_hash) =
+            match verify_trie_proof(state_root, &address, &account_proof)? {
+                Some(account) => {
+                    let account = UntrustedRlp::new(&account);
+                    (
+                        account.val_at(ACCOUNT_NONCE_INDEX).ok()?,
+                        account.val_at(ACCOUNT_BALANCE_INDEX).ok()?,
+                        account.val_at(ACCOUNT_STORAGE_ROOT_INDEX).ok()?,
+                        account.val_at(ACCOUNT_CODE_HASH_INDEX).ok()?,
+                    )
+                }
+                None => (U256::zero(), U256::zero(), keccak(&[0x80]), keccak(&[])),
+            };
+
+        let mut storage_proof = Vec::with_capacity(storage_proofs.len());
+        for (key, proof) in storage_proofs {
+            let value = match verify_trie_proof(storage_hash, &key, &proof)? {
+                Some(value) => UntrustedRlp::new(&value).as_val::<U256>().ok()?,
+                None => U256::zero(),
+            };
+            storage_proof.push(StorageProof { key, value, proof });
+        }
+
+        Some(AccountProof {
+            address,
+            balance,
+            nonce,
+            code_hash,
+            storage_hash,
+            account_proof,
+            storage_proof,
+        })
+    }
+}
+
+impl Serialize for AccountProof {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let account_proof: Vec<Data> = self.account_proof.iter().cloned().map(Data::from).collect();
+        let mut s = serializer.serialize_struct("AccountProof", 7)?;
+        s.serialize_field("address", &self.address)?;
+        s.serialize_field("accountProof", &account_proof)?;
+        s.serialize_field("balance", &Quantity::from(self.balance))?;
+        s.serialize_field("codeHash", &self.code_hash)?;
+        s.serialize_field("nonce", &Quantity::from(self.nonce))?;
+        s.serialize_field("storageHash", &self.storage_hash)?;
+        s.serialize_field("storageProof", &self.storage_proof)?;
+        s.end()
+    }
+}
+
+impl Serialize for StorageProof {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let proof: Vec<Data> = self.proof.iter().cloned().map(Data::from).collect();
+        let mut s = serializer.serialize_struct("StorageProof", 3)?;
+        s.serialize_field("key", &self.key)?;
+        s.serialize_field("value", &Quantity::from(self.value))?;
+        s.serialize_field("proof", &proof)?;
+        s.end()
+    }
+}
+
+fn keccak(data: &[u8]) -> H256 {
+    H256::from_slice(&HasherKeccak::new().digest(data))
+}
+
+/// Walk `proof` from `root` to `key`.
+///
+/// The outer `Option` is `None` when the proof is invalid, the inner one is `None`
+/// when the proof shows that `key` is absent from the trie.
+fn verify_trie_proof(root: H256, key: &[u8], proof: &[Bytes]) -> Option<Option<Bytes>> {
+    let trie = PatriciaTrie::new(Arc::new(MemoryDB::new(true)), Arc::new(HasherKeccak::new()));
+    trie.verify_proof(&root[..], key, proof.to_vec())
+        .map_err(|err| trace!("verify trie proof failed: {:?}", err))
+        .ok()
+}
+
 #[cfg(test)]
 mod test {
-    use super::StateProof;
-    use rlp;
+    use super::{AccountProof, StateProof};
+    use cita_trie::{MemoryDB, PatriciaTrie, Trie};
+    use cita_types::{Address, H256, U256};
+    use hasher::HasherKeccak;
+    use rlp::{self, RlpStream};
+    use std::sync::Arc;
+
+    fn new_trie() -> PatriciaTrie<MemoryDB, HasherKeccak> {
+        PatriciaTrie::new(
+            Arc::new(MemoryDB::new(false)),
+            Arc::new(HasherKeccak::new()),
+        )
+    }
+
+    fn encode_account(storage_root: H256) -> Vec<u8> {
+        let mut s = RlpStream::new_list(5);
+        s.append(&U256::from(1));
+        s.append(&U256::from(100));
+        s.append(&storage_root);
+        s.append(&H256::zero());
+        s.append(&H256::zero());
+        s.out()
+    }
+
+    /// Build a state with one account holding `key => value`, return the state root and proof.
+    fn build_proof(address: Address, key: H256, value: U256) -> (H256, StateProof) {
+        let mut storage = new_trie();
+        storage
+            .insert(key.to_vec(), rlp::encode(&value).into_vec())
+            .unwrap();
+        storage
+            .insert(
+                H256::from(42).to_vec(),
+                rlp::encode(&U256::from(7)).into_vec(),
+            )
+            .unwrap();
+        let storage_root = H256::from_slice(&storage.root().unwrap());
+        let value_proof = storage.get_proof(&key).unwrap();
+
+        let mut state = new_trie();
+        state
+            .insert(address.to_vec(), encode_account(storage_root))
+            .unwrap();
+        state
+            .insert(Address::from(1).to_vec(), encode_account(H256::zero()))
+            .unwrap();
+        let state_root = H256::from_slice(&state.root().unwrap());
+        let account_proof = state.get_proof(&address).unwrap();
+
+        (
+            state_root,
+            StateProof::new(address, account_proof, key, value_proof),
+        )
+    }
+
+    #[test]
+    fn test_verify_state_proof() {
+        let address = Address::from(0x1234);
+        let key = H256::from(3);
+        let (state_root, proof) = build_proof(address, key, U256::from(0x55aa));
+
+        assert_eq!(proof.verify(state_root), Some(H256::from(0x55aa)));
+    }
+
+    #[test]
+    fn test_verify_state_proof_with_wrong_root() {
+        let (_, proof) = build_proof(Address::from(0x1234), H256::from(3), U256::from(0x55aa));
+
+        assert_eq!(proof.verify(H256::from(0xdead)), None);
+    }
+
+    #[test]
+    fn test_verify_state_proof_with_tampered_value_proof() {
+        let (state_root, proof) =
+            build_proof(Address::from(0x1234), H256::from(3), U256::from(0x55aa));
+        let mut value_proof = proof.value_proof().clone();
+        let last = value_proof.last_mut().unwrap();
+        let len = last.len();
+        last[len - 1] ^= 0x01;
+        let tampered = StateProof::new(
+            *proof.address(),
+            proof.account_proof().clone(),
+            *proof.key(),
+            value_proof,
+        );
+
+        assert_eq!(tampered.verify(state_root), None);
+    }
 
     #[test]
     fn test_encode_and_decode_state_proof() {
@@ -87,4 +318,37 @@ mod test {
         let encoded_rlp = rlp::encode(&decoded_res).into_vec();
         assert_eq!(proof_rlp, encoded_rlp);
     }
+    #[test]
+    fn test_account_proof() {
+        let address = Address::from(0x1234);
+        let (state_root, proof) = build_proof(address, H256::from(3), U256::from(0x55aa));
+        let storage_proofs = vec![(H256::from(3), proof.value_proof().clone())];
+
+        let account_proof = AccountProof::new(
+            address,
+            state_root,
+            proof.account_proof().clone(),
+            storage_proofs,
+        )
+        .unwrap();
+        assert_eq!(account_proof.nonce, U256::from(1));
+        assert_eq!(account_proof.balance, U256::from(100));
+        assert_eq!(account_proof.storage_proof[0].value, U256::from(0x55aa));
+
+        let json = serde_json::to_value(&account_proof).unwrap();
+        assert_eq!(json["balance"], "0x64");
+        assert_eq!(json["storageProof"][0]["value"], "0x55aa");
+        assert_eq!(
+            json["accountProof"].as_array().unwrap().len(),
+            proof.account_proof().len()
+        );
+
+        assert!(AccountProof::new(
+            address,
+            H256::from(0xdead),
+            proof.account_proof().clone(),
+            vec![]
+        )
+        .is_none());
+    }
 }
@@ -127,6 +127,37 @@ impl Encodable for CryptoType {
     }
 }
 
+impl CryptoType {
+    /// Length of the signatures of this crypto type.
+    pub fn signature_len(&self) -> usize {
+        match *self {
+            CryptoType::DEFAULT => SIGNATURE_BYTES_LEN,
+            CryptoType::RESERVED => reserved_crypto::SIGNATURE_BYTES_LEN,
+        }
+    }
+
+    /// Length of the public keys of this crypto type.
+    pub fn pubkey_len(&self) -> usize {
+        match *self {
+            CryptoType::DEFAULT => PUBKEY_BYTES_LEN,
+            CryptoType::RESERVED => reserved_crypto::PUBKEY_BYTES_LEN,
+        }
+    }
+
+    /// Address of a signer of this crypto type, from its raw public key.
+    ///
+    /// The public key of the RESERVED crypto may not fit the `PubKey` of the
+    /// default crypto, so it is never converted to it.
+    pub fn signer_to_address(&self, signer: &[u8]) -> Address {
+        match *self {
+            CryptoType::DEFAULT => pubkey_to_address(&PubKey::from_slice(signer)),
+            CryptoType::RESERVED => {
+                Address::from_slice(&reserved_crypto::pubkey_to_address(signer))
+            }
+        }
+    }
+}
+
 impl From<ProtoCrypto> for CryptoType {
     fn from(c: ProtoCrypto) -> CryptoType {
         match c {
@@ -255,7 +286,7 @@ impl Transaction {
 
     // Specify the sender; this won't survive the serialize/deserialize process, but can be cloned.
     pub fn fake_sign(self, from: Address) -> SignedTransaction {
-        let signature = Signature::default();
+        let signature = Signature::default().to_vec();
         SignedTransaction {
             transaction: UnverifiedTransaction {
                 unsigned: self,
@@ -325,18 +356,29 @@ impl Transaction {
 }
 
 /// Signed transaction information without verified signature.
-#[derive(Default, Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct UnverifiedTransaction {
     /// Plain Transaction.
     unsigned: Transaction,
-    /// The signature
-    signature: Signature,
+    /// The signature, its length depends on the crypto type
+    signature: Vec<u8>,
     /// The Crypto Type
     crypto_type: CryptoType,
     /// Hash of the transaction
     hash: H256,
 }
 
+impl Default for UnverifiedTransaction {
+    fn default() -> Self {
+        UnverifiedTransaction {
+            unsigned: Transaction::default(),
+            signature: Signature::default().to_vec(),
+            crypto_type: CryptoType::default(),
+            hash: H256::default(),
+        }
+    }
+}
+
 impl Deref for UnverifiedTransaction {
     type Target = Transaction;
 
@@ -356,10 +398,15 @@ impl Decodable for UnverifiedTransaction {
         if d.item_count()? != 4 {
             return Err(DecoderError::RlpIncorrectListLen);
         }
+        let signature: Bytes = d.val_at(1)?;
+        let crypto_type: CryptoType = d.val_at(2)?;
+        if signature.len() != crypto_type.signature_len() {
+            return Err(DecoderError::Custom("Invalid signature length."));
+        }
         Ok(UnverifiedTransaction {
             unsigned: d.val_at(0)?,
-            signature: d.val_at(1)?,
-            crypto_type: d.val_at(2)?,
+            signature,
+            crypto_type,
             hash: d.val_at(3)?,
         })
     }
@@ -373,14 +420,15 @@ impl Encodable for UnverifiedTransaction {
 
 impl UnverifiedTransaction {
     fn create(utx: &ProtoUnverifiedTransaction, hash: H256) -> Result<Self, Error> {
-        if utx.get_signature().len() != SIGNATURE_BYTES_LEN {
+        let crypto_type = CryptoType::from(utx.get_crypto());
+        if utx.get_signature().len() != crypto_type.signature_len() {
             return Err(Error::InvalidSignature);
         }
 
         Ok(UnverifiedTransaction {
             unsigned: Transaction::create(utx.get_transaction())?,
-            signature: Signature::from(utx.get_signature()),
-            crypto_type: CryptoType::from(utx.get_crypto()),
+            signature: utx.get_signature().to_vec(),
+            crypto_type,
             hash,
         })
     }
@@ -409,7 +457,7 @@ impl UnverifiedTransaction {
         let tx = self.unsigned.proto_transaction();
 
         untx.set_transaction(tx);
-        untx.set_signature(self.signature.to_vec());
+        untx.set_signature(self.signature.clone());
 
         match self.crypto_type {
             CryptoType::DEFAULT => untx.set_crypto(ProtoCrypto::DEFAULT),
@@ -420,11 +468,22 @@ impl UnverifiedTransaction {
 }
 
 /// A `UnverifiedTransaction` with successfully recovered `sender`.
-#[derive(Default, Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct SignedTransaction {
     transaction: UnverifiedTransaction,
     sender: Address,
-    public: PubKey,
+    /// Public key of the signer, its length depends on the crypto type
+    signer: Bytes,
+}
+
+impl Default for SignedTransaction {
+    fn default() -> Self {
+        SignedTransaction {
+            transaction: UnverifiedTransaction::default(),
+            sender: Address::default(),
+            signer: PubKey::default().to_vec(),
+        }
+    }
 }
 
 /// RLP dose not support struct nesting well
@@ -434,8 +493,17 @@ impl Decodable for SignedTransaction {
             return Err(DecoderError::RlpIncorrectListLen);
         }
 
-        let public: PubKey = d.val_at(12)?;
+        let signer: Bytes = d.val_at(12)?;
         let version = d.val_at(8)?;
+        let signature: Bytes = d.val_at(9)?;
+        let crypto_type: CryptoType = d.val_at(10)?;
+        if signature.len() != crypto_type.signature_len() {
+            return Err(DecoderError::Custom("Invalid signature length."));
+        }
+        if signer.len() != crypto_type.pubkey_len() {
+            return Err(DecoderError::Custom("Invalid public key length."));
+        }
+        let sender = crypto_type.signer_to_address(&signer);
 
         Ok(SignedTransaction {
             transaction: UnverifiedTransaction {
@@ -454,12 +522,12 @@ impl Decodable for SignedTransaction {
                     },
                     version,
                 },
-                signature: d.val_at(9)?,
-                crypto_type: d.val_at(10)?,
+                signature,
                 hash: d.val_at(11)?,
+                crypto_type,
             },
-            sender: pubkey_to_address(&public),
-            public,
+            sender,
+            signer,
         })
     }
 }
@@ -487,7 +555,7 @@ impl Encodable for SignedTransaction {
         s.append(&self.crypto_type);
         s.append(&self.hash);
         //TODO: remove it
-        s.append(&self.public);
+        s.append(&self.signer);
     }
 }
 
@@ -511,17 +579,17 @@ impl SignedTransaction {
             return Err(Error::InvalidHash);
         }
 
-        if stx.get_signer().len() != PUBKEY_BYTES_LEN {
+        let crypto_type = CryptoType::from(stx.get_transaction_with_sig().get_crypto());
+        if stx.get_signer().len() != crypto_type.pubkey_len() {
             return Err(Error::InvalidPubKey);
         }
 
         let tx_hash = H256::from(stx.get_tx_hash());
-        let public = PubKey::from_slice(stx.get_signer());
-        let sender = pubkey_to_address(&public);
+        let sender = crypto_type.signer_to_address(stx.get_signer());
         Ok(SignedTransaction {
             transaction: UnverifiedTransaction::create(stx.get_transaction_with_sig(), tx_hash)?,
             sender,
-            public,
+            signer: stx.get_signer().to_vec(),
         })
     }
 
@@ -540,9 +608,9 @@ impl SignedTransaction {
         &self.sender
     }
 
-    /// Returns a public key of the sender.
-    pub fn public_key(&self) -> &PubKey {
-        &self.public
+    /// Returns the raw public key of the sender, of the transaction's crypto type.
+    pub fn signer(&self) -> &[u8] {
+        &self.signer
     }
 
     ///get protobuf of signed transaction
@@ -551,7 +619,7 @@ impl SignedTransaction {
         let utx = self.transaction.proto_unverified();
         stx.set_transaction_with_sig(utx);
         stx.set_tx_hash(self.hash().to_vec());
-        stx.set_signer(self.public.to_vec());
+        stx.set_signer(self.signer.clone());
         stx
     }
 }
@@ -586,6 +654,50 @@ mod tests {
         assert_eq!(stx_rlp, stx_encoded);
     }
 
+    #[test]
+    fn test_reserved_crypto_sender() {
+        let keypair = reserved_crypto::KeyPair::gen_keypair();
+        let mut stx = SignedTransaction::default().protobuf();
+        let signature = keypair.sign(stx.get_tx_hash()).unwrap();
+        stx.mut_transaction_with_sig().set_signature(signature);
+        stx.mut_transaction_with_sig()
+            .set_crypto(ProtoCrypto::RESERVED);
+        stx.set_signer(keypair.pubkey().to_vec());
+
+        let stx = SignedTransaction::create(&stx).unwrap();
+        assert_eq!(*stx.sender(), Address::from_slice(&keypair.address()));
+        assert_eq!(
+            stx.protobuf()
+                .get_transaction_with_sig()
+                .get_signature()
+                .len(),
+            128
+        );
+    }
+
+    #[test]
+    fn test_decode_invalid_signature_length() {
+        let mut stx = SignedTransaction::default();
+        stx.crypto_type = CryptoType::RESERVED;
+        let stx_rlp = rlp::encode(&stx).into_vec();
+        assert!(UntrustedRlp::new(&stx_rlp)
+            .as_val::<SignedTransaction>()
+            .is_err());
+
+        let mut utx = UnverifiedTransaction::default();
+        utx.signature = vec![0; 1];
+        let utx_rlp = rlp::encode(&utx).into_vec();
+        assert!(UntrustedRlp::new(&utx_rlp)
+            .as_val::<UnverifiedTransaction>()
+            .is_err());
+        utx.signature = vec![0; reserved_crypto::SIGNATURE_BYTES_LEN];
+        utx.crypto_type = CryptoType::RESERVED;
+        let utx_rlp = rlp::encode(&utx).into_vec();
+        assert!(UntrustedRlp::new(&utx_rlp)
+            .as_val::<UnverifiedTransaction>()
+            .is_ok());
+    }
+
     #[test]
     fn invalid_value() {
         let mut plain_transaction = ProtoTransaction::new();
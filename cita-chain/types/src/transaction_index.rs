@@ -13,7 +13,9 @@
 // limitations under the License.
 
 // FixMe: Rewrite
+use crate::block_number::BlockNumber;
 use cita_types::H256;
+use jsonrpc_types::rpc_types::Data;
 use rlp::{Decodable, DecoderError, Encodable, RlpStream, UntrustedRlp};
 
 #[derive(Debug)]
@@ -40,3 +42,13 @@ impl Encodable for TransactionIndex {
         s.append(&self.index);
     }
 }
+
+/// Where a transaction is, as chain tells the executor which replays the block up to it
+/// for `traceTransaction`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TracePosition {
+    pub block_number: BlockNumber,
+    /// RLP list of the transactions of the block up to and including it
+    pub transactions: Data,
+}
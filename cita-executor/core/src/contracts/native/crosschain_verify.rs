@@ -16,7 +16,6 @@ use crate::cita_executive::VmExecParams;
 use crate::contracts::{
     native::factory::Contract, solc::ChainManagement, tools::method as method_tools,
 };
-// use crate::state::StateProof;
 use cita_types::{Address, H256, U256};
 use core::header::Header;
 use core::libchain::chain::TxProof;
@@ -25,8 +24,10 @@ use ethabi;
 use crate::storage::Map;
 use crate::types::context::Context;
 use crate::types::errors::NativeError;
+use crate::types::state_proof::StateProof;
 use cita_vm::evm::DataProvider;
 use cita_vm::evm::InterpreterResult;
+use rlp::{Decodable, UntrustedRlp};
 
 lazy_static! {
     static ref VERIFY_TRANSACTION_FUNC: u32 =
@@ -103,11 +104,7 @@ impl CrossChainVerify {
             ethabi::ParamType::Bytes,
         ];
 
-        let result = ethabi::decode(&tokens, &data[4..]);
-        if result.is_err() {
-            return Err(NativeError::Internal("decode failed".to_string()));
-        }
-        let mut decoded = result.unwrap();
+        let mut decoded = decode_params(&tokens, &data)?;
         trace!("decoded = {:?}", decoded);
 
         let result = decoded.remove(0).to_address();
@@ -141,7 +138,7 @@ impl CrossChainVerify {
         let proof_data = result.unwrap();
         trace!("data = {:?}", proof_data);
 
-        let proof = TxProof::from_bytes(&proof_data);
+        let proof: TxProof = decode_rlp(&proof_data, "transaction proof")?;
 
         let relay_info = proof.extract_relay_info();
         if relay_info.is_none() {
@@ -195,108 +192,86 @@ impl CrossChainVerify {
 
     fn verify_state(
         &mut self,
-        _params: &VmExecParams,
-        _data_provider: &mut dyn DataProvider,
+        params: &VmExecParams,
+        data_provider: &mut dyn DataProvider,
     ) -> Result<InterpreterResult, NativeError> {
-        unimplemented!()
-        // let gas_cost = U256::from(10000);
-        // if params.gas < gas_cost {
-        //     return Err(NativeError::Internal("out of gas".to_string()));
-        // }
-        // let gas_left = params.gas - gas_cost;
-
-        // if params.data.is_none() {
-        //     return Err(NativeError::Internal("no data".to_string()));
-        // }
-
-        // let data = params.data.to_owned().unwrap();
-        // trace!("data = {:?}", data);
-        // let tokens = vec![
-        //     ethabi::ParamType::Uint(32),
-        //     ethabi::ParamType::Uint(64),
-        //     ethabi::ParamType::Bytes,
-        // ];
-
-        // let result = ethabi::decode(&tokens, &data[4..]);
-        // if result.is_err() {
-        //     return Err(NativeError::Internal("decode failed".to_string()));
-        // }
-        // let mut decoded = result.unwrap();
-        // trace!("decoded = {:?}", decoded);
-
-        // let result = decoded.remove(0).to_uint();
-        // if result.is_none() {
-        //     return Err(NativeError::Internal("decode 1th param failed".to_string()));
-        // }
-        // let chain_id = U256::from_big_endian(&result.unwrap());
-        // trace!("chain_id = {}", chain_id);
-
-        // let result = decoded.remove(0).to_uint();
-        // if result.is_none() {
-        //     return Err(NativeError::Internal("decode 2nd param failed".to_string()));
-        // }
-        // let block_number = U256::from_big_endian(&result.unwrap()).low_u64();
-        // trace!("block_number = {}", block_number);
-
-        // let result = self.state_roots.get_array(&chain_id).unwrap().get(
-        //     data_provider,
-        //     &params.code_address.unwrap(),
-        //     block_number,
-        // );
-        // if result.is_err() {
-        //     return Err(NativeError::Internal("get state root failed".to_string()));
-        // }
-        // let result1 = self.state_roots.get_array(&chain_id).unwrap().get(
-        //     data_provider,
-        //     &params.code_address.unwrap(),
-        //     block_number + 1,
-        // );
-        // if result1.is_err() {
-        //     return Err(NativeError::Internal(
-        //         "get next state root failed".to_string(),
-        //     ));
-        // }
-        // let state_root: H256 = result.unwrap().into();
-        // trace!("state_root = {:?}", state_root);
-        // let next_state_root: H256 = result1.unwrap().into();
-        // trace!("next_state_root = {:?}", next_state_root);
-        // if state_root == H256::zero() || next_state_root == H256::zero() {
-        //     return Err(NativeError::Internal(
-        //         "state root have not confirmed".to_string(),
-        //     ));
-        // }
-
-        // let result = decoded.remove(0).to_bytes();
-        // if result.is_none() {
-        //     return Err(NativeError::Internal("decode 3rd param failed".to_string()));
-        // }
-        // let state_proof_bytes = result.unwrap();
-        // trace!("state_proof_bytes = {:?}", state_proof_bytes);
-
-        // let state_proof = StateProof::from_bytes(&state_proof_bytes);
-        // let maybe_val = state_proof.verify(state_root);
-        // if maybe_val.is_none() {
-        //     return Err(NativeError::Internal(
-        //         "state proof verify failed".to_string(),
-        //     ));
-        // }
-        // let val = maybe_val.unwrap();
-        // trace!("val = {:?}", val);
-
-        // let tokens = vec![
-        //     ethabi::Token::Address((*state_proof.address()).into()),
-        //     ethabi::Token::Uint((*state_proof.key()).into()),
-        //     ethabi::Token::Uint(val.into()),
-        // ];
-        // let result = ethabi::encode(&tokens);
-        // trace!("encoded {:?}", result);
-
-        // self.output = result;
-        // Ok(InterpreterResult::Normal(
-        //     self.output.clone(),
-        //     gas_left.low_u64(),
-        //     vec![],
-        // ))
+        let gas_cost = 10000;
+        if params.gas < gas_cost {
+            return Err(NativeError::Internal("out of gas".to_string()));
+        }
+        let gas_left = params.gas - gas_cost;
+
+        let data = params.data.clone();
+        trace!("data = {:?}", data);
+        let tokens = vec![
+            ethabi::ParamType::Uint(32),
+            ethabi::ParamType::Uint(64),
+            ethabi::ParamType::Bytes,
+        ];
+
+        let mut decoded = decode_params(&tokens, &data)?;
+        trace!("decoded = {:?}", decoded);
+
+        let result = decoded.remove(0).to_uint();
+        if result.is_none() {
+            return Err(NativeError::Internal("decode 1th param failed".to_string()));
+        }
+        let chain_id = U256::from_big_endian(&result.unwrap());
+        trace!("chain_id = {}", chain_id);
+
+        let result = decoded.remove(0).to_uint();
+        if result.is_none() {
+            return Err(NativeError::Internal("decode 2nd param failed".to_string()));
+        }
+        let block_number = U256::from_big_endian(&result.unwrap()).low_u64();
+        trace!("block_number = {}", block_number);
+
+        let state_roots = self.state_roots.get_array(&chain_id)?;
+        let state_root: H256 = state_roots
+            .get(data_provider, &params.code_address, block_number)?
+            .into();
+        trace!("state_root = {:?}", state_root);
+        let next_state_root: H256 = state_roots
+            .get(data_provider, &params.code_address, block_number + 1)?
+            .into();
+        trace!("next_state_root = {:?}", next_state_root);
+        if state_root == H256::zero() || next_state_root == H256::zero() {
+            return Err(NativeError::Internal(
+                "state root have not confirmed".to_string(),
+            ));
+        }
+
+        let result = decoded.remove(0).to_bytes();
+        if result.is_none() {
+            return Err(NativeError::Internal("decode 3rd param failed".to_string()));
+        }
+        let state_proof_bytes = result.unwrap();
+        trace!("state_proof_bytes = {:?}", state_proof_bytes);
+
+        let state_proof: StateProof = decode_rlp(&state_proof_bytes, "state proof")?;
+        let maybe_val = state_proof.verify(state_root);
+        if maybe_val.is_none() {
+            return Err(NativeError::Internal(
+                "state proof verify failed".to_string(),
+            ));
+        }
+        let val = maybe_val.unwrap();
+        trace!("val = {:?}", val);
+
+        let tokens = vec![
+            ethabi::Token::Address((*state_proof.address()).into()),
+            ethabi::Token::Uint(U256::from(*state_proof.key()).into()),
+            ethabi::Token::Uint(U256::from(val).into()),
+        ];
+        let result = ethabi::encode(&tokens);
+        trace!("encoded {:?}", result);
+
+        self.output = result;
+        Ok(InterpreterResult::Normal(
+            self.output.clone(),
+            gas_left,
+            vec![],
+        ))
     }
 
     fn verify_block_header(
@@ -314,11 +289,7 @@ impl CrossChainVerify {
         trace!("data = {:?}", data);
         let tokens = vec![ethabi::ParamType::Uint(32), ethabi::ParamType::Bytes];
 
-        let result = ethabi::decode(&tokens, &data[4..]);
-        if result.is_err() {
-            return Err(NativeError::Internal("decode failed".to_string()));
-        }
-        let mut decoded = result.unwrap();
+        let mut decoded = decode_params(&tokens, &data)?;
         trace!("decoded = {:?}", decoded);
 
         let result = decoded.remove(0).to_uint();
@@ -333,7 +304,7 @@ impl CrossChainVerify {
         }
         let block_header_curr_bytes = result.unwrap();
         trace!("data = {:?}", block_header_curr_bytes);
-        let block_header_curr = Header::from_bytes(&block_header_curr_bytes);
+        let block_header_curr: Header = decode_rlp(&block_header_curr_bytes, "block header")?;
 
         let block_header_prev_bytes: Vec<u8> =
             self.block_headers
@@ -343,7 +314,7 @@ impl CrossChainVerify {
             trace!("sync first block header");
             block_header_curr.number() == 0
         } else {
-            let block_header_prev = Header::from_bytes(&block_header_prev_bytes);
+            let block_header_prev: Header = decode_rlp(&block_header_prev_bytes, "block header")?;
 
             let ret = ChainManagement::ext_authorities(
                 data_provider,
@@ -408,11 +379,7 @@ impl CrossChainVerify {
         trace!("data = {:?}", data);
         let tokens = vec![ethabi::ParamType::Uint(32)];
 
-        let result = ethabi::decode(&tokens, &data[4..]);
-        if result.is_err() {
-            return Err(NativeError::Internal("decode failed".to_string()));
-        }
-        let mut decoded = result.unwrap();
+        let mut decoded = decode_params(&tokens, &data)?;
         trace!("decoded = {:?}", decoded);
 
         let result = decoded.remove(0).to_uint();
@@ -429,7 +396,7 @@ impl CrossChainVerify {
         let block_number = if block_header_bytes.is_empty() {
             0
         } else {
-            let block_header = Header::from_bytes(&block_header_bytes);
+            let block_header: Header = decode_rlp(&block_header_bytes, "block header")?;
             block_header.number() + 1
         };
         trace!("block_number = {}", block_number);
@@ -446,3 +413,46 @@ impl CrossChainVerify {
         ))
     }
 }
+
+/// Decode the ABI encoded parameters following the 4 bytes function signature.
+fn decode_params(
+    tokens: &[ethabi::ParamType],
+    data: &[u8],
+) -> Result<Vec<ethabi::Token>, NativeError> {
+    let params = data
+        .get(4..)
+        .ok_or_else(|| NativeError::Internal("calldata too short".to_string()))?;
+    ethabi::decode(tokens, params).map_err(|_| NativeError::Internal("decode failed".to_string()))
+}
+
+/// Decode RLP bytes given by the caller, which must not panic on malformed input.
+fn decode_rlp<T: Decodable>(bytes: &[u8], name: &str) -> Result<T, NativeError> {
+    UntrustedRlp::new(bytes)
+        .as_val()
+        .map_err(|err| NativeError::Internal(format!("decode {} failed: {:?}", name, err)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode_params, decode_rlp};
+    use crate::types::state_proof::StateProof;
+    use ethabi::ParamType;
+
+    #[test]
+    fn test_decode_short_calldata() {
+        let tokens = vec![ParamType::Uint(32)];
+        assert!(decode_params(&tokens, &[0x12, 0x34]).is_err());
+        assert!(decode_params(&tokens, &[0x12, 0x34, 0x56, 0x78]).is_err());
+    }
+
+    #[test]
+    fn test_decode_malformed_state_proof() {
+        assert!(decode_rlp::<StateProof>(&[], "state proof").is_err());
+        assert!(decode_rlp::<StateProof>(&[0xf8, 0xff, 0x01], "state proof").is_err());
+        assert!(decode_rlp::<StateProof>(&[0xc1, 0x80], "state proof").is_err());
+
+        let proof = StateProof::default();
+        let bytes = rlp::encode(&proof).into_vec();
+        assert!(decode_rlp::<StateProof>(&bytes, "state proof").is_ok());
+    }
+}
@@ -13,6 +13,7 @@
 // limitations under the License.
 
 use crate::cita_executive::{call as ext_call, create as ext_create, CreateKind};
+use crate::tracer::{CallType, SharedCallTracer};
 use cita_trie::DB;
 use cita_types::{Address, H256, U256};
 use cita_vm::evm;
@@ -66,6 +67,8 @@ pub struct Store {
     pub inused: HashSet<Address>,
     pub evm_context: evm::Context,
     pub evm_cfg: evm::InterpreterConf,
+    // Records the internal calls and creates when the transaction is traced.
+    pub tracer: Option<SharedCallTracer>,
 }
 
 impl Store {
@@ -255,11 +258,18 @@ impl<B: DB + 'static> evm::DataProvider for DataProvider<B> {
         opcode: evm::OpCode,
         params: evm::InterpreterParams,
     ) -> (Result<evm::InterpreterResult, evm::Error>) {
-        match opcode {
+        let tracer = self.store.borrow().tracer.clone();
+        let trace_enter = |params: &evm::InterpreterParams| {
+            if let (Some(tracer), Some(call_type)) = (&tracer, CallType::from_opcode(opcode)) {
+                tracer.borrow_mut().enter(call_type, params);
+            }
+        };
+        let r = match opcode {
             evm::OpCode::CALL
             | evm::OpCode::CALLCODE
             | evm::OpCode::DELEGATECALL
             | evm::OpCode::STATICCALL => {
+                trace_enter(&params);
                 let r = ext_call(
                     self.block_provider.clone(),
                     self.state_provider.clone(),
@@ -267,7 +277,7 @@ impl<B: DB + 'static> evm::DataProvider for DataProvider<B> {
                     &params,
                 );
                 debug!("ext.call.result = {:?}", r);
-                r.or(Err(evm::Error::CallError))
+                r
             }
             evm::OpCode::CREATE | evm::OpCode::CREATE2 => {
                 let mut request = params.clone();
@@ -283,6 +293,7 @@ impl<B: DB + 'static> evm::DataProvider for DataProvider<B> {
                     .inc_nonce(&request.sender)
                     .or(Err(evm::Error::CallError))?;
 
+                trace_enter(&request);
                 let r = match opcode {
                     evm::OpCode::CREATE => ext_create(
                         self.block_provider.clone(),
@@ -299,12 +310,15 @@ impl<B: DB + 'static> evm::DataProvider for DataProvider<B> {
                         CreateKind::FromSaltAndCodeHash,
                     ),
                     _ => unimplemented!(),
-                }
-                .or(Err(evm::Error::CallError));
+                };
                 debug!("ext.create.result = {:?}", r);
                 r
             }
             _ => unimplemented!(),
+        };
+        if let Some(tracer) = tracer {
+            tracer.borrow_mut().exit(&r);
         }
+        r.or(Err(evm::Error::CallError))
     }
 }
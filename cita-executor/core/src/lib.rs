@@ -42,6 +42,7 @@ pub mod contracts;
 pub mod data_provider;
 pub mod libexecutor;
 pub mod storage;
+pub mod tracer;
 pub mod tx_gas_schedule;
 
 mod authentication;
@@ -50,4 +51,4 @@ mod trie_db;
 
 pub use crate::types::*;
 pub use cita_database as cita_db;
-pub use trie_db::TrieDB;
+pub use trie_db::{JournalDBType, Pruning, TrieDB};
@@ -12,8 +12,13 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use super::lru_cache::LRUCache;
+use crate::types::block_number::BlockNumber;
+use cita_types::traits::LowerHex;
 use cita_types::Address;
 use libproto::BlackList as ProtoBlackList;
+use rlp::{Decodable, DecoderError, Encodable, RlpStream, UntrustedRlp};
+use std::collections::BTreeMap;
 
 #[derive(PartialEq, Clone, Debug, Default)]
 pub struct BlackList {
@@ -74,3 +79,214 @@ impl BlackList {
         bl
     }
 }
+
+/// Addresses put on the black list by an admin.
+///
+/// Unlike the automatic entries they are kept in the executor's database,
+/// and are only cleared when removed or when they expire.
+#[derive(PartialEq, Clone, Debug, Default)]
+pub struct ManualBlackList {
+    // address => the height from which it is allowed again
+    entries: BTreeMap<Address, Option<BlockNumber>>,
+}
+
+impl ManualBlackList {
+    pub fn new() -> Self {
+        ManualBlackList {
+            entries: BTreeMap::new(),
+        }
+    }
+
+    pub fn entries(&self) -> &BTreeMap<Address, Option<BlockNumber>> {
+        &self.entries
+    }
+
+    pub fn contains(&self, address: &Address) -> bool {
+        self.entries.contains_key(address)
+    }
+
+    /// Add or replace an entry. It expires at `expires_at` if given.
+    pub fn insert(&mut self, address: Address, expires_at: Option<BlockNumber>) {
+        self.entries.insert(address, expires_at);
+    }
+
+    pub fn remove(&mut self, address: &Address) -> bool {
+        self.entries.remove(address).is_some()
+    }
+
+    pub fn addresses(&self) -> Vec<Address> {
+        self.entries.keys().cloned().collect()
+    }
+
+    /// Remove and return the entries that have expired at `height`.
+    pub fn expire(&mut self, height: BlockNumber) -> Vec<Address> {
+        let expired: Vec<Address> = self
+            .entries
+            .iter()
+            .filter(|(_, expires_at)| expires_at.map(|h| h <= height).unwrap_or(false))
+            .map(|(address, _)| *address)
+            .collect();
+        for address in &expired {
+            self.entries.remove(address);
+        }
+        expired
+    }
+}
+
+impl Encodable for ManualBlackList {
+    fn rlp_append(&self, s: &mut RlpStream) {
+        s.begin_list(self.entries.len());
+        for (address, expires_at) in &self.entries {
+            match expires_at {
+                Some(height) => {
+                    s.begin_list(2);
+                    s.append(address);
+                    s.append(height);
+                }
+                None => {
+                    s.begin_list(1);
+                    s.append(address);
+                }
+            }
+        }
+    }
+}
+
+impl Decodable for ManualBlackList {
+    fn decode(r: &UntrustedRlp) -> Result<Self, DecoderError> {
+        let mut entries = BTreeMap::new();
+        for entry in r.iter() {
+            let expires_at = match entry.item_count()? {
+                1 => None,
+                2 => Some(entry.val_at(1)?),
+                _ => return Err(DecoderError::RlpIncorrectListLen),
+            };
+            entries.insert(entry.val_at(0)?, expires_at);
+        }
+        Ok(ManualBlackList { entries })
+    }
+}
+
+/// Why an address is on the black list.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum BlackListReason {
+    /// Its transaction failed with NotEnoughCash or NotEnoughBaseQuota.
+    NotEnoughBalance,
+    /// An admin added it.
+    Manual,
+}
+
+/// One entry of the answer to getBlackList.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BlackListItem {
+    pub address: String,
+    pub reason: BlackListReason,
+    /// Height of the block that put it on the list, for automatic entries.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub since: Option<BlockNumber>,
+    /// Height from which it is allowed again, for expiring manual entries.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<BlockNumber>,
+}
+
+impl BlackListItem {
+    pub fn auto(address: &Address, since: BlockNumber) -> Self {
+        BlackListItem {
+            address: format!("0x{}", address.lower_hex()),
+            reason: BlackListReason::NotEnoughBalance,
+            since: Some(since),
+            expires_at: None,
+        }
+    }
+
+    pub fn manual(address: &Address, expires_at: Option<BlockNumber>) -> Self {
+        BlackListItem {
+            address: format!("0x{}", address.lower_hex()),
+            reason: BlackListReason::Manual,
+            since: None,
+            expires_at,
+        }
+    }
+}
+
+/// Both automatic and manual entries, as getBlackList answers them.
+pub fn black_list_items(
+    auto: &LRUCache<u64, Address>,
+    manual: &ManualBlackList,
+) -> Vec<BlackListItem> {
+    let mut items: Vec<BlackListItem> = manual
+        .entries()
+        .iter()
+        .map(|(address, expires_at)| BlackListItem::manual(address, *expires_at))
+        .collect();
+    items.extend(
+        auto.iter()
+            .filter(|(address, _)| !manual.contains(address))
+            .map(|(address, since)| BlackListItem::auto(address, *since)),
+    );
+    items
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_manual_black_list_expire() {
+        let a = Address::from(1);
+        let b = Address::from(2);
+        let c = Address::from(3);
+        let mut list = ManualBlackList::new();
+        list.insert(a, Some(10));
+        list.insert(b, Some(20));
+        list.insert(c, None);
+
+        assert!(list.expire(9).is_empty());
+        assert_eq!(list.expire(10), vec![a]);
+        assert_eq!(list.expire(100), vec![b]);
+        assert!(list.contains(&c));
+        assert!(list.remove(&c));
+        assert!(!list.remove(&c));
+    }
+
+    #[test]
+    fn test_manual_black_list_rlp() {
+        let mut list = ManualBlackList::new();
+        list.insert(Address::from(1), Some(10));
+        list.insert(Address::from(2), None);
+
+        let encoded = rlp::encode(&list);
+        assert_eq!(rlp::decode::<ManualBlackList>(&encoded), list);
+        assert_eq!(
+            rlp::decode::<ManualBlackList>(&rlp::encode(&ManualBlackList::new())),
+            ManualBlackList::new()
+        );
+    }
+
+    #[test]
+    fn test_black_list_items() {
+        let (a, b) = (Address::from(1), Address::from(2));
+        let mut auto = LRUCache::new(10);
+        auto.extend(&[a, b], 7);
+        let mut manual = ManualBlackList::new();
+        manual.insert(b, None);
+
+        let items = black_list_items(&auto, &manual);
+        assert_eq!(
+            items,
+            vec![BlackListItem::manual(&b, None), BlackListItem::auto(&a, 7)]
+        );
+        assert_eq!(manual.addresses(), vec![b]);
+    }
+
+    #[test]
+    fn test_black_list_item_json() {
+        let item = BlackListItem::manual(&Address::from(1), Some(10));
+        assert_eq!(
+            serde_json::to_string(&item).unwrap(),
+            r#"{"address":"0x0000000000000000000000000000000000000001","reason":"manual","expiresAt":10}"#
+        );
+    }
+}
@@ -28,6 +28,7 @@ use crate::libexecutor::executor::CitaTrieDB;
 use crate::libexecutor::sys_config::BlockSysConfig;
 use crate::libexecutor::sys_config::GlobalSysConfig;
 use crate::receipt::Receipt;
+use crate::tracer::{CallTrace, CallTracer};
 use crate::tx_gas_schedule::TxGasSchedule;
 pub use crate::types::block::{Block, BlockBody, OpenBlock};
 use crate::types::errors::Error;
@@ -133,12 +134,17 @@ impl ExecutedBlock {
         }
     }
 
-    pub fn apply_transaction(&mut self, t: &SignedTransaction, sys_config: &GlobalSysConfig) {
+    /// Execution env info of transaction `t`.
+    fn transaction_context(
+        &mut self,
+        t: &SignedTransaction,
+        sys_config: &GlobalSysConfig,
+    ) -> Context {
         let mut context = self.get_context();
         context.block_quota_limit = U256::from(sys_config.block_quota_limit);
         trace!("block quota limit is {:?}", context.block_quota_limit);
 
-        let conf = sys_config.block_sys_config.clone();
+        let conf = &sys_config.block_sys_config;
         self.account_gas
             .entry(*t.sender())
             .or_insert(self.account_gas_limit);
@@ -156,6 +162,36 @@ impl ExecutedBlock {
                 context.coin_base = conf.chain_owner;
             }
         }
+        context
+    }
+
+    /// Re-execute transaction `t` on the current state, and return its call tree.
+    pub fn trace_transaction(
+        &mut self,
+        t: &SignedTransaction,
+        sys_config: &GlobalSysConfig,
+    ) -> Result<Option<CallTrace>, ExecutionError> {
+        let context = self.transaction_context(t, sys_config);
+        let conf = sys_config.block_sys_config.clone();
+        let block_data_provider = EVMBlockDataProvider::new(context.clone());
+        let tracer = Arc::new(RefCell::new(CallTracer::default()));
+
+        CitaExecutive::new(
+            Arc::new(block_data_provider),
+            self.state.clone(),
+            &context,
+            conf.economical_model,
+        )
+        .with_tracer(tracer.clone())
+        .exec(t, &conf)?;
+
+        let trace = tracer.borrow_mut().take();
+        Ok(trace)
+    }
+
+    pub fn apply_transaction(&mut self, t: &SignedTransaction, sys_config: &GlobalSysConfig) {
+        let context = self.transaction_context(t, sys_config);
+        let conf = sys_config.block_sys_config.clone();
         let block_data_provider = EVMBlockDataProvider::new(context.clone());
 
         let tx_quota_used = match CitaExecutive::new(
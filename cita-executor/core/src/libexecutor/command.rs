@@ -12,25 +12,28 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use super::blacklist::ManualBlackList;
 use super::economical_model::EconomicalModel;
 use super::executor::CitaTrieDB;
 use super::executor::{make_consensus_config, Executor};
+use super::state_sync;
 use super::sys_config::GlobalSysConfig;
 use crate::cita_executive::{CitaExecutive, ExecutedResult as CitaExecuted};
 use crate::contracts::solc::{
     sys_config::ChainId, PermissionManagement, SysConfig, VersionManager,
 };
+use crate::header::Header;
 use crate::libexecutor::block::EVMBlockDataProvider;
 pub use crate::libexecutor::block::*;
 use crate::libexecutor::call_request::CallRequest;
-use crate::trie_db::TrieDB;
-use crate::types::block_number::{BlockTag, Tag};
+use crate::tracer::CallTrace;
+use crate::types::block_number::{BlockNumber, BlockTag, Tag};
 use crate::types::context::Context;
 use crate::types::errors::CallError;
 use crate::types::errors::ExecutionError;
+use crate::types::state_proof::AccountProof;
 use crate::types::transaction::{Action, SignedTransaction, Transaction};
 pub use byteorder::{BigEndian, ByteOrder};
-use cita_database::RocksDB;
 use cita_types::traits::LowerHex;
 use cita_types::{Address, H256, U256};
 use cita_vm::state::{State as CitaState, StateObjectInfo};
@@ -60,6 +63,8 @@ pub enum Command {
     EstimateQuota(CallRequest, BlockTag),
     SignCall(CallRequest),
     Call(SignedTransaction, BlockTag),
+    TraceTransaction(Vec<SignedTransaction>, BlockTag),
+    AccountProof(Address, Vec<H256>, BlockTag),
     ChainID,
     Metadata(String),
     EconomicalModel,
@@ -67,11 +72,18 @@ pub enum Command {
     Grow(ClosedBlock),
     Exit(BlockTag),
     CloneExecutorReader,
+    ManualBlackList,
+    AddBlackList(Address, Option<BlockNumber>),
+    RemoveBlackList(Address),
+    ExpireBlackList(BlockNumber),
+    TrieNodes(Vec<H256>),
+    StateHeaders,
+    StateSynced(Header),
 }
 
 #[cfg_attr(feature = "cargo-clippy", allow(clippy::large_enum_variant))]
 pub enum CommandResp {
-    StateAt(Option<CitaState<CitaTrieDB>>),
+    StateAt(Result<CitaState<CitaTrieDB>, CallError>),
     GenState(Option<CitaState<CitaTrieDB>>),
     CodeAt(Option<Bytes>),
     ABIAt(Option<Bytes>),
@@ -81,6 +93,8 @@ pub enum CommandResp {
     EstimateQuota(Result<Bytes, String>),
     SignCall(SignedTransaction),
     Call(Result<CitaExecuted, CallError>),
+    TraceTransaction(Result<Option<CallTrace>, CallError>),
+    AccountProof(Result<AccountProof, CallError>),
     ChainID(Option<ChainId>),
     Metadata(Result<MetaData, String>),
     EconomicalModel(EconomicalModel),
@@ -88,6 +102,13 @@ pub enum CommandResp {
     Grow(ExecutedResult),
     Exit,
     CloneExecutorReader(Executor),
+    ManualBlackList(ManualBlackList),
+    AddBlackList(Option<BlockNumber>),
+    RemoveBlackList(bool),
+    ExpireBlackList(Vec<Address>),
+    TrieNodes(Vec<Vec<u8>>),
+    StateHeaders(Vec<Header>),
+    StateSynced,
 }
 
 impl fmt::Display for Command {
@@ -103,6 +124,8 @@ impl fmt::Display for Command {
             Command::EstimateQuota(_, _) => write!(f, "Command::EstimateQuota"),
             Command::SignCall(_) => write!(f, "Command::SignCall"),
             Command::Call(_, _) => write!(f, "Command::Call"),
+            Command::TraceTransaction(_, _) => write!(f, "Command::TraceTransaction"),
+            Command::AccountProof(_, _, _) => write!(f, "Command::AccountProof"),
             Command::ChainID => write!(f, "Command::ChainID "),
             Command::Metadata(_) => write!(f, "Command::Metadata"),
             Command::EconomicalModel => write!(f, "Command::EconomicalModel"),
@@ -110,6 +133,13 @@ impl fmt::Display for Command {
             Command::Grow(_) => write!(f, "Command::Grow"),
             Command::Exit(_) => write!(f, "Command::Exit"),
             Command::CloneExecutorReader => write!(f, "Command::CloneExecutorReader"),
+            Command::ManualBlackList => write!(f, "Command::ManualBlackList"),
+            Command::AddBlackList(_, _) => write!(f, "Command::AddBlackList"),
+            Command::RemoveBlackList(_) => write!(f, "Command::RemoveBlackList"),
+            Command::ExpireBlackList(_) => write!(f, "Command::ExpireBlackList"),
+            Command::TrieNodes(_) => write!(f, "Command::TrieNodes"),
+            Command::StateHeaders => write!(f, "Command::StateHeaders"),
+            Command::StateSynced(_) => write!(f, "Command::StateSynced"),
         }
     }
 }
@@ -127,6 +157,8 @@ impl fmt::Display for CommandResp {
             CommandResp::EstimateQuota(_) => write!(f, "CommandResp::EstimateQuota"),
             CommandResp::SignCall(_) => write!(f, "CommandResp::SignCall"),
             CommandResp::Call(_) => write!(f, "CommandResp::Call"),
+            CommandResp::TraceTransaction(_) => write!(f, "CommandResp::TraceTransaction"),
+            CommandResp::AccountProof(_) => write!(f, "CommandResp::AccountProof"),
             CommandResp::ChainID(_) => write!(f, "CommandResp::ChainID "),
             CommandResp::Metadata(_) => write!(f, "CommandResp::Metadata"),
             CommandResp::EconomicalModel(_) => write!(f, "CommandResp::EconomicalModel"),
@@ -134,13 +166,20 @@ impl fmt::Display for CommandResp {
             CommandResp::Grow(_) => write!(f, "CommandResp::Grow"),
             CommandResp::Exit => write!(f, "CommandResp::Exit"),
             CommandResp::CloneExecutorReader(_) => write!(f, "CommandResp::CloneExecurorReader"),
+            CommandResp::ManualBlackList(_) => write!(f, "CommandResp::ManualBlackList"),
+            CommandResp::AddBlackList(_) => write!(f, "CommandResp::AddBlackList"),
+            CommandResp::RemoveBlackList(_) => write!(f, "CommandResp::RemoveBlackList"),
+            CommandResp::ExpireBlackList(_) => write!(f, "CommandResp::ExpireBlackList"),
+            CommandResp::TrieNodes(_) => write!(f, "CommandResp::TrieNodes"),
+            CommandResp::StateHeaders(_) => write!(f, "CommandResp::StateHeaders"),
+            CommandResp::StateSynced => write!(f, "CommandResp::StateSynced"),
         }
     }
 }
 
 pub trait Commander {
     fn operate(&mut self, command: Command) -> CommandResp;
-    fn state_at(&self, block_tag: BlockTag) -> Option<CitaState<CitaTrieDB>>;
+    fn state_at(&self, block_tag: BlockTag) -> Result<CitaState<CitaTrieDB>, CallError>;
     fn gen_state(&self, root: H256, parent_hash: H256) -> Option<CitaState<CitaTrieDB>>;
     fn code_at(&self, address: &Address, block_tag: BlockTag) -> Option<Bytes>;
     fn abi_at(&self, address: &Address, block_tag: BlockTag) -> Option<Bytes>;
@@ -150,6 +189,17 @@ pub trait Commander {
     fn estimate_quota(&self, request: CallRequest, block_tag: BlockTag) -> Result<Bytes, String>;
     fn sign_call(&self, request: CallRequest) -> SignedTransaction;
     fn call(&self, t: &SignedTransaction, block_tag: BlockTag) -> Result<CitaExecuted, CallError>;
+    fn trace_transaction(
+        &self,
+        transactions: Vec<SignedTransaction>,
+        block_tag: BlockTag,
+    ) -> Result<Option<CallTrace>, CallError>;
+    fn account_proof(
+        &self,
+        address: &Address,
+        keys: &[H256],
+        block_tag: BlockTag,
+    ) -> Result<AccountProof, CallError>;
     fn chain_id(&self) -> Option<ChainId>;
     fn metadata(&self, data: String) -> Result<MetaData, String>;
     fn economical_model(&self) -> EconomicalModel;
@@ -157,6 +207,12 @@ pub trait Commander {
     fn grow(&mut self, closed_block: &ClosedBlock) -> ExecutedResult;
     fn exit(&mut self, rollback_id: BlockTag);
     fn clone_executor_reader(&mut self) -> Self;
+    fn add_black_list(&self, address: Address, ttl: Option<BlockNumber>) -> Option<BlockNumber>;
+    fn remove_black_list(&self, address: &Address) -> bool;
+    fn expire_black_list(&self, height: BlockNumber) -> Vec<Address>;
+    fn trie_nodes(&self, hashes: &[H256]) -> Vec<Vec<u8>>;
+    fn state_headers(&self) -> Vec<Header>;
+    fn state_synced(&mut self, header: Header);
 }
 
 impl Commander for Executor {
@@ -188,6 +244,12 @@ impl Commander for Executor {
             Command::Call(signed_transaction, block_tag) => {
                 CommandResp::Call(self.call(&signed_transaction, block_tag))
             }
+            Command::TraceTransaction(transactions, block_tag) => {
+                CommandResp::TraceTransaction(self.trace_transaction(transactions, block_tag))
+            }
+            Command::AccountProof(address, keys, block_tag) => {
+                CommandResp::AccountProof(self.account_proof(&address, &keys, block_tag))
+            }
             Command::ChainID => CommandResp::ChainID(self.chain_id()),
             Command::Metadata(data) => CommandResp::Metadata(self.metadata(data)),
             Command::EconomicalModel => CommandResp::EconomicalModel(self.economical_model()),
@@ -206,13 +268,33 @@ impl Commander for Executor {
             Command::CloneExecutorReader => {
                 CommandResp::CloneExecutorReader(self.clone_executor_reader())
             }
+            Command::ManualBlackList => CommandResp::ManualBlackList(self.manual_black_list()),
+            Command::AddBlackList(address, ttl) => {
+                CommandResp::AddBlackList(self.add_black_list(address, ttl))
+            }
+            Command::RemoveBlackList(address) => {
+                CommandResp::RemoveBlackList(self.remove_black_list(&address))
+            }
+            Command::ExpireBlackList(height) => {
+                CommandResp::ExpireBlackList(self.expire_black_list(height))
+            }
+            Command::TrieNodes(hashes) => CommandResp::TrieNodes(self.trie_nodes(&hashes)),
+            Command::StateHeaders => CommandResp::StateHeaders(self.state_headers()),
+            Command::StateSynced(header) => {
+                self.state_synced(header);
+                CommandResp::StateSynced
+            }
         }
     }
 
     /// Attempt to get a copy of a specific block's final state.
-    fn state_at(&self, id: BlockTag) -> Option<CitaState<CitaTrieDB>> {
-        self.block_header(id)
-            .and_then(|h| self.gen_state(*h.state_root(), *h.parent_hash()))
+    fn state_at(&self, id: BlockTag) -> Result<CitaState<CitaTrieDB>, CallError> {
+        let header = self.block_header(id).ok_or(CallError::BlockNotFound)?;
+        if self.is_state_pruned(header.number()) {
+            return Err(CallError::StatePruned(self.earliest_state_height()));
+        }
+        self.gen_state(*header.state_root(), *header.parent_hash())
+            .ok_or(CallError::StateCorrupt)
     }
 
     /// Generate block's final state.
@@ -223,17 +305,18 @@ impl Commander for Executor {
 
     /// Get code by address
     fn code_at(&self, address: &Address, id: BlockTag) -> Option<Bytes> {
-        self.state_at(id).and_then(|mut s| s.code(address).ok())
+        self.state_at(id).ok().and_then(|mut s| s.code(address).ok())
     }
 
     /// Get abi by address
     fn abi_at(&self, address: &Address, id: BlockTag) -> Option<Bytes> {
-        self.state_at(id).and_then(|mut s| s.abi(address).ok())
+        self.state_at(id).ok().and_then(|mut s| s.abi(address).ok())
     }
 
     /// Get balance by address
     fn balance_at(&self, address: &Address, id: BlockTag) -> Option<Bytes> {
         self.state_at(id)
+            .ok()
             .and_then(|mut s| s.balance(address).ok())
             .map(|c| {
                 let balance = &mut [0u8; 32];
@@ -243,7 +326,7 @@ impl Commander for Executor {
     }
 
     fn nonce_at(&self, address: &Address, id: BlockTag) -> Option<U256> {
-        self.state_at(id).and_then(|mut s| s.nonce(address).ok())
+        self.state_at(id).ok().and_then(|mut s| s.nonce(address).ok())
     }
 
     fn eth_call(&self, request: CallRequest, id: BlockTag) -> Result<Bytes, String> {
@@ -262,7 +345,7 @@ impl Commander for Executor {
         let signed = self.sign_call(request);
         let header = self
             .block_header(id)
-            .ok_or_else(|| "Estimate Error CallError::StatePruned".to_owned())?;
+            .ok_or_else(|| "Estimate Error CallError::BlockNotFound".to_owned())?;
         let last_hashes = self.build_last_hashes(Some(header.hash().unwrap()), header.number());
 
         let context = Context {
@@ -294,9 +377,9 @@ impl Commander for Executor {
             // The same transaction will get different result in different state.
             // And the estimate action will change the state, so it should take the most primitive
             // state for each estimate.
-            let state = self.state_at(id).ok_or_else(|| {
-                ExecutionError::Internal("Estimate Error CallError::StatePruned".to_owned())
-            })?;
+            let state = self
+                .state_at(id)
+                .map_err(|err| ExecutionError::Internal(format!("Estimate Error {:?}", err)))?;
             let state = Arc::new(RefCell::new(state));
 
             let clone_conf = conf.clone();
@@ -386,7 +469,9 @@ impl Commander for Executor {
     }
 
     fn call(&self, t: &SignedTransaction, block_tag: BlockTag) -> Result<CitaExecuted, CallError> {
-        let header = self.block_header(block_tag).ok_or(CallError::StatePruned)?;
+        let header = self
+            .block_header(block_tag)
+            .ok_or(CallError::BlockNotFound)?;
         let last_hashes = self.build_last_hashes(Some(header.hash().unwrap()), header.number());
         let mut context = Context {
             block_number: header.number(),
@@ -404,33 +489,16 @@ impl Commander for Executor {
         };
         context.block_quota_limit = U256::from(self.sys_config.block_quota_limit);
 
-        // FIXME: Need to implement state_at
-        // that's just a copy of the state.
-        //        let mut state = self.state_at(block_tag).ok_or(CallError::StatePruned)?;
-
         // Never check permission and quota
         let mut conf = self.sys_config.block_sys_config.clone();
         conf.exempt_checking();
 
         let block_data_provider = EVMBlockDataProvider::new(context.clone());
 
-        let state_root = if let Some(h) = self.block_header(block_tag) {
-            (*h.state_root())
-        } else {
-            error!("Can not get state root from trie db!");
-            return Err(CallError::StatePruned);
-        };
-
-        let state = match CitaState::from_existing(
-            Arc::<TrieDB<RocksDB>>::clone(&self.state_db),
-            state_root,
-        ) {
-            Ok(state_db) => state_db,
-            Err(e) => {
-                error!("Can not get state from trie db! error: {:?}", e);
-                return Err(CallError::StatePruned);
-            }
-        };
+        let state = self.state_at(block_tag).map_err(|err| {
+            error!("Can not get state from trie db! error: {:?}", err);
+            err
+        })?;
 
         let state = Arc::new(RefCell::new(state));
         CitaExecutive::new(
@@ -443,6 +511,73 @@ impl Commander for Executor {
         .map_err(Into::into)
     }
 
+    /// Trace the last one of `transactions`, which are the leading transactions of the block
+    /// `block_tag`. The ones before it are replayed on the parent state first.
+    fn trace_transaction(
+        &self,
+        mut transactions: Vec<SignedTransaction>,
+        block_tag: BlockTag,
+    ) -> Result<Option<CallTrace>, CallError> {
+        let traced = transactions.pop().ok_or(CallError::TransactionNotFound)?;
+        let header = self
+            .block_header(block_tag)
+            .ok_or(CallError::BlockNotFound)?;
+        let parent = header
+            .number()
+            .checked_sub(1)
+            .ok_or(CallError::TransactionNotFound)?;
+        let parent_header = self
+            .block_header(BlockTag::Height(parent))
+            .ok_or(CallError::BlockNotFound)?;
+        if self.is_state_pruned(parent) {
+            return Err(CallError::StatePruned(self.earliest_state_height()));
+        }
+
+        // Execute with the system config the block was executed with.
+        let sys_config = GlobalSysConfig::load(&self, BlockTag::Height(parent));
+        let mut open_block = OpenBlock::default();
+        open_block.set_header(header.open_header().clone());
+        let last_hashes = self.build_last_hashes(Some(*header.parent_hash()), parent);
+        let mut block = ExecutedBlock::create(
+            &sys_config.block_sys_config,
+            open_block,
+            Arc::clone(&self.state_db),
+            *parent_header.state_root(),
+            last_hashes.into(),
+            self.eth_compatibility,
+        )
+        .map_err(|_| CallError::StateCorrupt)?;
+
+        for t in &transactions {
+            block.apply_transaction(t, &sys_config);
+        }
+        block
+            .trace_transaction(&traced, &sys_config)
+            .map_err(Into::into)
+    }
+
+    /// Prove an account and the given storage keys of it, as `eth_getProof` does.
+    fn account_proof(
+        &self,
+        address: &Address,
+        keys: &[H256],
+        block_tag: BlockTag,
+    ) -> Result<AccountProof, CallError> {
+        let state = self.state_at(block_tag)?;
+        let account_proof = state
+            .get_account_proof(address)
+            .map_err(|_| CallError::StateCorrupt)?;
+        let mut storage_proofs = Vec::with_capacity(keys.len());
+        for key in keys {
+            let proof = state
+                .get_storage_proof(address, key)
+                .map_err(|_| CallError::StateCorrupt)?;
+            storage_proofs.push((*key, proof));
+        }
+        AccountProof::new(*address, state.root, account_proof, storage_proofs)
+            .ok_or(CallError::StateCorrupt)
+    }
+
     fn chain_id(&self) -> Option<ChainId> {
         let version_manager = VersionManager::new(&self);
         let system_config = SysConfig::new(&self);
@@ -612,6 +747,7 @@ impl Commander for Executor {
         let command_req_receiver = self.command_req_receiver.clone();
         let command_resp_sender = self.command_resp_sender.clone();
         let eth_compatibility = self.eth_compatibility;
+        let pruning = self.pruning;
         Executor {
             current_header: RwLock::new(current_header),
             state_db,
@@ -622,8 +758,59 @@ impl Commander for Executor {
             command_req_receiver,
             command_resp_sender,
             eth_compatibility,
+            pruning,
         }
     }
+
+    /// Black list `address` until `ttl` blocks after the current height, or for good,
+    /// also when that height is out of range. Returns the height from which it is
+    /// allowed again.
+    fn add_black_list(&self, address: Address, ttl: Option<BlockNumber>) -> Option<BlockNumber> {
+        let expires_at = ttl.and_then(|ttl| self.get_current_height().checked_add(ttl));
+        let mut list = self.manual_black_list();
+        list.insert(address, expires_at);
+        self.write_manual_black_list(&list);
+        expires_at
+    }
+
+    fn remove_black_list(&self, address: &Address) -> bool {
+        let mut list = self.manual_black_list();
+        let removed = list.remove(address);
+        if removed {
+            self.write_manual_black_list(&list);
+        }
+        removed
+    }
+
+    fn expire_black_list(&self, height: BlockNumber) -> Vec<Address> {
+        let mut list = self.manual_black_list();
+        let expired = list.expire(height);
+        if !expired.is_empty() {
+            self.write_manual_black_list(&list);
+        }
+        expired
+    }
+
+    /// Serve the state trie nodes requested by a syncing peer.
+    fn trie_nodes(&self, hashes: &[H256]) -> Vec<Vec<u8>> {
+        state_sync::trie_nodes(&*self.state_db, hashes)
+    }
+
+    /// The header of the block two before the current one, whose state other nodes
+    /// may sync, and the headers of the two blocks after it.
+    fn state_headers(&self) -> Vec<Header> {
+        let height = self.get_current_height();
+        if height < 2 || self.is_state_pruned(height - 2) {
+            return Vec::new();
+        }
+        (height - 2..=height)
+            .filter_map(|height| self.block_header_by_height(height))
+            .collect()
+    }
+
+    fn state_synced(&mut self, header: Header) {
+        self.set_synced_header(header);
+    }
 }
 
 // TODO hope someone refactor these public function via macro
@@ -632,7 +819,7 @@ pub fn state_at(
     command_req_sender: &Sender<Command>,
     command_resp_receiver: &Receiver<CommandResp>,
     block_tag: BlockTag,
-) -> Option<CitaState<CitaTrieDB>> {
+) -> Result<CitaState<CitaTrieDB>, CallError> {
     let _ = command_req_sender.send(Command::StateAt(block_tag));
     match command_resp_receiver.recv().unwrap() {
         CommandResp::StateAt(r) => r,
@@ -757,6 +944,33 @@ pub fn call(
     }
 }
 
+pub fn trace_transaction(
+    command_req_sender: &Sender<Command>,
+    command_resp_receiver: &Receiver<CommandResp>,
+    transactions: Vec<SignedTransaction>,
+    block_tag: BlockTag,
+) -> Result<Option<CallTrace>, CallError> {
+    let _ = command_req_sender.send(Command::TraceTransaction(transactions, block_tag));
+    match command_resp_receiver.recv().unwrap() {
+        CommandResp::TraceTransaction(r) => r,
+        _ => unimplemented!(),
+    }
+}
+
+pub fn account_proof(
+    command_req_sender: &Sender<Command>,
+    command_resp_receiver: &Receiver<CommandResp>,
+    address: Address,
+    keys: Vec<H256>,
+    block_tag: BlockTag,
+) -> Result<AccountProof, CallError> {
+    let _ = command_req_sender.send(Command::AccountProof(address, keys, block_tag));
+    match command_resp_receiver.recv().unwrap() {
+        CommandResp::AccountProof(r) => r,
+        _ => unimplemented!(),
+    }
+}
+
 pub fn chain_id(
     command_req_sender: &Sender<Command>,
     command_resp_receiver: &Receiver<CommandResp>,
@@ -837,3 +1051,86 @@ pub fn clone_executor_reader(
         _ => unimplemented!(),
     }
 }
+
+pub fn manual_black_list(
+    command_req_sender: &Sender<Command>,
+    command_resp_receiver: &Receiver<CommandResp>,
+) -> ManualBlackList {
+    let _ = command_req_sender.send(Command::ManualBlackList);
+    match command_resp_receiver.recv().unwrap() {
+        CommandResp::ManualBlackList(r) => r,
+        _ => unimplemented!(),
+    }
+}
+
+pub fn add_black_list(
+    command_req_sender: &Sender<Command>,
+    command_resp_receiver: &Receiver<CommandResp>,
+    address: Address,
+    ttl: Option<BlockNumber>,
+) -> Option<BlockNumber> {
+    let _ = command_req_sender.send(Command::AddBlackList(address, ttl));
+    match command_resp_receiver.recv().unwrap() {
+        CommandResp::AddBlackList(r) => r,
+        _ => unimplemented!(),
+    }
+}
+
+pub fn remove_black_list(
+    command_req_sender: &Sender<Command>,
+    command_resp_receiver: &Receiver<CommandResp>,
+    address: Address,
+) -> bool {
+    let _ = command_req_sender.send(Command::RemoveBlackList(address));
+    match command_resp_receiver.recv().unwrap() {
+        CommandResp::RemoveBlackList(r) => r,
+        _ => unimplemented!(),
+    }
+}
+
+pub fn expire_black_list(
+    command_req_sender: &Sender<Command>,
+    command_resp_receiver: &Receiver<CommandResp>,
+    height: BlockNumber,
+) -> Vec<Address> {
+    let _ = command_req_sender.send(Command::ExpireBlackList(height));
+    match command_resp_receiver.recv().unwrap() {
+        CommandResp::ExpireBlackList(r) => r,
+        _ => unimplemented!(),
+    }
+}
+
+pub fn trie_nodes(
+    command_req_sender: &Sender<Command>,
+    command_resp_receiver: &Receiver<CommandResp>,
+    hashes: Vec<H256>,
+) -> Vec<Vec<u8>> {
+    let _ = command_req_sender.send(Command::TrieNodes(hashes));
+    match command_resp_receiver.recv().unwrap() {
+        CommandResp::TrieNodes(r) => r,
+        _ => unimplemented!(),
+    }
+}
+
+pub fn state_headers(
+    command_req_sender: &Sender<Command>,
+    command_resp_receiver: &Receiver<CommandResp>,
+) -> Vec<Header> {
+    let _ = command_req_sender.send(Command::StateHeaders);
+    match command_resp_receiver.recv().unwrap() {
+        CommandResp::StateHeaders(r) => r,
+        _ => unimplemented!(),
+    }
+}
+
+pub fn state_synced(
+    command_req_sender: &Sender<Command>,
+    command_resp_receiver: &Receiver<CommandResp>,
+    header: Header,
+) {
+    let _ = command_req_sender.send(Command::StateSynced(header));
+    match command_resp_receiver.recv().unwrap() {
+        CommandResp::StateSynced => {}
+        _ => unimplemented!(),
+    }
+}
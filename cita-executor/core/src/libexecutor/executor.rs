@@ -12,6 +12,7 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use super::blacklist::ManualBlackList;
 use super::command::{Command, CommandResp, Commander};
 use super::fsm::FSM;
 use super::sys_config::GlobalSysConfig;
@@ -21,7 +22,7 @@ use crate::core::context::LastHashes;
 use crate::header::*;
 pub use crate::libexecutor::block::*;
 use crate::libexecutor::genesis::Genesis;
-use crate::trie_db::TrieDB;
+use crate::trie_db::{Pruning, TrieDB};
 use crate::types::block_number::{BlockTag, Tag};
 use crate::types::db_indexes;
 use crate::types::db_indexes::DBIndex;
@@ -50,6 +51,7 @@ pub struct Executor {
     pub command_resp_sender: Sender<CommandResp>,
 
     pub eth_compatibility: bool,
+    pub pruning: Pruning,
 }
 
 impl Executor {
@@ -62,6 +64,7 @@ impl Executor {
         command_req_receiver: Receiver<Command>,
         command_resp_sender: Sender<CommandResp>,
         eth_compatibility: bool,
+        pruning: Pruning,
     ) -> Executor {
         let mut genesis = Genesis::init(&genesis_path);
 
@@ -93,9 +96,11 @@ impl Executor {
             command_req_receiver,
             command_resp_sender,
             eth_compatibility,
+            pruning,
         };
 
         executor.sys_config = GlobalSysConfig::load(&executor, BlockTag::Tag(Tag::Pending));
+        executor.track_state();
         info!(
             "executor init, current_height: {}, current_hash: {:?}",
             executor.get_current_height(),
@@ -194,7 +199,7 @@ impl Executor {
     /// Write data to db
     /// 1. Header
     /// 2. CurrentHash
-    /// 3. State
+    /// 3. State journal
     pub fn write_batch(&self, block: &ClosedBlock) {
         let height = block.number();
         let hash = block.hash().unwrap();
@@ -232,6 +237,102 @@ impl Executor {
         self.db
             .insert(Some(DataCategory::Extra), height_key.to_vec(), hash_value)
             .expect("Insert block hash error.");
+
+        // Journal the state nodes touched by this block, and prune what falls out of the window.
+        let journal = block.state.db.take_journal();
+        self.state_db
+            .commit_journal(height, journal, self.pruning)
+            .expect("Commit state journal error.");
+    }
+
+    /// Continue from the header whose state is synced from other nodes, the blocks
+    /// before it are left out.
+    pub fn set_synced_header(&mut self, header: Header) {
+        let height = header.number();
+        let hash = header.hash().unwrap();
+        info!(
+            "executor continues from synced block {}, hash {:?}",
+            height, hash
+        );
+
+        let hash_key = db_indexes::Hash2Header(hash).get_index();
+        let height_key = db_indexes::BlockNumber2Hash(height).get_index();
+        let current_hash_key = db_indexes::CurrentHash.get_index();
+        let hash_value = encode(&hash).to_vec();
+        self.db
+            .insert(Some(DataCategory::Headers), hash_key.to_vec(), header.rlp())
+            .expect("Insert block header error.");
+        self.db
+            .insert(
+                Some(DataCategory::Extra),
+                height_key.to_vec(),
+                hash_value.clone(),
+            )
+            .expect("Insert block hash error.");
+        self.db
+            .insert(
+                Some(DataCategory::Extra),
+                current_hash_key.to_vec(),
+                hash_value,
+            )
+            .expect("Insert block hash error.");
+
+        if let Pruning::KeepLatest(_) = self.pruning {
+            self.state_db
+                .track_state(*header.state_root())
+                .expect("Track synced state error.");
+        }
+        self.current_header = RwLock::new(header);
+        self.sys_config = GlobalSysConfig::load(self, BlockTag::Tag(Tag::Pending));
+    }
+
+    /// Count the references of the current state when pruning starts from it, e.g. the
+    /// genesis. Archive mode stops counting, so pruning again counts from scratch.
+    pub fn track_state(&self) {
+        let tracked = self
+            .state_db
+            .tracked_state_root()
+            .expect("Get tracked state root error.");
+        match self.pruning {
+            Pruning::KeepLatest(_) if tracked.is_none() => {
+                let root = self.current_state_root();
+                info!("count the references of state {:?} for pruning", root);
+                self.state_db.track_state(root).expect("Track state error.");
+            }
+            Pruning::Archive if tracked.is_some() => {
+                self.state_db.untrack_state().expect("Untrack state error.")
+            }
+            _ => {}
+        }
+    }
+
+    /// Height of the earliest block whose state is kept.
+    pub fn earliest_state_height(&self) -> BlockNumber {
+        self.state_db
+            .earliest_state_height()
+            .expect("Get earliest state height error.")
+    }
+
+    /// Whether the state of the block at `height` has been pruned.
+    pub fn is_state_pruned(&self, height: BlockNumber) -> bool {
+        height < self.earliest_state_height()
+    }
+
+    /// Admin-managed black list stored in db
+    pub fn manual_black_list(&self) -> ManualBlackList {
+        let key = db_indexes::ManualBlackList.get_index();
+        self.db
+            .get(Some(DataCategory::Extra), &key)
+            .expect("Get manual black list error.")
+            .map(|list| decode(list.as_slice()))
+            .unwrap_or_default()
+    }
+
+    pub fn write_manual_black_list(&self, list: &ManualBlackList) {
+        let key = db_indexes::ManualBlackList.get_index();
+        self.db
+            .insert(Some(DataCategory::Extra), key, encode(list).to_vec())
+            .expect("Insert manual black list error.");
     }
 
     /// Get block hash by number
@@ -373,10 +474,17 @@ impl Executor {
         let last_hashes = self.build_last_hashes(None, open_block.number() - 1);
         // let parent_hash = *open_block.parent_hash();
 
+        // Each execution records its own journal, only the one of the block finally
+        // committed by `write_batch` is kept.
+        let state_db = match self.pruning {
+            Pruning::Archive => self.state_db.clone(),
+            Pruning::KeepLatest(_) => Arc::new(self.state_db.journaled()),
+        };
+
         ExecutedBlock::create(
             &self.sys_config.block_sys_config,
             open_block,
-            self.state_db.clone(),
+            state_db,
             current_state_root,
             last_hashes.into(),
             self.eth_compatibility,
@@ -444,9 +552,12 @@ mod tests {
     use crate::libexecutor::command::{Command, CommandResp};
     use crate::libexecutor::fsm::FSM;
     use crate::tests::helpers;
+    use crate::tracer::CallType;
+    use crate::trie_db::Pruning;
     use crate::types::block_number::{BlockTag, Tag};
+    use crate::types::errors::CallError;
     use cita_crypto::{CreateKey, KeyPair};
-    use cita_types::Address;
+    use cita_types::{Address, H256};
     use std::thread;
     use std::time::Duration;
 
@@ -517,6 +628,151 @@ mod tests {
         assert_eq!(executor.get_current_height(), 0);
     }
 
+    #[test]
+    fn test_state_pruned_out_of_window() {
+        let keypair = KeyPair::gen_keypair();
+        let privkey = keypair.privkey();
+        let mut executor = helpers::init_executor();
+        executor.pruning = Pruning::KeepLatest(2);
+        executor.track_state();
+        let genesis_root = *executor.genesis_header().state_root();
+        assert_eq!(
+            executor.state_db.tracked_state_root().unwrap(),
+            Some(genesis_root)
+        );
+
+        let data = helpers::generate_contract();
+        for _i in 0..5 {
+            let block = helpers::create_block(&executor, Address::from(0), &data, (0, 1), &privkey);
+            let mut closed_block = executor.into_fsm(block.clone());
+            executor.grow(&closed_block);
+            closed_block.clear_cache();
+        }
+
+        assert!(executor.state_at(BlockTag::Height(5)).is_ok());
+        assert!(executor.state_at(BlockTag::Height(4)).is_ok());
+        match executor.state_at(BlockTag::Height(3)) {
+            Err(CallError::StatePruned(4)) => {}
+            _ => panic!("state of height 3 should be pruned"),
+        }
+        match executor.state_at(BlockTag::Height(6)) {
+            Err(CallError::BlockNotFound) => {}
+            _ => panic!("block 6 should not be found"),
+        }
+
+        executor.pruning = Pruning::Archive;
+        executor.track_state();
+        assert_eq!(executor.state_db.tracked_state_root().unwrap(), None);
+    }
+
+    #[test]
+    fn test_state_at_hash() {
+        let keypair = KeyPair::gen_keypair();
+        let privkey = keypair.privkey();
+        let mut executor = helpers::init_executor();
+
+        let data = helpers::generate_contract();
+        for _i in 0..3 {
+            let block = helpers::create_block(&executor, Address::from(0), &data, (0, 1), &privkey);
+            let mut closed_block = executor.into_fsm(block.clone());
+            executor.grow(&closed_block);
+            closed_block.clear_cache();
+        }
+
+        let header = executor.block_header_by_height(2).unwrap();
+        let hash = header.hash().unwrap();
+        assert_eq!(
+            executor.state_at(BlockTag::Hash(hash)).unwrap().root,
+            *header.state_root()
+        );
+        assert!(executor.state_at(BlockTag::Hash(H256::from(1))).is_err());
+    }
+
+    #[test]
+    fn test_account_proof() {
+        let executor = helpers::init_executor();
+        let address = Address::from(0x1234);
+        let keys = vec![H256::from(0), H256::from(1)];
+
+        let proof = executor
+            .account_proof(&address, &keys, BlockTag::Tag(Tag::Pending))
+            .unwrap();
+        assert_eq!(proof.address, address);
+        assert!(!proof.account_proof.is_empty());
+        assert_eq!(proof.storage_proof.len(), 2);
+        assert!(executor
+            .account_proof(&address, &keys, BlockTag::Hash(H256::from(1)))
+            .is_err());
+    }
+
+    #[test]
+    fn test_trace_transaction() {
+        let keypair = KeyPair::gen_keypair();
+        let privkey = keypair.privkey();
+        let mut executor = helpers::init_executor();
+
+        let data = helpers::generate_contract();
+        let block = helpers::create_block(&executor, Address::from(0), &data, (0, 2), &privkey);
+        let mut closed_block = executor.into_fsm(block.clone());
+        executor.grow(&closed_block);
+        closed_block.clear_cache();
+
+        let transactions = block.body().transactions().to_vec();
+        let trace = executor
+            .trace_transaction(transactions, BlockTag::Height(1))
+            .unwrap()
+            .unwrap();
+        assert_eq!(trace.call_type, CallType::Create);
+        assert_eq!(trace.from, *keypair.address());
+        assert!(trace.to.is_some());
+        assert!(trace.gas_used > 0);
+        assert_eq!(trace.error, None);
+
+        match executor.trace_transaction(vec![], BlockTag::Height(1)) {
+            Err(CallError::TransactionNotFound) => {}
+            _ => panic!("nothing to trace"),
+        }
+    }
+
+    #[test]
+    fn test_manual_black_list() {
+        let mut executor = helpers::init_executor();
+        let height = executor.get_current_height();
+        let (a, b) = (Address::from(1), Address::from(2));
+
+        assert_eq!(executor.add_black_list(a, Some(10)), Some(height + 10));
+        assert_eq!(executor.add_black_list(b, None), None);
+        assert!(executor.remove_black_list(&b));
+        assert!(!executor.remove_black_list(&b));
+
+        // kept in db, so a new reader sees it as well
+        let reader = executor.clone_executor_reader();
+        let list = reader.manual_black_list();
+        assert_eq!(list.entries().len(), 1);
+        assert_eq!(list.entries().get(&a), Some(&Some(height + 10)));
+
+        assert!(executor.expire_black_list(height + 9).is_empty());
+        assert_eq!(executor.expire_black_list(height + 10), vec![a]);
+        assert!(reader.manual_black_list().entries().is_empty());
+    }
+
+    #[test]
+    fn test_manual_black_list_out_of_range() {
+        let keypair = KeyPair::gen_keypair();
+        let privkey = keypair.privkey();
+        let mut executor = helpers::init_executor();
+        let data = helpers::generate_contract();
+        let block = helpers::create_block(&executor, Address::from(0), &data, (0, 1), &privkey);
+        let mut closed_block = executor.into_fsm(block.clone());
+        executor.grow(&closed_block);
+        closed_block.clear_cache();
+
+        // the end of an overflowing ttl is never reached
+        let a = Address::from(1);
+        assert_eq!(executor.add_black_list(a, Some(u64::max_value())), None);
+        assert_eq!(executor.manual_black_list().entries().get(&a), Some(&None));
+    }
+
     #[test]
     fn test_closed_block_grow() {
         let keypair = KeyPair::gen_keypair();
@@ -13,7 +13,7 @@
 // limitations under the License.
 
 use std::borrow::ToOwned;
-use std::collections::{btree_map::Keys, btree_map::Values, BTreeMap, HashSet};
+use std::collections::{btree_map::Iter, btree_map::Keys, btree_map::Values, BTreeMap, HashSet};
 
 /// This structure is used to perform lru based on block height
 /// supports sequential lru and precise deletion
@@ -121,6 +121,11 @@ where
     pub fn keys(&self) -> Values<V, K> {
         self.cache_by_value.values()
     }
+
+    /// Gets an iterator over the value-key pairs of the map, in order by value.
+    pub fn iter(&self) -> Iter<V, K> {
+        self.cache_by_value.iter()
+    }
 }
 
 #[cfg(test)]
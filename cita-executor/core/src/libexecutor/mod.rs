@@ -22,6 +22,7 @@ pub mod executor;
 pub mod fsm;
 pub mod genesis;
 pub mod lru_cache;
+pub mod state_sync;
 pub mod sys_config;
 
 pub use self::genesis::Genesis;
@@ -0,0 +1,459 @@
+// Copyright Rivtower Technologies LLC.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Download the state trie of a block from peers, node by node.
+//!
+//! The syncing node starts from the `state_root` of a block with a valid proof,
+//! requests the nodes it does not have yet in chunks, and verifies every node it
+//! receives against the hash it asked for. The children of a verified node are
+//! requested next: branch and extension nodes lead to more trie nodes, and the
+//! accounts found in the leaves of the state trie lead to their storage trie,
+//! code and abi. Once nothing is left to request, the whole state is in the
+//! database and the node can continue with normal block sync.
+//!
+//! The nodes are written without reference counts, a pruning node counts the
+//! references of the synced state once it is complete, see [`references`].
+//!
+//! [`references`]: ./fn.references.html
+
+use crate::header::Header;
+use cita_types::{clean_0x, Address, H256};
+use hashable::HASH_NULL_RLP;
+use hasher::Hasher;
+use rlp::{DecoderError, UntrustedRlp};
+use rustc_hex::{FromHex, ToHex};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt;
+use std::sync::Arc;
+
+/// The most nodes served in one response.
+pub const MAX_NODES_PER_CHUNK: usize = 384;
+
+/// Get the nodes of `hashes` from `db` to answer a peer, the unknown ones are skipped.
+pub fn trie_nodes<DB: cita_trie::DB>(db: &DB, hashes: &[H256]) -> Vec<Vec<u8>> {
+    hashes
+        .iter()
+        .take(MAX_NODES_PER_CHUNK)
+        .filter_map(|hash| db.get(&hash.0).ok().and_then(|node| node))
+        .collect()
+}
+
+/// How many times the nodes of the state at `root` are referred to in it. The nodes
+/// below a node referred to twice are counted twice, as inserting the state does.
+pub fn references<DB: cita_trie::DB>(db: &DB, root: H256) -> Result<HashMap<H256, u32>, DB::Error> {
+    let mut references = HashMap::new();
+    let mut children_of = HashMap::new();
+    let mut stack = vec![(root, NodeKind::Account)];
+    while let Some((hash, kind)) = stack.pop() {
+        if hash == HASH_NULL_RLP || hash == keccak(&[]) {
+            continue;
+        }
+        let count = references.entry(hash).or_insert(0u32);
+        *count = count.saturating_add(1);
+        if kind == NodeKind::Code {
+            continue;
+        }
+        if !children_of.contains_key(&hash) {
+            let node_children = match db.get(&hash.0)? {
+                Some(node) => children(&node, kind).unwrap_or_default(),
+                None => Vec::new(),
+            };
+            children_of.insert(hash, node_children);
+        }
+        stack.extend(children_of[&hash].iter().cloned());
+    }
+    Ok(references)
+}
+
+/// The header to sync the state at, if `headers` are it and the two headers after it.
+///
+/// A proof signs the proposal of a block, which leaves out the state root. The hash of
+/// the block covers it, and is signed in the proposal of the next block, so the proofs
+/// in both of the headers after it are checked.
+pub fn verify_headers(headers: &[Header], authorities: &[Address]) -> Option<Header> {
+    match headers {
+        [header, next, last]
+            if header.verify_next(next, authorities) && next.verify_next(last, authorities) =>
+        {
+            Some(header.clone())
+        }
+        _ => None,
+    }
+}
+
+/// Bytes in the state sync messages are `0x` prefixed hex.
+pub fn to_hex(data: &[u8]) -> String {
+    format!("0x{}", data.to_hex())
+}
+
+pub fn from_hex(data: &str) -> Option<Vec<u8>> {
+    clean_0x(data).from_hex().ok()
+}
+
+#[derive(Debug)]
+pub enum Error {
+    /// A node which is not requested, or does not match any requested hash.
+    Unrequested(H256),
+    /// A node which is not a valid trie node or account.
+    Malformed(H256),
+    Database(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Unrequested(hash) => write!(f, "unrequested node {:?}", hash),
+            Error::Malformed(hash) => write!(f, "malformed node {:?}", hash),
+            Error::Database(err) => write!(f, "database error: {}", err),
+        }
+    }
+}
+
+/// What a requested hash refers to.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum NodeKind {
+    /// A node of the state trie, whose leaves are accounts.
+    Account,
+    /// A node of the storage trie of an account.
+    Storage,
+    /// The code or abi of an account.
+    Code,
+}
+
+pub struct StateSync<DB: cita_trie::DB> {
+    db: Arc<DB>,
+    root: H256,
+    pending: VecDeque<(H256, NodeKind)>,
+    requested: HashMap<H256, NodeKind>,
+    scheduled: HashSet<H256>,
+    received: usize,
+}
+
+impl<DB: cita_trie::DB> StateSync<DB> {
+    /// Sync the state at `root` into `db`.
+    ///
+    /// The nodes already in `db` are not requested again. The nodes below them are
+    /// still checked, since a sync interrupted before may have left them out.
+    pub fn new(db: Arc<DB>, root: H256) -> Self {
+        let mut sync = StateSync {
+            db,
+            root,
+            pending: VecDeque::new(),
+            requested: HashMap::new(),
+            scheduled: HashSet::new(),
+            received: 0,
+        };
+        sync.schedule(root, NodeKind::Account);
+        sync
+    }
+
+    pub fn root(&self) -> H256 {
+        self.root
+    }
+
+    /// How many nodes are verified and written so far.
+    pub fn received(&self) -> usize {
+        self.received
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.pending.is_empty() && self.requested.is_empty()
+    }
+
+    /// Take at most `max` hashes to request from a peer.
+    pub fn next_request(&mut self, max: usize) -> Vec<H256> {
+        let len = max.min(self.pending.len());
+        self.pending
+            .drain(..len)
+            .map(|(hash, kind)| {
+                self.requested.insert(hash, kind);
+                hash
+            })
+            .collect()
+    }
+
+    /// Request the given hashes again, e.g. when the peer did not answer them.
+    pub fn retry(&mut self, hashes: &[H256]) {
+        for hash in hashes {
+            if let Some(kind) = self.requested.remove(hash) {
+                self.pending.push_back((*hash, kind));
+            }
+        }
+    }
+
+    /// Verify and save the nodes answered by a peer, then schedule their children.
+    ///
+    /// The nodes before the first bad one are kept, the caller should drop the
+    /// peer and retry the rest with another one.
+    pub fn feed(&mut self, nodes: Vec<Vec<u8>>) -> Result<usize, Error> {
+        let mut accepted = 0;
+        let mut result = Ok(());
+        for node in nodes {
+            let hash = keccak(&node);
+            let kind = match self.requested.get(&hash) {
+                Some(kind) => *kind,
+                None => {
+                    result = Err(Error::Unrequested(hash));
+                    break;
+                }
+            };
+            let children = match kind {
+                NodeKind::Code => Vec::new(),
+                _ => match children(&node, kind) {
+                    Ok(children) => children,
+                    Err(_) => {
+                        result = Err(Error::Malformed(hash));
+                        break;
+                    }
+                },
+            };
+            self.requested.remove(&hash);
+            self.db
+                .insert(hash.0.to_vec(), node)
+                .map_err(|err| Error::Database(format!("{:?}", err)))?;
+            for (child, kind) in children {
+                self.schedule(child, kind);
+            }
+            accepted += 1;
+        }
+        self.db
+            .flush()
+            .map_err(|err| Error::Database(format!("{:?}", err)))?;
+        self.received += accepted;
+        result.map(|_| accepted)
+    }
+
+    fn schedule(&mut self, hash: H256, kind: NodeKind) {
+        let mut stack = vec![(hash, kind)];
+        while let Some((hash, kind)) = stack.pop() {
+            if hash == HASH_NULL_RLP || hash == keccak(&[]) || !self.scheduled.insert(hash) {
+                continue;
+            }
+            match self.db.get(&hash.0).ok().and_then(|node| node) {
+                Some(node) => {
+                    if kind != NodeKind::Code {
+                        stack.extend(children(&node, kind).unwrap_or_default());
+                    }
+                }
+                None => self.pending.push_back((hash, kind)),
+            }
+        }
+    }
+}
+
+fn keccak(data: &[u8]) -> H256 {
+    H256::from(&hasher::HasherKeccak::new().digest(data)[..])
+}
+
+/// The hashes referred to by a trie node, inline nodes are walked through.
+fn children(node: &[u8], kind: NodeKind) -> Result<Vec<(H256, NodeKind)>, DecoderError> {
+    let mut children = Vec::new();
+    walk(&UntrustedRlp::new(node), kind, &mut children)?;
+    Ok(children)
+}
+
+fn walk(
+    rlp: &UntrustedRlp,
+    kind: NodeKind,
+    children: &mut Vec<(H256, NodeKind)>,
+) -> Result<(), DecoderError> {
+    match rlp.item_count()? {
+        // Empty node
+        0 => {}
+        // Leaf or extension node, the flag nibble of the path tells them apart.
+        2 => {
+            let path = rlp.at(0)?.data()?;
+            let is_leaf = path.first().map(|flag| flag & 0x20 != 0).unwrap_or(false);
+            if is_leaf {
+                if kind == NodeKind::Account {
+                    account(&rlp.at(1)?, children)?;
+                }
+            } else {
+                child(&rlp.at(1)?, kind, children)?;
+            }
+        }
+        // Branch node
+        17 => {
+            for i in 0..16 {
+                child(&rlp.at(i)?, kind, children)?;
+            }
+            let value = rlp.at(16)?;
+            if kind == NodeKind::Account && !value.is_empty() {
+                account(&value, children)?;
+            }
+        }
+        _ => return Err(DecoderError::RlpIncorrectListLen),
+    }
+    Ok(())
+}
+
+fn child(
+    rlp: &UntrustedRlp,
+    kind: NodeKind,
+    children: &mut Vec<(H256, NodeKind)>,
+) -> Result<(), DecoderError> {
+    if rlp.is_list() {
+        walk(rlp, kind, children)
+    } else if rlp.is_empty() {
+        Ok(())
+    } else {
+        children.push((rlp.as_val()?, kind));
+        Ok(())
+    }
+}
+
+/// An account is `[nonce, balance, storage_root, code_hash, abi_hash]`.
+fn account(value: &UntrustedRlp, children: &mut Vec<(H256, NodeKind)>) -> Result<(), DecoderError> {
+    let account = UntrustedRlp::new(value.data()?);
+    if account.item_count()? < 4 {
+        return Err(DecoderError::RlpIncorrectListLen);
+    }
+    children.push((account.val_at(2)?, NodeKind::Storage));
+    children.push((account.val_at(3)?, NodeKind::Code));
+    if account.item_count()? > 4 {
+        children.push((account.val_at(4)?, NodeKind::Code));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{keccak, references, trie_nodes, Error, StateSync};
+    use cita_types::{Address, H256, U256};
+    use cita_vm::state::{MemoryDB, State, StateObjectInfo};
+    use std::sync::Arc;
+
+    fn source_state() -> (Arc<MemoryDB>, H256) {
+        let db = Arc::new(MemoryDB::new(false));
+        let mut state = State::new(Arc::clone(&db)).unwrap();
+        for i in 1..64u64 {
+            let address = Address::from(i);
+            state.new_contract(&address, U256::from(i), U256::from(0), vec![]);
+            if i % 8 == 0 {
+                state.set_code(&address, vec![i as u8; 64]).unwrap();
+                for key in 0..16u64 {
+                    state
+                        .set_storage(&address, H256::from(key), H256::from(i))
+                        .unwrap();
+                }
+            }
+        }
+        state.commit().unwrap();
+        (db, state.root)
+    }
+
+    #[test]
+    fn test_state_sync() {
+        let (source, root) = source_state();
+        let target = Arc::new(MemoryDB::new(false));
+        let mut sync = StateSync::new(Arc::clone(&target), root);
+
+        while !sync.is_done() {
+            let hashes = sync.next_request(16);
+            let nodes = trie_nodes(&*source, &hashes);
+            assert_eq!(sync.feed(nodes).unwrap(), hashes.len());
+        }
+
+        let mut state = State::from_existing(target, root).unwrap();
+        for i in 1..64u64 {
+            let address = Address::from(i);
+            assert_eq!(state.balance(&address).unwrap(), U256::from(i));
+            if i % 8 == 0 {
+                assert_eq!(state.code(&address).unwrap(), vec![i as u8; 64]);
+                assert_eq!(
+                    state.get_storage(&address, &H256::from(15)).unwrap(),
+                    H256::from(i)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_state_sync_resume() {
+        let (source, root) = source_state();
+        let target = Arc::new(MemoryDB::new(false));
+
+        // The root and some nodes below it are saved, but not all of their children.
+        let mut sync = StateSync::new(Arc::clone(&target), root);
+        for _ in 0..3 {
+            let hashes = sync.next_request(4);
+            let nodes = trie_nodes(&*source, &hashes);
+            sync.feed(nodes).unwrap();
+        }
+        assert!(!sync.is_done());
+
+        let mut sync = StateSync::new(Arc::clone(&target), root);
+        assert!(!sync.is_done());
+        while !sync.is_done() {
+            let hashes = sync.next_request(16);
+            let nodes = trie_nodes(&*source, &hashes);
+            assert_eq!(sync.feed(nodes).unwrap(), hashes.len());
+        }
+
+        let mut state = State::from_existing(target, root).unwrap();
+        for i in 1..64u64 {
+            let address = Address::from(i);
+            assert_eq!(state.balance(&address).unwrap(), U256::from(i));
+            if i % 8 == 0 {
+                assert_eq!(
+                    state.get_storage(&address, &H256::from(3)).unwrap(),
+                    H256::from(i)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_references() {
+        let db = Arc::new(MemoryDB::new(false));
+        let mut state = State::new(Arc::clone(&db)).unwrap();
+        // Two contracts with the same code and storage
+        for i in 1..3u64 {
+            let address = Address::from(i);
+            state.new_contract(&address, U256::from(i), U256::from(0), vec![]);
+            state.set_code(&address, vec![1; 64]).unwrap();
+            state
+                .set_storage(&address, H256::from(1), H256::from(1))
+                .unwrap();
+        }
+        state.commit().unwrap();
+
+        let references = references(&*db, state.root).unwrap();
+        assert_eq!(references[&state.root], 1);
+        assert_eq!(references[&keccak(&[1; 64])], 2);
+        // The code, and the storage trie
+        assert!(references.values().filter(|count| **count == 2).count() >= 2);
+    }
+
+    #[test]
+    fn test_state_sync_rejects_bad_node() {
+        let (source, root) = source_state();
+        let mut sync = StateSync::new(Arc::new(MemoryDB::new(false)), root);
+
+        let hashes = sync.next_request(16);
+        let mut nodes = trie_nodes(&*source, &hashes);
+        nodes[0].push(0);
+        match sync.feed(nodes) {
+            Err(Error::Unrequested(_)) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+        assert_eq!(sync.received(), 0);
+
+        sync.retry(&hashes);
+        let hashes = sync.next_request(16);
+        let nodes = trie_nodes(&*source, &hashes);
+        assert_eq!(sync.feed(nodes).unwrap(), 1);
+        assert!(!sync.is_done());
+    }
+}
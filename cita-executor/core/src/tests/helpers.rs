@@ -30,6 +30,7 @@ use self::tempdir::TempDir;
 use crate::libexecutor::block::{BlockBody, ClosedBlock, OpenBlock};
 use crate::libexecutor::command;
 use crate::libexecutor::executor::Executor;
+use crate::trie_db::Pruning;
 use crate::types::header::OpenHeader;
 use crate::types::transaction::SignedTransaction;
 
@@ -122,6 +123,7 @@ pub fn init_executor2(
         command_req_receiver,
         command_resp_sender,
         false,
+        Pruning::Archive,
     );
     executor
 }
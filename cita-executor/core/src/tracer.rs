@@ -0,0 +1,237 @@
+// Copyright Rivtower Technologies LLC.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Call tracer.
+//!
+//! Records the call tree of a transaction: every call and create frame, with the gas it was
+//! given and used, its output, and why it failed or reverted.
+
+use cita_types::{Address, U256};
+use cita_vm::evm::{InterpreterParams, InterpreterResult, OpCode};
+use jsonrpc_types::rpc_types::{Data, Quantity};
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+use std::cell::RefCell;
+use std::fmt::Debug;
+use std::sync::Arc;
+use types::Bytes;
+
+const REVERTED: &str = "execution reverted";
+
+pub type SharedCallTracer = Arc<RefCell<CallTracer>>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum CallType {
+    Call,
+    CallCode,
+    DelegateCall,
+    StaticCall,
+    Create,
+    Create2,
+}
+
+impl CallType {
+    pub fn from_opcode(opcode: OpCode) -> Option<Self> {
+        match opcode {
+            OpCode::CALL => Some(CallType::Call),
+            OpCode::CALLCODE => Some(CallType::CallCode),
+            OpCode::DELEGATECALL => Some(CallType::DelegateCall),
+            OpCode::STATICCALL => Some(CallType::StaticCall),
+            OpCode::CREATE => Some(CallType::Create),
+            OpCode::CREATE2 => Some(CallType::Create2),
+            _ => None,
+        }
+    }
+
+    pub fn is_create(self) -> bool {
+        self == CallType::Create || self == CallType::Create2
+    }
+}
+
+/// A call frame, with its internal calls.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CallTrace {
+    pub call_type: CallType,
+    pub from: Address,
+    /// Callee, or the created contract. `None` if a create failed.
+    pub to: Option<Address>,
+    pub value: U256,
+    pub gas: u64,
+    pub gas_used: u64,
+    pub input: Bytes,
+    pub output: Bytes,
+    pub error: Option<String>,
+    pub calls: Vec<CallTrace>,
+}
+
+impl Serialize for CallTrace {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut s = serializer.serialize_struct("CallTrace", 10)?;
+        s.serialize_field("type", &self.call_type)?;
+        s.serialize_field("from", &self.from)?;
+        s.serialize_field("to", &self.to)?;
+        s.serialize_field("value", &Quantity::from(self.value))?;
+        s.serialize_field("gas", &Quantity::from(self.gas))?;
+        s.serialize_field("gasUsed", &Quantity::from(self.gas_used))?;
+        s.serialize_field("input", &Data::from(self.input.clone()))?;
+        s.serialize_field("output", &Data::from(self.output.clone()))?;
+        s.serialize_field("error", &self.error)?;
+        s.serialize_field("calls", &self.calls)?;
+        s.end()
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct CallTracer {
+    stack: Vec<CallTrace>,
+    root: Option<CallTrace>,
+}
+
+impl CallTracer {
+    /// Open a frame before entering a call or create.
+    pub fn enter(&mut self, call_type: CallType, params: &InterpreterParams) {
+        let to = if call_type.is_create() {
+            None
+        } else {
+            Some(params.contract.code_address)
+        };
+        self.stack.push(CallTrace {
+            call_type,
+            from: params.sender,
+            to,
+            value: params.value,
+            gas: params.gas_limit,
+            gas_used: 0,
+            input: params.input.clone(),
+            output: Vec::new(),
+            error: None,
+            calls: Vec::new(),
+        });
+    }
+
+    /// Close the innermost frame with the result of the call or create.
+    pub fn exit<E: Debug>(&mut self, result: &Result<InterpreterResult, E>) {
+        let mut frame = match self.stack.pop() {
+            Some(frame) => frame,
+            None => {
+                warn!("tracer exits a frame never entered");
+                return;
+            }
+        };
+        let gas_left = match result {
+            Ok(InterpreterResult::Normal(output, gas_left, _)) => {
+                frame.output = output.clone();
+                *gas_left
+            }
+            Ok(InterpreterResult::Create(output, gas_left, _, address)) => {
+                frame.to = Some(*address);
+                frame.output = output.clone();
+                *gas_left
+            }
+            Ok(InterpreterResult::Revert(output, gas_left)) => {
+                frame.output = output.clone();
+                frame.error = Some(REVERTED.to_owned());
+                *gas_left
+            }
+            Err(err) => {
+                frame.error = Some(format!("{:?}", err));
+                0
+            }
+        };
+        frame.gas_used = frame.gas.saturating_sub(gas_left);
+
+        match self.stack.last_mut() {
+            Some(parent) => parent.calls.push(frame),
+            None => self.root = Some(frame),
+        }
+    }
+
+    /// The call tree of the transaction, `None` if it does not run any code.
+    pub fn take(&mut self) -> Option<CallTrace> {
+        self.stack.clear();
+        self.root.take()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CallTracer, CallType};
+    use cita_types::{Address, U256};
+    use cita_vm::evm::{Contract, Error as EVMError, InterpreterParams, InterpreterResult};
+
+    fn params(sender: Address, code_address: Address, gas_limit: u64) -> InterpreterParams {
+        InterpreterParams {
+            sender,
+            gas_limit,
+            value: U256::from(1),
+            contract: Contract {
+                code_address,
+                code_data: vec![],
+            },
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_call_tree() {
+        let (a, b, c) = (Address::from(1), Address::from(2), Address::from(3));
+        let mut tracer = CallTracer::default();
+
+        tracer.enter(CallType::Call, &params(a, b, 1000));
+        tracer.enter(CallType::DelegateCall, &params(b, c, 500));
+        tracer.exit::<EVMError>(&Ok(InterpreterResult::Revert(vec![0x08], 200)));
+        tracer.enter(CallType::Create, &params(b, Address::zero(), 300));
+        tracer.exit::<EVMError>(&Err(EVMError::OutOfGas));
+        tracer.exit::<EVMError>(&Ok(InterpreterResult::Normal(vec![0x01], 100, vec![])));
+
+        let root = tracer.take().unwrap();
+        assert_eq!(root.call_type, CallType::Call);
+        assert_eq!(root.to, Some(b));
+        assert_eq!(root.gas_used, 900);
+        assert_eq!(root.output, vec![0x01]);
+        assert_eq!(root.error, None);
+        assert_eq!(root.calls.len(), 2);
+
+        let reverted = &root.calls[0];
+        assert_eq!(reverted.to, Some(c));
+        assert_eq!(reverted.gas_used, 300);
+        assert_eq!(reverted.error, Some("execution reverted".to_owned()));
+
+        let failed = &root.calls[1];
+        assert_eq!(failed.to, None);
+        assert_eq!(failed.gas_used, 300);
+        assert!(failed.error.is_some());
+
+        assert_eq!(tracer.take(), None);
+    }
+
+    #[test]
+    fn test_serialize() {
+        let mut tracer = CallTracer::default();
+        tracer.enter(
+            CallType::StaticCall,
+            &params(Address::from(1), Address::from(2), 16),
+        );
+        tracer.exit::<EVMError>(&Ok(InterpreterResult::Normal(vec![], 6, vec![])));
+
+        let value = serde_json::to_value(tracer.take().unwrap()).unwrap();
+        assert_eq!(value["type"], json!("STATICCALL"));
+        assert_eq!(value["gas"], json!("0x10"));
+        assert_eq!(value["gasUsed"], json!("0xa"));
+        assert_eq!(value["calls"], json!([]));
+    }
+}
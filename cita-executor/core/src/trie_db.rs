@@ -14,15 +14,65 @@
 
 use std::sync::Arc;
 
+use crate::libexecutor::state_sync;
+use crate::types::block_number::BlockNumber;
+use crate::types::db_indexes::{
+    BlockNumber2StateJournal, DBIndex, EarliestStateHeight, StateNodeRefCount, TrackedStateRoot,
+};
+use byteorder::{BigEndian, ByteOrder};
 use cita_database::error::DatabaseError;
 use cita_database::{DataCategory, Database};
 use cita_types::H256;
 use hashable::HASH_NULL_RLP;
 use parking_lot::RwLock;
+use rlp::{RlpStream, UntrustedRlp};
 use std::collections::HashMap;
 
 static NULL_RLP_STATIC: [u8; 1] = [0x80; 1];
 
+/// The kind of the state database, `journaldb_type` of the executor config.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JournalDBType {
+    Archive,
+    Pruned,
+}
+
+/// How much history of the state is kept in the database.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Pruning {
+    /// Keep the state of every block.
+    Archive,
+    /// Keep the state of the latest N blocks only.
+    KeepLatest(u64),
+}
+
+impl Pruning {
+    pub fn from_config(journaldb_type: JournalDBType, state_history: u64) -> Self {
+        match journaldb_type {
+            JournalDBType::Archive => Pruning::Archive,
+            JournalDBType::Pruned => Pruning::KeepLatest(state_history.max(1)),
+        }
+    }
+}
+
+/// Trie nodes inserted and removed through a journaled `TrieDB` handle.
+#[derive(Debug, Default)]
+pub struct Journal {
+    inserted: HashMap<Vec<u8>, u32>,
+    removed: HashMap<Vec<u8>, u32>,
+}
+
+impl Journal {
+    fn insert(&mut self, key: &[u8]) {
+        *self.inserted.entry(key.to_vec()).or_insert(0) += 1;
+    }
+
+    fn remove(&mut self, key: &[u8]) {
+        *self.removed.entry(key.to_vec()).or_insert(0) += 1;
+    }
+}
+
 #[derive(Debug)]
 pub struct TrieDB<DB>
 where
@@ -30,6 +80,7 @@ where
 {
     db: Arc<DB>,
     cache: Arc<RwLock<HashMap<Vec<u8>, Vec<u8>>>>,
+    journal: Option<Arc<RwLock<Journal>>>,
 }
 
 impl<DB> TrieDB<DB>
@@ -40,12 +91,209 @@ where
         TrieDB {
             db,
             cache: Arc::new(RwLock::new(HashMap::new())),
+            journal: None,
         }
     }
 
     pub fn database(&self) -> Arc<DB> {
         self.db.clone()
     }
+
+    /// Get a handle sharing the database and cache, which records every node
+    /// inserted and removed through it.
+    pub fn journaled(&self) -> Self {
+        TrieDB {
+            db: Arc::clone(&self.db),
+            cache: Arc::clone(&self.cache),
+            journal: Some(Arc::new(RwLock::new(Journal::default()))),
+        }
+    }
+
+    /// Take the nodes recorded so far, leaving an empty journal behind.
+    pub fn take_journal(&self) -> Journal {
+        self.journal
+            .as_ref()
+            .map(|journal| ::std::mem::replace(&mut *journal.write(), Journal::default()))
+            .unwrap_or_default()
+    }
+
+    /// The lowest height whose state is still in the database.
+    pub fn earliest_state_height(&self) -> Result<BlockNumber, DatabaseError> {
+        let key = EarliestStateHeight.get_index();
+        Ok(self
+            .db
+            .get(Some(DataCategory::Extra), &key)?
+            .map(|height| BigEndian::read_u64(&height))
+            .unwrap_or(0))
+    }
+
+    /// Save the journal of the block at `height`, then drop the nodes that are only
+    /// referenced by states older than the pruning window.
+    ///
+    /// Every inserted node gets its reference count increased right away, while the
+    /// removed ones are decreased only when their block falls out of the window.
+    /// Nodes without a reference count, e.g. written in archive mode, are never deleted.
+    ///
+    /// The counts, the journal and the earliest state height are written in one batch,
+    /// so a journal is never applied twice. The dead nodes are deleted after it, a crash
+    /// in between leaves them behind with a zero count.
+    pub fn commit_journal(
+        &self,
+        height: BlockNumber,
+        journal: Journal,
+        pruning: Pruning,
+    ) -> Result<(), DatabaseError> {
+        let history = match pruning {
+            Pruning::Archive => return Ok(()),
+            Pruning::KeepLatest(history) => history,
+        };
+
+        let mut ref_counts = HashMap::new();
+        for (node, count) in journal.inserted {
+            let hash = H256::from(node.as_slice());
+            let ref_count = self.ref_count(&hash)?.unwrap_or(0).saturating_add(count);
+            ref_counts.insert(hash, Some(ref_count));
+        }
+
+        let mut removed = Vec::new();
+        for (node, count) in journal.removed {
+            for _ in 0..count {
+                removed.push(node.clone());
+            }
+        }
+        let mut keys = vec![BlockNumber2StateJournal(height).get_index()];
+        let mut values = vec![encode_journal(&removed)];
+
+        // The journal of `era` is applied, after which the state of `era - 1` is gone.
+        let mut pruned_era = None;
+        if height + 1 > history {
+            let era = height + 1 - history;
+            let era_removed = if era <= self.earliest_state_height()? {
+                None
+            } else if era == height {
+                Some(removed)
+            } else {
+                self.journal(era)?
+            };
+            if let Some(era_removed) = era_removed {
+                for node in era_removed {
+                    let hash = H256::from(node.as_slice());
+                    let ref_count = match ref_counts.get(&hash) {
+                        Some(ref_count) => *ref_count,
+                        None => self.ref_count(&hash)?,
+                    };
+                    ref_counts.insert(hash, ref_count.map(|count| count.saturating_sub(1)));
+                }
+                let mut earliest = [0u8; 8];
+                BigEndian::write_u64(&mut earliest, era);
+                keys.push(EarliestStateHeight.get_index());
+                values.push(earliest.to_vec());
+                pruned_era = Some(era);
+            }
+        }
+
+        let mut dead_nodes = Vec::new();
+        for (hash, ref_count) in ref_counts {
+            if let Some(ref_count) = ref_count {
+                if ref_count == 0 {
+                    dead_nodes.push(hash);
+                }
+                keys.push(StateNodeRefCount(hash).get_index());
+                values.push(encode_ref_count(ref_count));
+            }
+        }
+        self.db
+            .insert_batch(Some(DataCategory::Extra), keys, values)?;
+
+        if let Some(era) = pruned_era {
+            trace!(
+                "prune state of era {}, {} nodes deleted",
+                era,
+                dead_nodes.len()
+            );
+            let mut dead_keys = vec![BlockNumber2StateJournal(era).get_index()];
+            dead_keys.extend(
+                dead_nodes
+                    .iter()
+                    .map(|hash| StateNodeRefCount(*hash).get_index()),
+            );
+            let dead_nodes: Vec<_> = dead_nodes.iter().map(|hash| hash.to_vec()).collect();
+            self.db
+                .remove_batch(Some(DataCategory::State), &dead_nodes)?;
+            self.db
+                .remove_batch(Some(DataCategory::Extra), &dead_keys)?;
+        }
+        Ok(())
+    }
+
+    /// Count the references to the nodes of the state at `root`, which is written
+    /// without a journal, e.g. the genesis or a synced state. Otherwise a node a later
+    /// block inserts again is counted once, and pruned while this state refers to it.
+    pub fn track_state(&self, root: H256) -> Result<(), DatabaseError> {
+        let references = state_sync::references(self, root)?;
+        let mut keys = Vec::with_capacity(references.len() + 1);
+        let mut values = Vec::with_capacity(references.len() + 1);
+        for (hash, count) in references {
+            let ref_count = self.ref_count(&hash)?.unwrap_or(0).saturating_add(count);
+            keys.push(StateNodeRefCount(hash).get_index());
+            values.push(encode_ref_count(ref_count));
+        }
+        keys.push(TrackedStateRoot.get_index());
+        values.push(root.to_vec());
+        self.db
+            .insert_batch(Some(DataCategory::Extra), keys, values)
+    }
+
+    /// The root of the state `track_state` started counting from, `None` if the
+    /// references are not counted.
+    pub fn tracked_state_root(&self) -> Result<Option<H256>, DatabaseError> {
+        let key = TrackedStateRoot.get_index();
+        Ok(self
+            .db
+            .get(Some(DataCategory::Extra), &key)?
+            .map(|root| H256::from(root.as_slice())))
+    }
+
+    /// Stop counting the references, e.g. in archive mode, which writes no journal.
+    pub fn untrack_state(&self) -> Result<(), DatabaseError> {
+        self.db
+            .remove(Some(DataCategory::Extra), &TrackedStateRoot.get_index())
+    }
+
+    /// The nodes removed by the block at `height`, if its journal is kept.
+    fn journal(&self, height: BlockNumber) -> Result<Option<Vec<Vec<u8>>>, DatabaseError> {
+        let key = BlockNumber2StateJournal(height).get_index();
+        Ok(self
+            .db
+            .get(Some(DataCategory::Extra), &key)?
+            .map(|removed| {
+                UntrustedRlp::new(&removed)
+                    .as_list::<Vec<u8>>()
+                    .unwrap_or_default()
+            }))
+    }
+
+    fn ref_count(&self, hash: &H256) -> Result<Option<u32>, DatabaseError> {
+        let key = StateNodeRefCount(*hash).get_index();
+        Ok(self
+            .db
+            .get(Some(DataCategory::Extra), &key)?
+            .map(|count| BigEndian::read_u32(&count)))
+    }
+}
+
+fn encode_journal(removed: &[Vec<u8>]) -> Vec<u8> {
+    let mut stream = RlpStream::new_list(removed.len());
+    for node in removed {
+        stream.append(node);
+    }
+    stream.out()
+}
+
+fn encode_ref_count(ref_count: u32) -> Vec<u8> {
+    let mut value = [0u8; 4];
+    BigEndian::write_u32(&mut value, ref_count);
+    value.to_vec()
 }
 
 /// "TrieDB" provides state read/write capabilities for executor.
@@ -69,6 +317,9 @@ where
         if H256::from(key.as_slice()) == HASH_NULL_RLP {
             return Ok(());
         }
+        if let Some(ref journal) = self.journal {
+            journal.write().insert(&key);
+        }
         self.cache.write().insert(key, value);
         Ok(())
     }
@@ -84,24 +335,38 @@ where
         }
     }
 
-    fn remove(&self, _key: &[u8]) -> Result<(), Self::Error> {
+    /// Nodes are never deleted right away since older states may still refer to them,
+    /// they are journaled and pruned later by `commit_journal`.
+    fn remove(&self, key: &[u8]) -> Result<(), Self::Error> {
+        if let Some(ref journal) = self.journal {
+            if H256::from(key) != HASH_NULL_RLP {
+                journal.write().remove(key);
+            }
+        }
         Ok(())
     }
 
     fn insert_batch(&self, keys: Vec<Vec<u8>>, values: Vec<Vec<u8>>) -> Result<(), Self::Error> {
         let mut cache = self.cache.write();
+        let mut journal = self.journal.as_ref().map(|journal| journal.write());
         for i in 0..keys.len() {
             let key = keys[i].clone();
             if H256::from(key.as_slice()) == HASH_NULL_RLP {
                 continue;
             }
+            if let Some(ref mut journal) = journal {
+                journal.insert(&key);
+            }
             let value = values[i].clone();
             cache.insert(key, value);
         }
         Ok(())
     }
 
-    fn remove_batch(&self, _keys: &[Vec<u8>]) -> Result<(), Self::Error> {
+    fn remove_batch(&self, keys: &[Vec<u8>]) -> Result<(), Self::Error> {
+        for key in keys {
+            self.remove(key)?;
+        }
         Ok(())
     }
 
@@ -128,6 +393,7 @@ where
         TrieDB {
             db: Arc::clone(&self.db),
             cache: Arc::clone(&self.cache),
+            journal: self.journal.clone(),
         }
     }
 }
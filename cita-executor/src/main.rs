@@ -21,18 +21,20 @@
 //!
 //! 1. Subscribe channel
 //!
-//!     | Queue    | PubModule | Message Type               |
-//!     | -------- | --------- | ------------------         |
-//!     | executor | Chain     | Request                    |
-//!     | executor | Chain     | Richstatus                 |
-//!     | executor | Chain     | StateSignal                |
-//!     | executor | Chain     | LocalSync                  |
-//!     | executor | Consensus | BlockWithProof             |
-//!     | executor | Consensus | SignedProposal             |
-//!     | executor | Consensus | MiscellaneousReq           |
-//!     | executor | Net       | SyncResponse               |
-//!     | executor | Net       | SignedProposal             |
-//!     | executor | Snapshot  | SnapshotReq                |
+//!     | Queue    | PubModule    | Message Type       |
+//!     | -------- | ------------ | ------------------ |
+//!     | executor | Chain        | Request            |
+//!     | executor | Chain        | Richstatus         |
+//!     | executor | Chain        | StateSignal        |
+//!     | executor | Chain        | LocalSync          |
+//!     | executor | Chain        | RawBytes           |
+//!     | executor | Consensus    | BlockWithProof     |
+//!     | executor | Consensus    | SignedProposal     |
+//!     | executor | Consensus    | MiscellaneousReq   |
+//!     | executor | Net          | SyncResponse       |
+//!     | executor | Net          | SignedProposal     |
+//!     | executor | Synchronizer | RawBytes           |
+//!     | executor | Snapshot     | SnapshotReq        |
 //!
 //! 2. Publish channel
 //!
@@ -43,6 +45,9 @@
 //!     | executor | Executor  | Chain     | ExecutedResult |
 //!     | executor | Executor  | Auth      | Miscellaneous  |
 //!     | executor | Executor  | Auth      | BlackList      |
+//!     | executor | Executor  | Auth      | RawBytes       |
+//!     | executor | Executor  | Jsonrpc   | RawBytes       |
+//!     | executor | Executor  | Net       | RawBytes       |
 //!     | executor | Executor  | Chain     | StateSignal    |
 //!
 //! ### Key behavior
@@ -97,6 +102,7 @@ extern crate serde_derive;
 extern crate util;
 
 use crate::core::libexecutor::executor::Executor;
+use crate::core::{JournalDBType, Pruning};
 use crate::postman::Postman;
 use cita_directories::DataPath;
 use clap::App;
@@ -107,6 +113,7 @@ use std::thread;
 use util::set_panic_handler;
 
 mod backlogs;
+mod metrics;
 mod postman;
 #[cfg(test)]
 mod tests;
@@ -116,7 +123,9 @@ include!(concat!(env!("OUT_DIR"), "/build_info.rs"));
 #[derive(Debug, PartialEq, Deserialize)]
 pub struct Options {
     prooftype: u8,
-    journaldb_type: String,
+    journaldb_type: JournalDBType,
+    #[serde(default = "default_state_history")]
+    state_history: u64,
     genesis_path: String,
     statedb_cache_size: usize,
     eth_compatibility: bool,
@@ -126,7 +135,8 @@ impl Options {
     pub fn default() -> Self {
         Options {
             prooftype: 2,
-            journaldb_type: String::from("archive"),
+            journaldb_type: JournalDBType::Archive,
+            state_history: default_state_history(),
             genesis_path: String::from("genesis.json"),
             statedb_cache_size: 5 * 1024 * 1024,
             eth_compatibility: false,
@@ -138,6 +148,10 @@ impl Options {
     }
 }
 
+fn default_state_history() -> u64 {
+    1000
+}
+
 fn main() {
     let matches = App::new("executor")
         .version(get_build_info_str(true))
@@ -146,7 +160,8 @@ fn main() {
         .about("CITA Block Chain Node powered by Rust")
         .args_from_usage(
             "-c, --config=[FILE] 'Sets a switch config file'
-                          -s, --stdout 'Log to console'",
+                          -s, --stdout 'Log to console'
+                          -m, --metrics=[ADDR] 'Serves Prometheus metrics on ADDR'",
         )
         .get_matches();
 
@@ -158,6 +173,10 @@ fn main() {
     info!("Version: {}", get_build_info_str(true));
     info!("Config: {:?}", options);
 
+    if let Some(addr) = matches.value_of("metrics") {
+        cita_metrics::start_server(addr).expect("Start metrics server failed");
+    }
+
     // start pubsub thread
     let (forward_req_sender, forward_req_receiver) = channel::unbounded();
     let (forward_resp_sender, forward_resp_receiver) = channel::unbounded();
@@ -174,9 +193,11 @@ fn main() {
             Chain >> RichStatus,
             Chain >> StateSignal,
             Chain >> LocalSync,
+            Chain >> RawBytes,
             Consensus >> BlockWithProof,
             Consensus >> SignedProposal,
             Net >> SyncResponse,
+            Synchronizer >> RawBytes,
             Snapshot >> SnapshotReq,
             Auth >> MiscellaneousReq,
         ]),
@@ -214,6 +235,7 @@ fn main() {
             command_req_receiver.clone(),
             command_resp_sender.clone(),
             options.eth_compatibility,
+            Pruning::from_config(options.journaldb_type, options.state_history),
         );
         let current_height = executor.get_current_height();
         let current_hash = executor.get_current_hash();
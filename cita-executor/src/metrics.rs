@@ -0,0 +1,33 @@
+// Copyright Rivtower Technologies LLC.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Metrics of executor, served on `/metrics`.
+
+use lazy_static::lazy_static;
+use prometheus::{register_histogram, register_int_gauge, Histogram, IntGauge};
+
+lazy_static! {
+    pub static ref BLOCK_EXECUTION_SECONDS: Histogram = register_histogram!(
+        "executor_block_execution_seconds",
+        "Time to execute the transactions of a block"
+    )
+    .unwrap();
+    pub static ref BLOCK_QUOTA_USED: IntGauge = register_int_gauge!(
+        "executor_block_quota_used",
+        "Quota used by the latest executed block"
+    )
+    .unwrap();
+    pub static ref EXECUTED_HEIGHT: IntGauge =
+        register_int_gauge!("executor_height", "Height of the latest executed block").unwrap();
+}
@@ -15,33 +15,60 @@
 use crate::core::contracts::solc::sys_config::ChainId;
 use crate::core::libexecutor::block::{ClosedBlock, OpenBlock};
 use crate::core::libexecutor::call_request::CallRequest;
+use crate::core::libexecutor::executor::CitaTrieDB;
+use crate::core::libexecutor::state_sync::{self, StateSync, MAX_NODES_PER_CHUNK};
 use crate::core::tx_gas_schedule::TxGasSchedule;
 use crate::types::block_number::{BlockTag, Tag};
 use crate::types::errors::ReceiptError;
+use crate::types::header::Header;
+use crate::types::state_proof::StateProof;
+use crate::types::transaction_index::TracePosition;
+use cita_rpc_ext::{self as ext, Service};
+use cita_types::traits::LowerHex;
 use cita_types::U256;
-use cita_types::{Address, H256};
+use cita_types::{clean_0x, Address, H256};
 use crossbeam_channel::{Receiver, Sender};
 use error::ErrorCode;
-use jsonrpc_types::rpc_types::{BlockNumber, CountOrCode};
+use jsonrpc_types::rpc_types::Data20;
 use libproto::auth::Miscellaneous;
 use libproto::blockchain::{RichStatus, StateSignal};
 use libproto::request::Request_oneof_req as Request;
 use libproto::router::{MsgType, RoutingKey, SubModules};
-use libproto::{request, response, Message};
+use libproto::{request, response, Message, MsgClass, OperateType};
 use libproto::{TryFrom, TryInto};
-use serde_json;
+use rlp::UntrustedRlp;
+use serde_json::{self, Value};
 use std::convert::Into;
+use std::sync::Arc;
+use std::time::Instant;
 use std::u8;
 
-use crate::core::libexecutor::blacklist::BlackList;
+use crate::core::libexecutor::blacklist::{self, BlackList};
 use crate::core::libexecutor::command;
 use crate::core::libexecutor::lru_cache::LRUCache;
 
 use std::sync::RwLock;
 
 use super::backlogs::{wrap_height, Backlogs};
+use crate::metrics;
+use cita_metrics::duration_to_seconds;
 use cita_vm::state::StateObjectInfo;
 
+/// Same as `CountOrCode`, but the block can also be given by its hash.
+#[derive(Debug, Deserialize)]
+struct AccountAt {
+    address: Data20,
+    block_id: BlockTag,
+}
+
+/// The state being synced from other nodes, at `header`.
+struct Syncing {
+    header: Header,
+    sync: StateSync<CitaTrieDB>,
+    // hashes of the nodes asked for last
+    requested: Vec<H256>,
+}
+
 pub struct Postman {
     backlogs: Backlogs,
     black_list_cache: RwLock<LRUCache<u64, Address>>,
@@ -51,6 +78,9 @@ pub struct Postman {
     fsm_resp_receiver: Receiver<ClosedBlock>,
     command_req_sender: Sender<command::Command>,
     command_resp_receiver: Receiver<command::CommandResp>,
+    // height of the block being executed, and when it was sent to executor
+    executing: Option<(u64, Instant)>,
+    state_sync: Option<Syncing>,
 }
 
 impl Postman {
@@ -74,6 +104,8 @@ impl Postman {
             fsm_resp_receiver,
             command_req_sender,
             command_resp_receiver,
+            executing: None,
+            state_sync: None,
         }
     }
 
@@ -165,6 +197,13 @@ impl Postman {
     fn handle_fsm_response(&mut self, closed_block: ClosedBlock) {
         let height = closed_block.number();
         info!("postman receive {}-th ClosedBlock from executor", height);
+        if let Some((executing_height, start)) = self.executing {
+            if executing_height == height {
+                metrics::BLOCK_EXECUTION_SECONDS.observe(duration_to_seconds(start.elapsed()));
+                self.executing = None;
+            }
+        }
+        metrics::BLOCK_QUOTA_USED.set(closed_block.quota_used().low_u64() as i64);
         self.backlogs.insert_closed(height, closed_block);
     }
 
@@ -181,6 +220,17 @@ impl Postman {
                 self.reply_chain_request(req);
             }
 
+            routing_key!(Chain >> RawBytes) => {
+                let data = msg.take_raw_bytes().unwrap_or_default();
+                self.reply_ext_request(&data);
+            }
+
+            routing_key!(Synchronizer >> RawBytes) => {
+                let origin = msg.get_origin();
+                let data = msg.take_raw_bytes().unwrap_or_default();
+                self.handle_state_sync(origin, &data)?;
+            }
+
             routing_key!(Chain >> RichStatus) => {
                 if let Some(status) = msg.take_rich_status() {
                     self.update_by_rich_status(&status);
@@ -345,6 +395,7 @@ impl Postman {
                 self.backlogs
                     .insert_completed_result(next_height, executed_result);
                 self.send_executed_info_to_chain(next_height).unwrap();
+                metrics::EXECUTED_HEIGHT.set(next_height as i64);
             }
             Err(reason) => trace!("{}", reason),
         }
@@ -355,16 +406,18 @@ impl Postman {
         match self.backlogs.ready(next_height) {
             Ok(open_block) => {
                 trace!("postman send {}-th block to executor", next_height);
+                if self.executing.map(|(height, _)| height) != Some(next_height) {
+                    self.executing = Some((next_height, Instant::now()));
+                }
                 let _ = self.fsm_req_sender.send(open_block.clone());
             }
             Err(reason) => trace!("{}", reason),
         }
     }
 
-    /// First notify jsonrpc clear blacklist
+    /// First notify auth to clear the black list, then restore the manual entries
     fn pub_init_black_list(&self) {
-        let mut init_list = Vec::new();
-        init_list.push(Address::default());
+        let init_list = vec![Address::default()];
         let black_list = BlackList::new()
             .set_black_list(init_list.clone())
             .set_clear_list(init_list);
@@ -374,6 +427,33 @@ impl Postman {
             routing_key!(Executor >> BlackList).into(),
             black_list_bytes.try_into().unwrap(),
         );
+
+        let manual =
+            command::manual_black_list(&self.command_req_sender, &self.command_resp_receiver);
+        self.pub_manual_black_list(manual.addresses(), Vec::new());
+    }
+
+    /// Tell auth about the entries added or removed by an admin.
+    fn pub_manual_black_list(&self, forbid: Vec<Address>, allow: Vec<Address>) {
+        if forbid.is_empty() && allow.is_empty() {
+            return;
+        }
+        let hex = |addresses: Vec<Address>| {
+            addresses
+                .iter()
+                .map(|address| format!("0x{}", address.lower_hex()))
+                .collect()
+        };
+        let list = ext::ManualBlackList {
+            forbid: hex(forbid),
+            allow: hex(allow),
+        };
+        let data = ext::Message::ManualBlackList(list).to_bytes();
+        let msg = Message::init(OperateType::Single, 0, MsgClass::RawBytes(data));
+        self.response_mq(
+            routing_key!(Executor >> RawBytes).into(),
+            msg.try_into().unwrap(),
+        );
     }
 
     /// Find the public key of all senders that caused the specified error message, and then publish it
@@ -393,6 +473,15 @@ impl Postman {
             .filter(|hash| hash != &H256::default())
             .collect();
 
+        // Manual entries are not cleared by balance, only when they expire
+        let expired = command::expire_black_list(
+            &self.command_req_sender,
+            &self.command_resp_receiver,
+            close_block.number(),
+        );
+        let manual =
+            command::manual_black_list(&self.command_req_sender, &self.command_resp_receiver);
+
         let schedule = TxGasSchedule::default();
         // Filter out accounts in the black list where the account balance has reached the benchmark value.
         // Get the smaller value between tx_create_gas and tx_gas for the benchmark value.
@@ -402,6 +491,7 @@ impl Postman {
             .read()
             .unwrap()
             .values()
+            .filter(|address| !manual.contains(address))
             .filter(|address| {
                 command::balance_at(
                     &self.command_req_sender,
@@ -430,6 +520,12 @@ impl Postman {
                 .prune(&clear_list)
                 .extend(&blacklist[..], close_block.number());
             clear_list.extend(black_list_cache.lru().iter());
+            clear_list.retain(|address| !manual.contains(address));
+            clear_list.extend(
+                expired
+                    .into_iter()
+                    .filter(|address| !black_list_cache.contains_by_value(address)),
+            );
         }
 
         let black_list = BlackList::new()
@@ -452,6 +548,279 @@ impl Postman {
         }
     }
 
+    /// Serve the methods of `cita_rpc_ext` for the executor, which chain passes on.
+    fn reply_ext_request(&self, data: &[u8]) {
+        let req = match ext::Message::from_bytes(data) {
+            Ok(ext::Message::Request(req)) => req,
+            Ok(_) => return,
+            Err(err) => {
+                warn!("invalid extension request: {:?}", err);
+                return;
+            }
+        };
+        if req.service() != Some(Service::Executor) && !req.is_chain_trace() {
+            return;
+        }
+
+        let result = match req.method.as_str() {
+            ext::ADD_BLACK_LIST => self.add_black_list(&req),
+            ext::REMOVE_BLACK_LIST => self.remove_black_list(&req),
+            ext::GET_BLACK_LIST => Ok(self.black_list_items()),
+            ext::TRACE_TRANSACTION => self.trace_transaction(&req),
+            ext::GET_PROOF => self.account_proof(&req),
+            method => Err(ext::Error::new(
+                ErrorCode::query_error(),
+                format!("{} is not served by executor", method),
+            )),
+        };
+        let resp = ext::Message::Response(ext::Response::new(req.id, result));
+        let msg = Message::init(OperateType::Single, 0, MsgClass::RawBytes(resp.to_bytes()));
+        self.response_mq(
+            routing_key!(Executor >> RawBytes).into(),
+            msg.try_into().unwrap(),
+        );
+    }
+
+    fn trace_transaction(&self, req: &ext::Request) -> Result<Value, ext::Error> {
+        let position: TracePosition = req.param(1)?;
+        let transactions: Vec<u8> = position.transactions.into();
+        let rlp = UntrustedRlp::new(&transactions);
+        let count = rlp
+            .item_count()
+            .map_err(|err| ext::Error::invalid_params(format!("{:?}", err)))?;
+        if count > ext::MAX_TRACE_TRANSACTIONS {
+            return Err(ext::Error::new(
+                ErrorCode::query_error(),
+                format!(
+                    "can not trace transactions after the first {} of a block",
+                    ext::MAX_TRACE_TRANSACTIONS
+                ),
+            ));
+        }
+        let transactions = rlp
+            .as_list()
+            .map_err(|err| ext::Error::invalid_params(format!("{:?}", err)))?;
+        command::trace_transaction(
+            &self.command_req_sender,
+            &self.command_resp_receiver,
+            transactions,
+            BlockTag::Height(position.block_number),
+        )
+        .map(|trace| serde_json::to_value(trace).unwrap())
+        .map_err(|err| ext::Error::new(ErrorCode::query_error(), err))
+    }
+
+    fn account_proof(&self, req: &ext::Request) -> Result<Value, ext::Error> {
+        let address: Data20 = req.param(0)?;
+        let keys: Vec<H256> = req.param(1)?;
+        let block_tag: Option<BlockTag> = req.param(2)?;
+        command::account_proof(
+            &self.command_req_sender,
+            &self.command_resp_receiver,
+            Address::from_slice(address.as_ref()),
+            keys,
+            block_tag.unwrap_or(BlockTag::Tag(Tag::Latest)),
+        )
+        .map(|proof| serde_json::to_value(proof).unwrap())
+        .map_err(|err| ext::Error::new(ErrorCode::query_error(), err))
+    }
+
+    fn add_black_list(&self, req: &ext::Request) -> Result<Value, ext::Error> {
+        let address: Data20 = req.param(0)?;
+        let ttl: Option<u64> = req.param(1)?;
+        let address = Address::from_slice(address.as_ref());
+        let expires_at = command::add_black_list(
+            &self.command_req_sender,
+            &self.command_resp_receiver,
+            address,
+            ttl,
+        );
+        self.pub_manual_black_list(vec![address], Vec::new());
+        Ok(serde_json::to_value(blacklist::BlackListItem::manual(&address, expires_at)).unwrap())
+    }
+
+    fn remove_black_list(&self, req: &ext::Request) -> Result<Value, ext::Error> {
+        let address: Data20 = req.param(0)?;
+        let address = Address::from_slice(address.as_ref());
+        let removed = command::remove_black_list(
+            &self.command_req_sender,
+            &self.command_resp_receiver,
+            address,
+        );
+        // An automatic entry stays until the balance is enough.
+        let automatic = self
+            .black_list_cache
+            .read()
+            .unwrap()
+            .contains_by_value(&address);
+        if removed && !automatic {
+            self.pub_manual_black_list(Vec::new(), vec![address]);
+        }
+        Ok(Value::Bool(removed))
+    }
+
+    fn black_list_items(&self) -> Value {
+        let manual =
+            command::manual_black_list(&self.command_req_sender, &self.command_resp_receiver);
+        let items = blacklist::black_list_items(&self.black_list_cache.read().unwrap(), &manual);
+        serde_json::to_value(items).unwrap()
+    }
+
+    /// Serve the state sync requests of other nodes, and sync the state from their answers.
+    fn handle_state_sync(&mut self, origin: u32, data: &[u8]) -> Result<(), BlockTag> {
+        match ext::Message::from_bytes(data) {
+            Ok(ext::Message::GetStateHeaders) => {
+                let headers =
+                    command::state_headers(&self.command_req_sender, &self.command_resp_receiver);
+                let headers = headers
+                    .iter()
+                    .map(|header| state_sync::to_hex(&header.rlp()))
+                    .collect();
+                self.pub_state_sync(origin, ext::Message::StateHeaders(headers));
+            }
+            Ok(ext::Message::GetTrieNodes(hashes)) => {
+                let hashes = hashes
+                    .iter()
+                    .filter_map(|hash| clean_0x(hash).parse().ok())
+                    .collect();
+                let nodes = command::trie_nodes(
+                    &self.command_req_sender,
+                    &self.command_resp_receiver,
+                    hashes,
+                );
+                let nodes = nodes.iter().map(|node| state_sync::to_hex(node)).collect();
+                self.pub_state_sync(origin, ext::Message::TrieNodes(nodes));
+            }
+            Ok(ext::Message::StateHeaders(headers)) => self.start_state_sync(&headers)?,
+            Ok(ext::Message::TrieNodes(nodes)) => self.continue_state_sync(&nodes)?,
+            Ok(_) => {}
+            Err(err) => warn!("invalid state sync message: {:?}", err),
+        }
+        Ok(())
+    }
+
+    /// Start to sync the state at the first of `headers` when still at genesis.
+    ///
+    /// Network sends the headers again when a request is not answered for a while,
+    /// then the nodes not received yet are asked for again.
+    ///
+    /// The proofs are checked against the validators of genesis, the only ones known
+    /// before syncing. Once the validators change, no headers pass, and network syncs
+    /// the blocks instead after its retries.
+    fn start_state_sync(&mut self, headers: &[String]) -> Result<(), BlockTag> {
+        if let Some(syncing) = self.state_sync.as_mut() {
+            let requested = ::std::mem::replace(&mut syncing.requested, Vec::new());
+            syncing.sync.retry(&requested);
+            return self.step_state_sync();
+        }
+        if self.get_current_height() != 0 {
+            return Ok(());
+        }
+
+        let headers: Vec<Header> = headers
+            .iter()
+            .filter_map(|header| state_sync::from_hex(header))
+            .filter_map(|header| UntrustedRlp::new(&header).as_val().ok())
+            .collect();
+        let executor =
+            command::clone_executor_reader(&self.command_req_sender, &self.command_resp_receiver);
+        let header = match state_sync::verify_headers(&headers, &executor.sys_config.validators) {
+            Some(header) => header,
+            None => {
+                warn!(
+                    "state sync: headers without proofs of the genesis validators, \
+                     the validators may have changed since"
+                );
+                return Ok(());
+            }
+        };
+        info!(
+            "state sync: sync the state of {}-th block, root {:?}",
+            header.number(),
+            header.state_root()
+        );
+        let sync = StateSync::new(Arc::clone(&executor.state_db), *header.state_root());
+        self.state_sync = Some(Syncing {
+            header,
+            sync,
+            requested: Vec::new(),
+        });
+        self.step_state_sync()
+    }
+
+    /// Save the nodes answered, and ask for the next ones.
+    fn continue_state_sync(&mut self, nodes: &[String]) -> Result<(), BlockTag> {
+        let syncing = match self.state_sync.as_mut() {
+            Some(syncing) => syncing,
+            None => return Ok(()),
+        };
+        let nodes: Vec<Vec<u8>> = nodes
+            .iter()
+            .filter_map(|node| state_sync::from_hex(node))
+            .collect();
+        let answered = !nodes.is_empty();
+        let received = syncing.sync.received();
+        if let Err(err) = syncing.sync.feed(nodes) {
+            warn!("state sync: {}", err);
+        }
+        if answered && syncing.sync.received() == received {
+            // an answer to an earlier request, or from a bad node
+            return Ok(());
+        }
+        let requested = ::std::mem::replace(&mut syncing.requested, Vec::new());
+        syncing.sync.retry(&requested);
+        self.step_state_sync()
+    }
+
+    /// Ask for the next nodes. Once the state is complete, tell chain to continue from
+    /// the synced block, and restart from it.
+    fn step_state_sync(&mut self) -> Result<(), BlockTag> {
+        let syncing = match self.state_sync.as_mut() {
+            Some(syncing) => syncing,
+            None => return Ok(()),
+        };
+        if !syncing.sync.is_done() {
+            syncing.requested = syncing.sync.next_request(MAX_NODES_PER_CHUNK);
+            let hashes = syncing
+                .requested
+                .iter()
+                .map(|hash| format!("0x{}", hash.lower_hex()))
+                .collect();
+            self.pub_state_sync(0, ext::Message::GetTrieNodes(hashes));
+            return Ok(());
+        }
+
+        let syncing = self.state_sync.take().expect("checked above; qed");
+        let height = syncing.header.number();
+        info!(
+            "state sync: {} nodes received, continue from {}-th block",
+            syncing.sync.received(),
+            height
+        );
+        command::state_synced(
+            &self.command_req_sender,
+            &self.command_resp_receiver,
+            syncing.header.clone(),
+        );
+        let header = state_sync::to_hex(&syncing.header.rlp());
+        self.pub_state_sync(0, ext::Message::StateSynced(header));
+        Err(BlockTag::Height(height))
+    }
+
+    /// Publish a state sync message, network sends it to `origin`, or to a node it
+    /// picks if zero.
+    fn pub_state_sync(&self, origin: u32, ext_msg: ext::Message) {
+        let msg = Message::init(
+            OperateType::Single,
+            origin,
+            MsgClass::RawBytes(ext_msg.to_bytes()),
+        );
+        self.response_mq(
+            routing_key!(Executor >> SyncResponse).into(),
+            msg.try_into().unwrap(),
+        );
+    }
+
     fn update_by_rich_status(&mut self, rich_status: &RichStatus) {
         let next_height = wrap_height(rich_status.get_height() as usize + 1);
         self.backlogs.prune(next_height);
@@ -483,14 +852,14 @@ impl Postman {
         match req.req.unwrap() {
             Request::call(call) => {
                 trace!("Chainvm Call {:?}", call);
-                let _ = serde_json::from_str::<BlockNumber>(&call.height)
+                let _ = serde_json::from_str::<BlockTag>(&call.height)
                     .map(|block_id| {
                         let call_request = CallRequest::from(call);
                         command::eth_call(
                             &self.command_req_sender,
                             &self.command_resp_receiver,
                             call_request,
-                            block_id.into(),
+                            block_id,
                         )
                         .map(|ok| {
                             response.set_call_result(ok);
@@ -508,14 +877,14 @@ impl Postman {
 
             Request::estimate_quota(call) => {
                 trace!("Estimate quota with params: {:?}", call);
-                let _ = serde_json::from_str::<BlockNumber>(&call.height)
+                let _ = serde_json::from_str::<BlockTag>(&call.height)
                     .map(|block_id| {
                         let call_request = CallRequest::from(call);
                         command::estimate_quota(
                             &self.command_req_sender,
                             &self.command_resp_receiver,
                             call_request,
-                            block_id.into(),
+                            block_id,
                         )
                         .map(|ok| {
                             response.set_call_result(ok);
@@ -533,7 +902,7 @@ impl Postman {
 
             Request::transaction_count(tx_count) => {
                 trace!("transaction count request from jsonrpc {:?}", tx_count);
-                let _ = serde_json::from_str::<CountOrCode>(&tx_count)
+                let _ = serde_json::from_str::<AccountAt>(&tx_count)
                     .map_err(|err| {
                         response.set_code(ErrorCode::query_error());
                         response.set_error_msg(format!("{:?}", err));
@@ -544,7 +913,7 @@ impl Postman {
                             &self.command_req_sender,
                             &self.command_resp_receiver,
                             address,
-                            tx_count.block_id.into(),
+                            tx_count.block_id,
                         ) {
                             Some(nonce) => {
                                 response.set_transaction_count(u64::from(nonce));
@@ -558,7 +927,7 @@ impl Postman {
 
             Request::code(code_content) => {
                 trace!("code request from jsonrpc  {:?}", code_content);
-                let _ = serde_json::from_str::<CountOrCode>(&code_content)
+                let _ = serde_json::from_str::<AccountAt>(&code_content)
                     .map_err(|err| {
                         response.set_code(ErrorCode::query_error());
                         response.set_error_msg(format!("{:?}", err));
@@ -569,7 +938,7 @@ impl Postman {
                             &self.command_req_sender,
                             &self.command_resp_receiver,
                             address,
-                            code_content.block_id.into(),
+                            code_content.block_id,
                         ) {
                             response.set_contract_code(code);
                         } else {
@@ -580,7 +949,7 @@ impl Postman {
 
             Request::abi(abi_content) => {
                 trace!("abi request from jsonrpc  {:?}", abi_content);
-                let _ = serde_json::from_str::<CountOrCode>(&abi_content)
+                let _ = serde_json::from_str::<AccountAt>(&abi_content)
                     .map_err(|err| {
                         response.set_code(ErrorCode::query_error());
                         response.set_error_msg(format!("{:?}", err));
@@ -591,7 +960,7 @@ impl Postman {
                             &self.command_req_sender,
                             &self.command_resp_receiver,
                             address,
-                            abi_content.block_id.into(),
+                            abi_content.block_id,
                         ) {
                             response.set_contract_abi(abi);
                         } else {
@@ -602,7 +971,7 @@ impl Postman {
 
             Request::balance(balance_content) => {
                 trace!("balance request from jsonrpc  {:?}", balance_content);
-                let _ = serde_json::from_str::<CountOrCode>(&balance_content)
+                let _ = serde_json::from_str::<AccountAt>(&balance_content)
                     .map_err(|err| {
                         response.set_code(ErrorCode::query_error());
                         response.set_error_msg(format!("{:?}", err));
@@ -613,7 +982,7 @@ impl Postman {
                             &self.command_req_sender,
                             &self.command_resp_receiver,
                             address,
-                            balance_content.block_id.into(),
+                            balance_content.block_id,
                         ) {
                             response.set_balance(balance);
                         } else {
@@ -637,28 +1006,32 @@ impl Postman {
 
             Request::state_proof(state_info) => {
                 trace!("state_proof info is {:?}", state_info);
-                let _ = serde_json::from_str::<BlockNumber>(&state_info.height)
+                let _ = serde_json::from_str::<BlockTag>(&state_info.height)
                     .map(|block_id| {
                         match command::state_at(
                             &self.command_req_sender,
                             &self.command_resp_receiver,
-                            block_id.into(),
+                            block_id,
                         )
+                        .map_err(|err| format!("{}", err))
                         .and_then(|state| {
-                            state
-                                .get_storage_proof(
-                                    &Address::from(state_info.get_address()),
-                                    &H256::from(state_info.get_position()),
-                                )
-                                .ok()
+                            let address = Address::from(state_info.get_address());
+                            let key = H256::from(state_info.get_position());
+                            let account_proof = state.get_account_proof(&address);
+                            let value_proof = state.get_storage_proof(&address, &key);
+                            match (account_proof, value_proof) {
+                                (Ok(account_proof), Ok(value_proof)) => {
+                                    Ok(StateProof::new(address, account_proof, key, value_proof))
+                                }
+                                _ => Err("get state proof failed".to_string()),
+                            }
                         }) {
-                            Some(state_proof_bs) => {
-                                let buf: Vec<u8> = state_proof_bs.into_iter().flatten().collect();
-                                response.set_state_proof(buf);
+                            Ok(state_proof) => {
+                                response.set_state_proof(rlp::encode(&state_proof).into_vec());
                             }
-                            None => {
+                            Err(error_msg) => {
                                 response.set_code(ErrorCode::query_error());
-                                response.set_error_msg("get state proof failed".to_string());
+                                response.set_error_msg(error_msg);
                             }
                         }
                     })
@@ -670,28 +1043,28 @@ impl Postman {
 
             Request::storage_key(skey) => {
                 trace!("storage key info is {:?}", skey);
-                let _ = serde_json::from_str::<BlockNumber>(&skey.height)
+                let _ = serde_json::from_str::<BlockTag>(&skey.height)
                     .map(|block_id| {
                         match command::state_at(
                             &self.command_req_sender,
                             &self.command_resp_receiver,
-                            block_id.into(),
+                            block_id,
                         )
+                        .map_err(|err| format!("{}", err))
                         .and_then(|mut state| {
                             state
                                 .get_storage(
                                     &Address::from(skey.get_address()),
                                     &H256::from(skey.get_position()),
                                 )
-                                .ok()
+                                .map_err(|_| "get storage at something failed".to_string())
                         }) {
-                            Some(storage_val) => {
+                            Ok(storage_val) => {
                                 response.set_storage_value(storage_val.to_vec());
                             }
-                            None => {
+                            Err(error_msg) => {
                                 response.set_code(ErrorCode::query_error());
-                                response
-                                    .set_error_msg("get storage at something failed".to_string());
+                                response.set_error_msg(error_msg);
                             }
                         }
                     })
@@ -736,8 +1109,11 @@ impl Postman {
 mod tests {
     use self::helpers::generate_executed_result;
     use super::*;
+    use crate::core::libexecutor::blacklist::ManualBlackList;
     use crate::tests::helpers;
+    use crate::types::state_proof::{AccountProof, StorageProof};
     use libproto::Message;
+    use serde_json::json;
 
     #[test]
     fn test_bootstrap_broadcast_at_0th() {
@@ -752,11 +1128,19 @@ mod tests {
         ::std::thread::spawn(move || {
             let command = command_req_receiver.recv().unwrap();
             match command {
-                command::Command::LoadExecutedResult(0) => command_resp_sender.send(
-                    command::CommandResp::LoadExecutedResult(libproto::ExecutedResult::new()),
-                ),
+                command::Command::LoadExecutedResult(0) => {
+                    let _ = command_resp_sender.send(command::CommandResp::LoadExecutedResult(
+                        libproto::ExecutedResult::new(),
+                    ));
+                }
                 _ => panic!("received should be Command::LoadExecutedResult(0)"),
             }
+            let command = command_req_receiver.recv().unwrap();
+            match command {
+                command::Command::ManualBlackList => command_resp_sender
+                    .send(command::CommandResp::ManualBlackList(ManualBlackList::new())),
+                _ => panic!("received should be Command::ManualBlackList"),
+            }
         });
         postman.bootstrap_broadcast();
 
@@ -793,11 +1177,19 @@ mod tests {
             }
             let command = command_req_receiver.recv().unwrap();
             match command {
-                command::Command::LoadExecutedResult(2) => command_resp_sender.send(
-                    command::CommandResp::LoadExecutedResult(libproto::ExecutedResult::new()),
-                ),
+                command::Command::LoadExecutedResult(2) => {
+                    let _ = command_resp_sender.send(command::CommandResp::LoadExecutedResult(
+                        libproto::ExecutedResult::new(),
+                    ));
+                }
                 _ => panic!("received should be Command::LoadExecutedResult(2)"),
             }
+            let command = command_req_receiver.recv().unwrap();
+            match command {
+                command::Command::ManualBlackList => command_resp_sender
+                    .send(command::CommandResp::ManualBlackList(ManualBlackList::new())),
+                _ => panic!("received should be Command::ManualBlackList"),
+            }
         });
         postman.bootstrap_broadcast();
 
@@ -1229,6 +1621,252 @@ mod tests {
         assert!(postman.backlogs.get_completed_result(5).is_some());
     }
 
+    #[test]
+    fn test_add_black_list() {
+        let mut postman = helpers::generate_postman(5, H256::from(0));
+        let (mq_resp_sender, mq_resp_receiver) = crossbeam_channel::unbounded();
+        let (command_req_sender, command_req_receiver) = crossbeam_channel::bounded(0);
+        let (command_resp_sender, command_resp_receiver) = crossbeam_channel::bounded(0);
+        postman.mq_resp_sender = mq_resp_sender;
+        postman.command_req_sender = command_req_sender;
+        postman.command_resp_receiver = command_resp_receiver;
+
+        let address = Address::from(1);
+        ::std::thread::spawn(move || match command_req_receiver.recv().unwrap() {
+            command::Command::AddBlackList(added, Some(10)) if added == address => {
+                let _ = command_resp_sender.send(command::CommandResp::AddBlackList(Some(15)));
+            }
+            _ => panic!("received should be Command::AddBlackList"),
+        });
+        let req = ext::Message::Request(ext::Request {
+            id: vec![1],
+            method: ext::ADD_BLACK_LIST.to_owned(),
+            params: vec![
+                json!("0x0000000000000000000000000000000000000001"),
+                json!(10),
+            ],
+        });
+        postman.reply_ext_request(&req.to_bytes());
+
+        let mut messages = mq_resp_receiver.try_iter().map(|(key, message)| {
+            assert_eq!(routing_key!(Executor >> RawBytes), RoutingKey::from(key));
+            let data = Message::try_from(message)
+                .unwrap()
+                .take_raw_bytes()
+                .unwrap();
+            ext::Message::from_bytes(&data).unwrap()
+        });
+        assert_eq!(
+            messages.next(),
+            Some(ext::Message::ManualBlackList(ext::ManualBlackList {
+                forbid: vec!["0x0000000000000000000000000000000000000001".to_owned()],
+                allow: vec![],
+            }))
+        );
+        assert_eq!(
+            messages.next(),
+            Some(ext::Message::Response(ext::Response::new(
+                vec![1],
+                Ok(json!({
+                    "address": "0x0000000000000000000000000000000000000001",
+                    "reason": "manual",
+                    "expiresAt": 15,
+                }))
+            )))
+        );
+        assert_eq!(messages.next(), None);
+    }
+
+    #[test]
+    fn test_trace_transaction() {
+        let mut postman = helpers::generate_postman(5, H256::from(0));
+        let (mq_resp_sender, mq_resp_receiver) = crossbeam_channel::unbounded();
+        let (command_req_sender, command_req_receiver) = crossbeam_channel::bounded(0);
+        let (command_resp_sender, command_resp_receiver) = crossbeam_channel::bounded(0);
+        postman.mq_resp_sender = mq_resp_sender;
+        postman.command_req_sender = command_req_sender;
+        postman.command_resp_receiver = command_resp_receiver;
+
+        ::std::thread::spawn(move || match command_req_receiver.recv().unwrap() {
+            command::Command::TraceTransaction(ref txs, BlockTag::Height(3)) if txs.is_empty() => {
+                let _ = command_resp_sender.send(command::CommandResp::TraceTransaction(Ok(None)));
+            }
+            _ => panic!("received should be Command::TraceTransaction"),
+        });
+        // The RLP of an empty list
+        let position = TracePosition {
+            block_number: 3,
+            transactions: vec![0xc0].into(),
+        };
+        let req = ext::Message::Request(ext::Request {
+            id: vec![2],
+            method: ext::TRACE_TRANSACTION.to_owned(),
+            params: vec![json!("0x01"), serde_json::to_value(position).unwrap()],
+        });
+        postman.reply_ext_request(&req.to_bytes());
+
+        let (key, message) = mq_resp_receiver.recv().unwrap();
+        assert_eq!(routing_key!(Executor >> RawBytes), RoutingKey::from(key));
+        let data = Message::try_from(message)
+            .unwrap()
+            .take_raw_bytes()
+            .unwrap();
+        assert_eq!(
+            ext::Message::from_bytes(&data).unwrap(),
+            ext::Message::Response(ext::Response::new(vec![2], Ok(Value::Null)))
+        );
+    }
+
+    #[test]
+    fn test_trace_too_many_transactions() {
+        let mut postman = helpers::generate_postman(5, H256::from(0));
+        let (mq_resp_sender, mq_resp_receiver) = crossbeam_channel::unbounded();
+        postman.mq_resp_sender = mq_resp_sender;
+
+        // Nothing is replayed, the command thread is not asked
+        let mut transactions = rlp::RlpStream::new_list(ext::MAX_TRACE_TRANSACTIONS + 1);
+        for _ in 0..=ext::MAX_TRACE_TRANSACTIONS {
+            transactions.append_empty_data();
+        }
+        let position = TracePosition {
+            block_number: 3,
+            transactions: transactions.out().into(),
+        };
+        let req = ext::Message::Request(ext::Request {
+            id: vec![2],
+            method: ext::TRACE_TRANSACTION.to_owned(),
+            params: vec![json!("0x01"), serde_json::to_value(position).unwrap()],
+        });
+        postman.reply_ext_request(&req.to_bytes());
+
+        let (_, message) = mq_resp_receiver.recv().unwrap();
+        let data = Message::try_from(message)
+            .unwrap()
+            .take_raw_bytes()
+            .unwrap();
+        match ext::Message::from_bytes(&data).unwrap() {
+            ext::Message::Response(resp) => assert!(resp.result.is_err()),
+            msg => panic!("unexpected message {:?}", msg),
+        }
+    }
+
+    #[test]
+    fn test_get_proof() {
+        let mut postman = helpers::generate_postman(5, H256::from(0));
+        let (mq_resp_sender, mq_resp_receiver) = crossbeam_channel::unbounded();
+        let (command_req_sender, command_req_receiver) = crossbeam_channel::bounded(0);
+        let (command_resp_sender, command_resp_receiver) = crossbeam_channel::bounded(0);
+        postman.mq_resp_sender = mq_resp_sender;
+        postman.command_req_sender = command_req_sender;
+        postman.command_resp_receiver = command_resp_receiver;
+
+        let address = Address::from(0x1234);
+        let key = H256::from(3);
+        ::std::thread::spawn(move || match command_req_receiver.recv().unwrap() {
+            command::Command::AccountProof(proved, keys, BlockTag::Height(7))
+                if proved == address && keys == vec![key] =>
+            {
+                let proof = AccountProof {
+                    address,
+                    balance: U256::from(0x10),
+                    nonce: U256::from(1),
+                    code_hash: H256::from(1),
+                    storage_hash: H256::from(2),
+                    account_proof: vec![vec![0xc0]],
+                    storage_proof: vec![StorageProof {
+                        key,
+                        value: U256::from(0x55aa),
+                        proof: vec![vec![0xc1, 0x80]],
+                    }],
+                };
+                let _ = command_resp_sender.send(command::CommandResp::AccountProof(Ok(proof)));
+            }
+            _ => panic!("received should be Command::AccountProof"),
+        });
+        let req = ext::Message::Request(ext::Request {
+            id: vec![3],
+            method: ext::GET_PROOF.to_owned(),
+            params: vec![
+                json!("0x0000000000000000000000000000000000001234"),
+                json!(["0x0000000000000000000000000000000000000000000000000000000000000003"]),
+                json!("0x7"),
+            ],
+        });
+        postman.reply_ext_request(&req.to_bytes());
+
+        let (key, message) = mq_resp_receiver.recv().unwrap();
+        assert_eq!(routing_key!(Executor >> RawBytes), RoutingKey::from(key));
+        let data = Message::try_from(message)
+            .unwrap()
+            .take_raw_bytes()
+            .unwrap();
+        let expected = json!({
+            "address": "0x0000000000000000000000000000000000001234",
+            "accountProof": ["0xc0"],
+            "balance": "0x10",
+            "codeHash": "0x0000000000000000000000000000000000000000000000000000000000000001",
+            "nonce": "0x1",
+            "storageHash": "0x0000000000000000000000000000000000000000000000000000000000000002",
+            "storageProof": [{
+                "key": "0x0000000000000000000000000000000000000000000000000000000000000003",
+                "value": "0x55aa",
+                "proof": ["0xc180"],
+            }],
+        });
+        assert_eq!(
+            ext::Message::from_bytes(&data).unwrap(),
+            ext::Message::Response(ext::Response::new(vec![3], Ok(expected)))
+        );
+    }
+
+    #[test]
+    fn test_serve_trie_nodes() {
+        let mut postman = helpers::generate_postman(5, H256::from(0));
+        let (mq_resp_sender, mq_resp_receiver) = crossbeam_channel::unbounded();
+        let (command_req_sender, command_req_receiver) = crossbeam_channel::bounded(0);
+        let (command_resp_sender, command_resp_receiver) = crossbeam_channel::bounded(0);
+        postman.mq_resp_sender = mq_resp_sender;
+        postman.command_req_sender = command_req_sender;
+        postman.command_resp_receiver = command_resp_receiver;
+
+        ::std::thread::spawn(move || match command_req_receiver.recv().unwrap() {
+            command::Command::TrieNodes(hashes) if hashes == vec![H256::from(3)] => {
+                let _ = command_resp_sender.send(command::CommandResp::TrieNodes(vec![vec![0xc0]]));
+            }
+            _ => panic!("received should be Command::TrieNodes"),
+        });
+        let req = ext::Message::GetTrieNodes(vec![
+            "0x0000000000000000000000000000000000000000000000000000000000000003".to_owned(),
+        ]);
+        assert_eq!(postman.handle_state_sync(7, &req.to_bytes()), Ok(()));
+
+        let (key, message) = mq_resp_receiver.recv().unwrap();
+        assert_eq!(
+            routing_key!(Executor >> SyncResponse),
+            RoutingKey::from(key)
+        );
+        let mut message = Message::try_from(message).unwrap();
+        assert_eq!(message.get_origin(), 7);
+        let data = message.take_raw_bytes().unwrap();
+        assert_eq!(
+            ext::Message::from_bytes(&data).unwrap(),
+            ext::Message::TrieNodes(vec!["0xc0".to_owned()])
+        );
+    }
+
+    #[test]
+    fn test_state_headers_after_genesis() {
+        // only a node still at genesis syncs the state
+        let mut postman = helpers::generate_postman(5, H256::from(0));
+        let (mq_resp_sender, mq_resp_receiver) = crossbeam_channel::unbounded();
+        postman.mq_resp_sender = mq_resp_sender;
+
+        let headers = ext::Message::StateHeaders(vec!["0xc0".to_owned()]);
+        assert_eq!(postman.handle_state_sync(7, &headers.to_bytes()), Ok(()));
+        assert!(postman.state_sync.is_none());
+        assert!(mq_resp_receiver.try_recv().is_err());
+    }
+
     fn backlogs_prepare(postman: &mut Postman) {
         let execute_result_0 = generate_executed_result(0);
         let execute_result_1 = generate_executed_result(1);
@@ -0,0 +1,285 @@
+// Copyright Rivtower Technologies LLC.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Access control of a listener: which methods it serves and how often a client may call them.
+//!
+//! Method patterns are either a full method name, or contain one `*` matching any
+//! characters, such as `get*`, `*Filter` or `*`. Rate limits are token buckets kept
+//! per client IP, plus one per client IP and method for methods with their own limit.
+//! The admin methods of `cita_rpc_ext` are refused unless the listener serves admins.
+
+use crate::config::{AccessConfig, RateLimit};
+use cita_rpc_ext as ext;
+use std::collections::HashMap;
+use std::fmt;
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
+use util::Mutex;
+
+/// Error codes of the server error range, as used by other Ethereum-like nodes.
+pub const METHOD_NOT_ALLOWED_CODE: i64 = -32004;
+pub const RATE_LIMITED_CODE: i64 = -32005;
+
+/// Idle buckets are full again, they are dropped this often.
+const CLEAN_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AccessError {
+    MethodNotAllowed(String),
+    RateLimited(String),
+}
+
+impl AccessError {
+    pub fn code(&self) -> i64 {
+        match self {
+            AccessError::MethodNotAllowed(_) => METHOD_NOT_ALLOWED_CODE,
+            AccessError::RateLimited(_) => RATE_LIMITED_CODE,
+        }
+    }
+}
+
+impl fmt::Display for AccessError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AccessError::MethodNotAllowed(method) => {
+                write!(f, "Method {} is not allowed", method)
+            }
+            AccessError::RateLimited(method) => {
+                write!(f, "Rate limit of {} exceeded, please retry later", method)
+            }
+        }
+    }
+}
+
+impl From<AccessError> for jsonrpc_types::Error {
+    fn from(err: AccessError) -> Self {
+        jsonrpc_types::Error::server_error(err.code(), &err.to_string())
+    }
+}
+
+#[derive(Debug)]
+struct TokenBucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl TokenBucket {
+    fn new(limit: RateLimit, now: Instant) -> Self {
+        TokenBucket {
+            tokens: f64::from(limit.burst),
+            updated: now,
+        }
+    }
+
+    fn refill(&mut self, limit: RateLimit, now: Instant) {
+        let elapsed = now.duration_since(self.updated);
+        let elapsed = elapsed.as_secs() as f64 + f64::from(elapsed.subsec_nanos()) / 1e9;
+        self.tokens = (self.tokens + elapsed * f64::from(limit.rate)).min(f64::from(limit.burst));
+        self.updated = now;
+    }
+
+    fn is_full(&self, limit: RateLimit) -> bool {
+        self.tokens >= f64::from(limit.burst)
+    }
+}
+
+/// Buckets are keyed by client IP, and by the method for methods with their own limit.
+type BucketKey = (IpAddr, Option<String>);
+
+pub struct Access {
+    config: AccessConfig,
+    buckets: Mutex<HashMap<BucketKey, TokenBucket>>,
+    cleaned: Mutex<Instant>,
+}
+
+impl Access {
+    pub fn new(config: AccessConfig) -> Self {
+        Access {
+            config,
+            buckets: Mutex::new(HashMap::new()),
+            cleaned: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Check the method against the patterns, then take a token for the call.
+    pub fn check(&self, ip: IpAddr, method: &str) -> Result<(), AccessError> {
+        self.check_method(method)?;
+        self.check_rate(ip, method, Instant::now())
+    }
+
+    fn check_method(&self, method: &str) -> Result<(), AccessError> {
+        let allowed = self.config.allow_methods.as_ref().map_or(true, |patterns| {
+            patterns.iter().any(|pattern| matches(pattern, method))
+        });
+        let denied = (ext::is_admin(method) && !self.config.admin)
+            || self
+                .config
+                .deny_methods
+                .iter()
+                .any(|pattern| matches(pattern, method));
+        if allowed && !denied {
+            Ok(())
+        } else {
+            Err(AccessError::MethodNotAllowed(method.to_owned()))
+        }
+    }
+
+    fn check_rate(&self, ip: IpAddr, method: &str, now: Instant) -> Result<(), AccessError> {
+        let limits = {
+            let mut limits = Vec::with_capacity(2);
+            if let Some(limit) = self.config.rate_limit {
+                limits.push(((ip, None), limit));
+            }
+            if let Some(limit) = self.config.method_rate_limits.get(method) {
+                limits.push(((ip, Some(method.to_owned())), *limit));
+            }
+            limits
+        };
+        if limits.is_empty() {
+            return Ok(());
+        }
+
+        let mut buckets = self.buckets.lock();
+        self.clean(&mut buckets, now);
+        // Both buckets must have a token before any is taken.
+        for (key, limit) in limits.iter() {
+            let bucket = buckets
+                .entry(key.clone())
+                .or_insert_with(|| TokenBucket::new(*limit, now));
+            bucket.refill(*limit, now);
+            if bucket.tokens < 1.0 {
+                return Err(AccessError::RateLimited(method.to_owned()));
+            }
+        }
+        for (key, _) in limits.iter() {
+            if let Some(bucket) = buckets.get_mut(key) {
+                bucket.tokens -= 1.0;
+            }
+        }
+        Ok(())
+    }
+
+    fn clean(&self, buckets: &mut HashMap<BucketKey, TokenBucket>, now: Instant) {
+        let mut cleaned = self.cleaned.lock();
+        if now.duration_since(*cleaned) < CLEAN_INTERVAL {
+            return;
+        }
+        let config = &self.config;
+        buckets.retain(|(_, method), bucket| {
+            let limit = match method {
+                Some(method) => config.method_rate_limits.get(method).cloned(),
+                None => config.rate_limit,
+            };
+            limit.map_or(false, |limit| {
+                bucket.refill(limit, now);
+                !bucket.is_full(limit)
+            })
+        });
+        *cleaned = now;
+    }
+}
+
+/// Whether the method matches the pattern, which contains at most one `*`.
+fn matches(pattern: &str, method: &str) -> bool {
+    match pattern.find('*') {
+        Some(pos) => {
+            let (prefix, suffix) = (&pattern[..pos], &pattern[pos + 1..]);
+            method.len() >= prefix.len() + suffix.len()
+                && method.starts_with(prefix)
+                && method.ends_with(suffix)
+        }
+        None => pattern == method,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{matches, Access, AccessError};
+    use crate::config::{AccessConfig, RateLimit};
+    use std::net::IpAddr;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_matches() {
+        assert!(matches("*", "getLogs"));
+        assert!(matches("get*", "getLogs"));
+        assert!(matches("*Filter", "newFilter"));
+        assert!(matches("getLogs", "getLogs"));
+        assert!(!matches("getLogs", "getLog"));
+        assert!(!matches("get*", "peersInfo"));
+        assert!(!matches("ab*ba", "aba"));
+    }
+
+    #[test]
+    fn test_methods() {
+        let access = Access::new(AccessConfig {
+            allow_methods: Some(vec!["get*".to_owned(), "blockNumber".to_owned()]),
+            deny_methods: vec!["getLogs".to_owned()],
+            ..Default::default()
+        });
+        let ip: IpAddr = "127.0.0.1".parse().unwrap();
+        assert_eq!(access.check(ip, "blockNumber"), Ok(()));
+        assert_eq!(access.check(ip, "getBlockByNumber"), Ok(()));
+        assert_eq!(
+            access.check(ip, "getLogs"),
+            Err(AccessError::MethodNotAllowed("getLogs".to_owned()))
+        );
+        assert!(access.check(ip, "peersInfo").is_err());
+    }
+
+    #[test]
+    fn test_admin_methods() {
+        let ip: IpAddr = "127.0.0.1".parse().unwrap();
+        let access = Access::new(AccessConfig::default());
+        assert_eq!(access.check(ip, "getBlackList"), Ok(()));
+        assert_eq!(
+            access.check(ip, "addBlackList"),
+            Err(AccessError::MethodNotAllowed("addBlackList".to_owned()))
+        );
+
+        let access = Access::new(AccessConfig {
+            admin: true,
+            ..Default::default()
+        });
+        assert_eq!(access.check(ip, "addBlackList"), Ok(()));
+    }
+
+    #[test]
+    fn test_rate_limit() {
+        let mut access = AccessConfig::default();
+        access.rate_limit = Some(RateLimit { rate: 1, burst: 3 });
+        access
+            .method_rate_limits
+            .insert("getLogs".to_owned(), RateLimit { rate: 1, burst: 1 });
+        let access = Access::new(access);
+        let ip: IpAddr = "127.0.0.1".parse().unwrap();
+        let other: IpAddr = "127.0.0.2".parse().unwrap();
+        let now = Instant::now();
+
+        assert_eq!(access.check_rate(ip, "getLogs", now), Ok(()));
+        // The bucket of getLogs is empty, the one of the IP is untouched.
+        assert!(access.check_rate(ip, "getLogs", now).is_err());
+        assert_eq!(access.check_rate(ip, "blockNumber", now), Ok(()));
+        assert_eq!(access.check_rate(ip, "blockNumber", now), Ok(()));
+        assert_eq!(
+            access.check_rate(ip, "blockNumber", now),
+            Err(AccessError::RateLimited("blockNumber".to_owned()))
+        );
+        assert_eq!(access.check_rate(other, "blockNumber", now), Ok(()));
+
+        let later = now + Duration::from_secs(1);
+        assert_eq!(access.check_rate(ip, "getLogs", later), Ok(()));
+        assert!(access.check_rate(ip, "blockNumber", later).is_err());
+    }
+}
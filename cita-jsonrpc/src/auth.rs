@@ -0,0 +1,156 @@
+// Copyright Rivtower Technologies LLC.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Bearer token authentication of the RPC listeners.
+//!
+//! A client sends `Authorization: Bearer <token>`, where the token is either one of
+//! the configured API keys, or a JWT signed with HS256 by the configured secret.
+//! The identity of an API key is the name it is configured with, the one of a JWT
+//! is its `sub` claim.
+
+use crate::config::AuthConfig;
+use jsonwebtoken::{Algorithm, Validation};
+use std::collections::HashMap;
+use std::fmt;
+
+const BEARER_PREFIX: &str = "Bearer ";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AuthError {
+    MissingToken,
+    InvalidToken,
+}
+
+impl fmt::Display for AuthError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AuthError::MissingToken => write!(f, "missing bearer token"),
+            AuthError::InvalidToken => write!(f, "invalid bearer token"),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct Claims {
+    sub: String,
+}
+
+pub struct Authenticator {
+    /// Identities by API key
+    api_keys: HashMap<String, String>,
+    jwt_secret: Option<String>,
+}
+
+impl Authenticator {
+    pub fn new(config: AuthConfig) -> Self {
+        let api_keys = config
+            .api_keys
+            .into_iter()
+            .map(|(identity, key)| (key, identity))
+            .collect();
+        Authenticator {
+            api_keys,
+            jwt_secret: config.jwt_secret,
+        }
+    }
+
+    /// Returns the identity of the `Authorization` header.
+    pub fn authenticate(&self, authorization: Option<&str>) -> Result<String, AuthError> {
+        let token = authorization
+            .and_then(|value| {
+                if value.starts_with(BEARER_PREFIX) {
+                    Some(value[BEARER_PREFIX.len()..].trim())
+                } else {
+                    None
+                }
+            })
+            .filter(|token| !token.is_empty())
+            .ok_or(AuthError::MissingToken)?;
+
+        if let Some(identity) = self.api_keys.get(token) {
+            return Ok(identity.clone());
+        }
+        match self.jwt_secret {
+            Some(ref secret) => jsonwebtoken::decode::<Claims>(
+                token,
+                secret.as_bytes(),
+                &Validation::new(Algorithm::HS256),
+            )
+            .map(|data| data.claims.sub)
+            .map_err(|e| {
+                debug!("decode jwt: {}", e);
+                AuthError::InvalidToken
+            }),
+            None => Err(AuthError::InvalidToken),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AuthError, Authenticator};
+    use crate::config::AuthConfig;
+    use jsonwebtoken::{encode, Header};
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    fn authenticator() -> Authenticator {
+        let mut config = AuthConfig::default();
+        config
+            .api_keys
+            .insert("alice".to_owned(), "alice-key".to_owned());
+        config.jwt_secret = Some("secret".to_owned());
+        Authenticator::new(config)
+    }
+
+    fn jwt(sub: &str, secret: &str) -> String {
+        let exp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+            + 3600;
+        let claims = json!({"sub": sub, "exp": exp});
+        encode(&Header::default(), &claims, secret.as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn test_api_key() {
+        let auth = authenticator();
+        assert_eq!(
+            auth.authenticate(Some("Bearer alice-key")),
+            Ok("alice".to_owned())
+        );
+        assert_eq!(
+            auth.authenticate(Some("Bearer bob-key")),
+            Err(AuthError::InvalidToken)
+        );
+        assert_eq!(
+            auth.authenticate(Some("alice-key")),
+            Err(AuthError::MissingToken)
+        );
+        assert_eq!(auth.authenticate(None), Err(AuthError::MissingToken));
+    }
+
+    #[test]
+    fn test_jwt() {
+        let auth = authenticator();
+        let token = format!("Bearer {}", jwt("bob", "secret"));
+        assert_eq!(auth.authenticate(Some(&token)), Ok("bob".to_owned()));
+
+        let token = format!("Bearer {}", jwt("bob", "other secret"));
+        assert_eq!(
+            auth.authenticate(Some(&token)),
+            Err(AuthError::InvalidToken)
+        );
+    }
+}
@@ -12,6 +12,8 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use serde::de::{Deserialize, Deserializer, Error};
+use std::collections::HashMap;
 use std::convert::Into;
 use ws::Settings;
 
@@ -23,6 +25,8 @@ pub struct Config {
     pub http_config: HttpConfig,
     pub ws_config: WsConfig,
     pub new_tx_flow_config: NewTxFlowConfig,
+    #[serde(default)]
+    pub health_config: HealthConfig,
 }
 
 impl Config {
@@ -37,6 +41,39 @@ pub struct NewTxFlowConfig {
     pub buffer_duration: u32, //in unit of ns
 }
 
+/// Checks of `GET /health` and `GET /ready`.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+#[serde(default)]
+pub struct HealthConfig {
+    /// Seconds between two status queries to chain, must not be 0
+    #[serde(deserialize_with = "deserialize_non_zero")]
+    pub probe_interval: u64,
+    /// Seconds without any message from MQ before the node is unhealthy
+    pub mq_timeout: u64,
+    /// Block intervals the height may stay still before the node is not ready
+    pub lag_intervals: u32,
+}
+
+impl Default for HealthConfig {
+    fn default() -> Self {
+        HealthConfig {
+            probe_interval: 3,
+            mq_timeout: 30,
+            lag_intervals: 10,
+        }
+    }
+}
+
+fn deserialize_non_zero<'de, D>(deserializer: D) -> Result<u64, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    match u64::deserialize(deserializer)? {
+        0 => Err(D::Error::custom("must not be 0")),
+        value => Ok(value),
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone, Copy)]
 pub struct ProfileConfig {
     pub enable: bool,
@@ -50,6 +87,16 @@ pub struct WsConfig {
     pub thread_number: usize,
     pub listen_ip: String,
     pub listen_port: String,
+    #[serde(default)]
+    pub access: AccessConfig,
+    pub tls: Option<TlsConfig>,
+    pub auth: Option<AuthConfig>,
+    /// Most subscriptions of one connection, 0 means no limit
+    #[serde(default = "default_max_subscriptions_per_connection")]
+    pub max_subscriptions_per_connection: usize,
+    /// Most subscriptions of all connections, 0 means no limit
+    #[serde(default = "default_max_subscriptions")]
+    pub max_subscriptions: usize,
 
     max_connections: usize,
     queue_size: usize,
@@ -77,6 +124,14 @@ pub struct WsConfig {
     tcp_nodelay: bool,
 }
 
+fn default_max_subscriptions_per_connection() -> usize {
+    64
+}
+
+fn default_max_subscriptions() -> usize {
+    10_000
+}
+
 impl Into<Settings> for WsConfig {
     fn into(self) -> Settings {
         Settings {
@@ -102,7 +157,7 @@ impl Into<Settings> for WsConfig {
             masking_strict: self.masking_strict,
             key_strict: self.key_strict,
             method_strict: self.method_strict,
-            encrypt_server: self.encrypt_server,
+            encrypt_server: self.encrypt_server || self.tls.is_some(),
             tcp_nodelay: self.tcp_nodelay,
         }
     }
@@ -116,4 +171,57 @@ pub struct HttpConfig {
     pub listen_port: String,
     pub timeout: u64,
     pub allow_origin: Option<String>,
+    #[serde(default)]
+    pub access: AccessConfig,
+    pub tls: Option<TlsConfig>,
+    pub auth: Option<AuthConfig>,
+}
+
+/// Certificate chain and private key of a listener, both in PEM.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TlsConfig {
+    pub cert_file: String,
+    pub key_file: String,
+    /// Seconds a client has to finish the TLS handshake
+    #[serde(default = "default_handshake_timeout")]
+    pub handshake_timeout: u64,
+}
+
+fn default_handshake_timeout() -> u64 {
+    10
+}
+
+/// Bearer tokens accepted by a listener, see `auth`.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct AuthConfig {
+    /// API keys by the identity of their owner
+    pub api_keys: HashMap<String, String>,
+    /// Secret of HS256 signed JWTs, whose identity is the `sub` claim
+    pub jwt_secret: Option<String>,
+}
+
+/// Methods served by a listener and rate limits of its clients, see `access`.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct AccessConfig {
+    /// Patterns of the methods served, all methods if not set
+    pub allow_methods: Option<Vec<String>>,
+    /// Patterns of the methods refused, even if allowed
+    pub deny_methods: Vec<String>,
+    /// Serve the admin methods, such as `addBlackList`, which are refused by default
+    pub admin: bool,
+    /// Limit of all requests from a client IP
+    pub rate_limit: Option<RateLimit>,
+    /// Limits of a method's requests from a client IP
+    pub method_rate_limits: HashMap<String, RateLimit>,
+}
+
+/// A token bucket.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct RateLimit {
+    /// Requests allowed per second
+    pub rate: u32,
+    /// Requests allowed at once
+    pub burst: u32,
 }
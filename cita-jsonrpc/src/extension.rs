@@ -0,0 +1,164 @@
+// Copyright Rivtower Technologies LLC.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Calls of the methods of `cita_rpc_ext`, which the proto requests can not carry.
+//!
+//! They are published as `RawBytes` and their outputs are built here, the other methods
+//! still go through `complete_and_into_proto` and `Output::from_res_info`.
+
+use crate::helper::{ExtReply, RpcMap, TransferType};
+use cita_rpc_ext::{self as ext, service_of};
+use jsonrpc_types::rpc_request::{PartialRequest, RequestInfo};
+use libproto::router::{MsgType, RoutingKey, SubModules};
+use libproto::{Message, MsgClass, OperateType, TryInto};
+use pubsub::channel::Sender;
+use serde_json::{self, Value};
+use uuid::Uuid;
+
+pub type RawSender = Sender<(String, Vec<u8>)>;
+
+#[derive(Debug)]
+pub struct ExtCall {
+    pub info: RequestInfo,
+    /// Id of the JSON-RPC request as the client sent it
+    pub id: Value,
+    pub request: ext::Request,
+}
+
+impl ExtCall {
+    /// The call of `part_req`, `None` if it calls a method of `jsonrpc-types`.
+    pub fn from_partial(part_req: &PartialRequest) -> Option<ExtCall> {
+        let req = serde_json::to_value(part_req).ok()?;
+        let method = req.get("method").and_then(Value::as_str)?;
+        let params = match req.get("params") {
+            Some(Value::Array(params)) => params.clone(),
+            _ => return None,
+        };
+        service_of(method, &params)?;
+        Some(ExtCall {
+            info: part_req.get_info(),
+            id: req.get("id").cloned().unwrap_or(Value::Null),
+            request: ext::Request {
+                id: Uuid::new_v4().as_bytes().to_vec(),
+                method: method.to_owned(),
+                params,
+            },
+        })
+    }
+
+    pub fn method(&self) -> &str {
+        &self.request.method
+    }
+
+    /// Wait for the output in `responses` and publish the call.
+    pub fn publish(self, responses: &RpcMap, sender: &RawSender, reply: ExtReply) {
+        responses.lock().insert(
+            self.request.id.clone(),
+            TransferType::EXTENSION((self.id, reply)),
+        );
+
+        let data = ext::Message::Request(self.request).to_bytes();
+        let msg = Message::init(OperateType::Single, 0, MsgClass::RawBytes(data));
+        // NOTE: send failure is handled as timeout error
+        let _ = sender.send((
+            routing_key!(Jsonrpc >> RawBytes).into(),
+            msg.try_into().unwrap(),
+        ));
+    }
+}
+
+/// Deliver the output of the response to the client waiting for it.
+pub fn reply(responses: &RpcMap, resp: ext::Response) {
+    let transfer = responses.lock().remove(&resp.id);
+    match transfer {
+        Some(TransferType::EXTENSION((id, reply))) => {
+            let output = output(id, resp.result);
+            let _ = match reply {
+                ExtReply::Http(sender) => sender.send(output).map_err(|e| {
+                    error!("http: {:?}", e);
+                }),
+                ExtReply::Websocket(sender) => sender.send(output.to_string()).map_err(|e| {
+                    error!("ws: {:?}", e);
+                }),
+            };
+        }
+        Some(_) => error!("extension response of request {:?}", resp.id),
+        None => warn!("receive lost request_id {:?}", resp.id),
+    }
+}
+
+pub fn output(id: Value, result: Result<Value, ext::Error>) -> Value {
+    match result {
+        Ok(result) => json!({"jsonrpc": "2.0", "id": id, "result": result}),
+        Err(err) => failure(id, err.code, &err.message),
+    }
+}
+
+fn failure(id: Value, code: i64, message: &str) -> Value {
+    json!({"jsonrpc": "2.0", "id": id, "error": {"code": code, "message": message}})
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{output, ExtCall};
+    use cita_rpc_ext::Error;
+    use jsonrpc_types::rpc_request::PartialRequest;
+    use serde_json::{self, Value};
+
+    fn partial(req: Value) -> PartialRequest {
+        serde_json::from_value(req).unwrap()
+    }
+
+    #[test]
+    fn test_from_partial() {
+        let call = ExtCall::from_partial(&partial(json!({
+            "jsonrpc": "2.0",
+            "id": 7,
+            "method": "getLogs",
+            "params": [{"fromBlock": "0x1"}, {"blockNumber": 3, "logIndex": 1}],
+        })))
+        .unwrap();
+        assert_eq!(call.id, json!(7));
+        assert_eq!(call.method(), "getLogs");
+        assert_eq!(
+            call.request.params[1],
+            json!({"blockNumber": 3, "logIndex": 1})
+        );
+        assert_eq!(call.request.id.len(), 16);
+
+        let plain = partial(json!({
+            "jsonrpc": "2.0",
+            "id": 8,
+            "method": "getLogs",
+            "params": [{"fromBlock": "0x1"}],
+        }));
+        assert!(ExtCall::from_partial(&plain).is_none());
+    }
+
+    #[test]
+    fn test_output() {
+        assert_eq!(
+            output(json!("a"), Ok(json!([]))),
+            json!({"jsonrpc": "2.0", "id": "a", "result": []})
+        );
+        assert_eq!(
+            output(json!(1), Err(Error::invalid_params("bad cursor"))),
+            json!({
+                "jsonrpc": "2.0",
+                "id": 1,
+                "error": {"code": -32602, "message": "bad cursor"},
+            })
+        );
+    }
+}
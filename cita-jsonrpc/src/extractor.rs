@@ -19,7 +19,8 @@ use jsonrpc_types::rpc_request::{
 };
 use libproto::request::Request as ProtoRequest;
 
-use crate::mq_publisher::{HybridRequest, MQRequest};
+use crate::extension::ExtCall;
+use crate::mq_publisher::{Call, HybridRequest, MQRequest};
 use crate::service_error::ServiceError;
 
 pub trait FutExtractor<T> {
@@ -57,17 +58,22 @@ impl FutExtractor<JsonrpcRequest> for hyper::Request<hyper::Body> {
     }
 }
 
-impl Extractor<HybridRequest> for PartialRequest {
+impl Extractor<Call> for PartialRequest {
     type Error = ServiceError;
 
-    fn extract_from(self) -> Result<HybridRequest, Self::Error> {
+    fn extract_from(self) -> Result<Call, Self::Error> {
+        if let Some(ext_call) = ExtCall::from_partial(&self) {
+            return Ok(Call::Extension(ext_call));
+        }
         let req_info = self.get_info();
 
         self.complete_and_into_proto()
             .map_err(|e| ServiceError::JsonrpcPartCompleteError(req_info, e))
-            .map(|req: (JsonRequest, ProtoRequest)| HybridRequest {
-                json_req: req.0,
-                proto_req: req.1,
+            .map(|req: (JsonRequest, ProtoRequest)| {
+                Call::Hybrid(HybridRequest {
+                    json_req: req.0,
+                    proto_req: req.1,
+                })
             })
     }
 }
@@ -78,12 +84,12 @@ impl FutExtractor<MQRequest> for JsonrpcRequest {
 
     fn extract_from(self) -> Self::Fut {
         let fut_ret: FutureResult<MQRequest, ServiceError> = match self {
-            JsonrpcRequest::Single(part_req) => Extractor::<HybridRequest>::extract_from(part_req)
-                .map(|hybrid_req| MQRequest::Single(Box::new(hybrid_req))),
+            JsonrpcRequest::Single(part_req) => Extractor::<Call>::extract_from(part_req)
+                .map(|call| MQRequest::Single(Box::new(call))),
             JsonrpcRequest::Batch(part_reqs) => part_reqs
                 .into_iter()
-                .map(Extractor::<HybridRequest>::extract_from)
-                .collect::<Result<Vec<HybridRequest>, ServiceError>>()
+                .map(Extractor::<Call>::extract_from)
+                .collect::<Result<Vec<Call>, ServiceError>>()
                 .map(MQRequest::Batch),
         }
         .into();
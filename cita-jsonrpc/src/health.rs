@@ -0,0 +1,207 @@
+// Copyright Rivtower Technologies LLC.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Liveness and readiness of the node, served on `GET /health` and `GET /ready`.
+//!
+//! The node is healthy while messages keep coming from MQ, a status query is sent
+//! to chain every `probe_interval` so that there is always something to receive.
+//! It is ready when it is healthy, not behind the highest height network knows of
+//! other nodes, and its height has advanced within `lag_intervals` block intervals.
+
+use crate::config::HealthConfig;
+use crate::helper::{RpcMap, TransferType};
+use libproto::request::Request as ProtoRequest;
+use libproto::router::{MsgType, RoutingKey, SubModules};
+use pubsub::channel::Sender;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+use util::Mutex;
+use uuid::Uuid;
+
+/// Block interval assumed before chain tells the real one.
+const DEFAULT_BLOCK_INTERVAL: Duration = Duration::from_secs(3);
+/// Blocks the node may be behind other nodes, while the latest block is being delivered.
+const SYNC_TOLERANCE: u64 = 2;
+
+pub type HealthState = Arc<Mutex<Health>>;
+
+#[derive(Debug)]
+pub struct Health {
+    config: HealthConfig,
+    last_message: Option<Instant>,
+    // The highest height of other nodes
+    network_height: u64,
+    height: Option<(u64, Instant)>,
+    block_interval: Duration,
+}
+
+impl Health {
+    pub fn new(config: HealthConfig) -> Self {
+        Health {
+            config,
+            last_message: None,
+            network_height: 0,
+            height: None,
+            block_interval: DEFAULT_BLOCK_INTERVAL,
+        }
+    }
+
+    /// A message arrived from MQ.
+    pub fn on_message(&mut self, now: Instant) {
+        self.last_message = Some(now);
+    }
+
+    /// The highest height of other nodes, as network knows.
+    pub fn on_network_height(&mut self, height: u64) {
+        self.network_height = height;
+    }
+
+    /// The current height of chain, only a higher one restarts the lag timer.
+    pub fn on_height(&mut self, height: u64, now: Instant) {
+        match self.height {
+            Some((current, _)) if current >= height => {}
+            _ => self.height = Some((height, now)),
+        }
+    }
+
+    /// The block interval of chain, in milliseconds.
+    pub fn on_block_interval(&mut self, interval: u64) {
+        if interval > 0 {
+            self.block_interval = Duration::from_millis(interval);
+        }
+    }
+
+    pub fn check_health(&self, now: Instant) -> Result<(), String> {
+        let mq_timeout = Duration::from_secs(self.config.mq_timeout);
+        match self.last_message {
+            Some(at) if now.duration_since(at) <= mq_timeout => Ok(()),
+            Some(at) => Err(format!(
+                "no message from MQ for {}s",
+                now.duration_since(at).as_secs()
+            )),
+            None => Err("no message from MQ yet".to_owned()),
+        }
+    }
+
+    /// Returns the current height if the node is ready to serve.
+    pub fn check_ready(&self, now: Instant) -> Result<u64, String> {
+        self.check_health(now)?;
+        let max_lag = self.block_interval * self.config.lag_intervals;
+        match self.height {
+            Some((height, _)) if height + SYNC_TOLERANCE < self.network_height => Err(format!(
+                "synchronizing blocks, height {} of {}",
+                height, self.network_height
+            )),
+            Some((height, at)) if now.duration_since(at) <= max_lag => Ok(height),
+            Some((height, at)) => Err(format!(
+                "height {} has not advanced for {}s",
+                height,
+                now.duration_since(at).as_secs()
+            )),
+            None => Err("no status from chain yet".to_owned()),
+        }
+    }
+}
+
+/// Query the height of chain every `interval`, its response keeps the MQ check alive.
+pub fn probe_chain(responses: RpcMap, tx: Sender<(String, ProtoRequest)>, interval: Duration) {
+    let mut last_request_id = None;
+    loop {
+        // Forget the last query if chain has not answered it.
+        if let Some(request_id) = last_request_id.take() {
+            responses.lock().remove(&request_id);
+        }
+
+        let request_id = Uuid::new_v4().as_bytes().to_vec();
+        let mut request = ProtoRequest::new();
+        request.set_block_number(true);
+        request.set_request_id(request_id.clone());
+        responses
+            .lock()
+            .insert(request_id.clone(), TransferType::HEALTH);
+        if let Err(e) = tx.send((routing_key!(Jsonrpc >> Request).into(), request)) {
+            error!("health probe: {:?}", e);
+        }
+        last_request_id = Some(request_id);
+
+        thread::sleep(interval);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Health;
+    use crate::config::HealthConfig;
+    use std::time::{Duration, Instant};
+
+    fn health() -> Health {
+        Health::new(HealthConfig {
+            probe_interval: 3,
+            mq_timeout: 30,
+            lag_intervals: 10,
+        })
+    }
+
+    #[test]
+    fn test_health() {
+        let start = Instant::now();
+        let mut health = health();
+        assert!(health.check_health(start).is_err());
+
+        health.on_message(start);
+        assert!(health.check_health(start + Duration::from_secs(30)).is_ok());
+        assert!(health
+            .check_health(start + Duration::from_secs(31))
+            .is_err());
+    }
+
+    #[test]
+    fn test_ready() {
+        let start = Instant::now();
+        let mut health = health();
+        health.on_message(start);
+        assert!(health.check_ready(start).is_err());
+
+        health.on_block_interval(1000);
+        health.on_height(5, start);
+        assert_eq!(health.check_ready(start), Ok(5));
+
+        // The same height does not restart the lag timer.
+        let later = start + Duration::from_secs(9);
+        health.on_message(later);
+        health.on_height(5, later);
+        assert_eq!(health.check_ready(start + Duration::from_secs(10)), Ok(5));
+        assert!(health.check_ready(start + Duration::from_secs(11)).is_err());
+
+        let later = start + Duration::from_secs(11);
+        health.on_height(6, later);
+        assert_eq!(health.check_ready(later), Ok(6));
+
+        // Other nodes are far ahead.
+        health.on_network_height(9);
+        assert!(health.check_ready(later).is_err());
+        health.on_height(7, later);
+        assert_eq!(health.check_ready(later), Ok(7));
+    }
+
+    #[test]
+    fn test_zero_probe_interval() {
+        let config: Result<HealthConfig, _> = serde_json::from_str(r#"{"probe_interval": 0}"#);
+        assert!(config.is_err());
+        let config: HealthConfig = serde_json::from_str(r#"{"probe_interval": 1}"#).unwrap();
+        assert_eq!(config.probe_interval, 1);
+        assert_eq!(config.mq_timeout, 30);
+    }
+}
@@ -14,20 +14,32 @@
 
 use futures::sync::oneshot;
 use jsonrpc_types::rpc_request::RequestInfo;
-use jsonrpc_types::rpc_response::Output;
 use libproto::request::Request as ProtoRequest;
 use libproto::router::{MsgType, RoutingKey, SubModules};
 use pubsub::channel::Sender;
+use serde_json::Value;
 use std::collections::HashMap;
+use std::net::IpAddr;
 use std::sync::Arc;
 use util::Mutex;
 use ws;
 
 pub enum TransferType {
     /// http output sender
-    HTTP((RequestInfo, oneshot::Sender<Output>)),
+    HTTP((RequestInfo, oneshot::Sender<Value>)),
     /// websocket output sender
     WEBSOCKET((RequestInfo, ws::Sender)),
+    /// id of the JSON-RPC request of an extension call, and where its output goes
+    EXTENSION((Value, ExtReply)),
+    /// websocket subscriptions to push the output to, and whether to push its items one by one
+    SUBSCRIPTION((Vec<String>, bool)),
+    /// status query of the health check
+    HEALTH,
+}
+
+pub enum ExtReply {
+    Http(oneshot::Sender<Value>),
+    Websocket(ws::Sender),
 }
 
 pub type RpcMap = Arc<Mutex<HashMap<Vec<u8>, TransferType>>>;
@@ -43,9 +55,24 @@ pub fn select_topic(method: &str) -> String {
     }
 }
 
+/// What goes between the request id and the client appended to it, chain finds the
+/// client by it in `filters::filterdb::client_of`.
+pub const CLIENT_TAG: &[u8] = b"client:";
+
+/// Append the client to the request id of the requests installing a filter,
+/// chain limits how many filters one client may install by it.
+pub fn tag_client(method: &str, req: &mut ProtoRequest, remote_ip: IpAddr) {
+    if let "newFilter" | "newBlockFilter" = method {
+        let request_id = req.mut_request_id();
+        request_id.extend_from_slice(CLIENT_TAG);
+        request_id.extend_from_slice(remote_ip.to_string().as_bytes());
+    }
+}
+
 #[cfg(test)]
 mod test {
-    use super::select_topic;
+    use super::{select_topic, tag_client};
+    use libproto::request::Request as ProtoRequest;
 
     #[test]
     fn test_get_topic() {
@@ -61,4 +88,18 @@ mod test {
         );
         assert_eq!(select_topic("error"), "jsonrpc.request".to_string());
     }
+
+    #[test]
+    fn test_tag_client() {
+        let ip = "127.0.0.1".parse().unwrap();
+        let mut req = ProtoRequest::new();
+        req.set_request_id(vec![0x63; 16]);
+        tag_client("blockNumber", &mut req, ip);
+        assert_eq!(req.get_request_id(), &[0x63; 16][..]);
+        tag_client("newFilter", &mut req, ip);
+        // The request id chain's `client_of` is tested with.
+        let mut expected = vec![0x63; 16];
+        expected.extend_from_slice(b"client:127.0.0.1");
+        assert_eq!(req.get_request_id(), &expected[..]);
+    }
 }
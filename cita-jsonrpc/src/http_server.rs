@@ -13,46 +13,118 @@
 // limitations under the License.
 
 use futures::future::{self as future, Future};
+use futures::Stream;
 use hyper::header::{
     HeaderMap as Headers, HeaderName, HeaderValue, ACCEPT, ACCESS_CONTROL_ALLOW_HEADERS,
     ACCESS_CONTROL_ALLOW_METHODS, ACCESS_CONTROL_ALLOW_ORIGIN, ACCESS_CONTROL_MAX_AGE,
-    CONTENT_TYPE, ORIGIN, USER_AGENT,
+    AUTHORIZATION, CONTENT_TYPE, ORIGIN, USER_AGENT, WWW_AUTHENTICATE,
 };
+use hyper::server::conn::{AddrIncoming, AddrStream};
 use hyper::service::{MakeService, Service};
 use hyper::{Body, Method, Request, Response, StatusCode};
 use jsonrpc_types::{rpc_request::RpcRequest as JsonrpcRequest, rpc_types::Id as RpcId};
 use libproto::request::Request as ProtoRequest;
+use openssl::ssl::SslAcceptor;
 use pubsub::channel::Sender;
-use std::net::{SocketAddr, TcpListener};
+use serde_json::Value;
+use std::io::{self, Read, Write};
+use std::net::{IpAddr, SocketAddr, TcpListener};
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
+use tokio::net::{TcpListener as AsyncTcpListener, TcpStream};
+use tokio::reactor::Handle;
+use tokio_io::{AsyncRead, AsyncWrite};
+use tokio_openssl::{SslAcceptorExt, SslStream};
+use tokio_timer::{Delay, Timeout};
 use util::Mutex;
 
+use crate::access::Access;
+use crate::auth::Authenticator;
+use crate::config::HttpConfig;
+use crate::extension::RawSender;
 use crate::extractor::FutExtractor;
+use crate::health::HealthState;
 use crate::helper::{ReqSender, RpcMap};
 use crate::http_header::{Origin, CONTENT_TYPE_JSON_STR, CONTENT_TYPE_PLAIN_TEXT_STR};
+use crate::metrics;
 use crate::mq_publisher::{AccessLog as MQAccessLog, MQRequest, Publisher, TimeoutPublisher};
 use crate::response::{HyperResponseExt, IntoResponse};
 
 const TCP_BACKLOG: i32 = 1024;
 const CORS_CACHE: u32 = 86_400u32;
+/// TLS handshakes in progress at once.
+const TLS_HANDSHAKES: usize = 128;
+/// Pause of accepting after an error, such as running out of file descriptors.
+const ACCEPT_ERROR_DELAY: Duration = Duration::from_secs(1);
 
 struct Inner {
     pub tx: ReqSender,
+    pub ext_tx: Mutex<RawSender>,
     pub responses: RpcMap,
+    pub health: HealthState,
+    pub access: Arc<Access>,
+    pub auth: Option<Authenticator>,
     pub timeout: Duration,
     pub http_headers: Headers,
 }
 
 pub struct Jsonrpc {
     inner: Arc<Inner>,
+    remote_ip: IpAddr,
 }
 
 pub struct JsonrpcMakeService {
     inner: Arc<Inner>,
 }
 
-impl<Ctx> MakeService<Ctx> for JsonrpcMakeService {
+/// A connection of the listener, plain or over TLS.
+pub trait Connection {
+    fn remote_addr(&self) -> SocketAddr;
+}
+
+impl Connection for AddrStream {
+    fn remote_addr(&self) -> SocketAddr {
+        AddrStream::remote_addr(self)
+    }
+}
+
+/// A TLS connection, with the address of its peer taken before the handshake.
+pub struct TlsStream {
+    inner: SslStream<TcpStream>,
+    remote_addr: SocketAddr,
+}
+
+impl Connection for TlsStream {
+    fn remote_addr(&self) -> SocketAddr {
+        self.remote_addr
+    }
+}
+
+impl Read for TlsStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.read(buf)
+    }
+}
+
+impl Write for TlsStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.inner.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl AsyncRead for TlsStream {}
+
+impl AsyncWrite for TlsStream {
+    fn shutdown(&mut self) -> futures::Poll<(), io::Error> {
+        self.inner.shutdown()
+    }
+}
+
+impl<'a, C: Connection> MakeService<&'a C> for JsonrpcMakeService {
     type ReqBody = Body;
     type ResBody = Body;
     type Error = hyper::Error;
@@ -60,9 +132,10 @@ impl<Ctx> MakeService<Ctx> for JsonrpcMakeService {
     type Future = Box<dyn Future<Item = Self::Service, Error = Self::Error> + Send>;
     type MakeError = hyper::Error;
 
-    fn make_service(&mut self, _: Ctx) -> Self::Future {
+    fn make_service(&mut self, conn: &'a C) -> Self::Future {
         Box::new(future::ok(Jsonrpc {
             inner: Arc::clone(&self.inner),
+            remote_ip: conn.remote_addr().ip(),
         }))
     }
 }
@@ -71,6 +144,7 @@ struct AccessLog {
     user_agent: String,
     http_method: Method,
     http_path: String,
+    identity: Option<String>,
     rpc_info: Option<RpcAccessLog>,
 }
 
@@ -111,10 +185,15 @@ impl AccessLog {
             user_agent,
             http_method: http_method.clone(),
             http_path: http_path.to_owned(),
+            identity: None,
             rpc_info: None,
         }
     }
 
+    pub fn set_identity(&mut self, identity: String) {
+        self.identity = Some(identity);
+    }
+
     pub fn set_rpc_info(&mut self, rpc_acc_log: RpcAccessLog) {
         self.rpc_info = Some(rpc_acc_log);
     }
@@ -125,6 +204,9 @@ impl ::std::fmt::Display for AccessLog {
         write!(f, "user-agent={}", self.user_agent)?;
         write!(f, ", http-method={}", self.http_method)?;
         write!(f, ", http-path={}", self.http_path)?;
+        if let Some(ref identity) = self.identity {
+            write!(f, ", identity={}", identity)?;
+        }
         match self.rpc_info {
             Some(RpcAccessLog::Single(ref sl)) => {
                 write!(f, ", rpc-type=single")?;
@@ -156,40 +238,99 @@ impl Service for Jsonrpc {
 
     fn call(&mut self, http_req: Request<Self::ReqBody>) -> Self::Future {
         let sender = { self.inner.tx.lock().clone() };
+        let ext_sender = { self.inner.ext_tx.lock().clone() };
         let responses = Arc::clone(&self.inner.responses);
         let timeout = self.inner.timeout;
         let http_headers = self.inner.http_headers.clone();
+        let access = Arc::clone(&self.inner.access);
+        let remote_ip = self.remote_ip;
 
         let http_path = http_req.uri().path().to_owned();
         let mut access_log = AccessLog::new(http_req.method(), &http_path, &http_headers);
 
         match (http_req.method(), http_path.as_ref()) {
             (&Method::POST, "/") => {
+                if let Some(ref auth) = self.inner.auth {
+                    let authorization = http_req
+                        .headers()
+                        .get(AUTHORIZATION)
+                        .and_then(|value| value.to_str().ok());
+                    match auth.authenticate(authorization) {
+                        Ok(identity) => access_log.set_identity(identity),
+                        Err(err) => {
+                            info!("{}, auth-error={}", access_log, err);
+                            return Box::new(future::ok(unauthorized(http_headers)));
+                        }
+                    }
+                }
+
+                let start = Instant::now();
+                let rpc_method = Arc::new(Mutex::new(metrics::UNKNOWN_METHOD.to_owned()));
                 let fut_resp = FutExtractor::<JsonrpcRequest>::extract_from(http_req)
                     .and_then(FutExtractor::<MQRequest>::extract_from)
                     .and_then({
                         let headers = http_headers.clone();
+                        let rpc_method = Arc::clone(&rpc_method);
 
                         move |mq_req| {
                             // logging
-                            access_log.set_rpc_info(RpcAccessLog::from(mq_req.access_log()));
+                            let mq_log = mq_req.access_log();
+                            *rpc_method.lock() = match mq_log {
+                                MQAccessLog::Single {
+                                    method: Some(ref method),
+                                    ..
+                                } => method.clone(),
+                                MQAccessLog::Single { method: None, .. } => {
+                                    metrics::UNKNOWN_METHOD.to_owned()
+                                }
+                                MQAccessLog::Batch { .. } => metrics::BATCH_METHOD.to_owned(),
+                            };
+                            access_log.set_rpc_info(RpcAccessLog::from(mq_log));
                             info!("{}", access_log);
 
                             let timeout_responses = Arc::clone(&responses);
-                            let pulibsher = Publisher::new(responses, sender, headers);
+                            let pulibsher = Publisher::new(
+                                responses, sender, ext_sender, headers, access, remote_ip,
+                            );
                             let pulibsher =
                                 TimeoutPublisher::new(pulibsher, timeout, timeout_responses);
 
                             pulibsher.publish(mq_req)
                         }
                     })
-                    .then(move |resp| match resp {
-                        Ok(resp) => Ok(resp),
-                        Err(err) => Ok(err.into_response(http_headers.clone())),
+                    .then(move |resp| {
+                        let resp = match resp {
+                            Ok(resp) => resp,
+                            Err(err) => err.into_response(http_headers.clone()),
+                        };
+                        metrics::observe_request(
+                            &rpc_method.lock(),
+                            resp.status(),
+                            start.elapsed(),
+                        );
+                        Ok(resp)
                     });
 
                 Box::new(fut_resp)
             }
+            (&Method::GET, "/health") => {
+                // Probed every few seconds, keep them out of the info log.
+                debug!("{}", access_log);
+                let result = self.inner.health.lock().check_health(Instant::now());
+                let resp = health_response(result.map(|_| json!({"status": "ok"})), http_headers);
+
+                Box::new(future::ok(resp))
+            }
+            (&Method::GET, "/ready") => {
+                debug!("{}", access_log);
+                let result = self.inner.health.lock().check_ready(Instant::now());
+                let resp = health_response(
+                    result.map(|height| json!({"status": "ok", "height": height})),
+                    http_headers,
+                );
+
+                Box::new(future::ok(resp))
+            }
             (&Method::OPTIONS, "/") => {
                 info!("{}", access_log);
                 let resp = Response::default().with_headers(handle_preflighted(http_headers));
@@ -208,6 +349,27 @@ impl Service for Jsonrpc {
     }
 }
 
+fn health_response(result: Result<Value, String>, headers: Headers) -> Response<Body> {
+    let (status, body) = match result {
+        Ok(body) => (StatusCode::OK, body),
+        Err(reason) => (
+            StatusCode::SERVICE_UNAVAILABLE,
+            json!({"status": "unavailable", "reason": reason}),
+        ),
+    };
+    Response::default()
+        .with_headers(headers)
+        .with_status(status)
+        .with_body(Body::from(body.to_string()))
+}
+
+fn unauthorized(mut headers: Headers) -> Response<Body> {
+    headers.insert(WWW_AUTHENTICATE, HeaderValue::from_static("Bearer"));
+    Response::default()
+        .with_headers(headers)
+        .with_status(StatusCode::UNAUTHORIZED)
+}
+
 fn handle_preflighted(mut headers: Headers) -> Headers {
     use crate::http_header::{HeaderMapExt, X_REQUESTED_WITH_STR};
 
@@ -215,7 +377,14 @@ fn handle_preflighted(mut headers: Headers) -> Headers {
     let plain_text = HeaderValue::from_static(CONTENT_TYPE_PLAIN_TEXT_STR);
     let cors_cache = HeaderValue::from(CORS_CACHE);
     let allow_methods = vec![Method::POST, Method::OPTIONS];
-    let allow_headers = vec![ORIGIN, CONTENT_TYPE, x_requested_with, USER_AGENT, ACCEPT];
+    let allow_headers = vec![
+        ORIGIN,
+        CONTENT_TYPE,
+        x_requested_with,
+        USER_AGENT,
+        ACCEPT,
+        AUTHORIZATION,
+    ];
 
     headers.insert(CONTENT_TYPE, plain_text);
     headers.insert_vec(ACCESS_CONTROL_ALLOW_METHODS, allow_methods);
@@ -225,25 +394,31 @@ fn handle_preflighted(mut headers: Headers) -> Headers {
     headers
 }
 
-pub type JsonrpcServer = hyper::Server<hyper::server::conn::AddrIncoming, JsonrpcMakeService>;
+pub type JsonrpcServer = Box<dyn Future<Item = (), Error = hyper::Error> + Send>;
+
+enum Listener {
+    Plain(hyper::server::Builder<AddrIncoming>),
+    Tls(AsyncTcpListener, SslAcceptor, Duration),
+}
+
 pub struct Server {
     addr: SocketAddr,
-    jsonrpc: JsonrpcServer,
+    listener: Listener,
+    make_jsonrpc_svc: JsonrpcMakeService,
 }
 
 impl Server {
     pub fn create(
         addr: &SocketAddr,
         tx: Sender<(String, ProtoRequest)>,
+        ext_tx: RawSender,
         responses: RpcMap,
-        timeout: u64,
-        allow_origin: &Option<String>,
+        health: HealthState,
+        config: &HttpConfig,
     ) -> Result<Self, Box<dyn std::error::Error>> {
-        let listener = listener_from_socket_addr(&addr)?;
-        let addr = listener.local_addr()?;
-        let timeout = Duration::from_secs(timeout);
+        let timeout = Duration::from_secs(config.timeout);
         let json = HeaderValue::from_static(CONTENT_TYPE_JSON_STR);
-        let allow_origin = Origin::from_config(allow_origin)?;
+        let allow_origin = Origin::from_config(&config.allow_origin)?;
 
         let mut http_headers = Headers::new();
         http_headers.insert(CONTENT_TYPE, json);
@@ -252,17 +427,33 @@ impl Server {
         let make_jsonrpc_svc = JsonrpcMakeService {
             inner: Arc::new(Inner {
                 tx: Mutex::new(tx),
+                ext_tx: Mutex::new(ext_tx),
                 responses,
+                health,
+                access: Arc::new(Access::new(config.access.clone())),
+                auth: config.auth.clone().map(Authenticator::new),
                 timeout,
                 http_headers,
             }),
         };
 
         // NOTE: sleep_on_errors is turned on by default
-        hyper::Server::from_tcp(listener)
-            .map(|builder| builder.http1_keepalive(true).serve(make_jsonrpc_svc))
-            .map(|jsonrpc| Self { addr, jsonrpc })
-            .map_err(Box::from)
+        let listener = listener_from_socket_addr(&addr)?;
+        let addr = listener.local_addr()?;
+        let listener = match config.tls {
+            Some(ref tls) => {
+                let acceptor = crate::tls::acceptor(tls)?;
+                let listener = AsyncTcpListener::from_std(listener, &Handle::default())?;
+                let handshake_timeout = Duration::from_secs(tls.handshake_timeout);
+                Listener::Tls(listener, acceptor, handshake_timeout)
+            }
+            None => Listener::Plain(hyper::Server::from_tcp(listener)?),
+        };
+        Ok(Self {
+            addr,
+            listener,
+            make_jsonrpc_svc,
+        })
     }
 
     // used in test code
@@ -271,8 +462,60 @@ impl Server {
         self.addr
     }
 
-    pub fn jsonrpc(self) -> JsonrpcServer {
-        self.jsonrpc
+    /// Serve until `shutdown` completes.
+    pub fn jsonrpc<F>(self, shutdown: F) -> JsonrpcServer
+    where
+        F: Future<Item = ()> + Send + 'static,
+    {
+        match self.listener {
+            Listener::Plain(builder) => Box::new(
+                builder
+                    .http1_keepalive(true)
+                    .serve(self.make_jsonrpc_svc)
+                    .with_graceful_shutdown(shutdown),
+            ),
+            Listener::Tls(listener, acceptor, handshake_timeout) => {
+                // A failed or timed out handshake drops its connection only.
+                let incoming = listener
+                    .incoming()
+                    .then(|res| match res {
+                        Ok(stream) => future::Either::A(future::ok::<_, io::Error>(Some(stream))),
+                        Err(e) => {
+                            warn!("accept failed: {}", e);
+                            let delay = Delay::new(Instant::now() + ACCEPT_ERROR_DELAY);
+                            future::Either::B(delay.then(|_| Ok(None)))
+                        }
+                    })
+                    .filter_map(|stream| stream)
+                    .map(move |stream| {
+                        let remote_addr = stream.peer_addr();
+                        let handshake = acceptor.accept_async(stream);
+                        Timeout::new(handshake, handshake_timeout).then(move |res| {
+                            match (res, remote_addr) {
+                                (Ok(inner), Ok(remote_addr)) => {
+                                    Ok::<_, io::Error>(Some(TlsStream { inner, remote_addr }))
+                                }
+                                (Ok(_), Err(e)) => {
+                                    debug!("TLS connection closed: {}", e);
+                                    Ok(None)
+                                }
+                                (Err(e), _) => {
+                                    debug!("TLS handshake failed or timed out: {:?}", e);
+                                    Ok(None)
+                                }
+                            }
+                        })
+                    })
+                    .buffer_unordered(TLS_HANDSHAKES)
+                    .filter_map(|stream| stream);
+                Box::new(
+                    hyper::Server::builder(incoming)
+                        .http1_keepalive(true)
+                        .serve(self.make_jsonrpc_svc)
+                        .with_graceful_shutdown(shutdown),
+                )
+            }
+        }
     }
 }
 
@@ -292,12 +535,16 @@ pub fn listener_from_socket_addr(addr: &SocketAddr) -> std::io::Result<TcpListen
 #[cfg(test)]
 mod integration_test {
     use super::*;
+    use crate::config::HealthConfig;
+    use crate::extension;
+    use crate::health::{Health, HealthState};
     use crate::helper::TransferType;
+    use cita_rpc_ext as ext;
     use futures::{sync::oneshot, Stream};
     use jsonrpc_proto::response::OutputExt;
     use jsonrpc_types;
     use jsonrpc_types::rpc_response::Output;
-    use libproto::protos;
+    use libproto::{protos, Message, TryFrom};
     use pubsub::channel::{self, Sender};
     use serde_json;
     use std::collections::HashMap;
@@ -321,7 +568,9 @@ mod integration_test {
 
     fn start_server(
         responses: RpcMap,
+        health: HealthState,
         tx: Sender<(String, ProtoRequest)>,
+        ext_tx: RawSender,
         timeout: u64,
         allow_origin: Option<String>,
     ) -> Serve {
@@ -333,14 +582,24 @@ mod integration_test {
             .name(format!("test-server-{}", Uuid::new_v4()))
             .spawn(move || {
                 let (shutdown_tx, shutdown_rx) = oneshot::channel::<()>();
-                let server = Server::create(&addr, tx, responses, timeout, &allow_origin).unwrap();
+                let config = HttpConfig {
+                    enable: true,
+                    thread_number: None,
+                    listen_ip: addr.ip().to_string(),
+                    listen_port: addr.port().to_string(),
+                    timeout,
+                    allow_origin,
+                    access: Default::default(),
+                    tls: None,
+                    auth: None,
+                };
+                let server = Server::create(&addr, tx, ext_tx, responses, health, &config).unwrap();
 
                 let addr = server.local_addr();
                 addr_tx.send((addr, shutdown_tx)).unwrap();
 
                 let jsonrpc_server = server
-                    .jsonrpc()
-                    .with_graceful_shutdown(shutdown_rx)
+                    .jsonrpc(shutdown_rx)
                     .map_err(|err| eprintln!("server err {}", err));
 
                 let mut rt = tokio::runtime::Builder::new()
@@ -370,9 +629,20 @@ mod integration_test {
 
         // For message forwarding
         let (tx_relay, rx_relay) = channel::unbounded();
+        let (tx_ext, rx_ext) = channel::unbounded();
         let backlog_capacity = 256;
         let responses = Arc::new(Mutex::new(HashMap::with_capacity(backlog_capacity)));
-        let serve = start_server(responses.clone(), tx_relay, 3, Some(String::from("*")));
+        let health = Arc::new(Mutex::new(Health::new(HealthConfig::default())));
+        // Messages come from MQ, but chain has not told its height.
+        health.lock().on_message(Instant::now());
+        let serve = start_server(
+            responses.clone(),
+            health,
+            tx_relay,
+            tx_ext,
+            3,
+            Some(String::from("*")),
+        );
 
         let http_responses = responses.clone();
         let (tx_quit, rx_quit) = channel::unbounded();
@@ -394,7 +664,8 @@ mod integration_test {
                 if let Some(val) = value {
                     match val {
                         TransferType::HTTP((req_info, sender)) => {
-                            let _ = sender.send(Output::from_res_info(content, req_info));
+                            let output = Output::from_res_info(content, req_info);
+                            let _ = sender.send(serde_json::to_value(output).unwrap());
                         }
                         TransferType::WEBSOCKET((req_info, sender)) => {
                             let _ = sender.send(
@@ -402,10 +673,20 @@ mod integration_test {
                                     .unwrap(),
                             );
                         }
+                        TransferType::EXTENSION(_)
+                        | TransferType::SUBSCRIPTION(_)
+                        | TransferType::HEALTH => {}
                     }
                 } else {
                     warn!("receive lost request_id {:?}", content.request_id);
                 }
+            } else if let Ok((_topic, data)) = rx_ext.try_recv() {
+                // Reply a page of logs, as chain does.
+                let raw_bytes = Message::try_from(&data).unwrap().take_raw_bytes().unwrap();
+                if let Ok(ext::Message::Request(req)) = ext::Message::from_bytes(&raw_bytes) {
+                    let page = json!({"logs": [], "nextCursor": req.params[1]});
+                    extension::reply(&http_responses, ext::Response::new(req.id, Ok(page)));
+                }
             } else {
                 if rx_quit.try_recv().is_ok() {
                     break;
@@ -445,7 +726,14 @@ mod integration_test {
                 Some(&HeaderValue::from_vec(vec![Method::POST, Method::OPTIONS]))
             );
             let x_requested_with = HeaderName::from_static(X_REQUESTED_WITH_STR);
-            let expect_headers = vec![ORIGIN, CONTENT_TYPE, x_requested_with, USER_AGENT, ACCEPT];
+            let expect_headers = vec![
+                ORIGIN,
+                CONTENT_TYPE,
+                x_requested_with,
+                USER_AGENT,
+                ACCEPT,
+                AUTHORIZATION,
+            ];
             assert_eq!(
                 headers.get(ACCESS_CONTROL_ALLOW_HEADERS),
                 Some(&HeaderValue::from_vec(expect_headers))
@@ -554,11 +842,77 @@ mod integration_test {
                 })
         });
 
+        let data = format!(
+            "{}",
+            json!({
+                "jsonrpc": "2.0",
+                "method": "getLogs",
+                "params": [{}, {"blockNumber": 3, "logIndex": 1}],
+                "id": 76,
+            })
+        );
+        let req = hyper::Request::post(uri.clone())
+            .body(hyper::Body::from(data))
+            .unwrap();
+        let work_logs_page = client.request(req).and_then(|resp| {
+            assert_eq!(resp.status().as_u16(), 200);
+            resp.into_body()
+                .fold(vec![], |mut buf, chunk| {
+                    buf.write(chunk.as_ref()).unwrap();
+                    futures::future::ok(buf).map_err(|e: hyper::Error| e)
+                })
+                .and_then(|buf| {
+                    let rv: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+                    assert_eq!(
+                        rv,
+                        json!({
+                            "jsonrpc": "2.0",
+                            "id": 76,
+                            "result": {
+                                "logs": [],
+                                "nextCursor": {"blockNumber": 3, "logIndex": 1},
+                            },
+                        })
+                    );
+                    Ok(())
+                })
+        });
+
+        let health_uri = hyper::Uri::from_str(
+            format!("http://{}:{}/health", serve.addr.ip(), serve.addr.port()).as_str(),
+        )
+        .unwrap();
+        let work_health = client.get(health_uri).and_then(|resp| {
+            assert_eq!(resp.status().as_u16(), 200);
+            Ok(())
+        });
+
+        let ready_uri = hyper::Uri::from_str(
+            format!("http://{}:{}/ready", serve.addr.ip(), serve.addr.port()).as_str(),
+        )
+        .unwrap();
+        let work_ready = client.get(ready_uri).and_then(|resp| {
+            assert_eq!(resp.status().as_u16(), 503);
+            resp.into_body()
+                .fold(vec![], |mut buf, chunk| {
+                    buf.write(chunk.as_ref()).unwrap();
+                    futures::future::ok(buf).map_err(|e: hyper::Error| e)
+                })
+                .and_then(|buf| {
+                    let rv: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+                    assert_eq!(rv["status"], "unavailable");
+                    Ok(())
+                })
+        });
+
         works.push(Box::new(work_empty));
         works.push(Box::new(work_options));
         works.push(Box::new(work_method_not_found));
         works.push(Box::new(work_peercount));
         works.push(Box::new(work_peercount_batch));
+        works.push(Box::new(work_logs_page));
+        works.push(Box::new(work_health));
+        works.push(Box::new(work_ready));
 
         let mut core = Core::new().unwrap();
         core.run(futures::future::join_all(works)).unwrap();
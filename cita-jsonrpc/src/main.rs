@@ -27,6 +27,11 @@
 //!     | jsonrpc | Chain     | Response     |
 //!     | jsonrpc | Executor  | Response     |
 //!     | jsonrpc | Net       | Response     |
+//!     | jsonrpc | Auth      | RawBytes     |
+//!     | jsonrpc | Chain     | RawBytes     |
+//!     | jsonrpc | Executor  | RawBytes     |
+//!     | jsonrpc | Chain     | RichStatus   |
+//!     | jsonrpc | Net       | Status       |
 //!
 //! 2. Publish channel
 //!
@@ -36,6 +41,8 @@
 //!     | jsonrpc | Jsonrpc   | Chain     | Request           |
 //!     | jsonrpc | Jsonrpc   | Net       | RequestNet        |
 //!     | jsonrpc | jsonrpc   | Net       | RequestPeersInfo  |
+//!     | jsonrpc | Jsonrpc   | Auth      | RawBytes          |
+//!     | jsonrpc | Jsonrpc   | Chain     | RawBytes          |
 //!
 //! ### Key behavior
 //!
@@ -49,6 +56,15 @@
 //! Websocket and Http only write to this structure and write the internal transaction
 //! uuid number and `TransferType`.
 //!
+//! Websocket clients may also `subscribe` to new blocks, logs and pending transactions,
+//! see `subscription`. Blocks and logs are pushed when chain broadcasts a new `RichStatus`,
+//! pending transactions only for those sent through this jsonrpc instance.
+//!
+//! Methods which the proto requests can not carry, see `cita_rpc_ext`, are published as
+//! `RawBytes` and answered with `RawBytes`, see `extension`.
+//!
+//! The HTTP server also serves `GET /health` and `GET /ready` for load balancers, see `health`.
+//!
 
 #[macro_use]
 extern crate libproto;
@@ -56,32 +72,43 @@ extern crate libproto;
 extern crate cita_logger as logger;
 #[macro_use]
 extern crate serde_derive;
-#[cfg_attr(test, macro_use)]
+#[macro_use]
 extern crate serde_json;
 #[macro_use]
 extern crate util;
 
+mod access;
+mod auth;
 mod config;
+mod extension;
 mod extractor;
 mod fdlimit;
+mod health;
 mod helper;
 mod http_header;
 mod http_server;
+mod metrics;
 mod mq_handler;
 mod mq_publisher;
 mod response;
 mod service_error;
 mod soliloquy;
+mod subscription;
+mod tls;
 mod ws_handler;
 
+use crate::access::Access;
+use crate::auth::Authenticator;
 use crate::config::{NewTxFlowConfig, ProfileConfig};
 use crate::fdlimit::set_fd_limit;
+use crate::health::Health;
 use crate::http_server::Server;
 use crate::soliloquy::Soliloquy;
+use crate::subscription::Subscriptions;
 use crate::ws_handler::WsFactory;
 use clap::App;
 use cpuprofiler::PROFILER;
-use futures::Future;
+use futures::{future, Future};
 use libproto::request::{self as reqlib, BatchRequest};
 use libproto::router::{MsgType, RoutingKey, SubModules};
 use libproto::Message;
@@ -105,7 +132,8 @@ fn main() {
         .about("CITA JSON-RPC by Rust")
         .args_from_usage(
             "-c, --config=[FILE] 'Sets a custom config file'
-                          -s, --stdout 'Log to console'",
+                          -s, --stdout 'Log to console'
+                          -m, --metrics=[ADDR] 'Serves Prometheus metrics on ADDR'",
         )
         .get_matches();
 
@@ -113,6 +141,10 @@ fn main() {
     micro_service_init!("cita-jsonrpc", "CITA:jsonrpc", stdout);
     info!("Version: {}", get_build_info_str(true));
 
+    if let Some(addr) = matches.value_of("metrics") {
+        cita_metrics::start_server(addr).expect("Start metrics server failed");
+    }
+
     let config_path = matches.value_of("config").unwrap_or("jsonrpc.toml");
 
     let config = config::Config::new(config_path);
@@ -137,6 +169,9 @@ fn main() {
     // used for deal with RequestRpc
     let (tx, rx) = channel::unbounded();
     let soli_resp_tx = tx_sub.clone();
+    // extension calls skip the relay, they are never batched
+    let ws_ext_tx = tx_pub.clone();
+    let http_ext_tx = tx_pub.clone();
 
     start_pubsub(
         "jsonrpc",
@@ -145,6 +180,11 @@ fn main() {
             Chain >> Response,
             Executor >> Response,
             Net >> Response,
+            Auth >> RawBytes,
+            Chain >> RawBytes,
+            Executor >> RawBytes,
+            Chain >> RichStatus,
+            Net >> Status,
         ]),
         tx_sub,
         rx_pub,
@@ -156,7 +196,21 @@ fn main() {
     let responses = Arc::new(Mutex::new(HashMap::with_capacity(backlog_capacity)));
     let http_responses = Arc::clone(&responses);
     let ws_responses = Arc::clone(&responses);
-    let mut mq_handle = mq_handler::MqHandler::new(responses);
+    let subscriptions = Arc::new(Mutex::new(Subscriptions::new(
+        config.ws_config.max_subscriptions_per_connection,
+        config.ws_config.max_subscriptions,
+    )));
+    let ws_subscriptions = Arc::clone(&subscriptions);
+    let health = Arc::new(Mutex::new(Health::new(config.health_config)));
+    let http_health = Arc::clone(&health);
+    let probe_responses = Arc::clone(&responses);
+    let mut mq_handle =
+        mq_handler::MqHandler::new(responses, subscriptions, health, tx_relay.clone());
+
+    // health probe
+    let probe_tx = tx_relay.clone();
+    let probe_interval = Duration::from_secs(config.health_config.probe_interval);
+    thread::spawn(move || health::probe_chain(probe_responses, probe_tx, probe_interval));
 
     //dispatch
     let tx_flow_config = config.new_tx_flow_config;
@@ -215,7 +269,22 @@ fn main() {
             let url =
                 ws_config.listen_ip.clone() + ":" + &ws_config.listen_port.clone().to_string();
             //let factory = WsFactory::new(ws_responses, tx_pub, 0);
-            let factory = WsFactory::new(ws_responses, tx, 0);
+            let access = Access::new(ws_config.access.clone());
+            let auth = ws_config.auth.clone().map(Authenticator::new);
+            let tls = ws_config
+                .tls
+                .as_ref()
+                .map(|tls| tls::acceptor(tls).expect("Load WebSocket TLS certificate failed"));
+            let factory = WsFactory::new(
+                ws_responses,
+                ws_subscriptions,
+                access,
+                auth,
+                tls,
+                tx,
+                ws_ext_tx,
+                0,
+            );
             info!("WebSocket Listening on {}", url);
             let mut ws_build = ws::Builder::new();
             ws_build.with_settings(ws_config.into());
@@ -236,16 +305,20 @@ fn main() {
             .unwrap_or_else(num_cpus::get);
 
         let addr = addr.parse().unwrap();
-        let timeout = http_config.timeout;
-        let allow_origin = http_config.allow_origin;
         let _ = thread::Builder::new()
             .name(String::from("http worker"))
             .spawn(move || {
-                let server =
-                    Server::create(&addr, tx_relay, http_responses, timeout, &allow_origin)
-                        .unwrap();
+                let server = Server::create(
+                    &addr,
+                    tx_relay,
+                    http_ext_tx,
+                    http_responses,
+                    http_health,
+                    &http_config,
+                )
+                .unwrap();
                 let jsonrpc_server = server
-                    .jsonrpc()
+                    .jsonrpc(future::empty::<(), ()>())
                     .map_err(|err| eprintln!("server err {}", err));
 
                 let mut rt = tokio::runtime::Builder::new()
@@ -0,0 +1,50 @@
+// Copyright Rivtower Technologies LLC.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Metrics of jsonrpc, served on `/metrics`.
+
+use cita_metrics::duration_to_seconds;
+use hyper::StatusCode;
+use lazy_static::lazy_static;
+use prometheus::{register_histogram_vec, register_int_counter_vec, HistogramVec, IntCounterVec};
+use std::time::Duration;
+
+/// Method label of a batch request.
+pub const BATCH_METHOD: &str = "batch";
+/// Method label of a request which can not be parsed.
+pub const UNKNOWN_METHOD: &str = "unknown";
+
+lazy_static! {
+    static ref REQUEST_SECONDS: HistogramVec = register_histogram_vec!(
+        "jsonrpc_request_duration_seconds",
+        "Time to answer a HTTP JSON-RPC request",
+        &["method"]
+    )
+    .unwrap();
+    static ref RESPONSES: IntCounterVec = register_int_counter_vec!(
+        "jsonrpc_responses_total",
+        "HTTP JSON-RPC responses by status code",
+        &["method", "status"]
+    )
+    .unwrap();
+}
+
+pub fn observe_request(method: &str, status: StatusCode, elapsed: Duration) {
+    REQUEST_SECONDS
+        .with_label_values(&[method])
+        .observe(duration_to_seconds(elapsed));
+    RESPONSES
+        .with_label_values(&[method, status.as_str()])
+        .inc();
+}
@@ -12,26 +12,50 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use crate::helper::{RpcMap, TransferType};
+use crate::extension;
+use crate::health::HealthState;
+use crate::helper::{select_topic, RpcMap, TransferType};
+use crate::subscription::SubscriptionMap;
+use cita_rpc_ext as ext;
 use jsonrpc_proto::response::OutputExt;
+use jsonrpc_types::rpc_request::RequestInfo;
 use jsonrpc_types::rpc_response::Output;
+use jsonrpc_types::rpc_types::TxResponse;
+use libproto::request::Request as ProtoRequest;
 use libproto::router::{MsgType, RoutingKey, SubModules};
 use libproto::Message;
 use libproto::TryFrom;
-use serde_json;
+use pubsub::channel::Sender;
+use serde_json::{self, Value};
+use std::time::Instant;
+
+const TX_OK: &str = "OK";
 
-#[derive(Default)]
 pub struct MqHandler {
     responses: RpcMap,
+    subscriptions: SubscriptionMap,
+    health: HealthState,
+    tx: Sender<(String, ProtoRequest)>,
 }
 
 impl MqHandler {
-    pub fn new(responses: RpcMap) -> Self {
-        MqHandler { responses }
+    pub fn new(
+        responses: RpcMap,
+        subscriptions: SubscriptionMap,
+        health: HealthState,
+        tx: Sender<(String, ProtoRequest)>,
+    ) -> Self {
+        MqHandler {
+            responses,
+            subscriptions,
+            health,
+            tx,
+        }
     }
 
     pub fn handle(&mut self, key: &str, body: &[u8]) -> Result<(), ()> {
         trace!("get msg from routing_key {}", key);
+        self.health.lock().on_message(Instant::now());
 
         let mut msg = Message::try_from(body).map_err(|e| {
             error!("try_from: {:?}", e);
@@ -47,6 +71,10 @@ impl MqHandler {
                     error!("empty response message");
                 })?;
 
+                if content.has_tx_state() {
+                    self.notify_pending_transaction(content.get_tx_state());
+                }
+
                 let resp = {
                     let request_id = &content.request_id;
                     trace!("from response request_id {:?}", request_id);
@@ -57,11 +85,13 @@ impl MqHandler {
 
                 match resp {
                     TransferType::HTTP((req_info, sender)) => {
-                        sender
-                            .send(Output::from_res_info(content, req_info))
-                            .map_err(|e| {
-                                error!("http: {:?}", e);
-                            })?;
+                        let output = Output::from_res_info(content, req_info);
+                        let output = serde_json::to_value(&output).map_err(|e| {
+                            error!("http: {:?}", e);
+                        })?;
+                        sender.send(output).map_err(|e| {
+                            error!("http: {:?}", e);
+                        })?;
                     }
                     TransferType::WEBSOCKET((req_info, sender)) => {
                         let json_body =
@@ -73,12 +103,100 @@ impl MqHandler {
                             error!("ws: {:?}", e);
                         })?;
                     }
+                    TransferType::SUBSCRIPTION((ids, split)) => {
+                        let output = Output::from_res_info(content, RequestInfo::null());
+                        match serde_json::to_value(&output) {
+                            Ok(Value::Object(mut output)) => match output.remove("result") {
+                                Some(result) => {
+                                    self.subscriptions.lock().notify(&ids, split, result)
+                                }
+                                None => warn!("subscription query failed: {:?}", output),
+                            },
+                            _ => error!("subscription: unexpected output"),
+                        }
+                    }
+                    TransferType::HEALTH => {
+                        if content.get_code() == 0 {
+                            self.health
+                                .lock()
+                                .on_height(content.get_block_number(), Instant::now());
+                        }
+                    }
+                    TransferType::EXTENSION(_) => {
+                        error!(
+                            "proto response of extension request {:?}",
+                            content.request_id
+                        );
+                    }
                 };
             }
+            routing_key!(Auth >> RawBytes)
+            | routing_key!(Chain >> RawBytes)
+            | routing_key!(Executor >> RawBytes) => {
+                let data = msg.take_raw_bytes().ok_or_else(|| {
+                    error!("empty raw bytes message");
+                })?;
+                // Chain passes requests to the executor on the same key, and the executor
+                // tells auth about the black list, skip them.
+                match ext::Message::from_bytes(&data) {
+                    Ok(ext::Message::Response(resp)) => extension::reply(&self.responses, resp),
+                    Ok(_) => {}
+                    Err(e) => error!("extension response: {:?}", e),
+                }
+            }
+            routing_key!(Chain >> RichStatus) => {
+                if let Some(status) = msg.take_rich_status() {
+                    {
+                        let mut health = self.health.lock();
+                        health.on_block_interval(status.get_interval());
+                        health.on_height(status.get_height(), Instant::now());
+                    }
+                    self.notify_new_height(status.get_height());
+                }
+            }
+            routing_key!(Net >> Status) => {
+                if let Some(status) = msg.take_status() {
+                    self.health.lock().on_network_height(status.get_height());
+                }
+            }
             _ => {
                 warn!("receive unexpect key {}", key);
             }
         };
         Ok(())
     }
+    fn notify_new_height(&mut self, height: u64) {
+        let queries = {
+            let mut subscriptions = self.subscriptions.lock();
+            subscriptions
+                .new_heights(height)
+                .into_iter()
+                .flat_map(|height| subscriptions.queries(height))
+                .collect::<Vec<_>>()
+        };
+        for query in queries {
+            let topic = select_topic(query.method);
+            self.responses.lock().insert(
+                query.request.request_id.clone(),
+                TransferType::SUBSCRIPTION((query.ids, query.split)),
+            );
+            let _ = self.tx.send((topic, query.request)).map_err(|e| {
+                error!("subscription: {:?}", e);
+            });
+        }
+    }
+
+    fn notify_pending_transaction(&self, tx_state: &str) {
+        let subscriptions = self.subscriptions.lock();
+        if subscriptions.is_empty() {
+            return;
+        }
+        if let Ok(tx_response) = serde_json::from_str::<TxResponse>(tx_state) {
+            if tx_response.status == TX_OK {
+                if let Ok(hash) = serde_json::to_value(&tx_response.hash) {
+                    subscriptions.notify_pending_transaction(hash);
+                }
+            }
+        }
+    }
 }
@@ -16,15 +16,22 @@ use std::time::Duration;
 
 use futures::{future::Future, stream::FuturesOrdered, sync::oneshot};
 use hyper::HeaderMap as Headers;
+use jsonrpc_types::rpc_response::RpcFailure;
 use jsonrpc_types::{
-    rpc_request::Request as JsonRequest, rpc_response::Output as JsonrpcResponse,
+    rpc_request::{Request as JsonRequest, RequestInfo},
+    rpc_response::Output as JsonrpcResponse,
     rpc_types::Id as JsonrpcId,
 };
 use libproto::request::Request as ProtoRequest;
 use pubsub::channel::Sender;
+use serde_json::{self, Value};
+use std::net::IpAddr;
+use std::sync::Arc;
 use tokio_timer::{clock, Delay};
 
-use crate::helper::{select_topic, RpcMap, TransferType};
+use crate::access::Access;
+use crate::extension::{ExtCall, RawSender};
+use crate::helper::{select_topic, tag_client, ExtReply, RpcMap, TransferType};
 use crate::response::{BatchFutureResponse, PublishFutResponse, SingleFutureResponse};
 use crate::service_error::ServiceError;
 type HyperResponse = hyper::Response<hyper::Body>;
@@ -35,10 +42,50 @@ pub struct HybridRequest {
     pub proto_req: ProtoRequest,
 }
 
+#[derive(Debug)]
+pub enum Call {
+    /// A method of `jsonrpc-types`
+    Hybrid(HybridRequest),
+    /// A method of `cita_rpc_ext`
+    Extension(ExtCall),
+}
+
+impl Call {
+    pub fn method(&self) -> &str {
+        match self {
+            Call::Hybrid(ref hybrid_req) => hybrid_req.json_req.get_method(),
+            Call::Extension(ref ext_call) => ext_call.method(),
+        }
+    }
+
+    pub fn info(&self) -> RequestInfo {
+        match self {
+            Call::Hybrid(ref hybrid_req) => hybrid_req.json_req.get_info(),
+            Call::Extension(ref ext_call) => ext_call.info.clone(),
+        }
+    }
+
+    pub fn request_id(&self) -> Vec<u8> {
+        match self {
+            Call::Hybrid(ref hybrid_req) => hybrid_req.proto_req.request_id.clone(),
+            Call::Extension(ref ext_call) => ext_call.request.id.clone(),
+        }
+    }
+
+    fn id(&self) -> JsonrpcId {
+        match self {
+            Call::Hybrid(ref hybrid_req) => hybrid_req.json_req.id.clone(),
+            Call::Extension(ref ext_call) => {
+                serde_json::from_value(ext_call.id.clone()).unwrap_or(JsonrpcId::Null)
+            }
+        }
+    }
+}
+
 #[derive(Debug)]
 pub enum MQRequest {
-    Single(Box<HybridRequest>),
-    Batch(Vec<HybridRequest>),
+    Single(Box<Call>),
+    Batch(Vec<Call>),
 }
 
 pub enum AccessLog {
@@ -54,12 +101,12 @@ pub enum AccessLog {
 impl MQRequest {
     pub fn access_log(&self) -> AccessLog {
         match self {
-            MQRequest::Single(ref hybrid_req) => AccessLog::Single {
-                id: hybrid_req.json_req.id.clone(),
-                method: Some(hybrid_req.json_req.get_method().to_owned()),
+            MQRequest::Single(ref call) => AccessLog::Single {
+                id: call.id(),
+                method: Some(call.method().to_owned()),
             },
-            MQRequest::Batch(ref hybrid_reqs) => AccessLog::Batch {
-                count: Some(hybrid_reqs.len()),
+            MQRequest::Batch(ref calls) => AccessLog::Batch {
+                count: Some(calls.len()),
             },
         }
     }
@@ -70,15 +117,28 @@ pub type ProtoReqSender = Sender<(String, ProtoRequest)>;
 pub struct Publisher {
     responses: RpcMap,
     sender: ProtoReqSender,
+    ext_sender: RawSender,
     headers: Headers,
+    access: Arc<Access>,
+    remote_ip: IpAddr,
 }
 
 impl Publisher {
-    pub fn new(responses: RpcMap, sender: ProtoReqSender, headers: Headers) -> Self {
+    pub fn new(
+        responses: RpcMap,
+        sender: ProtoReqSender,
+        ext_sender: RawSender,
+        headers: Headers,
+        access: Arc<Access>,
+        remote_ip: IpAddr,
+    ) -> Self {
         Self {
             responses,
             sender,
+            ext_sender,
             headers,
+            access,
+            remote_ip,
         }
     }
 
@@ -97,7 +157,7 @@ impl Publisher {
                 let rxs = reqs
                     .into_iter()
                     .map(|req| self.send_request(req))
-                    .collect::<Vec<oneshot::Receiver<JsonrpcResponse>>>();
+                    .collect::<Vec<oneshot::Receiver<Value>>>();
 
                 let resp = BatchFutureResponse::new(
                     FuturesOrdered::from_iter(rxs).collect(),
@@ -108,18 +168,34 @@ impl Publisher {
         }
     }
 
-    fn send_request(&mut self, hybrid_req: HybridRequest) -> oneshot::Receiver<JsonrpcResponse> {
-        let (json_req, proto_req) = (hybrid_req.json_req, hybrid_req.proto_req);
+    fn send_request(&mut self, call: Call) -> oneshot::Receiver<Value> {
         let (tx, rx) = oneshot::channel();
-        let topic = select_topic(json_req.get_method());
 
-        self.responses.lock().insert(
-            proto_req.request_id.clone(),
-            TransferType::HTTP((json_req.get_info(), tx)),
-        );
+        // A refused request of a batch fails alone, the others are still sent.
+        if let Err(err) = self.access.check(self.remote_ip, call.method()) {
+            let failure = RpcFailure::from_options(call.info(), err.into());
+            let _ = tx.send(serde_json::to_value(JsonrpcResponse::Failure(failure)).unwrap());
+            return rx;
+        }
+
+        match call {
+            Call::Hybrid(hybrid_req) => {
+                let (json_req, mut proto_req) = (hybrid_req.json_req, hybrid_req.proto_req);
+                let topic = select_topic(json_req.get_method());
+                tag_client(json_req.get_method(), &mut proto_req, self.remote_ip);
+
+                self.responses.lock().insert(
+                    proto_req.request_id.clone(),
+                    TransferType::HTTP((json_req.get_info(), tx)),
+                );
 
-        // NOTE: send failure is handled as timeout error
-        let _ = self.sender.send((topic, proto_req));
+                // NOTE: send failure is handled as timeout error
+                let _ = self.sender.send((topic, proto_req));
+            }
+            Call::Extension(ext_call) => {
+                ext_call.publish(&self.responses, &self.ext_sender, ExtReply::Http(tx));
+            }
+        }
 
         rx
     }
@@ -145,22 +221,12 @@ impl TimeoutPublisher {
         req: MQRequest,
     ) -> Box<dyn Future<Item = HyperResponse, Error = ServiceError> + Send + 'static> {
         use futures::future::Either;
-        use std::sync::Arc;
 
         let timeout = Delay::new(clock::now() + self.timeout);
         let timeout_responses = Arc::clone(&self.timeout_responses);
         let (req_info, req_ids) = match req {
-            MQRequest::Single(ref hybrid_req) => (
-                Some(hybrid_req.json_req.get_info()),
-                vec![hybrid_req.proto_req.request_id.clone()],
-            ),
-            MQRequest::Batch(ref hybrid_reqs) => (
-                None,
-                hybrid_reqs
-                    .iter()
-                    .map(|ref req| req.proto_req.request_id.clone())
-                    .collect(),
-            ),
+            MQRequest::Single(ref call) => (Some(call.info()), vec![call.request_id()]),
+            MQRequest::Batch(ref calls) => (None, calls.iter().map(Call::request_id).collect()),
         };
 
         let fut_resp = self
@@ -15,8 +15,7 @@
 use futures::stream::{Collect, FuturesOrdered};
 use futures::{future::Future, sync::oneshot, Async, Poll};
 use hyper::{HeaderMap as Headers, Response as HyperResponse, StatusCode};
-use jsonrpc_types::rpc_response::Output;
-use serde_json;
+use serde_json::{self, Value};
 
 use crate::service_error::ServiceError;
 
@@ -90,12 +89,12 @@ where
 }
 
 pub struct SingleFutureResponse {
-    output: oneshot::Receiver<Output>,
+    output: oneshot::Receiver<Value>,
     headers: Option<Headers>,
 }
 
 impl SingleFutureResponse {
-    pub fn new(output: oneshot::Receiver<Output>, headers: Headers) -> SingleFutureResponse {
+    pub fn new(output: oneshot::Receiver<Value>, headers: Headers) -> SingleFutureResponse {
         SingleFutureResponse {
             output,
             headers: Some(headers),
@@ -104,7 +103,7 @@ impl SingleFutureResponse {
 }
 
 impl FutureResponse for SingleFutureResponse {
-    type Output = oneshot::Receiver<Output>;
+    type Output = oneshot::Receiver<Value>;
 
     fn inner_output(&mut self) -> &mut Self::Output {
         &mut self.output
@@ -128,7 +127,7 @@ impl Future for SingleFutureResponse {
     }
 }
 
-type BatchOutput = Collect<FuturesOrdered<oneshot::Receiver<Output>>>;
+type BatchOutput = Collect<FuturesOrdered<oneshot::Receiver<Value>>>;
 
 pub struct BatchFutureResponse {
     output: BatchOutput,
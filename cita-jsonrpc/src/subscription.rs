@@ -0,0 +1,463 @@
+// Copyright Rivtower Technologies LLC.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Push subscriptions over WebSocket.
+//!
+//! A client sends `subscribe` with one of `newHeads`, `logs` (plus a filter) or
+//! `newPendingTransactions`, and gets a subscription id back. Whenever chain broadcasts
+//! a new `RichStatus`, the new blocks and their matching logs are queried from chain and
+//! pushed to the subscribers as `subscription` notifications.
+//!
+//! At most `MAX_CATCH_UP_BLOCKS` blocks are pushed for one `RichStatus`. If chain got
+//! further ahead, the older blocks are skipped and the `newHeads` and `logs` subscribers
+//! get a `subscriptionMissed` notification with the `fromBlock` and `toBlock` skipped.
+//!
+//! A connection may have at most `max_subscriptions_per_connection` subscriptions, and
+//! all of them at most `max_subscriptions`, both of `ws_config`.
+//!
+//! Pending transaction hashes are pushed as soon as auth accepts a transaction sent
+//! through this jsonrpc instance. Transactions sent through other jsonrpc instances or
+//! received from other nodes are not notified.
+
+use crate::access::AccessError;
+use jsonrpc_proto::complete::CompleteInto;
+use jsonrpc_types::rpc_request::PartialRequest;
+use jsonrpc_types::rpc_types::Filter;
+use jsonrpc_types::Error;
+use libproto::request::Request as ProtoRequest;
+use serde_json::{self, Value};
+use std::collections::HashMap;
+use std::sync::Arc;
+use util::Mutex;
+use ws::{self, util::Token};
+
+pub const SUBSCRIBE_METHOD: &str = "subscribe";
+pub const UNSUBSCRIBE_METHOD: &str = "unsubscribe";
+const NOTIFICATION_METHOD: &str = "subscription";
+const MISSED_METHOD: &str = "subscriptionMissed";
+/// Most blocks notified for one new height, the older ones are reported as missed.
+pub const MAX_CATCH_UP_BLOCKS: u64 = 32;
+const INVALID_REQUEST_CODE: i64 = -32600;
+const INVALID_PARAMS_CODE: i64 = -32602;
+const LIMIT_EXCEEDED_CODE: i64 = -32005;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SubscriptionKind {
+    NewHeads,
+    /// Filter object of `getLogs`, `fromBlock` and `toBlock` are set per block.
+    Logs(Value),
+    NewPendingTransactions,
+}
+
+impl SubscriptionKind {
+    pub fn from_params(params: &[Value]) -> Result<Self, String> {
+        match params.get(0).and_then(Value::as_str) {
+            Some("newHeads") => Ok(SubscriptionKind::NewHeads),
+            Some("newPendingTransactions") => Ok(SubscriptionKind::NewPendingTransactions),
+            Some("logs") => {
+                let mut filter = params.get(1).cloned().unwrap_or_else(|| json!({}));
+                serde_json::from_value::<Filter>(filter.clone())
+                    .map_err(|err| format!("invalid logs filter: {}", err))?;
+                if let Some(filter) = filter.as_object_mut() {
+                    filter.remove("fromBlock");
+                    filter.remove("toBlock");
+                }
+                Ok(SubscriptionKind::Logs(filter))
+            }
+            Some(kind) => Err(format!("unknown subscription kind: {}", kind)),
+            None => Err("missing subscription kind".to_owned()),
+        }
+    }
+}
+
+pub struct Subscription {
+    kind: SubscriptionKind,
+    sender: ws::Sender,
+}
+
+/// Query sent to chain on behalf of subscriptions, and where to push its result.
+pub struct SubscriptionQuery {
+    pub ids: Vec<String>,
+    pub split: bool,
+    pub method: &'static str,
+    pub request: ProtoRequest,
+}
+
+#[derive(Default)]
+pub struct Subscriptions {
+    next_id: u64,
+    latest_height: Option<u64>,
+    subscriptions: HashMap<String, Subscription>,
+    /// Most subscriptions of one connection, 0 means no limit
+    max_per_connection: usize,
+    /// Most subscriptions of all connections, 0 means no limit
+    max_subscriptions: usize,
+}
+
+pub type SubscriptionMap = Arc<Mutex<Subscriptions>>;
+
+impl Subscriptions {
+    pub fn new(max_per_connection: usize, max_subscriptions: usize) -> Self {
+        Subscriptions {
+            max_per_connection,
+            max_subscriptions,
+            ..Default::default()
+        }
+    }
+
+    /// Every `logs` subscription costs a query per block, so their number is limited.
+    pub fn subscribe(
+        &mut self,
+        kind: SubscriptionKind,
+        sender: ws::Sender,
+    ) -> Result<String, String> {
+        if self.max_subscriptions != 0 && self.subscriptions.len() >= self.max_subscriptions {
+            return Err(format!(
+                "too many subscriptions, at most {}",
+                self.max_subscriptions
+            ));
+        }
+        let token = sender.token();
+        if self.max_per_connection != 0
+            && self
+                .subscriptions
+                .values()
+                .filter(|sub| sub.sender.token() == token)
+                .count()
+                >= self.max_per_connection
+        {
+            return Err(format!(
+                "too many subscriptions of the connection, at most {}",
+                self.max_per_connection
+            ));
+        }
+
+        self.next_id += 1;
+        let id = format!("{:#x}", self.next_id);
+        self.subscriptions
+            .insert(id.clone(), Subscription { kind, sender });
+        Ok(id)
+    }
+
+    /// A connection can only cancel its own subscriptions.
+    pub fn unsubscribe(&mut self, id: &str, token: Token) -> bool {
+        match self.subscriptions.get(id) {
+            Some(sub) if sub.sender.token() == token => {
+                self.subscriptions.remove(id);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    pub fn remove_connection(&mut self, token: Token) {
+        self.subscriptions
+            .retain(|_, sub| sub.sender.token() != token);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.subscriptions.is_empty()
+    }
+
+    /// Record the new chain height, and return the heights not notified yet.
+    /// Subscribers are told about the heights skipped to catch up.
+    pub fn new_heights(&mut self, height: u64) -> Vec<u64> {
+        let heights = match self.latest_height {
+            Some(latest) if latest >= height => vec![],
+            Some(latest) if !self.is_empty() => {
+                let (missed, heights) = catch_up(latest, height);
+                if let Some((from, to)) = missed {
+                    warn!("subscriptions skip blocks {} to {}", from, to);
+                    self.notify_missed(from, to);
+                }
+                heights
+            }
+            _ => vec![height],
+        };
+        self.latest_height = Some(self.latest_height.map_or(height, |h| h.max(height)));
+        heights
+    }
+
+    fn notify_missed(&self, from: u64, to: u64) {
+        let ids = self.ids_of(|kind| *kind != SubscriptionKind::NewPendingTransactions);
+        for id in ids {
+            let sub = &self.subscriptions[&id];
+            let _ = sub
+                .sender
+                .send(missed(&id, from, to))
+                .map_err(|err| warn!("push subscription {} failed: {:?}", id, err));
+        }
+    }
+
+    /// Build the queries needed to notify the block at `height`.
+    pub fn queries(&self, height: u64) -> Vec<SubscriptionQuery> {
+        let number = format!("{:#x}", height);
+        let mut queries = Vec::new();
+
+        let heads = self.ids_of(|kind| *kind == SubscriptionKind::NewHeads);
+        if !heads.is_empty() {
+            let method = "getBlockByNumber";
+            if let Some(request) = build_request(method, json!([number, false])) {
+                queries.push(SubscriptionQuery {
+                    ids: heads,
+                    split: false,
+                    method,
+                    request,
+                });
+            }
+        }
+
+        for (id, sub) in self.subscriptions.iter() {
+            if let SubscriptionKind::Logs(ref filter) = sub.kind {
+                let mut filter = filter.clone();
+                if let Some(filter) = filter.as_object_mut() {
+                    filter.insert("fromBlock".to_owned(), json!(number));
+                    filter.insert("toBlock".to_owned(), json!(number));
+                }
+                let method = "getLogs";
+                if let Some(request) = build_request(method, json!([filter])) {
+                    queries.push(SubscriptionQuery {
+                        ids: vec![id.clone()],
+                        split: true,
+                        method,
+                        request,
+                    });
+                }
+            }
+        }
+        queries
+    }
+
+    /// Push `result` to the subscriptions, or each of its items if `split` is set.
+    pub fn notify(&self, ids: &[String], split: bool, mut result: Value) {
+        if !split {
+            // Only the header is pushed for a new block.
+            if let Some(block) = result.as_object_mut() {
+                block.remove("body");
+            }
+        }
+        let results = match result {
+            Value::Array(items) if split => items,
+            result => vec![result],
+        };
+        for id in ids {
+            let sub = match self.subscriptions.get(id) {
+                Some(sub) => sub,
+                None => continue,
+            };
+            for item in results.iter().cloned() {
+                let _ = sub
+                    .sender
+                    .send(notification(id, item))
+                    .map_err(|err| warn!("push subscription {} failed: {:?}", id, err));
+            }
+        }
+    }
+
+    pub fn notify_pending_transaction(&self, hash: Value) {
+        let ids = self.ids_of(|kind| *kind == SubscriptionKind::NewPendingTransactions);
+        self.notify(&ids, false, hash);
+    }
+
+    fn ids_of<F>(&self, f: F) -> Vec<String>
+    where
+        F: Fn(&SubscriptionKind) -> bool,
+    {
+        self.subscriptions
+            .iter()
+            .filter(|(_, sub)| f(&sub.kind))
+            .map(|(id, _)| id.clone())
+            .collect()
+    }
+}
+
+/// Handle `subscribe` and `unsubscribe` locally, returns `None` for any other method.
+/// `check` tells whether the client may call the method.
+pub fn handle_request<F>(
+    subscriptions: &SubscriptionMap,
+    text: &str,
+    sender: &ws::Sender,
+    check: F,
+) -> Option<String>
+where
+    F: FnOnce(&str) -> Result<(), AccessError>,
+{
+    let req: Value = serde_json::from_str(text).ok()?;
+    let method = req.get("method").and_then(Value::as_str)?;
+    if method != SUBSCRIBE_METHOD && method != UNSUBSCRIBE_METHOD {
+        return None;
+    }
+    let id = req.get("id").cloned().unwrap_or(Value::Null);
+    if let Err(err) = check(method) {
+        return Some(failure(id, err.code(), &err.to_string()));
+    }
+    let params = match req.get("params") {
+        Some(Value::Array(params)) => params.clone(),
+        None => vec![],
+        Some(_) => {
+            return Some(failure(
+                id,
+                INVALID_REQUEST_CODE,
+                "params should be an array",
+            ))
+        }
+    };
+
+    let reply = if method == SUBSCRIBE_METHOD {
+        match SubscriptionKind::from_params(&params) {
+            Ok(kind) => match subscriptions.lock().subscribe(kind, sender.clone()) {
+                Ok(sub_id) => success(id, json!(sub_id)),
+                Err(err) => failure(id, LIMIT_EXCEEDED_CODE, &err),
+            },
+            Err(err) => failure(id, INVALID_PARAMS_CODE, &err),
+        }
+    } else {
+        match params.get(0).and_then(Value::as_str) {
+            Some(sub_id) => {
+                let removed = subscriptions.lock().unsubscribe(sub_id, sender.token());
+                success(id, json!(removed))
+            }
+            None => failure(id, INVALID_PARAMS_CODE, "missing subscription id"),
+        }
+    };
+    Some(reply)
+}
+
+fn build_request(method: &str, params: Value) -> Option<ProtoRequest> {
+    let text = json!({"jsonrpc": "2.0", "id": 0, "method": method, "params": params}).to_string();
+    serde_json::from_str::<PartialRequest>(&text)
+        .map_err(Error::from)
+        .and_then(|part_req| part_req.complete_and_into_proto())
+        .map(|(_, req)| req)
+        .map_err(|err| error!("build subscription request {} failed: {:?}", method, err))
+        .ok()
+}
+
+/// The blocks after `latest` up to `height` to notify, at most `MAX_CATCH_UP_BLOCKS`
+/// of them, and the range skipped if there are more.
+fn catch_up(latest: u64, height: u64) -> (Option<(u64, u64)>, Vec<u64>) {
+    let from = latest + 1;
+    if height - latest > MAX_CATCH_UP_BLOCKS {
+        let first = height - MAX_CATCH_UP_BLOCKS + 1;
+        (Some((from, first - 1)), (first..=height).collect())
+    } else {
+        (None, (from..=height).collect())
+    }
+}
+
+fn missed(id: &str, from: u64, to: u64) -> String {
+    json!({
+        "jsonrpc": "2.0",
+        "method": MISSED_METHOD,
+        "params": {
+            "subscription": id,
+            "fromBlock": format!("{:#x}", from),
+            "toBlock": format!("{:#x}", to),
+        },
+    })
+    .to_string()
+}
+
+fn notification(id: &str, result: Value) -> String {
+    json!({
+        "jsonrpc": "2.0",
+        "method": NOTIFICATION_METHOD,
+        "params": {"subscription": id, "result": result},
+    })
+    .to_string()
+}
+
+fn success(id: Value, result: Value) -> String {
+    json!({"jsonrpc": "2.0", "id": id, "result": result}).to_string()
+}
+
+fn failure(id: Value, code: i64, message: &str) -> String {
+    json!({"jsonrpc": "2.0", "id": id, "error": {"code": code, "message": message}}).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_subscription_kind_from_params() {
+        assert_eq!(
+            SubscriptionKind::from_params(&[json!("newHeads")]),
+            Ok(SubscriptionKind::NewHeads)
+        );
+        assert_eq!(
+            SubscriptionKind::from_params(&[json!("newPendingTransactions")]),
+            Ok(SubscriptionKind::NewPendingTransactions)
+        );
+        assert_eq!(
+            SubscriptionKind::from_params(&[
+                json!("logs"),
+                json!({"fromBlock": "0x1", "topics": []})
+            ]),
+            Ok(SubscriptionKind::Logs(json!({"topics": []})))
+        );
+        assert!(SubscriptionKind::from_params(&[json!("unknown")]).is_err());
+        assert!(SubscriptionKind::from_params(&[]).is_err());
+    }
+
+    #[test]
+    fn test_new_heights() {
+        let mut subscriptions = Subscriptions::default();
+        assert_eq!(subscriptions.new_heights(10), vec![10]);
+        assert_eq!(subscriptions.new_heights(10), Vec::<u64>::new());
+        // Nothing to catch up without subscribers.
+        assert_eq!(subscriptions.new_heights(13), vec![13]);
+        assert_eq!(subscriptions.new_heights(12), Vec::<u64>::new());
+    }
+
+    #[test]
+    fn test_catch_up() {
+        assert_eq!(catch_up(10, 12), (None, vec![11, 12]));
+        let (missed, heights) = catch_up(10, 10 + MAX_CATCH_UP_BLOCKS);
+        assert_eq!(missed, None);
+        assert_eq!(heights.len() as u64, MAX_CATCH_UP_BLOCKS);
+
+        let (missed, heights) = catch_up(10, 1000);
+        assert_eq!(missed, Some((11, 1000 - MAX_CATCH_UP_BLOCKS)));
+        assert_eq!(heights.len() as u64, MAX_CATCH_UP_BLOCKS);
+        assert_eq!(heights[0], 1000 - MAX_CATCH_UP_BLOCKS + 1);
+        assert_eq!(heights.last(), Some(&1000));
+    }
+
+    #[test]
+    fn test_missed() {
+        let v: Value = serde_json::from_str(&missed("0x1", 11, 20)).unwrap();
+        assert_eq!(
+            v,
+            json!({
+                "jsonrpc": "2.0",
+                "method": "subscriptionMissed",
+                "params": {"subscription": "0x1", "fromBlock": "0xb", "toBlock": "0x14"},
+            })
+        );
+    }
+
+    #[test]
+    fn test_notification() {
+        let v: Value = serde_json::from_str(&notification("0x1", json!("0xab"))).unwrap();
+        assert_eq!(
+            v,
+            json!({
+                "jsonrpc": "2.0",
+                "method": "subscription",
+                "params": {"subscription": "0x1", "result": "0xab"},
+            })
+        );
+    }
+}
@@ -0,0 +1,26 @@
+// Copyright Rivtower Technologies LLC.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::config::TlsConfig;
+use openssl::error::ErrorStack;
+use openssl::ssl::{SslAcceptor, SslFiletype, SslMethod};
+
+/// Server side TLS of a listener, with the certificate and key of the config.
+pub fn acceptor(config: &TlsConfig) -> Result<SslAcceptor, ErrorStack> {
+    let mut builder = SslAcceptor::mozilla_intermediate(SslMethod::tls())?;
+    builder.set_private_key_file(&config.key_file, SslFiletype::PEM)?;
+    builder.set_certificate_chain_file(&config.cert_file)?;
+    builder.check_private_key()?;
+    Ok(builder.build())
+}
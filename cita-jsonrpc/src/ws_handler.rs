@@ -12,30 +12,47 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use crate::helper::{select_topic, RpcMap, TransferType};
+use crate::access::Access;
+use crate::auth::Authenticator;
+use crate::extension::{ExtCall, RawSender};
+use crate::helper::{select_topic, tag_client, ExtReply, RpcMap, TransferType};
+use crate::subscription::{self, SubscriptionMap};
 use jsonrpc_proto::complete::CompleteInto;
 use jsonrpc_types::rpc_request::{PartialRequest, RequestInfo};
 use jsonrpc_types::rpc_response::RpcFailure;
 use jsonrpc_types::Error;
 use libproto::request::Request as ProtoRequest;
 use num_cpus;
+use openssl::ssl::{SslAcceptor, SslStream};
 use pubsub::channel::Sender;
 use serde_json;
+use std::net::{IpAddr, Ipv4Addr};
+use std::str;
 use std::sync::Arc;
 use threadpool::ThreadPool;
-use ws::{self as ws, CloseCode, Factory, Handler};
+use ws::{self as ws, util::TcpStream, CloseCode, Factory, Handler, Handshake};
 
 pub struct WsFactory {
     //TODO 定时清理工作
     responses: RpcMap,
+    subscriptions: SubscriptionMap,
+    access: Arc<Access>,
+    auth: Option<Arc<Authenticator>>,
+    tls: Option<Arc<SslAcceptor>>,
     thread_pool: ThreadPool,
     tx: Sender<(String, ProtoRequest)>,
+    ext_tx: RawSender,
 }
 
 impl WsFactory {
     pub fn new(
         responses: RpcMap,
+        subscriptions: SubscriptionMap,
+        access: Access,
+        auth: Option<Authenticator>,
+        tls: Option<SslAcceptor>,
         tx: Sender<(String, ProtoRequest)>,
+        ext_tx: RawSender,
         thread_num: usize,
     ) -> WsFactory {
         let thread_number = if thread_num == 0 {
@@ -46,8 +63,13 @@ impl WsFactory {
         let thread_pool = ThreadPool::with_name("ws_thread_pool".to_string(), thread_number);
         WsFactory {
             responses,
+            subscriptions,
+            access: Arc::new(access),
+            auth: auth.map(Arc::new),
+            tls: tls.map(Arc::new),
             thread_pool,
             tx,
+            ext_tx,
         }
     }
 }
@@ -58,37 +80,107 @@ impl Factory for WsFactory {
         WsHandler {
             sender: ws,
             responses: Arc::clone(&self.responses),
+            subscriptions: Arc::clone(&self.subscriptions),
+            access: Arc::clone(&self.access),
+            auth: self.auth.clone(),
+            tls: self.tls.clone(),
+            remote_ip: IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            identity: None,
             tx: self.tx.clone(),
+            ext_tx: self.ext_tx.clone(),
             thread_pool: self.thread_pool.clone(),
         }
     }
 }
 
 impl Handler for WsHandler {
+    fn on_request(&mut self, req: &ws::Request) -> ws::Result<ws::Response> {
+        if let Some(ref auth) = self.auth {
+            let authorization = req
+                .header("authorization")
+                .and_then(|value| str::from_utf8(value).ok());
+            match auth.authenticate(authorization) {
+                Ok(identity) => self.identity = Some(identity),
+                Err(err) => {
+                    info!("WebSocket auth-error={}", err);
+                    let body = err.to_string().into_bytes();
+                    return Ok(ws::Response::new(401, "Unauthorized", body));
+                }
+            }
+        }
+        ws::Response::from_request(req)
+    }
+
+    fn upgrade_ssl_server(&mut self, sock: TcpStream) -> ws::Result<SslStream<TcpStream>> {
+        match self.tls {
+            Some(ref acceptor) => acceptor.accept(sock).map_err(From::from),
+            None => Err(ws::Error::new(
+                ws::ErrorKind::Internal,
+                "TLS is not configured",
+            )),
+        }
+    }
+
+    fn on_open(&mut self, shake: Handshake) -> ws::Result<()> {
+        if let Some(addr) = shake.peer_addr {
+            self.remote_ip = addr.ip();
+        }
+        if let Some(ref identity) = self.identity {
+            info!(
+                "WebSocket opened from {} identity {}",
+                self.remote_ip, identity
+            );
+        }
+        Ok(())
+    }
+
     fn on_message(&mut self, msg: ws::Message) -> ws::Result<()> {
         trace!("Server got message '{}'  post thread_pool deal task ", msg);
         let tx = self.tx.clone();
+        let ext_tx = self.ext_tx.clone();
         let response = Arc::clone(&self.responses);
+        let subscriptions = Arc::clone(&self.subscriptions);
+        let access = Arc::clone(&self.access);
+        let remote_ip = self.remote_ip;
         let sender = self.sender.clone();
 
         self.thread_pool.execute(move || {
             let mut req_info = RequestInfo::null();
+            let text = msg.into_text().unwrap();
+
+            let check = |method: &str| access.check(remote_ip, method);
+            if let Some(reply) = subscription::handle_request(&subscriptions, &text, &sender, check)
+            {
+                let _ = sender.send(reply);
+                return;
+            }
 
-            let _ = serde_json::from_str::<PartialRequest>(&msg.into_text().unwrap())
+            let _ = serde_json::from_str::<PartialRequest>(&text)
                 .map_err(Error::from)
                 .and_then(|part_req| {
                     req_info = part_req.get_info();
-                    part_req.complete_and_into_proto().map(|(full_req, req)| {
-                        let request_id = req.request_id.clone();
-                        let topic = select_topic(&full_req.get_method());
-                        let _ = tx.send((topic, req));
-                        let value = (req_info.clone(), sender.clone());
-                        {
-                            response
-                                .lock()
-                                .insert(request_id, TransferType::WEBSOCKET(value));
-                        }
-                    })
+                    if let Some(ext_call) = ExtCall::from_partial(&part_req) {
+                        access.check(remote_ip, ext_call.method())?;
+                        let reply = ExtReply::Websocket(sender.clone());
+                        ext_call.publish(&response, &ext_tx, reply);
+                        return Ok(());
+                    }
+                    part_req
+                        .complete_and_into_proto()
+                        .and_then(|(full_req, mut req)| {
+                            access.check(remote_ip, &full_req.get_method())?;
+                            tag_client(&full_req.get_method(), &mut req, remote_ip);
+                            let request_id = req.request_id.clone();
+                            let topic = select_topic(&full_req.get_method());
+                            let _ = tx.send((topic, req));
+                            let value = (req_info.clone(), sender.clone());
+                            {
+                                response
+                                    .lock()
+                                    .insert(request_id, TransferType::WEBSOCKET(value));
+                            }
+                            Ok(())
+                        })
                 })
                 .map_err(|err| {
                     // TODO 错误返回
@@ -108,13 +200,23 @@ impl Handler for WsHandler {
             reason,
             self.sender.token().0
         );
+        self.subscriptions
+            .lock()
+            .remove_connection(self.sender.token());
     }
 }
 
 #[derive(Clone)]
 pub struct WsHandler {
     responses: RpcMap,
+    subscriptions: SubscriptionMap,
+    access: Arc<Access>,
+    auth: Option<Arc<Authenticator>>,
+    tls: Option<Arc<SslAcceptor>>,
+    remote_ip: IpAddr,
+    identity: Option<String>,
     thread_pool: ThreadPool,
     sender: ws::Sender,
     tx: Sender<(String, ProtoRequest)>,
+    ext_tx: RawSender,
 }
@@ -0,0 +1,523 @@
+// Copyright Rivtower Technologies LLC.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Encrypted private keys, in the version 3 JSON keystore format of Ethereum.
+//!
+//! The key is encrypted with AES-128-CTR, by a key derived from the password
+//! with scrypt or PBKDF2-HMAC-SHA256. The MAC is the keccak256 hash of the second
+//! half of the derived key and the ciphertext, so a wrong password is detected
+//! before the key is used.
+//!
+//! A node unlocks its keystore at start with the password from a file, or from the
+//! `CITA_KEYSTORE_PASSWORD_FILE` or `CITA_KEYSTORE_PASSWORD` environment variable.
+//! A file holding the plain hex key is still accepted.
+
+#[macro_use]
+extern crate serde_derive;
+
+use crypto::aes::{self, KeySize};
+use crypto::hmac::Hmac;
+use crypto::pbkdf2::pbkdf2;
+use crypto::scrypt::{scrypt, ScryptParams};
+use crypto::sha2::Sha256;
+use crypto::util::fixed_time_eq;
+use rand::{OsRng, Rng};
+use rustc_hex::{FromHex, ToHex};
+use std::io::Write;
+#[cfg(unix)]
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+use std::path::Path;
+use std::{env, fmt, fs, io};
+use tiny_keccak::keccak256;
+use uuid::Uuid;
+
+/// Environment variable holding the password
+pub const PASSWORD_ENV: &str = "CITA_KEYSTORE_PASSWORD";
+/// Environment variable holding the path of a file with the password
+pub const PASSWORD_FILE_ENV: &str = "CITA_KEYSTORE_PASSWORD_FILE";
+
+const VERSION: u32 = 3;
+const CIPHER: &str = "aes-128-ctr";
+const PRF: &str = "hmac-sha256";
+const DKLEN: usize = 32;
+const SALT_LEN: usize = 32;
+const IV_LEN: usize = 16;
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    Json(serde_json::Error),
+    InvalidHex,
+    UnsupportedVersion(u32),
+    UnsupportedCipher(String),
+    UnsupportedKdf(String),
+    InvalidKdfParams,
+    InvalidPassword,
+    NoPassword,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "Keystore io error: {}", e),
+            Error::Json(e) => write!(f, "Invalid keystore: {}", e),
+            Error::InvalidHex => f.write_str("Invalid hex in keystore"),
+            Error::UnsupportedVersion(version) => {
+                write!(f, "Unsupported keystore version {}", version)
+            }
+            Error::UnsupportedCipher(cipher) => write!(f, "Unsupported cipher {}", cipher),
+            Error::UnsupportedKdf(kdf) => write!(f, "Unsupported kdf {}", kdf),
+            Error::InvalidKdfParams => f.write_str("Invalid kdf params"),
+            Error::InvalidPassword => f.write_str("Invalid password"),
+            Error::NoPassword => write!(
+                f,
+                "No keystore password, set {} or {}",
+                PASSWORD_FILE_ENV, PASSWORD_ENV
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Error::Json(e)
+    }
+}
+
+/// The function deriving the encryption key from the password.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Kdf {
+    Scrypt { n: u32, r: u32, p: u32 },
+    Pbkdf2 { c: u32 },
+}
+
+impl Default for Kdf {
+    /// The standard scrypt params of geth
+    fn default() -> Self {
+        Kdf::Scrypt {
+            n: 262_144,
+            r: 8,
+            p: 1,
+        }
+    }
+}
+
+impl Kdf {
+    fn derive_key(self, password: &[u8], salt: &[u8]) -> Result<[u8; DKLEN], Error> {
+        let mut key = [0u8; DKLEN];
+        match self {
+            Kdf::Scrypt { n, r, p } => {
+                // Bounds of rust-crypto, which panics outside them.
+                if n < 2 || !n.is_power_of_two() || r == 0 || p == 0 || n.trailing_zeros() >= r * 16
+                {
+                    return Err(Error::InvalidKdfParams);
+                }
+                let params = ScryptParams::new(n.trailing_zeros() as u8, r, p);
+                scrypt(password, salt, &params, &mut key);
+            }
+            Kdf::Pbkdf2 { c } => {
+                if c == 0 {
+                    return Err(Error::InvalidKdfParams);
+                }
+                pbkdf2(&mut Hmac::new(Sha256::new(), password), salt, c, &mut key);
+            }
+        }
+        Ok(key)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CipherParams {
+    iv: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct KdfParams {
+    dklen: usize,
+    salt: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    n: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    r: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    p: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    c: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    prf: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Crypto {
+    cipher: String,
+    ciphertext: String,
+    cipherparams: CipherParams,
+    kdf: String,
+    kdfparams: KdfParams,
+    mac: String,
+}
+
+/// A private key encrypted by a password
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Keystore {
+    version: u32,
+    id: String,
+    /// Hex of the address, without 0x
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    address: Option<String>,
+    #[serde(alias = "Crypto")]
+    crypto: Crypto,
+}
+
+impl Keystore {
+    /// Encrypt the private key with the password
+    pub fn encrypt(
+        privkey: &[u8],
+        address: Option<&[u8]>,
+        password: &str,
+        kdf: Kdf,
+    ) -> Result<Self, Error> {
+        let mut rng =
+            OsRng::new().map_err(|e| Error::Io(io::Error::new(io::ErrorKind::Other, e)))?;
+        let mut salt = [0u8; SALT_LEN];
+        let mut iv = [0u8; IV_LEN];
+        rng.fill_bytes(&mut salt);
+        rng.fill_bytes(&mut iv);
+
+        let key = kdf.derive_key(password.as_bytes(), &salt)?;
+        let ciphertext = aes_ctr(&key[..16], &iv, privkey);
+        let mac = mac(&key, &ciphertext);
+
+        let kdfparams = match kdf {
+            Kdf::Scrypt { n, r, p } => KdfParams {
+                dklen: DKLEN,
+                salt: salt.to_hex(),
+                n: Some(n),
+                r: Some(r),
+                p: Some(p),
+                c: None,
+                prf: None,
+            },
+            Kdf::Pbkdf2 { c } => KdfParams {
+                dklen: DKLEN,
+                salt: salt.to_hex(),
+                n: None,
+                r: None,
+                p: None,
+                c: Some(c),
+                prf: Some(PRF.to_owned()),
+            },
+        };
+        Ok(Keystore {
+            version: VERSION,
+            id: Uuid::new_v4().to_string(),
+            address: address.map(|address| address.to_hex()),
+            crypto: Crypto {
+                cipher: CIPHER.to_owned(),
+                ciphertext: ciphertext.to_hex(),
+                cipherparams: CipherParams { iv: iv.to_hex() },
+                kdf: match kdf {
+                    Kdf::Scrypt { .. } => "scrypt",
+                    Kdf::Pbkdf2 { .. } => "pbkdf2",
+                }
+                .to_owned(),
+                kdfparams,
+                mac: mac.to_hex(),
+            },
+        })
+    }
+
+    /// Decrypt the private key with the password
+    pub fn decrypt(&self, password: &str) -> Result<Vec<u8>, Error> {
+        if self.crypto.cipher != CIPHER {
+            return Err(Error::UnsupportedCipher(self.crypto.cipher.clone()));
+        }
+        let salt = from_hex(&self.crypto.kdfparams.salt)?;
+        let iv = from_hex(&self.crypto.cipherparams.iv)?;
+        let ciphertext = from_hex(&self.crypto.ciphertext)?;
+        if iv.len() != IV_LEN {
+            return Err(Error::InvalidHex);
+        }
+
+        let key = self.kdf()?.derive_key(password.as_bytes(), &salt)?;
+        if !fixed_time_eq(&mac(&key, &ciphertext), &from_hex(&self.crypto.mac)?) {
+            return Err(Error::InvalidPassword);
+        }
+        Ok(aes_ctr(&key[..16], &iv, &ciphertext))
+    }
+
+    /// Encrypt the key again with a new password, keeping the id and the kdf
+    pub fn change_password(&self, password: &str, new_password: &str) -> Result<Self, Error> {
+        let privkey = self.decrypt(password)?;
+        let address = match self.address {
+            Some(ref address) => Some(from_hex(address)?),
+            None => None,
+        };
+        let mut keystore = Keystore::encrypt(
+            &privkey,
+            address.as_ref().map(Vec::as_slice),
+            new_password,
+            self.kdf()?,
+        )?;
+        keystore.id = self.id.clone();
+        Ok(keystore)
+    }
+
+    pub fn kdf(&self) -> Result<Kdf, Error> {
+        let params = &self.crypto.kdfparams;
+        if params.dklen != DKLEN {
+            return Err(Error::InvalidKdfParams);
+        }
+        match self.crypto.kdf.as_str() {
+            "scrypt" => match (params.n, params.r, params.p) {
+                (Some(n), Some(r), Some(p)) => Ok(Kdf::Scrypt { n, r, p }),
+                _ => Err(Error::InvalidKdfParams),
+            },
+            "pbkdf2" => match (params.c, params.prf.as_ref().map(String::as_str)) {
+                (Some(c), Some(PRF)) => Ok(Kdf::Pbkdf2 { c }),
+                (_, Some(prf)) => Err(Error::UnsupportedKdf(prf.to_owned())),
+                _ => Err(Error::InvalidKdfParams),
+            },
+            kdf => Err(Error::UnsupportedKdf(kdf.to_owned())),
+        }
+    }
+
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn address(&self) -> Option<&str> {
+        self.address.as_ref().map(String::as_str)
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, Error> {
+        let keystore: Keystore = serde_json::from_str(json)?;
+        if keystore.version != VERSION {
+            return Err(Error::UnsupportedVersion(keystore.version));
+        }
+        Ok(keystore)
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap()
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        Keystore::from_json(&fs::read_to_string(path)?)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        write_secret(path, (self.to_json() + "\n").as_bytes())?;
+        Ok(())
+    }
+}
+
+/// Write a file only readable by its owner, for keystores and plain keys.
+pub fn write_secret<P: AsRef<Path>>(path: P, data: &[u8]) -> io::Result<()> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    options.mode(0o600);
+    let mut file = options.open(path)?;
+    // The mode is only applied to new files.
+    #[cfg(unix)]
+    file.set_permissions(fs::Permissions::from_mode(0o600))?;
+    file.write_all(data)
+}
+
+/// The password in the file, without the trailing line break
+pub fn read_password_file<P: AsRef<Path>>(path: P) -> Result<String, Error> {
+    let password = fs::read_to_string(path)?;
+    Ok(password
+        .trim_end_matches(|c| c == '\n' || c == '\r')
+        .to_owned())
+}
+
+/// The password in the given file,
+/// or else the one of `CITA_KEYSTORE_PASSWORD_FILE` or `CITA_KEYSTORE_PASSWORD`.
+pub fn password(password_file: Option<&Path>) -> Result<String, Error> {
+    if let Some(path) = password_file {
+        return read_password_file(path);
+    }
+    if let Ok(path) = env::var(PASSWORD_FILE_ENV) {
+        return read_password_file(path);
+    }
+    env::var(PASSWORD_ENV).map_err(|_| Error::NoPassword)
+}
+
+/// Read the private key file of a node.
+///
+/// A keystore is unlocked with the password given by `password`;
+/// a plain hex key, as written by older versions of create-key-addr, is returned as is.
+pub fn load_privkey<P: AsRef<Path>>(
+    path: P,
+    password_file: Option<&Path>,
+) -> Result<Vec<u8>, Error> {
+    let content = fs::read_to_string(path)?;
+    let content = content.trim();
+    if content.starts_with('{') {
+        Keystore::from_json(content)?.decrypt(&password(password_file)?)
+    } else {
+        from_hex(content)
+    }
+}
+
+fn aes_ctr(key: &[u8], iv: &[u8], input: &[u8]) -> Vec<u8> {
+    let mut output = vec![0u8; input.len()];
+    aes::ctr(KeySize::KeySize128, key, iv).process(input, &mut output);
+    output
+}
+
+fn mac(key: &[u8; DKLEN], ciphertext: &[u8]) -> [u8; 32] {
+    let mut data = key[16..].to_vec();
+    data.extend_from_slice(ciphertext);
+    keccak256(&data)
+}
+
+fn from_hex(hex: &str) -> Result<Vec<u8>, Error> {
+    let hex = if hex.starts_with("0x") {
+        &hex[2..]
+    } else {
+        hex
+    };
+    hex.from_hex().map_err(|_| Error::InvalidHex)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempdir::TempDir;
+
+    const PRIVKEY: &str = "7a28b5ba57c53603b0b07b56bba752f7784bf506fa95edc395f5cf6c7514fe9d";
+
+    // The PBKDF2 test vector of the Web3 Secret Storage Definition.
+    const PBKDF2_KEYSTORE: &str = r#"{
+        "crypto" : {
+            "cipher" : "aes-128-ctr",
+            "cipherparams" : {
+                "iv" : "6087dab2f9fdbbfaddc31a909735c1e6"
+            },
+            "ciphertext" : "5318b4d5bcd28de64ee5559e671353e16f075ecae9f99c7a79a38af5f869aa46",
+            "kdf" : "pbkdf2",
+            "kdfparams" : {
+                "c" : 262144,
+                "dklen" : 32,
+                "prf" : "hmac-sha256",
+                "salt" : "ae3cd4e7013836a3df6bd7241b12db061dbe2c6785853cce422d148a624ce0bd"
+            },
+            "mac" : "517ead924a9d0dc3124507e3393d175ce3ff7c1e96529c6c555ce9e51205e9b2"
+        },
+        "id" : "3198bc9c-6672-5ab3-d995-4942343ae5b6",
+        "version" : 3
+    }"#;
+
+    const LIGHT_SCRYPT: Kdf = Kdf::Scrypt {
+        n: 1024,
+        r: 8,
+        p: 1,
+    };
+
+    #[test]
+    fn test_decrypt_ethereum_keystore() {
+        let keystore = Keystore::from_json(PBKDF2_KEYSTORE).unwrap();
+        assert_eq!(keystore.kdf().unwrap(), Kdf::Pbkdf2 { c: 262_144 });
+        assert_eq!(
+            keystore.decrypt("testpassword").unwrap(),
+            from_hex(PRIVKEY).unwrap()
+        );
+        match keystore.decrypt("wrongpassword") {
+            Err(Error::InvalidPassword) => {}
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_encrypt_and_decrypt() {
+        let privkey = from_hex(PRIVKEY).unwrap();
+        let address = [1u8; 20];
+        for kdf in [LIGHT_SCRYPT, Kdf::Pbkdf2 { c: 1000 }].iter() {
+            let keystore = Keystore::encrypt(&privkey, Some(&address), "secret", *kdf).unwrap();
+            let keystore = Keystore::from_json(&keystore.to_json()).unwrap();
+            assert_eq!(keystore.kdf().unwrap(), *kdf);
+            assert_eq!(keystore.address(), Some(&address.to_hex()[..]));
+            assert_eq!(keystore.decrypt("secret").unwrap(), privkey);
+            assert!(keystore.decrypt("Secret").is_err());
+        }
+    }
+
+    #[test]
+    fn test_change_password() {
+        let privkey = from_hex(PRIVKEY).unwrap();
+        let keystore = Keystore::encrypt(&privkey, None, "old", LIGHT_SCRYPT).unwrap();
+        assert!(keystore.change_password("wrong", "new").is_err());
+        let changed = keystore.change_password("old", "new").unwrap();
+        assert_eq!(changed.id(), keystore.id());
+        assert_eq!(changed.kdf().unwrap(), LIGHT_SCRYPT);
+        assert!(changed.decrypt("old").is_err());
+        assert_eq!(changed.decrypt("new").unwrap(), privkey);
+    }
+
+    #[test]
+    fn test_load_privkey() {
+        let dir = TempDir::new("keystore").unwrap();
+        let privkey = from_hex(PRIVKEY).unwrap();
+        let password_path = dir.path().join("password");
+        fs::write(&password_path, "secret\n").unwrap();
+
+        let plain_path = dir.path().join("privkey");
+        fs::write(&plain_path, format!("0x{}\n", PRIVKEY)).unwrap();
+        assert_eq!(load_privkey(&plain_path, None).unwrap(), privkey);
+
+        let keystore_path = dir.path().join("keystore");
+        Keystore::encrypt(&privkey, None, "secret", LIGHT_SCRYPT)
+            .unwrap()
+            .save(&keystore_path)
+            .unwrap();
+        assert_eq!(
+            load_privkey(&keystore_path, Some(&password_path)).unwrap(),
+            privkey
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_secret_file_mode() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = TempDir::new("keystore").unwrap();
+        let path = dir.path().join("keystore");
+        fs::write(&path, "old").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o644)).unwrap();
+
+        Keystore::encrypt(&from_hex(PRIVKEY).unwrap(), None, "secret", LIGHT_SCRYPT)
+            .unwrap()
+            .save(&path)
+            .unwrap();
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+
+        let new_path = dir.path().join("privkey");
+        write_secret(&new_path, b"0x00").unwrap();
+        let mode = fs::metadata(&new_path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+}
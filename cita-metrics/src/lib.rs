@@ -0,0 +1,143 @@
+// Copyright Rivtower Technologies LLC.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Prometheus metrics endpoint shared by the micro-services.
+//!
+//! Each service registers its metrics in the default `prometheus` registry,
+//! and calls [`start_server`] to serve them on `GET /metrics` in the text format.
+//!
+//! [`start_server`]: ./fn.start_server.html
+
+#[macro_use]
+extern crate cita_logger as logger;
+
+use prometheus::{Encoder, TextEncoder};
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::thread;
+use std::time::Duration;
+
+pub const METRICS_PATH: &str = "/metrics";
+
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Serve the metrics on `addr` from a background thread, returns the bound address.
+pub fn start_server(addr: &str) -> io::Result<SocketAddr> {
+    let listener = TcpListener::bind(addr)?;
+    let local_addr = listener.local_addr()?;
+    thread::Builder::new()
+        .name("metrics".to_owned())
+        .spawn(move || {
+            for stream in listener.incoming() {
+                match stream {
+                    Ok(stream) => {
+                        if let Err(e) = serve(stream) {
+                            debug!("[Metrics] Serve error: {:?}", e);
+                        }
+                    }
+                    Err(e) => warn!("[Metrics] Accept error: {:?}", e),
+                }
+            }
+        })?;
+    info!("[Metrics] Listening on {}", local_addr);
+    Ok(local_addr)
+}
+
+/// All registered metrics in the Prometheus text format.
+pub fn gather() -> Vec<u8> {
+    let mut buffer = Vec::new();
+    if let Err(e) = TextEncoder::new().encode(&prometheus::gather(), &mut buffer) {
+        error!("[Metrics] Encode error: {:?}", e);
+    }
+    buffer
+}
+
+/// Duration in seconds, the unit of Prometheus histograms.
+pub fn duration_to_seconds(d: Duration) -> f64 {
+    d.as_secs() as f64 + f64::from(d.subsec_nanos()) / 1e9
+}
+
+fn serve(mut stream: TcpStream) -> io::Result<()> {
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    // Skip the headers, the request has no body.
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 || line.trim().is_empty() {
+            break;
+        }
+    }
+
+    let mut parts = request_line.split_whitespace();
+    let response = match (parts.next(), parts.next()) {
+        (Some("GET"), Some(METRICS_PATH)) => {
+            response("200 OK", TextEncoder::new().format_type(), &gather())
+        }
+        _ => response("404 Not Found", "text/plain", b"Not Found"),
+    };
+    stream.write_all(&response)?;
+    stream.flush()
+}
+
+fn response(status: &str, content_type: &str, body: &[u8]) -> Vec<u8> {
+    let mut response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        status,
+        content_type,
+        body.len()
+    )
+    .into_bytes();
+    response.extend_from_slice(body);
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{duration_to_seconds, start_server};
+    use prometheus::{register_int_counter, IntCounter};
+    use std::io::{Read, Write};
+    use std::net::{SocketAddr, TcpStream};
+    use std::time::Duration;
+
+    #[test]
+    fn test_duration_to_seconds() {
+        assert!((duration_to_seconds(Duration::from_millis(1500)) - 1.5).abs() < 1e-9);
+    }
+
+    fn get(addr: SocketAddr, path: &str) -> String {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        write!(stream, "GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    #[test]
+    fn test_serve_metrics() {
+        let counter: IntCounter =
+            register_int_counter!("test_requests_total", "Requests in test").unwrap();
+        counter.inc_by(3);
+        let addr = start_server("127.0.0.1:0").unwrap();
+
+        let response = get(addr, "/metrics");
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.contains("text/plain; version=0.0.4"));
+        assert!(response.contains("test_requests_total 3"));
+
+        let response = get(addr, "/");
+        assert!(response.starts_with("HTTP/1.1 404 Not Found\r\n"));
+    }
+}
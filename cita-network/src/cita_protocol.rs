@@ -18,6 +18,7 @@ use byteorder::{ByteOrder, NetworkEndian};
 use bytes::{BufMut, Bytes, BytesMut};
 use cita_types::Address;
 use logger::{error, warn};
+use snap;
 use std::str;
 
 /// Implementation of the multiplexed line-based protocol.
@@ -37,7 +38,8 @@ use std::str;
 /// | Address                | u8;20                    |
 /// | Length of Key          | u8                       |
 /// | TTL                    | u8                       |
-/// | Reserved               | u8;2                     |
+/// | Flags                  | u8                       |
+/// | Reserved               | u8                       |
 /// +------------------------+--------------------------+
 /// | Key                    | bytes of a str           |
 /// +------------------------+--------------------------+
@@ -50,13 +52,19 @@ const NETMSG_START: u64 = 0xDEAD_BEEF_0000_0000;
 
 /// According to CITA frame, defines its frame header length as:
 /// "Symbol for Start" + "Length of Full Payload" + "Version"+
-///  "Address"+ "Length of Key"+"TTL"+"Reserved",
-/// And this will consume "4 + 4 + 8 + 20 + 1 + 1+ 2" fixed-lengths of the frame.
-pub const CITA_FRAME_HEADER_LEN: usize = 4 + 4 + 8 + 20 + 1 + 1 + 2;
+///  "Address"+ "Length of Key"+"TTL"+"Flags"+"Reserved",
+/// And this will consume "4 + 4 + 8 + 20 + 1 + 1 + 1 + 1" fixed-lengths of the frame.
+pub const CITA_FRAME_HEADER_LEN: usize = 4 + 4 + 8 + 20 + 1 + 1 + 1 + 1;
 pub const HEAD_VERSION_OFFSET: usize = 4 + 4;
 pub const HEAD_ADDRESS_OFFSET: usize = 4 + 4 + 8;
 pub const HEAD_KEY_LEN_OFFSET: usize = 4 + 4 + 8 + 20;
 pub const HEAD_TTL_OFFSET: usize = 4 + 4 + 8 + 20 + 1;
+pub const HEAD_FLAGS_OFFSET: usize = 4 + 4 + 8 + 20 + 1 + 1;
+
+// The message is compressed by snappy.
+pub const FLAG_SNAPPY: u8 = 0b0000_0001;
+// Messages not longer than this are never compressed.
+pub const COMPRESS_THRESHOLD: usize = 1024;
 
 pub const DEFAULT_TTL_NUM: u8 = 0;
 pub const CONSENSUS_TTL_NUM: u8 = 9;
@@ -96,6 +104,16 @@ impl Default for NetMessageUnit {
 }
 
 pub fn pubsub_message_to_network_message(info: &NetMessageUnit) -> Option<Bytes> {
+    encode_network_message(info, false)
+}
+
+/// Same as `pubsub_message_to_network_message`, but compress the message if it is worth it.
+/// Only for the peers which support compressed messages.
+pub fn pubsub_message_to_compressed_network_message(info: &NetMessageUnit) -> Option<Bytes> {
+    encode_network_message(info, true)
+}
+
+fn encode_network_message(info: &NetMessageUnit, compress: bool) -> Option<Bytes> {
     let length_key = info.key.len();
     // Use 1 byte to store key length.
     if length_key == 0 || length_key > u8::max_value() as usize {
@@ -105,7 +123,20 @@ pub fn pubsub_message_to_network_message(info: &NetMessageUnit) -> Option<Bytes>
         );
         return None;
     }
-    let length_full = length_key + info.data.len();
+    let compressed = if compress && info.data.len() > COMPRESS_THRESHOLD {
+        snap::Encoder::new()
+            .compress_vec(&info.data)
+            .ok()
+            .filter(|compressed| compressed.len() < info.data.len())
+    } else {
+        None
+    };
+    let (flags, data) = match compressed {
+        Some(ref compressed) => (FLAG_SNAPPY, compressed),
+        None => (0, &info.data),
+    };
+
+    let length_full = length_key + data.len();
     // Use 1 bytes to store the length for key, then store key, the last part is body.
     if length_full > u32::max_value() as usize {
         error!(
@@ -123,15 +154,21 @@ pub fn pubsub_message_to_network_message(info: &NetMessageUnit) -> Option<Bytes>
     buf.put(info.addr.to_vec());
     buf.put_u8(length_key as u8);
     buf.put_u8(info.ttl);
-    buf.put_u16_be(0);
+    buf.put_u8(flags);
+    buf.put_u8(0);
 
     buf.put(info.key.as_bytes());
-    buf.put_slice(&info.data);
+    buf.put_slice(data);
 
     Some(buf.into())
 }
 
-pub fn network_message_to_pubsub_message(buf: &mut BytesMut) -> Option<NetMessageUnit> {
+/// Decode a network message, None if it is invalid or longer than `max_frame_len`,
+/// before or after decompressed.
+pub fn network_message_to_pubsub_message(
+    buf: &mut BytesMut,
+    max_frame_len: usize,
+) -> Option<NetMessageUnit> {
     if buf.len() < CITA_FRAME_HEADER_LEN {
         return None;
     }
@@ -147,12 +184,20 @@ pub fn network_message_to_pubsub_message(buf: &mut BytesMut) -> Option<NetMessag
     if length_full > buf.len() || length_full == 0 {
         return None;
     }
+    if length_full > max_frame_len {
+        error!(
+            "[CitaProtocol] Network message is too long {} > {}.",
+            length_full, max_frame_len
+        );
+        return None;
+    }
 
     let addr = Address::from_slice(&head_buf[HEAD_ADDRESS_OFFSET..]);
     let version = NetworkEndian::read_u64(&head_buf[HEAD_VERSION_OFFSET..]);
 
     let length_key = head_buf[HEAD_KEY_LEN_OFFSET] as usize;
     let ttl = head_buf[HEAD_TTL_OFFSET];
+    let flags = head_buf[HEAD_FLAGS_OFFSET];
     if length_key == 0 {
         error!("[CitaProtocol] Network message key is empty.");
         return None;
@@ -178,9 +223,36 @@ pub fn network_message_to_pubsub_message(buf: &mut BytesMut) -> Option<NetMessag
     if length_full == length_key {
         warn!("[CitaProtocol] Network message is empty.");
     }
+    let data = if flags & FLAG_SNAPPY != 0 {
+        // Check the length before allocating for the decompressed message.
+        match snap::decompress_len(&buf[..]) {
+            Ok(len) if len + length_key <= max_frame_len => {}
+            Ok(len) => {
+                error!(
+                    "[CitaProtocol] Decompressed network message is too long {} > {}.",
+                    len + length_key,
+                    max_frame_len
+                );
+                return None;
+            }
+            Err(err) => {
+                error!("[CitaProtocol] Network message decompress error {:?}.", err);
+                return None;
+            }
+        }
+        match snap::Decoder::new().decompress_vec(&buf[..]) {
+            Ok(data) => data,
+            Err(err) => {
+                error!("[CitaProtocol] Network message decompress error {:?}.", err);
+                return None;
+            }
+        }
+    } else {
+        buf.to_vec()
+    };
     Some(NetMessageUnit {
         key,
-        data: buf.to_vec(),
+        data,
         addr,
         version,
         ttl,
@@ -190,14 +262,18 @@ pub fn network_message_to_pubsub_message(buf: &mut BytesMut) -> Option<NetMessag
 #[cfg(test)]
 mod test {
     use super::{
-        network_message_to_pubsub_message, pubsub_message_to_network_message, NetMessageUnit,
+        network_message_to_pubsub_message, pubsub_message_to_compressed_network_message,
+        pubsub_message_to_network_message, NetMessageUnit, CITA_FRAME_HEADER_LEN,
+        HEAD_FLAGS_OFFSET,
     };
     use bytes::BytesMut;
 
+    const MAX_FRAME_LEN: usize = 1024 * 1024;
+
     #[test]
     fn convert_empty_message() {
         let buf = pubsub_message_to_network_message(&NetMessageUnit::default());
-        let pub_msg_opt = network_message_to_pubsub_message(&mut BytesMut::new());
+        let pub_msg_opt = network_message_to_pubsub_message(&mut BytesMut::new(), MAX_FRAME_LEN);
         assert!(pub_msg_opt.is_none());
         assert!(buf.is_none());
     }
@@ -211,10 +287,46 @@ mod test {
         msg.data = data.clone();
 
         let buf = pubsub_message_to_network_message(&msg).unwrap();
-        let pub_msg_opt = network_message_to_pubsub_message(&mut buf.try_mut().unwrap());
+        let pub_msg_opt =
+            network_message_to_pubsub_message(&mut buf.try_mut().unwrap(), MAX_FRAME_LEN);
         assert!(pub_msg_opt.is_some());
         let info = pub_msg_opt.unwrap();
         assert_eq!(key, info.key);
         assert_eq!(data, info.data);
     }
+
+    #[test]
+    fn convert_compressed_messages() {
+        let mut msg = NetMessageUnit::default();
+        msg.key = "this-is-the-key".to_string();
+        msg.data = vec![7; 4096];
+
+        let buf = pubsub_message_to_compressed_network_message(&msg).unwrap();
+        assert_eq!(buf[HEAD_FLAGS_OFFSET], 1);
+        assert!(buf.len() < CITA_FRAME_HEADER_LEN + msg.key.len() + msg.data.len());
+        let info =
+            network_message_to_pubsub_message(&mut buf.try_mut().unwrap(), MAX_FRAME_LEN).unwrap();
+        assert_eq!(msg.key, info.key);
+        assert_eq!(msg.data, info.data);
+
+        // Small messages are not compressed.
+        msg.data = vec![1, 3, 5, 7, 9];
+        let buf = pubsub_message_to_compressed_network_message(&msg).unwrap();
+        assert_eq!(buf[HEAD_FLAGS_OFFSET], 0);
+    }
+
+    #[test]
+    fn reject_oversize_messages() {
+        let mut msg = NetMessageUnit::default();
+        msg.key = "this-is-the-key".to_string();
+        msg.data = vec![7; 4096];
+
+        let buf = pubsub_message_to_network_message(&msg).unwrap();
+        assert!(network_message_to_pubsub_message(&mut buf.try_mut().unwrap(), 4096).is_none());
+
+        // Compressed to less than the limit, but too long after decompressed.
+        let buf = pubsub_message_to_compressed_network_message(&msg).unwrap();
+        assert!(buf.len() < 4096);
+        assert!(network_message_to_pubsub_message(&mut buf.try_mut().unwrap(), 4096).is_none());
+    }
 }
@@ -24,14 +24,50 @@ pub struct NetConfig {
     pub port: Option<usize>,
     pub peers: Option<Vec<PeerConfig>>,
     pub max_connects: Option<usize>,
+    /// Use mutual TLS with the certificates in `tls` on the connections.
+    /// Without `tls`, the connections are only encrypted with secio, using a key
+    /// generated on start.
     pub enable_tls: Option<bool>,
+    pub tls: Option<TlsConfig>,
     pub enable_discovery: Option<bool>,
+    /// Seconds a misbehaving peer is banned for
+    pub ban_secs: Option<u64>,
+    /// File to save the banned peers in
+    pub ban_list_path: Option<String>,
+    /// Authenticate peers by their node keys on connect.
+    /// The connections are encrypted with secio too, as the handshake is bound to it.
+    pub enable_auth: Option<bool>,
+    /// Node private key, in hex or a keystore file
+    pub privkey_path: Option<String>,
+    /// Addresses allowed to connect besides the nodes in `NodeManager`
+    pub allowlist_path: Option<String>,
+    /// Compress large messages to the peers which support it
+    pub enable_compression: Option<bool>,
+    /// Max length of a message in bytes, before or after decompressed, 64M by default.
+    /// Longer messages are dropped, so it must hold a sync batch of the biggest blocks.
+    pub max_frame_len: Option<usize>,
+    /// Max messages a peer may send in a second before it is penalized, 0 for no limit
+    pub flood_limit: Option<u32>,
+    /// Sync the state of a recent block from the peers instead of all the blocks,
+    /// when starting from genesis
+    pub state_sync: Option<bool>,
 }
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct PeerConfig {
     pub ip: Option<String>,
     pub port: Option<usize>,
+    /// Common name of the TLS certificate of the peer
+    pub common_name: Option<String>,
+}
+
+/// Certificate chain and private key of the node, and the CA bundle the certificates
+/// of the peers must be signed by, all in PEM.
+#[derive(Debug, Deserialize, Clone)]
+pub struct TlsConfig {
+    pub cert_file: String,
+    pub key_file: String,
+    pub ca_file: String,
 }
 
 impl NetConfig {
@@ -90,7 +126,39 @@ mod tests {
         assert_eq!(config.port, Some(4000));
         assert_eq!(config.max_connects, Some(4));
         assert_eq!(config.enable_tls, Some(true));
-        assert_eq!(config.peers.unwrap().len(), 2);
+        assert!(config.tls.is_none());
+        let peers = config.peers.unwrap();
+        assert_eq!(peers.len(), 2);
+        assert_eq!(peers[0].common_name, Some("test1.cita".to_owned()));
+        assert_eq!(peers[1].common_name, None);
         assert_eq!(config.enable_discovery, None);
+        assert_eq!(config.enable_auth, None);
+        assert_eq!(config.state_sync, None);
+    }
+
+    #[test]
+    fn tls_test() {
+        let toml_str = r#"
+        port = 4000
+        enable_tls = true
+        [tls]
+            cert_file = "node.crt"
+            key_file = "node.key"
+            ca_file = "ca.crt"
+        [[peers]]
+            ip = "127.0.0.1"
+            port = 4001
+            common_name = "test1.cita"
+        "#;
+
+        let mut tmp_file: NamedTempFile = NamedTempFile::new().unwrap();
+        tmp_file.write_all(toml_str.as_bytes()).unwrap();
+        let path = tmp_file.path().to_str().unwrap();
+        let config = NetConfig::new(path);
+
+        let tls = config.tls.unwrap();
+        assert_eq!(tls.cert_file, "node.crt");
+        assert_eq!(tls.key_file, "node.key");
+        assert_eq!(tls.ca_file, "ca.crt");
     }
 }
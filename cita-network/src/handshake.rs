@@ -0,0 +1,273 @@
+// Copyright Rivtower Technologies LLC.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//! Authentication of peers on connect.
+//!
+//! When `enable_auth` is set, each side of a new session sends a random nonce in its
+//! `InitMsg`, and answers the `InitMsg` of the other side with a signature made by its
+//! node key. A session is authenticated once the signature recovers to the address claimed
+//! in the `InitMsg`, and that address is in the allowlist file or in the node list of the
+//! `NodeManager` contract. Messages of a session are not forwarded before that.
+//!
+//! The signature covers both nonces, both addresses and the secio public key of the
+//! recipient, see [`Transcript`]. A node in the middle can not relay the signature of
+//! its own session with a node to another one, as its secio key differs from the key of
+//! that other node. So the sessions are always encrypted if the authentication is enabled.
+//!
+//! [`Transcript`]: ./struct.Transcript.html
+
+use cita_crypto::{pubkey_to_address, PrivKey, Sign, Signature, SIGNATURE_BYTES_LEN};
+use cita_types::{clean_0x, Address, H256};
+use hashable::Hashable;
+use std::collections::{BTreeSet, HashSet};
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::{Arc, RwLock};
+use tentacle::SessionId;
+
+/// Key of the message carrying the handshake signature.
+pub const AUTH_KEY: &str = "network.auth";
+// Prefix of the signed data, so that a peer can not get anything else signed by the node key.
+const HANDSHAKE_DOMAIN: &[u8] = b"CITA network handshake v2";
+
+/// What a handshake signature is bound to, the same for the signer and the recipient.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Transcript {
+    /// Nonce sent by the signer in its `InitMsg`
+    pub signer_nonce: H256,
+    /// Nonce sent by the recipient in its `InitMsg`
+    pub recipient_nonce: H256,
+    pub signer: Address,
+    pub recipient: Address,
+    /// Secio public key of the recipient, the remote one for the signer and the local one
+    /// for the recipient, empty if the session is not encrypted
+    pub recipient_key: Vec<u8>,
+}
+
+impl Transcript {
+    fn hash(&self) -> H256 {
+        let mut data = HANDSHAKE_DOMAIN.to_vec();
+        data.extend_from_slice(&self.signer_nonce[..]);
+        data.extend_from_slice(&self.recipient_nonce[..]);
+        data.extend_from_slice(&self.signer[..]);
+        data.extend_from_slice(&self.recipient[..]);
+        data.extend_from_slice(&self.recipient_key);
+        data.crypt_hash()
+    }
+
+    pub fn sign(&self, privkey: &PrivKey) -> Option<Vec<u8>> {
+        Signature::sign(privkey, &self.hash())
+            .ok()
+            .map(|signature| signature.to_vec())
+    }
+
+    /// Whether the signature is made by the signer over this transcript.
+    pub fn verify(&self, signature: &[u8]) -> bool {
+        if signature.len() != SIGNATURE_BYTES_LEN {
+            return false;
+        }
+        Signature::from(signature)
+            .recover(&self.hash())
+            .map(|pubkey| pubkey_to_address(&pubkey) == self.signer)
+            .unwrap_or(false)
+    }
+}
+
+/// Addresses allowed to connect.
+#[derive(Debug, Default)]
+pub struct Allowlist {
+    // Addresses in the allowlist file
+    listed: BTreeSet<Address>,
+    // Nodes in the `NodeManager` contract
+    nodes: BTreeSet<Address>,
+}
+
+impl Allowlist {
+    /// Load the allowlist file, one address per line. Empty lines and lines starting
+    /// with `#` are skipped.
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let mut listed = BTreeSet::new();
+        for line in fs::read_to_string(path)?.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let addr = Address::from_str(clean_0x(line)).map_err(|_| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("invalid address {} in allowlist", line),
+                )
+            })?;
+            listed.insert(addr);
+        }
+        Ok(Allowlist {
+            listed,
+            nodes: BTreeSet::new(),
+        })
+    }
+
+    pub fn set_nodes(&mut self, nodes: BTreeSet<Address>) {
+        self.nodes = nodes;
+    }
+
+    pub fn is_allowed(&self, addr: &Address) -> bool {
+        self.listed.contains(addr) || self.nodes.contains(addr)
+    }
+}
+
+/// Sessions which passed the authentication, shared with the transfer protocol.
+#[derive(Debug, Clone, Default)]
+pub struct AuthenticatedSessions {
+    enabled: bool,
+    sessions: Arc<RwLock<HashSet<SessionId>>>,
+}
+
+impl AuthenticatedSessions {
+    pub fn new(enabled: bool) -> Self {
+        AuthenticatedSessions {
+            enabled,
+            sessions: Arc::new(RwLock::new(HashSet::new())),
+        }
+    }
+
+    pub fn insert(&self, session_id: SessionId) {
+        self.sessions.write().unwrap().insert(session_id);
+    }
+
+    pub fn remove(&self, session_id: SessionId) {
+        self.sessions.write().unwrap().remove(&session_id);
+    }
+
+    /// Whether the messages of the session can be forwarded,
+    /// always true if the authentication is disabled.
+    pub fn is_allowed(&self, session_id: SessionId) -> bool {
+        !self.enabled || self.sessions.read().unwrap().contains(&session_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Allowlist, AuthenticatedSessions, Transcript};
+    use cita_crypto::{CreateKey, KeyPair};
+    use cita_types::{Address, H256};
+    use std::io::Write;
+    use std::str::FromStr;
+    use tempfile::NamedTempFile;
+    use tentacle::SessionId;
+
+    #[test]
+    fn sign_and_verify() {
+        let signer = KeyPair::gen_keypair();
+        let transcript = Transcript {
+            signer_nonce: H256::random(),
+            recipient_nonce: H256::random(),
+            signer: signer.address(),
+            recipient: Address::random(),
+            recipient_key: vec![1; 33],
+        };
+        let signature = transcript.sign(signer.privkey()).unwrap();
+
+        assert!(transcript.verify(&signature));
+        assert!(!transcript.verify(&signature[1..]));
+        let mut other = transcript.clone();
+        other.recipient_nonce = H256::random();
+        assert!(!other.verify(&signature));
+        let mut other = transcript.clone();
+        other.signer = Address::random();
+        assert!(!other.verify(&signature));
+    }
+
+    // A node M in the middle connects to A and B, claims to be B to A and to be A to B,
+    // and passes the nonces and the signature of A on to B.
+    #[test]
+    fn relayed_signature_rejected() {
+        let a = KeyPair::gen_keypair();
+        let b = KeyPair::gen_keypair();
+        let (b_key, m_key) = (vec![0xb; 33], vec![0xc; 33]);
+        // Nonce A sent to M, which M sends to B as its own
+        let a_nonce = H256::random();
+        // Nonce B sent to M, which M sends to A as its own
+        let b_nonce = H256::random();
+
+        // A signs for its session with M, whose secio key is M's.
+        let signed_by_a = Transcript {
+            signer_nonce: a_nonce,
+            recipient_nonce: b_nonce,
+            signer: a.address(),
+            recipient: b.address(),
+            recipient_key: m_key,
+        };
+        let signature = signed_by_a.sign(a.privkey()).unwrap();
+        assert!(signed_by_a.verify(&signature));
+
+        // B checks the signature against its own secio key.
+        let checked_by_b = Transcript {
+            signer_nonce: a_nonce,
+            recipient_nonce: b_nonce,
+            signer: a.address(),
+            recipient: b.address(),
+            recipient_key: b_key.clone(),
+        };
+        assert!(!checked_by_b.verify(&signature));
+
+        // A session between A and B directly is authenticated.
+        let direct = Transcript {
+            recipient_key: b_key,
+            ..signed_by_a
+        };
+        assert!(checked_by_b.verify(&direct.sign(a.privkey()).unwrap()));
+    }
+
+    #[test]
+    fn load_allowlist() {
+        let mut file = NamedTempFile::new().unwrap();
+        file.write_all(
+            b"# consortium members\n\
+              0x1111111111111111111111111111111111111111\n\
+              \n\
+              2222222222222222222222222222222222222222\n",
+        )
+        .unwrap();
+        let mut allowlist = Allowlist::load(file.path()).unwrap();
+
+        for addr in &[
+            "1111111111111111111111111111111111111111",
+            "2222222222222222222222222222222222222222",
+        ] {
+            assert!(allowlist.is_allowed(&Address::from_str(addr).unwrap()));
+        }
+        let node = Address::random();
+        assert!(!allowlist.is_allowed(&node));
+        allowlist.set_nodes(vec![node].into_iter().collect());
+        assert!(allowlist.is_allowed(&node));
+
+        file.write_all(b"0xinvalid\n").unwrap();
+        assert!(Allowlist::load(file.path()).is_err());
+    }
+
+    #[test]
+    fn authenticated_sessions() {
+        let session_id = SessionId::new(1);
+        assert!(AuthenticatedSessions::new(false).is_allowed(session_id));
+
+        let sessions = AuthenticatedSessions::new(true);
+        let shared = sessions.clone();
+        assert!(!shared.is_allowed(session_id));
+        sessions.insert(session_id);
+        assert!(shared.is_allowed(session_id));
+        sessions.remove(session_id);
+        assert!(!shared.is_allowed(session_id));
+    }
+}
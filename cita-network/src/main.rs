@@ -28,6 +28,7 @@
 //!     | network_consensus | Consensus | RawBytes              |
 //!     | network           | Chain     | Status                |
 //!     | network           | Chain     | SyncResponse          |
+//!     | network           | Executor  | RawBytes              |
 //!     | network           | Jsonrpc   | RequestNet            |
 //!     | network           | Jsonrpc   | RequestPeersInfo      |
 //!     | network           | Auth      | GetBlockTxn           |
@@ -35,16 +36,46 @@
 //!
 //! 2. Publish channel
 //!
-//!     |       Queue       | PubModule | SubModule           | Message Type          |
-//!     | ----------------- | --------- | ------------------- | --------------------- |
-//!     | network           | Net       | Chain, Executor     | SyncResponse          |
-//!     | network           | Net       | Snapshot            | SnapshotResp          |
-//!     | network           | Net       | Jsonrpc             | Response              |
-//!     | network_tx        | Net       | Auth                | Request               |
-//!     | network_consensus | Net       | Consensus           | ComapctSignedProposal |
-//!     | network_consensus | Net       | Consensus           | RawBytes              |
-//!     | network           | Net       | Auth                | BlockTxn              |
-//!     | network           | Net       | Auth                | GetBlockTxn           |
+//!     |       Queue       | PubModule    | SubModule       | Message Type          |
+//!     | ----------------- | ------------ | --------------- | --------------------- |
+//!     | network           | Net          | Chain, Executor | SyncResponse          |
+//!     | network           | Synchronizer | Executor        | RawBytes              |
+//!     | network           | Net          | Snapshot        | SnapshotResp          |
+//!     | network           | Net          | Jsonrpc         | Response              |
+//!     | network           | Net          | Jsonrpc         | Status                |
+//!     | network_tx        | Net          | Auth            | Request               |
+//!     | network_consensus | Net          | Consensus       | ComapctSignedProposal |
+//!     | network_consensus | Net          | Consensus       | RawBytes              |
+//!     | network           | Net          | Auth            | BlockTxn              |
+//!     | network           | Net          | Auth            | GetBlockTxn           |
+//!
+//! ### Configuration
+//!
+//! Options of network.toml besides the listening `port` and the `peers`:
+//!
+//! |       Option       | Default | Description                                          |
+//! | ------------------ | ------- | ---------------------------------------------------- |
+//! | max_connects       | 666     | Max number of connected peers                        |
+//! | enable_tls         | false   | Mutual TLS with the certificates of `tls`, or only   |
+//! |                    |         | encrypt the connections with secio without them      |
+//! | tls                | none    | `cert_file`, `key_file` and `ca_file` in PEM         |
+//! | enable_auth        | false   | Authenticate peers by node keys, implies enable_tls  |
+//! | enable_discovery   | true    | Discover peers from the connected ones               |
+//! | enable_compression | true    | Compress large messages to the peers supporting it   |
+//! | max_frame_len      | 64M     | Max length in bytes of a message, longer are dropped |
+//! | ban_secs           | 3600    | Seconds a misbehaving peer is banned for             |
+//! | flood_limit        | 5000    | Max messages per second of a peer, 0 for no limit    |
+//! | state_sync         | false   | Sync the state of a recent block instead of all the  |
+//! |                    |         | blocks when starting from genesis                    |
+//!
+//! With TLS, a peer dialed must present the `common_name` set for it in `peers`, and
+//! a peer connecting the `common_name` of one of the peers, unless none is set. The
+//! peers are only the configured ones, discovery is disabled. The certificates are
+//! reloaded when the config or one of them changes. See [`tls`] for more details.
+//!
+//! A sync response carries 20 blocks, so `max_frame_len` must hold 20 of the biggest
+//! blocks. The block quota limit bounds a block to `BQL / 200` bytes of transactions,
+//! raise `max_frame_len` to `20 * BQL / 200` if it is above about 670M quota.
 //!
 //! ### p2p binary protocol
 //! | Start      | Full length | Key length | Key value      | Message value    |
@@ -83,20 +114,27 @@ extern crate cita_logger as logger;
 extern crate util;
 pub mod cita_protocol;
 pub mod config;
+pub mod handshake;
+pub mod metrics;
 pub mod mq_agent;
 pub mod network;
 pub mod node_manager;
 pub mod p2p_protocol;
+pub mod peer_score;
 pub mod synchronizer;
+pub mod tls;
 
 use crate::config::{AddressConfig, NetConfig};
 use crate::mq_agent::MqAgent;
 use crate::network::Network;
 use crate::node_manager::{NodesManager, DEFAULT_PORT};
 use crate::p2p_protocol::{
-    node_discovery::create_discovery_meta, transfer::create_transfer_meta, SHandle,
+    node_discovery::create_discovery_meta,
+    transfer::{create_transfer_meta, DEFAULT_FLOOD_LIMIT, MAX_FRAME_LENGTH},
+    SHandle,
 };
 use crate::synchronizer::Synchronizer;
+use crate::tls::Tls;
 use clap::App;
 use dotenv;
 use futures::prelude::*;
@@ -104,7 +142,8 @@ use notify::{RecommendedWatcher, RecursiveMode, Watcher};
 use std::path::Path;
 use std::sync::mpsc::channel;
 use std::thread;
-use tentacle::{builder::ServiceBuilder, secio::SecioKeyPair};
+use tentacle::{builder::ServiceBuilder, secio::SecioKeyPair, utils::multiaddr_to_socketaddr};
+use tokio::runtime::Runtime;
 use util::set_panic_handler;
 
 include!(concat!(env!("OUT_DIR"), "/build_info.rs"));
@@ -121,7 +160,8 @@ fn main() {
         .args_from_usage(
             "-c, --config=[FILE] 'Sets a custom config file'
                         -a, --address=[FILE] 'Sets an address file'
-                        -s, --stdout 'Log to console'",
+                        -s, --stdout 'Log to console'
+                        -m, --metrics=[ADDR] 'Serves Prometheus metrics on ADDR'",
         )
         .get_matches();
 
@@ -129,6 +169,10 @@ fn main() {
     micro_service_init!("cita-network", "CITA:network", stdout);
     info!("Version: {}", get_build_info_str(true));
 
+    if let Some(addr) = matches.value_of("metrics") {
+        cita_metrics::start_server(addr).expect("Start metrics server failed");
+    }
+
     let config_file = matches.value_of("config").unwrap_or("network.toml");
 
     let config_path = Path::new(config_file);
@@ -159,8 +203,31 @@ fn main() {
     // End init config
 
     let mut nodes_mgr = NodesManager::from_config(config.clone(), own_addr.addr);
+
+    // The TLS tunnels run on a runtime of their own, in front of the p2p service.
+    let port = config.port.unwrap_or(DEFAULT_PORT);
+    let tls_enabled = config.enable_tls.unwrap_or(false);
+    if tls_enabled && config.tls.is_none() {
+        warn!("No tls certificates in the config, the connections are only encrypted with secio.");
+    }
+    let tls_runtime = if tls_enabled && config.tls.is_some() {
+        Some(Runtime::new().expect("Create the TLS runtime failed"))
+    } else {
+        None
+    };
+    let tls = tls_runtime.as_ref().map(|runtime| {
+        Tls::start(&config, runtime.executor())
+            .unwrap_or_else(|e| panic!("Start TLS failed: {}", e))
+    });
+    if let Some(ref tls) = tls {
+        nodes_mgr.set_tls(tls.clone());
+    }
     let mut mq_agent = MqAgent::default();
-    let mut synchronizer_mgr = Synchronizer::new(mq_agent.client(), nodes_mgr.client());
+    let mut synchronizer_mgr = Synchronizer::new(
+        mq_agent.client(),
+        nodes_mgr.client(),
+        config.state_sync.unwrap_or(false),
+    );
     let mut network_mgr = Network::new(
         mq_agent.client(),
         nodes_mgr.client(),
@@ -169,13 +236,21 @@ fn main() {
     mq_agent.set_nodes_mgr_client(nodes_mgr.client());
     mq_agent.set_network_client(network_mgr.client());
 
-    let transfer_meta =
-        create_transfer_meta(network_mgr.client(), nodes_mgr.client(), own_addr.addr);
+    let transfer_meta = create_transfer_meta(
+        network_mgr.client(),
+        nodes_mgr.client(),
+        own_addr.addr,
+        nodes_mgr.authenticated_sessions(),
+        config.max_frame_len.unwrap_or(MAX_FRAME_LENGTH),
+        config.enable_compression.unwrap_or(true),
+        config.flood_limit.unwrap_or(DEFAULT_FLOOD_LIMIT),
+    );
     let mut service_cfg = ServiceBuilder::default()
         .insert_protocol(transfer_meta)
         .forever(true);
 
-    let discovery_flag = config.enable_discovery.unwrap_or(true);
+    // The peers over TLS are the configured ones, checked by their common names.
+    let discovery_flag = config.enable_discovery.unwrap_or(true) && tls.is_none();
     let (tx, rx) = channel();
     let mut watcher: RecommendedWatcher =
         Watcher::new(tx, std::time::Duration::from_secs(NOTIFY_DELAY_SECS)).unwrap();
@@ -184,18 +259,36 @@ fn main() {
         service_cfg = service_cfg.insert_protocol(discovery_meta);
     } else if watcher.watch(dir, RecursiveMode::NonRecursive).is_ok() {
         let notify_client = nodes_mgr.client();
+        let notify_tls = tls.clone();
         thread::spawn(move || {
-            NodesManager::notify_config_change(rx, notify_client, fname);
+            NodesManager::notify_config_change(rx, notify_client, fname, notify_tls);
         });
     }
 
-    if config.enable_tls.unwrap_or(false) {
-        service_cfg = service_cfg.key_pair(SecioKeyPair::secp256k1_generated());
+    // The handshake of the authentication is bound to the secio keys.
+    if (tls_enabled && tls.is_none()) || nodes_mgr.is_auth_enabled() {
+        let key_pair = SecioKeyPair::secp256k1_generated();
+        nodes_mgr.set_secio_key(key_pair.to_peer_id().as_bytes().to_vec());
+        service_cfg = service_cfg.key_pair(key_pair);
     }
-    let mut service = service_cfg.build(SHandle::new(nodes_mgr.client()));
+    let mut service = service_cfg.build(SHandle::new(nodes_mgr.client(), tls.clone()));
 
-    let addr = format!("/ip4/0.0.0.0/tcp/{}", config.port.unwrap_or(DEFAULT_PORT));
-    let _ = service.listen(addr.parse().unwrap());
+    // Behind the tunnels, the service takes any port on loopback and the tunnels are
+    // told the one it got.
+    match tls {
+        Some(ref tls) => {
+            let service_addr = service
+                .listen("/ip4/127.0.0.1/tcp/0".parse().unwrap())
+                .ok()
+                .and_then(|addr| multiaddr_to_socketaddr(&addr))
+                .expect("Listen on loopback failed");
+            tls.listen(port, service_addr)
+                .unwrap_or_else(|e| panic!("Start TLS failed: {}", e));
+        }
+        None => {
+            let _ = service.listen(format!("/ip4/0.0.0.0/tcp/{}", port).parse().unwrap());
+        }
+    }
     nodes_mgr.set_service_task_sender(service.control().clone());
     // End init p2p protocols
 
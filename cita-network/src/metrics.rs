@@ -0,0 +1,33 @@
+// Copyright Rivtower Technologies LLC.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Metrics of network, served on `/metrics`.
+
+use lazy_static::lazy_static;
+use prometheus::{register_int_counter, register_int_gauge, IntCounter, IntGauge};
+
+lazy_static! {
+    pub static ref PEERS: IntGauge =
+        register_int_gauge!("network_peers", "Connected peers").unwrap();
+    pub static ref BYTES_RECEIVED: IntCounter = register_int_counter!(
+        "network_received_bytes_total",
+        "Bytes of messages received from peers"
+    )
+    .unwrap();
+    pub static ref BYTES_SENT: IntCounter = register_int_counter!(
+        "network_sent_bytes_total",
+        "Bytes of messages sent to peers"
+    )
+    .unwrap();
+}
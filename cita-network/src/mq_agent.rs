@@ -65,6 +65,7 @@ impl MqAgent {
                 Chain >> Status,
                 Chain >> RichStatus,
                 Chain >> SyncResponse,
+                Executor >> SyncResponse,
                 Jsonrpc >> RequestNet,
                 Jsonrpc >> RequestPeersInfo,
                 Snapshot >> SnapshotReq
@@ -195,6 +196,20 @@ impl MqAgentClient {
             warn!("[MqAgent] Publish synchronize blocks failed: {:?}", e);
         }
     }
+
+    // Pass the state sync messages of other nodes on to the executor
+    pub fn pub_state_sync(&self, msg: PubMessage) {
+        if let Err(e) = self.pub_other_modules.send((msg.key, msg.data)) {
+            warn!("[MqAgent] Publish state sync message failed: {:?}", e);
+        }
+    }
+
+    // Publish the highest status of other nodes
+    pub fn pub_global_status(&self, msg: PubMessage) {
+        if let Err(e) = self.pub_other_modules.send((msg.key, msg.data)) {
+            warn!("[MqAgent] Publish global status failed: {:?}", e);
+        }
+    }
 }
 
 pub struct PubMessage {
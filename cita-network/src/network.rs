@@ -148,6 +148,11 @@ impl LocalMessage {
                     msg,
                 );
             }
+            routing_key!(Executor >> SyncResponse) => {
+                service
+                    .sync_client
+                    .handle_local_state_sync(SynchronizerMessage::new(self.key, self.data));
+            }
             routing_key!(Jsonrpc >> RequestNet) => {
                 self.reply_rpc(&self.data, service);
             }
@@ -214,7 +219,7 @@ impl LocalMessage {
 
                     // Get peers from rx channel
                     // FIXME: This is a block receive, double check about this
-                    let peers = rx.recv().unwrap();
+                    let (peers, banned) = rx.recv().unwrap();
 
                     let peers_info = PeersInfo {
                         amount: peers.len() as u32,
@@ -222,7 +227,14 @@ impl LocalMessage {
                         error_message: None,
                     };
 
-                    if let Ok(json_peers_info) = serde_json::to_value(peers_info) {
+                    if let Ok(mut json_peers_info) = serde_json::to_value(peers_info) {
+                        // Banned peers with the time their ban ends, in seconds since the epoch.
+                        if let (Some(object), Ok(banned)) = (
+                            json_peers_info.as_object_mut(),
+                            serde_json::to_value(banned),
+                        ) {
+                            object.insert("bannedPeers".to_owned(), banned);
+                        }
                         response.set_peers_info(json_peers_info.to_string());
                     } else {
                         response.set_code(ErrorCode::InternalError.code());
@@ -315,6 +327,11 @@ impl RemoteMessage {
                     .sync_client
                     .handle_remote_response(SynchronizerMessage::new(self.key, self.data));
             }
+            routing_key!(Synchronizer >> RawBytes) => {
+                service
+                    .sync_client
+                    .handle_remote_state_sync(SynchronizerMessage::new(self.key, self.data));
+            }
             routing_key!(Synchronizer >> SyncRequest) => {
                 service.mq_client.pub_sync_request(PubMessage::new(
                     routing_key!(Net >> SyncRequest).into(),
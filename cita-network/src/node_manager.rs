@@ -13,12 +13,18 @@
 // limitations under the License.
 
 use crate::cita_protocol::{
-    pubsub_message_to_network_message, NetMessageUnit, CONSENSUS_STR, CONSENSUS_TTL_NUM,
+    pubsub_message_to_compressed_network_message, pubsub_message_to_network_message,
+    NetMessageUnit, CONSENSUS_STR, CONSENSUS_TTL_NUM,
 };
 use crate::config::NetConfig;
+use crate::handshake::{Allowlist, AuthenticatedSessions, Transcript, AUTH_KEY};
+use crate::metrics;
 use crate::p2p_protocol::transfer::TRANSFER_PROTOCOL_ID;
+use crate::peer_score::{Misbehavior, Peer, PeerScores, DEFAULT_BAN_SECS};
+use crate::tls::Tls;
 use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
-use cita_types::Address;
+use cita_crypto::{KeyPair, PrivKey};
+use cita_types::{Address, H256};
 use fnv::FnvHashMap as HashMap;
 use libproto::{Message as ProtoMessage, TryInto};
 use notify::DebouncedEvent;
@@ -30,6 +36,7 @@ use std::{
     convert::Into,
     io::Cursor,
     net::{SocketAddr, ToSocketAddrs},
+    path::PathBuf,
     time::{Duration, Instant},
 };
 use tentacle::{
@@ -42,6 +49,8 @@ pub const DEFAULT_MAX_CONNECTS: usize = 666;
 pub const DEFAULT_MAX_KNOWN_ADDRS: usize = 1000;
 pub const DEFAULT_PORT: usize = 4000;
 pub const CHECK_CONNECTED_NODES: Duration = Duration::from_secs(3);
+pub const DEFAULT_BAN_LIST_PATH: &str = "banned_peers.json";
+pub const DEFAULT_PRIVKEY_PATH: &str = "privkey";
 
 // Score uses to manage known_nodes list. If a node has too low score, do not dial it again.
 // Maybe some complex algorithm can be designed later. But for now, just keeps as simple as below:
@@ -200,6 +209,25 @@ pub struct NodesManager {
 
     dialing_node: Option<SocketAddr>,
     self_addr: Option<SocketAddr>,
+
+    peer_scores: PeerScores,
+
+    // Node key to answer the handshake of peers, None if the authentication is disabled.
+    privkey: Option<PrivKey>,
+    // Secio public key of this node, empty if the sessions are not encrypted
+    secio_key: Vec<u8>,
+    allowlist: Allowlist,
+    // Nonce sent to each session in its handshake
+    handshake_nonces: HashMap<SessionId, H256>,
+    // Sessions which have sent an init message, but not the signature of the nonce yet
+    unauthenticated: HashMap<SessionId, (SessionType, InitMsg)>,
+    authenticated_sessions: AuthenticatedSessions,
+
+    // Sessions which take compressed messages
+    compressed_sessions: BTreeSet<SessionId>,
+
+    // The TLS tunnels to dial the peers through, None if TLS is disabled
+    tls: Option<Tls>,
 }
 
 impl NodesManager {
@@ -226,6 +254,15 @@ impl NodesManager {
             gossip_key_version: HashMap::default(),
             self_version: 0,
             consensus_topology: ConsensusNodeTopology::new(peer_key),
+            peer_scores: PeerScores::new(DEFAULT_BAN_SECS, None),
+            privkey: None,
+            secio_key: Vec::new(),
+            allowlist: Allowlist::default(),
+            handshake_nonces: HashMap::default(),
+            unauthenticated: HashMap::default(),
+            authenticated_sessions: AuthenticatedSessions::new(false),
+            compressed_sessions: BTreeSet::default(),
+            tls: None,
         }
     }
 
@@ -234,6 +271,41 @@ impl NodesManager {
         let max_connects = cfg.max_connects.unwrap_or(DEFAULT_MAX_CONNECTS);
         node_mgr.max_connects = max_connects;
         node_mgr.peer_key = key;
+        node_mgr.peer_scores = PeerScores::new(
+            cfg.ban_secs.unwrap_or(DEFAULT_BAN_SECS),
+            Some(PathBuf::from(
+                cfg.ban_list_path
+                    .unwrap_or_else(|| DEFAULT_BAN_LIST_PATH.to_owned()),
+            )),
+        );
+
+        if cfg.enable_auth.unwrap_or(false) {
+            let path = cfg
+                .privkey_path
+                .unwrap_or_else(|| DEFAULT_PRIVKEY_PATH.to_owned());
+            let privkey = cita_keystore::load_privkey(&path, None)
+                .ok()
+                .filter(|privkey| privkey.len() == PrivKey::len())
+                .map(|privkey| PrivKey::from_slice(&privkey))
+                .unwrap_or_else(|| panic!("Cannot load the node private key from {}", path));
+            let addr = KeyPair::from_privkey(privkey)
+                .expect("Invalid node private key")
+                .address();
+            if addr != key {
+                panic!(
+                    "The node private key is of {:?}, not the node address {:?}",
+                    addr, key
+                );
+            }
+            node_mgr.privkey = Some(privkey);
+            node_mgr.authenticated_sessions = AuthenticatedSessions::new(true);
+
+            if let Some(path) = cfg.allowlist_path {
+                node_mgr.allowlist = Allowlist::load(&path).unwrap_or_else(|err| {
+                    panic!("Cannot load the allowlist from {}: {:?}", path, err)
+                });
+            }
+        }
 
         if let Some(cfg_addrs) = cfg.peers {
             for addr in cfg_addrs {
@@ -250,10 +322,13 @@ impl NodesManager {
         node_mgr
     }
 
+    /// Reload the peers when the config changes, and the TLS certificates when either
+    /// the config or one of them changes.
     pub fn notify_config_change(
         rx: StdReceiver<DebouncedEvent>,
         node_client: NodesManagerClient,
         fname: String,
+        tls: Option<Tls>,
     ) {
         loop {
             match rx.recv() {
@@ -261,6 +336,12 @@ impl NodesManager {
                     DebouncedEvent::Create(path_buf) | DebouncedEvent::Write(path_buf) => {
                         if path_buf.is_file() {
                             let file_name = path_buf.file_name().unwrap().to_str().unwrap();
+                            if let Some(ref tls) = tls {
+                                if file_name == fname || tls.is_cert_file(file_name) {
+                                    info!("file {} changed, reload TLS certificates!", file_name);
+                                    tls.reload(&NetConfig::new(&fname));
+                                }
+                            }
                             if file_name == fname {
                                 info!("file {} changed, will auto reload!", file_name);
 
@@ -295,11 +376,13 @@ impl NodesManager {
                     match msg {
                         Ok(data) => {
                             data.handle(self);
+                            metrics::PEERS.set(self.connected_addrs.len() as i64);
                         },
                         Err(err) => error!("[NodeManager] Receive data error {:?}", err),
                     }
                 }
                 recv(self.check_connected_nodes) -> _ => {
+                    self.peer_scores.recover();
                     self.dial_nodes();
                 }
             }
@@ -370,11 +453,21 @@ impl NodesManager {
                     continue;
                 }
 
-                // Dial this address
+                // Dial this address, through its TLS forwarder if TLS is enabled
+                let dial_addr = match self.tls {
+                    Some(ref tls) => match tls.forward(key) {
+                        Ok(addr) => addr,
+                        Err(e) => {
+                            warn!("[NodeManager] Forward to {:?} over TLS failed: {}", key, e);
+                            break;
+                        }
+                    },
+                    None => key,
+                };
                 if let Some(ref mut ctrl) = self.service_ctrl {
                     self.dialing_node = Some(key);
                     info!("Trying to dial: {:?}", self.dialing_node);
-                    match ctrl.dial(socketaddr_to_multiaddr(key), DialProtocol::All) {
+                    match ctrl.dial(socketaddr_to_multiaddr(dial_addr), DialProtocol::All) {
                         Ok(_) => {
                             // Need DIALING_SCORE for every dial.
                             value.score -= DIALING_SCORE;
@@ -404,6 +497,73 @@ impl NodesManager {
         self.service_ctrl = Some(ctrl);
     }
 
+    pub fn set_tls(&mut self, tls: Tls) {
+        self.tls = Some(tls);
+    }
+
+    pub fn authenticated_sessions(&self) -> AuthenticatedSessions {
+        self.authenticated_sessions.clone()
+    }
+
+    /// Whether the sessions must be encrypted, as the handshake is bound to secio keys.
+    pub fn is_auth_enabled(&self) -> bool {
+        self.privkey.is_some()
+    }
+
+    pub fn set_secio_key(&mut self, key: Vec<u8>) {
+        self.secio_key = key;
+    }
+
+    // Send the message to the sessions, compressed to the sessions which support it.
+    fn send_message(&mut self, ids: Vec<SessionId>, msg_unit: &NetMessageUnit) {
+        let (compressed, plain): (Vec<SessionId>, Vec<SessionId>) = ids
+            .into_iter()
+            .partition(|id| self.compressed_sessions.contains(id));
+
+        for (ids, compress) in vec![(compressed, true), (plain, false)] {
+            if ids.is_empty() {
+                continue;
+            }
+            let buf = if compress {
+                pubsub_message_to_compressed_network_message(msg_unit)
+            } else {
+                pubsub_message_to_network_message(msg_unit)
+            };
+            if let (Some(buf), Some(ctrl)) = (buf, self.service_ctrl.as_mut()) {
+                metrics::BYTES_SENT.inc_by((buf.len() * ids.len()) as i64);
+                let _ = ctrl.filter_broadcast(TargetSession::Multi(ids), TRANSFER_PROTOCOL_ID, buf);
+            }
+        }
+    }
+
+    // The address a session is connected with
+    fn session_addr(&self, session_id: SessionId) -> Option<SocketAddr> {
+        self.connected_addrs
+            .get(&session_id)
+            .map(|addr| addr.conn_addr)
+            .or_else(|| {
+                self.pending_connected_addrs
+                    .get(&session_id)
+                    .map(|info| info.addr)
+            })
+    }
+
+    // The peer of a session, its address counts once the handshake has verified it,
+    // before that or without the handshake the peer is its IP alone.
+    fn session_peer(&self, session_id: SessionId) -> Option<Peer> {
+        let ip = self.session_addr(session_id)?.ip();
+        let verified = self
+            .connected_peer_keys
+            .iter()
+            .find(|(_, id)| **id == session_id)
+            .map(|(address, _)| *address)
+            .filter(|_| self.is_auth_enabled());
+        Some(match verified {
+            Some(address) => Peer::new(address, ip),
+            None => Peer::at_ip(ip),
+        })
+    }
+
     pub fn translate_address(&mut self) {
         for (key, value) in self.config_addrs.iter_mut() {
             // The address has translated.
@@ -507,6 +667,14 @@ impl NodesManagerClient {
         self.send_req(NodesManagerMessage::DealRichStatus(req));
     }
 
+    pub fn misbehave(&self, req: MisbehaveReq) {
+        self.send_req(NodesManagerMessage::Misbehave(req));
+    }
+
+    pub fn authenticate_node(&self, req: AuthenticateNodeReq) {
+        self.send_req(NodesManagerMessage::AuthenticateNode(req));
+    }
+
     fn send_req(&self, req: NodesManagerMessage) {
         if let Err(e) = self.sender.try_send(req) {
             warn!(
@@ -535,6 +703,8 @@ pub enum NodesManagerMessage {
     GetPeersInfo(GetPeersInfoReq),
     ModifiedConfigPeers(ModifiedConfigPeersReq),
     DealRichStatus(DealRichStatusReq),
+    Misbehave(MisbehaveReq),
+    AuthenticateNode(AuthenticateNodeReq),
 }
 
 impl NodesManagerMessage {
@@ -556,14 +726,23 @@ impl NodesManagerMessage {
             NodesManagerMessage::ModifiedConfigPeers(req) => req.handle(service),
             NodesManagerMessage::RetransNetMsg(req) => req.handle(service),
             NodesManagerMessage::DealRichStatus(req) => req.handle(service),
+            NodesManagerMessage::Misbehave(req) => req.handle(service),
+            NodesManagerMessage::AuthenticateNode(req) => req.handle(service),
         }
     }
 }
 
+// Length of an encoded InitMsg: chain id (u64) + peer key (Address)
+pub const INIT_MSG_LEN: usize = 8 + 20;
+// Length of an encoded InitMsg with a handshake nonce (H256)
+pub const AUTH_INIT_MSG_LEN: usize = INIT_MSG_LEN + 32;
+
 #[derive(Default, Clone)]
 pub struct InitMsg {
     pub chain_id: u64,
     pub peer_key: Address,
+    // Only sent if the authentication is enabled, to keep compatible with old nodes.
+    pub nonce: Option<H256>,
 }
 
 impl Into<Vec<u8>> for InitMsg {
@@ -576,6 +755,9 @@ impl Into<Vec<u8>> for InitMsg {
 
         out.extend_from_slice(&chain_id_data);
         out.extend_from_slice(&key_data);
+        if let Some(nonce) = self.nonce {
+            out.extend_from_slice(&nonce[..]);
+        }
         out
     }
 }
@@ -586,9 +768,18 @@ impl From<Vec<u8>> for InitMsg {
         chain_id_data.copy_from_slice(&data[..8]);
         let mut chain_id_data = Cursor::new(chain_id_data);
         let chain_id = chain_id_data.read_u64::<BigEndian>().unwrap();
-        let peer_key = Address::from_slice(&data[8..]);
+        let peer_key = Address::from_slice(&data[8..INIT_MSG_LEN]);
+        let nonce = if data.len() == AUTH_INIT_MSG_LEN {
+            Some(H256::from_slice(&data[INIT_MSG_LEN..]))
+        } else {
+            None
+        };
 
-        InitMsg { chain_id, peer_key }
+        InitMsg {
+            chain_id,
+            peer_key,
+            nonce,
+        }
     }
 }
 
@@ -596,18 +787,107 @@ pub struct AddConnectedNodeReq {
     session_id: SessionId,
     ty: SessionType,
     init_msg: InitMsg,
+    // Secio public key of the peer, empty if the session is not encrypted
+    remote_key: Vec<u8>,
 }
 
 impl AddConnectedNodeReq {
-    pub fn new(session_id: SessionId, ty: SessionType, init_msg: InitMsg) -> Self {
+    pub fn new(
+        session_id: SessionId,
+        ty: SessionType,
+        init_msg: InitMsg,
+        remote_key: Vec<u8>,
+    ) -> Self {
         AddConnectedNodeReq {
             session_id,
             ty,
             init_msg,
+            remote_key,
         }
     }
 
     pub fn handle(self, service: &mut NodesManager) {
+        if let Some(addr) = service.session_addr(self.session_id) {
+            // The address is not verified yet, refusing it only refuses the claimant.
+            let peer = Peer::new(self.init_msg.peer_key, addr.ip());
+            let banned = [Peer::at_ip(addr.ip()), peer]
+                .iter()
+                .find(|peer| service.peer_scores.is_banned(peer))
+                .cloned();
+            if let Some(peer) = banned {
+                info!(
+                    "[NodeManager] Peer {} is banned, refuse Session [{:?}]",
+                    peer, self.session_id
+                );
+                if self.ty == SessionType::Outbound {
+                    if let Some(ref mut node_status) = service.known_addrs.get_mut(&addr) {
+                        node_status.score -= REFUSED_SCORE;
+                    }
+                    service.dialing_node = None;
+                }
+                if let Some(ref mut ctrl) = service.service_ctrl {
+                    let _ = ctrl.disconnect(self.session_id);
+                }
+                return;
+            }
+        }
+
+        if service.privkey.is_some() {
+            self.challenge(service);
+        } else {
+            self.add(service);
+        }
+    }
+
+    // Answer the init message of the peer, and wait for the answer of ours before adding
+    // the node.
+    fn challenge(self, service: &mut NodesManager) {
+        let own_nonce = service.handshake_nonces.get(&self.session_id);
+        let signature = match (own_nonce, self.init_msg.nonce, service.privkey) {
+            (Some(own_nonce), Some(nonce), Some(privkey)) => Transcript {
+                signer_nonce: *own_nonce,
+                recipient_nonce: nonce,
+                signer: service.peer_key,
+                recipient: self.init_msg.peer_key,
+                recipient_key: self.remote_key.clone(),
+            }
+            .sign(&privkey),
+            _ => None,
+        };
+        let signature = match signature {
+            Some(signature) => signature,
+            None => {
+                warn!(
+                    "[NodeManager] Session [{:?}] of {:?} did not start a handshake, disconnect it.",
+                    self.session_id, self.init_msg.peer_key
+                );
+                if let Some(ref mut ctrl) = service.service_ctrl {
+                    let _ = ctrl.disconnect(self.session_id);
+                }
+                return;
+            }
+        };
+
+        let mut msg_unit = NetMessageUnit::default();
+        msg_unit.key = AUTH_KEY.to_string();
+        msg_unit.data = signature;
+
+        if let Some(buf) = pubsub_message_to_network_message(&msg_unit) {
+            if let Some(ref mut ctrl) = service.service_ctrl {
+                metrics::BYTES_SENT.inc_by(buf.len() as i64);
+                let ret = ctrl.send_message_to(self.session_id, TRANSFER_PROTOCOL_ID, buf);
+                debug!(
+                    "[NodeManager] Send handshake signature, id: {:?}, ret: {:?}",
+                    self.session_id, ret
+                );
+            }
+        }
+        service
+            .unauthenticated
+            .insert(self.session_id, (self.ty, self.init_msg));
+    }
+
+    fn add(self, service: &mut NodesManager) {
         if let Some(repeated_id) = service.connected_peer_keys.get(&self.init_msg.peer_key) {
             // Repeated connected, it can a duplicated connected to the same node, or a duplicated
             // node connected to this server. But in either case, disconnect this session.
@@ -708,19 +988,36 @@ impl AddConnectedNodeReq {
 #[derive(Default)]
 pub struct NetworkInitReq {
     session_id: SessionId,
+    // Whether the session negotiated compressed messages
+    compression: bool,
 }
 
 impl NetworkInitReq {
-    pub fn new(session_id: SessionId) -> Self {
-        NetworkInitReq { session_id }
+    pub fn new(session_id: SessionId, compression: bool) -> Self {
+        NetworkInitReq {
+            session_id,
+            compression,
+        }
     }
 
     pub fn handle(self, service: &mut NodesManager) {
         let peer_key = service.peer_key;
+        if self.compression {
+            service.compressed_sessions.insert(self.session_id);
+        }
+
+        let nonce = if service.privkey.is_some() {
+            let nonce = H256::random();
+            service.handshake_nonces.insert(self.session_id, nonce);
+            Some(nonce)
+        } else {
+            None
+        };
 
         let init_msg = InitMsg {
             chain_id: 0,
             peer_key,
+            nonce,
         };
 
         let mut msg_unit = NetMessageUnit::default();
@@ -729,6 +1026,7 @@ impl NetworkInitReq {
 
         if let Some(buf) = pubsub_message_to_network_message(&msg_unit) {
             if let Some(ref mut ctrl) = service.service_ctrl {
+                metrics::BYTES_SENT.inc_by(buf.len() as i64);
                 let ret = ctrl.send_message_to(self.session_id, TRANSFER_PROTOCOL_ID, buf);
                 info!(
                     "[NodeManager] Send network init message!, id: {:?}, peer_addr: {:?}, ret: {:?}",
@@ -903,6 +1201,11 @@ impl DelConnectedNodeReq {
     pub fn handle(self, service: &mut NodesManager) {
         info!("[NodeManager] Disconnected session [{:?}]", self.session_id);
 
+        service.handshake_nonces.remove(&self.session_id);
+        service.unauthenticated.remove(&self.session_id);
+        service.authenticated_sessions.remove(self.session_id);
+        service.compressed_sessions.remove(&self.session_id);
+
         if let Some(addr) = service.connected_addrs.remove(&self.session_id) {
             let trans_addr = addr.trans_addr.unwrap_or(addr.conn_addr);
             self.fix_node_status(trans_addr, service);
@@ -997,12 +1300,7 @@ impl RetransNetMsgReq {
                 self.msg_unit.ttl = 0;
             }
 
-            if let Some(buf) = pubsub_message_to_network_message(&self.msg_unit) {
-                if let Some(ref mut ctrl) = service.service_ctrl {
-                    let _ =
-                        ctrl.filter_broadcast(TargetSession::Multi(ids), TRANSFER_PROTOCOL_ID, buf);
-                }
-            }
+            service.send_message(ids, &self.msg_unit);
         }
     }
 }
@@ -1030,6 +1328,32 @@ impl DealRichStatusReq {
         service
             .consensus_topology
             .update_validators(rich_status.get_height(), validators);
+
+        if service.privkey.is_some() {
+            let nodes: BTreeSet<Address> = rich_status
+                .get_nodes()
+                .iter()
+                .map(|node| Address::from_slice(node))
+                .collect();
+            service.allowlist.set_nodes(nodes);
+
+            // Disconnect the peers which are not allowed any more.
+            let removed: Vec<(Address, SessionId)> = service
+                .connected_peer_keys
+                .iter()
+                .filter(|(key, _)| !service.allowlist.is_allowed(key))
+                .map(|(key, session_id)| (*key, *session_id))
+                .collect();
+            if let Some(ref mut ctrl) = service.service_ctrl {
+                for (key, session_id) in removed {
+                    info!(
+                        "[NodeManager] {:?} is not allowed any more, disconnect session [{:?}]",
+                        key, session_id
+                    );
+                    let _ = ctrl.disconnect(session_id);
+                }
+            }
+        }
     }
 }
 
@@ -1066,11 +1390,13 @@ impl BroadcastReq {
             info.ttl = CONSENSUS_TTL_NUM;
         }
 
-        if let Some(buf) = pubsub_message_to_network_message(&info) {
-            if let Some(ref mut ctrl) = service.service_ctrl {
-                let _ = ctrl.filter_broadcast(TargetSession::All, TRANSFER_PROTOCOL_ID, buf);
-            }
-        }
+        let ids: Vec<SessionId> = service
+            .connected_addrs
+            .keys()
+            .chain(service.pending_connected_addrs.keys())
+            .cloned()
+            .collect();
+        service.send_message(ids, &info);
     }
 }
 
@@ -1097,11 +1423,7 @@ impl SingleTxReq {
         msg_unit.key = self.key;
         msg_unit.data = self.msg.try_into().unwrap();
 
-        if let Some(buf) = pubsub_message_to_network_message(&msg_unit) {
-            if let Some(ref mut ctrl) = service.service_ctrl {
-                let _ = ctrl.send_message_to(dst, TRANSFER_PROTOCOL_ID, buf);
-            }
-        }
+        service.send_message(vec![dst], &msg_unit);
     }
 }
 
@@ -1126,12 +1448,15 @@ impl GetPeerCountReq {
     }
 }
 
+/// Connected peers with their IPs, and banned peers with the time their ban ends
+pub type PeersInfo = (HashMap<Address, String>, BTreeMap<Peer, u64>);
+
 pub struct GetPeersInfoReq {
-    return_channel: Sender<HashMap<Address, String>>,
+    return_channel: Sender<PeersInfo>,
 }
 
 impl GetPeersInfoReq {
-    pub fn new(return_channel: Sender<HashMap<Address, String>>) -> Self {
+    pub fn new(return_channel: Sender<PeersInfo>) -> Self {
         GetPeersInfoReq { return_channel }
     }
 
@@ -1151,7 +1476,8 @@ impl GetPeersInfoReq {
 
         debug!("[NodeManager] get peers info : {:?}", peers);
 
-        if let Err(e) = self.return_channel.try_send(peers) {
+        let banned = service.peer_scores.banned().clone();
+        if let Err(e) = self.return_channel.try_send((peers, banned)) {
             warn!("[NodeManager] Send peers info failed : {:?}", e);
         }
     }
@@ -1210,3 +1536,130 @@ impl ModifiedConfigPeersReq {
         }
     }
 }
+
+pub struct MisbehaveReq {
+    session_id: SessionId,
+    kind: Misbehavior,
+}
+
+impl MisbehaveReq {
+    pub fn new(session_id: SessionId, kind: Misbehavior) -> Self {
+        MisbehaveReq { session_id, kind }
+    }
+
+    pub fn handle(self, service: &mut NodesManager) {
+        let peer = match service.session_peer(self.session_id) {
+            Some(peer) => peer,
+            None => {
+                // The session is gone, so there is nothing to score.
+                warn!(
+                    "[NodeManager] Disconnect unknown session [{:?}] misbehaved: {:?}",
+                    self.session_id, self.kind
+                );
+                if let Some(ref mut ctrl) = service.service_ctrl {
+                    let _ = ctrl.disconnect(self.session_id);
+                }
+                return;
+            }
+        };
+
+        if service.peer_scores.misbehave(peer, self.kind) {
+            // Banned, disconnect all the sessions with this peer.
+            let sessions: Vec<SessionId> = service
+                .connected_addrs
+                .keys()
+                .chain(service.pending_connected_addrs.keys())
+                .filter(|id| service.session_peer(**id) == Some(peer))
+                .cloned()
+                .collect();
+            if let Some(ref mut ctrl) = service.service_ctrl {
+                for session_id in sessions {
+                    info!(
+                        "[NodeManager] Disconnect session [{:?}] of banned peer {}",
+                        session_id, peer
+                    );
+                    let _ = ctrl.disconnect(session_id);
+                }
+            }
+        }
+    }
+}
+
+pub struct AuthenticateNodeReq {
+    session_id: SessionId,
+    signature: Vec<u8>,
+}
+
+impl AuthenticateNodeReq {
+    pub fn new(session_id: SessionId, signature: Vec<u8>) -> Self {
+        AuthenticateNodeReq {
+            session_id,
+            signature,
+        }
+    }
+
+    pub fn handle(self, service: &mut NodesManager) {
+        if service.privkey.is_none() {
+            debug!(
+                "[NodeManager] Authentication is disabled, ignore the handshake signature of session [{:?}]",
+                self.session_id
+            );
+            return;
+        }
+
+        let nonce = service.handshake_nonces.remove(&self.session_id);
+        let (ty, init_msg) = match (nonce, service.unauthenticated.remove(&self.session_id)) {
+            (Some(nonce), Some((ty, init_msg))) => {
+                let transcript = init_msg.nonce.map(|peer_nonce| Transcript {
+                    signer_nonce: peer_nonce,
+                    recipient_nonce: nonce,
+                    signer: init_msg.peer_key,
+                    recipient: service.peer_key,
+                    recipient_key: service.secio_key.clone(),
+                });
+                if !transcript.map_or(false, |transcript| transcript.verify(&self.signature)) {
+                    warn!(
+                        "[NodeManager] Session [{:?}] failed to prove it is {:?}",
+                        self.session_id, init_msg.peer_key
+                    );
+                    MisbehaveReq::new(self.session_id, Misbehavior::InvalidHandshake)
+                        .handle(service);
+                    self.disconnect(service);
+                    return;
+                }
+                (ty, init_msg)
+            }
+            _ => {
+                warn!(
+                    "[NodeManager] Unexpected handshake signature from session [{:?}]",
+                    self.session_id
+                );
+                MisbehaveReq::new(self.session_id, Misbehavior::InvalidHandshake).handle(service);
+                self.disconnect(service);
+                return;
+            }
+        };
+
+        if !service.allowlist.is_allowed(&init_msg.peer_key) {
+            info!(
+                "[NodeManager] {:?} of session [{:?}] is not allowed to connect",
+                init_msg.peer_key, self.session_id
+            );
+            self.disconnect(service);
+            return;
+        }
+
+        info!(
+            "[NodeManager] Session [{:?}] authenticated as {:?}",
+            self.session_id, init_msg.peer_key
+        );
+        service.authenticated_sessions.insert(self.session_id);
+        AddConnectedNodeReq::new(self.session_id, ty, init_msg, Vec::new()).add(service);
+    }
+
+    fn disconnect(&self, service: &mut NodesManager) {
+        if let Some(ref mut ctrl) = service.service_ctrl {
+            let _ = ctrl.disconnect(self.session_id);
+        }
+    }
+}
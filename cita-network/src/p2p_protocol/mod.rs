@@ -13,9 +13,13 @@
 // limitations under the License.
 
 use crate::node_manager::{
-    AddRepeatedNodeReq, ConnectedSelfReq, DelConnectedNodeReq, DialedErrorReq, NodesManagerClient,
-    PendingConnectedNodeReq,
+    AddRepeatedNodeReq, ConnectedSelfReq, DelConnectedNodeReq, DialedErrorReq, MisbehaveReq,
+    NodesManagerClient, PendingConnectedNodeReq,
 };
+use crate::p2p_protocol::transfer::TRANSFER_PROTOCOL_ID;
+use crate::peer_score::Misbehavior;
+use crate::tls::Tls;
+use std::net::SocketAddr;
 use tentacle::{
     context::ServiceContext,
     error,
@@ -30,11 +34,24 @@ pub mod transfer;
 // This handle will be shared with all protocol
 pub struct SHandle {
     nodes_mgr_client: NodesManagerClient,
+    // The TLS tunnels, which the sessions come through if set
+    tls: Option<Tls>,
 }
 
 impl SHandle {
-    pub fn new(nodes_mgr_client: NodesManagerClient) -> Self {
-        SHandle { nodes_mgr_client }
+    pub fn new(nodes_mgr_client: NodesManagerClient, tls: Option<Tls>) -> Self {
+        SHandle {
+            nodes_mgr_client,
+            tls,
+        }
+    }
+
+    // The address of the peer, instead of its TLS tunnel
+    fn peer_addr(&self, addr: SocketAddr) -> SocketAddr {
+        match self.tls {
+            Some(ref tls) => tls.peer_addr(addr),
+            None => addr,
+        }
     }
 }
 
@@ -42,7 +59,7 @@ impl ServiceHandle for SHandle {
     fn handle_error(&mut self, _env: &mut ServiceContext, error: ServiceError) {
         match error {
             ServiceError::DialerError { address, error } => {
-                let address = multiaddr_to_socketaddr(&address).unwrap();
+                let address = self.peer_addr(multiaddr_to_socketaddr(&address).unwrap());
 
                 // If dial to a connected node, need add it to connected address list.
                 match error {
@@ -85,6 +102,11 @@ impl ServiceHandle for SHandle {
                     "[P2pProtocol] Protocol Error, stream id: {:?}, protocol id: {:?}, error: {:?}",
                     id, proto_id, error
                 );
+                // Mostly a frame longer than the max frame length.
+                if proto_id == TRANSFER_PROTOCOL_ID {
+                    let req = MisbehaveReq::new(id, Misbehavior::UndecodableFrame);
+                    self.nodes_mgr_client.misbehave(req);
+                }
             }
             ServiceError::ProtocolSelectError {
                 proto_name,
@@ -135,6 +157,7 @@ impl ServiceHandle for SHandle {
         match event {
             ServiceEvent::SessionOpen { session_context } => {
                 if let Some(sock_addr) = multiaddr_to_socketaddr(&session_context.address) {
+                    let sock_addr = self.peer_addr(sock_addr);
                     info!("[P2pProtocol] Service open on : {:?}, session id: {:?}, ty: {:?}, public_key: {:?}",
                           sock_addr, session_context.id, session_context.ty, session_context.remote_pubkey);
                     let req = PendingConnectedNodeReq::new(
@@ -12,7 +12,10 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use crate::node_manager::{AddNodeReq, GetRandomNodesReq, NodeSource, NodesManagerClient};
+use crate::node_manager::{
+    AddNodeReq, GetRandomNodesReq, MisbehaveReq, NodeSource, NodesManagerClient,
+};
+use crate::peer_score::Misbehavior as PeerMisbehavior;
 use pubsub::channel::unbounded;
 use tentacle::{
     builder::MetaBuilder,
@@ -54,8 +57,10 @@ impl AddressManager for NodesAddressManager {
         }
     }
 
-    fn misbehave(&mut self, _session_id: SessionId, _kind: Misbehavior) -> MisbehaveResult {
-        warn!("[NodeDiscovery] Has not handled misbehave in this version!");
+    fn misbehave(&mut self, session_id: SessionId, _kind: Misbehavior) -> MisbehaveResult {
+        warn!("[NodeDiscovery] Session [{:?}] misbehaved", session_id);
+        let req = MisbehaveReq::new(session_id, PeerMisbehavior::Discovery);
+        self.nodes_mgr_client.misbehave(req);
         MisbehaveResult::Disconnect
     }
 
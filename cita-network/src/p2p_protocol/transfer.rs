@@ -12,14 +12,20 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use crate::cita_protocol::network_message_to_pubsub_message;
+use crate::cita_protocol::{network_message_to_pubsub_message, CITA_FRAME_HEADER_LEN};
+use crate::handshake::{AuthenticatedSessions, AUTH_KEY};
+use crate::metrics;
 use crate::network::{NetworkClient, RemoteMessage};
 use crate::node_manager::{
-    AddConnectedNodeReq, InitMsg, NetworkInitReq, NodesManagerClient, RetransNetMsgReq,
+    AddConnectedNodeReq, AuthenticateNodeReq, InitMsg, MisbehaveReq, NetworkInitReq,
+    NodesManagerClient, RetransNetMsgReq, AUTH_INIT_MSG_LEN, INIT_MSG_LEN,
 };
+use crate::peer_score::Misbehavior;
 use bytes::BytesMut;
 use cita_types::Address;
 use libproto::{Message as ProtoMessage, TryFrom, TryInto};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
 use tentacle::{
     builder::MetaBuilder,
     context::{ProtocolContext, ProtocolContextMutRef},
@@ -29,11 +35,19 @@ use tentacle::{
 };
 use tokio::codec::length_delimited::LengthDelimitedCodec;
 
-// Quota (1 byte) = 200,
-// Max 20 block in one transfer.
-// 512M can support BQL set to 2 ** 32 - 1
-pub const MAX_FRAME_LENGTH: usize = 512 * 1024 * 1204;
+// Default of the max length of a message, `max_frame_len` in network.toml.
+// A sync response carries up to SYNC_STEP (20) blocks, and a byte of a transaction costs
+// 200 quota, so 64M holds a batch of blocks using about 670M quota each.
+// Raise it if the block quota limit allows bigger batches: 20 * BQL / 200 bytes.
+pub const MAX_FRAME_LENGTH: usize = 64 * 1024 * 1024;
 pub const TRANSFER_PROTOCOL_ID: ProtocolId = ProtocolId::new(1);
+pub const TRANSFER_VERSION: &str = "0.0.2";
+// The version supporting compressed messages.
+pub const COMPRESSION_VERSION: &str = "0.0.3";
+// A session sending more than `flood_limit` messages in a FLOOD_CHECK_INTERVAL is flooding.
+pub const FLOOD_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+// Default of `flood_limit` in network.toml.
+pub const DEFAULT_FLOOD_LIMIT: u32 = 5000;
 
 struct TransferProtocol {
     proto_id: ProtocolId,
@@ -41,6 +55,39 @@ struct TransferProtocol {
     network_client: NetworkClient,
     nodes_mgr_client: NodesManagerClient,
     self_address: Address,
+    // Start of the current interval and the messages received in it, of each session
+    message_counts: HashMap<SessionId, (Instant, u32)>,
+    authenticated_sessions: AuthenticatedSessions,
+    max_frame_len: usize,
+    // Max messages of a session in a FLOOD_CHECK_INTERVAL, 0 for no limit
+    flood_limit: u32,
+}
+
+impl TransferProtocol {
+    fn misbehave(&self, session_id: SessionId, kind: Misbehavior) {
+        warn!(
+            "[Transfer] Session [{:?}] misbehaved: {:?}",
+            session_id, kind
+        );
+        self.nodes_mgr_client
+            .misbehave(MisbehaveReq::new(session_id, kind));
+    }
+
+    // Count a message of the session, return true if the session is flooding.
+    fn is_flooding(&mut self, session_id: SessionId) -> bool {
+        if self.flood_limit == 0 {
+            return false;
+        }
+        let now = Instant::now();
+        let (start, count) = self.message_counts.entry(session_id).or_insert((now, 0));
+        if now.duration_since(*start) >= FLOOD_CHECK_INTERVAL {
+            *start = now;
+            *count = 0;
+        }
+        *count += 1;
+        // Only report once in an interval.
+        *count == self.flood_limit + 1
+    }
 }
 
 impl ServiceProtocol for TransferProtocol {
@@ -53,7 +100,7 @@ impl ServiceProtocol for TransferProtocol {
         );
         self.connected_session_ids.push(control.session.id);
 
-        let req = NetworkInitReq::new(control.session.id);
+        let req = NetworkInitReq::new(control.session.id, version == COMPRESSION_VERSION);
         self.nodes_mgr_client.network_init(req);
 
         info!(
@@ -70,6 +117,7 @@ impl ServiceProtocol for TransferProtocol {
             .cloned()
             .collect();
         self.connected_session_ids = new_list;
+        self.message_counts.remove(&control.session.id);
 
         info!(
             "[Transfer] Disconnected proto id [{}] close on session [{}]",
@@ -78,23 +126,59 @@ impl ServiceProtocol for TransferProtocol {
     }
 
     fn received(&mut self, env: ProtocolContextMutRef, data: bytes::Bytes) {
+        metrics::BYTES_RECEIVED.inc_by(data.len() as i64);
         let mut data = BytesMut::from(data);
+        let sid = env.session.id;
+
+        if self.is_flooding(sid) {
+            self.misbehave(sid, Misbehavior::Flooding);
+        }
 
-        if let Some(mut info) = network_message_to_pubsub_message(&mut data) {
+        if let Some(mut info) = network_message_to_pubsub_message(&mut data, self.max_frame_len) {
             if info.key.eq(&"network.init".to_string()) {
+                if info.data.len() != INIT_MSG_LEN && info.data.len() != AUTH_INIT_MSG_LEN {
+                    self.misbehave(sid, Misbehavior::InvalidMessage);
+                    return;
+                }
                 let msg = InitMsg::from(info.data);
-                let req = AddConnectedNodeReq::new(env.session.id, env.session.ty, msg);
+                let remote_key = env
+                    .session
+                    .remote_pubkey
+                    .as_ref()
+                    .map(|key| key.peer_id().as_bytes().to_vec())
+                    .unwrap_or_default();
+                let req = AddConnectedNodeReq::new(sid, env.session.ty, msg, remote_key);
                 self.nodes_mgr_client.add_connected_node(req);
                 return;
             }
 
+            if info.key == AUTH_KEY {
+                let req = AuthenticateNodeReq::new(sid, info.data);
+                self.nodes_mgr_client.authenticate_node(req);
+                return;
+            }
+
+            // Messages arriving while the signature is still being verified are dropped too.
+            if !self.authenticated_sessions.is_allowed(sid) {
+                debug!(
+                    "[Transfer] Drop message {} of unauthenticated session [{:?}]",
+                    info.key, sid
+                );
+                return;
+            }
+
             if info.addr == self.self_address {
                 debug!("[Transfer] Recieve myself {:?} message", info.addr);
                 return;
             }
 
-            let sid = env.session.id;
-            let mut msg = ProtoMessage::try_from(&info.data).unwrap();
+            let mut msg = match ProtoMessage::try_from(&info.data) {
+                Ok(msg) => msg,
+                Err(_) => {
+                    self.misbehave(sid, Misbehavior::InvalidMessage);
+                    return;
+                }
+            };
             msg.set_origin(sid.value() as u32);
             self.network_client
                 .handle_remote_message(RemoteMessage::new(
@@ -109,7 +193,10 @@ impl ServiceProtocol for TransferProtocol {
                 self.nodes_mgr_client.retrans_net_msg(req);
             }
         } else {
-            warn!("[Transfer] Cannot convert network message to pubsub message!");
+            warn!(
+                "[Transfer] Cannot convert network message to pubsub message, or it is too long!"
+            );
+            self.misbehave(sid, Misbehavior::UndecodableFrame);
         }
     }
 }
@@ -118,12 +205,21 @@ pub fn create_transfer_meta(
     network_client: NetworkClient,
     nodes_mgr_client: NodesManagerClient,
     self_address: Address,
+    authenticated_sessions: AuthenticatedSessions,
+    max_frame_len: usize,
+    enable_compression: bool,
+    flood_limit: u32,
 ) -> ProtocolMeta {
+    let mut versions = vec![TRANSFER_VERSION.to_owned()];
+    if enable_compression {
+        versions.push(COMPRESSION_VERSION.to_owned());
+    }
+
     MetaBuilder::default()
         .id(TRANSFER_PROTOCOL_ID)
-        .codec(|| {
+        .codec(move || {
             let mut lcodec = LengthDelimitedCodec::new();
-            lcodec.set_max_frame_length(MAX_FRAME_LENGTH);
+            lcodec.set_max_frame_length(max_frame_len + CITA_FRAME_HEADER_LEN);
             Box::new(lcodec)
         })
         .service_handle(move || {
@@ -133,10 +229,14 @@ pub fn create_transfer_meta(
                 network_client: network_client.clone(),
                 nodes_mgr_client: nodes_mgr_client.clone(),
                 self_address,
+                message_counts: HashMap::new(),
+                authenticated_sessions: authenticated_sessions.clone(),
+                max_frame_len,
+                flood_limit,
             });
             ProtocolHandle::Callback(handle)
         })
         .name(|_| "/cita/transfer".to_owned())
-        .support_versions(vec!["0.0.2".to_owned()])
+        .support_versions(versions)
         .build()
 }
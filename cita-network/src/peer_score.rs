@@ -0,0 +1,316 @@
+// Copyright Rivtower Technologies LLC.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Behaviour scores of peers, and the list of banned peers.
+//!
+//! Every peer starts with `FULL_SCORE`, loses the penalty of each protocol violation
+//! and gets `RECOVER_SCORE` back on every round of `NodesManager`. A peer whose score
+//! falls to `BAN_SCORE` is banned for a period, the ban list is saved to a file so it
+//! survives restarts.
+//!
+//! A peer is a node address at an IP once the handshake has verified the address, so
+//! the nodes sharing an IP are scored apart, and a node can not be banned by another one
+//! claiming its address. Before that, or without the handshake, a peer could claim any
+//! address, so it is its IP alone.
+
+use cita_types::{clean_0x, Address};
+use serde::de::{Deserialize, Deserializer, Error};
+use serde::ser::{Serialize, Serializer};
+use serde_json;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::fs;
+use std::net::IpAddr;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::SystemTime;
+
+pub const FULL_SCORE: i32 = 100;
+// A peer with a score not above BAN_SCORE is banned.
+pub const BAN_SCORE: i32 = 0;
+// Score given back to every peer for each round.
+pub const RECOVER_SCORE: i32 = 1;
+pub const DEFAULT_BAN_SECS: u64 = 3600;
+
+/// A verified node address at an IP, written as `address@ip`, or an IP alone.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Peer {
+    pub address: Option<Address>,
+    pub ip: IpAddr,
+}
+
+impl Peer {
+    /// The node of `address` at `ip`, the address must be verified by the handshake.
+    pub fn new(address: Address, ip: IpAddr) -> Self {
+        Peer {
+            address: Some(address),
+            ip,
+        }
+    }
+
+    /// Any node at `ip`.
+    pub fn at_ip(ip: IpAddr) -> Self {
+        Peer { address: None, ip }
+    }
+}
+
+impl fmt::Display for Peer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.address {
+            Some(address) => write!(f, "{:?}@{}", address, self.ip),
+            None => write!(f, "{}", self.ip),
+        }
+    }
+}
+
+impl FromStr for Peer {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (address, ip) = match s.find('@') {
+            Some(at) => {
+                let address = Address::from_str(clean_0x(&s[..at]))
+                    .map_err(|_| format!("invalid address of peer {}", s))?;
+                (Some(address), &s[at + 1..])
+            }
+            None => (None, s),
+        };
+        let ip = ip
+            .parse()
+            .map_err(|_| format!("invalid ip of peer {}", s))?;
+        Ok(Peer { address, ip })
+    }
+}
+
+impl Serialize for Peer {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for Peer {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(D::Error::custom)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Misbehavior {
+    /// A frame which is not a CITA network message, or is too long
+    UndecodableFrame,
+    /// A message which can not be decoded
+    InvalidMessage,
+    /// Sync blocks which were not requested from the peer, or are malformed
+    InvalidSyncBlocks,
+    /// Too many messages in a short time
+    Flooding,
+    /// Misbehaviour reported by the discovery protocol
+    Discovery,
+    /// A handshake signature which does not match the claimed address
+    InvalidHandshake,
+}
+
+impl Misbehavior {
+    pub fn penalty(self) -> i32 {
+        match self {
+            Misbehavior::UndecodableFrame => 25,
+            Misbehavior::InvalidMessage => 25,
+            Misbehavior::InvalidSyncBlocks => 30,
+            Misbehavior::Flooding => 10,
+            Misbehavior::Discovery => 10,
+            Misbehavior::InvalidHandshake => 50,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct PeerScores {
+    scores: HashMap<Peer, i32>,
+    /// Banned peers with the time their ban ends, in seconds since the epoch
+    banned: BTreeMap<Peer, u64>,
+    ban_secs: u64,
+    path: Option<PathBuf>,
+}
+
+impl PeerScores {
+    pub fn new(ban_secs: u64, path: Option<PathBuf>) -> Self {
+        let mut peer_scores = PeerScores {
+            ban_secs,
+            path,
+            ..Default::default()
+        };
+        if let Some(ref path) = peer_scores.path {
+            if let Ok(data) = fs::read(path) {
+                match serde_json::from_slice(&data) {
+                    Ok(banned) => peer_scores.banned = banned,
+                    Err(e) => warn!("[PeerScores] Ignore invalid ban list {:?}: {:?}", path, e),
+                }
+            }
+        }
+        peer_scores.prune(now());
+        peer_scores
+    }
+
+    pub fn score(&self, peer: &Peer) -> i32 {
+        self.scores.get(peer).cloned().unwrap_or(FULL_SCORE)
+    }
+
+    /// Deduct the penalty of the misbehaviour from the peer.
+    /// Return true if the peer is banned for it.
+    pub fn misbehave(&mut self, peer: Peer, kind: Misbehavior) -> bool {
+        let score = self.scores.entry(peer).or_insert(FULL_SCORE);
+        *score -= kind.penalty();
+        info!(
+            "[PeerScores] Peer {} misbehaved: {:?}, score: {}",
+            peer, kind, *score
+        );
+        if *score > BAN_SCORE {
+            return false;
+        }
+
+        self.scores.remove(&peer);
+        let until = now() + self.ban_secs;
+        warn!("[PeerScores] Ban peer {} until {}", peer, until);
+        self.banned.insert(peer, until);
+        self.save();
+        true
+    }
+
+    pub fn is_banned(&self, peer: &Peer) -> bool {
+        self.banned
+            .get(peer)
+            .map(|until| *until > now())
+            .unwrap_or(false)
+    }
+
+    /// Banned peers with the time their ban ends
+    pub fn banned(&self) -> &BTreeMap<Peer, u64> {
+        &self.banned
+    }
+
+    /// Give back some score to every peer, and lift the ended bans.
+    pub fn recover(&mut self) {
+        for score in self.scores.values_mut() {
+            *score += RECOVER_SCORE;
+        }
+        self.scores.retain(|_, score| *score < FULL_SCORE);
+        if self.prune(now()) {
+            self.save();
+        }
+    }
+
+    fn prune(&mut self, now: u64) -> bool {
+        let len = self.banned.len();
+        self.banned.retain(|_, until| *until > now);
+        len != self.banned.len()
+    }
+
+    fn save(&self) {
+        if let Some(ref path) = self.path {
+            let data = serde_json::to_vec(&self.banned).unwrap();
+            if let Err(e) = fs::write(path, data) {
+                warn!("[PeerScores] Save ban list to {:?} failed: {:?}", path, e);
+            }
+        }
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Misbehavior, Peer, PeerScores, FULL_SCORE, RECOVER_SCORE};
+    use cita_types::Address;
+    use tempfile::NamedTempFile;
+
+    fn peer(address: u64, ip: &str) -> Peer {
+        Peer::new(Address::from(address), ip.parse().unwrap())
+    }
+
+    #[test]
+    fn ban_after_misbehaviours() {
+        let node = peer(1, "10.0.0.1");
+        let mut peer_scores = PeerScores::new(60, None);
+
+        assert!(!peer_scores.misbehave(node, Misbehavior::InvalidSyncBlocks));
+        assert_eq!(peer_scores.score(&node), FULL_SCORE - 30);
+        peer_scores.recover();
+        assert_eq!(peer_scores.score(&node), FULL_SCORE - 30 + RECOVER_SCORE);
+
+        assert!(!peer_scores.misbehave(node, Misbehavior::InvalidSyncBlocks));
+        assert!(!peer_scores.misbehave(node, Misbehavior::InvalidSyncBlocks));
+        assert!(!peer_scores.is_banned(&node));
+        assert!(peer_scores.misbehave(node, Misbehavior::UndecodableFrame));
+        assert!(peer_scores.is_banned(&node));
+        assert!(!peer_scores.is_banned(&peer(1, "10.0.0.2")));
+    }
+
+    #[test]
+    fn ban_peers_sharing_ip_apart() {
+        let mut peer_scores = PeerScores::new(60, None);
+        let banned = peer(1, "127.0.0.1");
+        while !peer_scores.misbehave(banned, Misbehavior::Flooding) {}
+
+        assert!(peer_scores.is_banned(&banned));
+        assert!(!peer_scores.is_banned(&peer(2, "127.0.0.1")));
+        assert_eq!(peer_scores.score(&peer(2, "127.0.0.1")), FULL_SCORE);
+    }
+
+    #[test]
+    fn ban_unverified_peers_by_ip() {
+        let mut peer_scores = PeerScores::new(60, None);
+        let banned = Peer::at_ip("10.0.0.1".parse().unwrap());
+        while !peer_scores.misbehave(banned, Misbehavior::Flooding) {}
+
+        assert!(peer_scores.is_banned(&banned));
+        assert!(!peer_scores.is_banned(&Peer::at_ip("10.0.0.2".parse().unwrap())));
+        assert!(!peer_scores.is_banned(&peer(1, "10.0.0.1")));
+    }
+
+    #[test]
+    fn peer_to_and_from_string() {
+        let peer = peer(0x1234, "::1");
+        assert_eq!(peer.to_string().parse::<Peer>(), Ok(peer));
+        let ip_only = Peer::at_ip("10.0.0.1".parse().unwrap());
+        assert_eq!(ip_only.to_string(), "10.0.0.1");
+        assert_eq!("10.0.0.1".parse::<Peer>(), Ok(ip_only));
+        assert!("0x1234@10.0.0".parse::<Peer>().is_err());
+        assert!("0x12x4@10.0.0.1".parse::<Peer>().is_err());
+    }
+
+    #[test]
+    fn ban_list_persisted() {
+        let file = NamedTempFile::new().unwrap();
+        let path = file.path().to_path_buf();
+        let node = peer(1, "10.0.0.1");
+
+        let mut peer_scores = PeerScores::new(60, Some(path.clone()));
+        while !peer_scores.misbehave(node, Misbehavior::Flooding) {}
+
+        let reloaded = PeerScores::new(60, Some(path.clone()));
+        assert!(reloaded.is_banned(&node));
+        assert_eq!(reloaded.banned(), peer_scores.banned());
+
+        // An ended ban is dropped on load.
+        let mut peer_scores = PeerScores::new(0, Some(path.clone()));
+        while !peer_scores.misbehave(node, Misbehavior::Flooding) {}
+        assert!(!PeerScores::new(0, Some(path)).is_banned(&node));
+    }
+}
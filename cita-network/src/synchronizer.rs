@@ -13,15 +13,17 @@
 // limitations under the License.
 
 use crate::mq_agent::{MqAgentClient, PubMessage};
-use crate::node_manager::{BroadcastReq, NodesManagerClient, SingleTxReq};
+use crate::node_manager::{BroadcastReq, MisbehaveReq, NodesManagerClient, SingleTxReq};
+use crate::peer_score::Misbehavior;
+use cita_rpc_ext as ext;
 use libproto::blockchain::{Block, Status};
 use libproto::router::{MsgType, RoutingKey, SubModules};
 use libproto::routing_key;
-use libproto::{Message, OperateType, SyncRequest, SyncResponse};
+use libproto::{Message, MsgClass, OperateType, SyncRequest, SyncResponse};
 use libproto::{TryFrom, TryInto};
 use pubsub::channel::{unbounded, Receiver, Sender};
 use rand::{thread_rng, Rng, ThreadRng};
-use std::collections::{BTreeMap, HashSet, VecDeque};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
 use std::convert::Into;
 use std::time::{Duration, Instant};
 use std::u8;
@@ -29,6 +31,14 @@ use tentacle::SessionId;
 
 const SYNC_STEP: u64 = 20;
 const SYNC_TIME_OUT: u64 = 9;
+/// How far the other nodes must be ahead of genesis to sync the state instead of the blocks
+const STATE_SYNC_MIN_HEIGHT: u64 = 1000;
+/// Times to ask for the headers to sync the state at, before syncing the blocks instead
+const STATE_SYNC_RETRIES: u8 = 3;
+/// A node asking for more than `MAX_STATE_SYNC_SERVES` headers or chunks of trie nodes
+/// in a STATE_SYNC_SERVE_INTERVAL is not served until the next one
+const STATE_SYNC_SERVE_INTERVAL: Duration = Duration::from_secs(1);
+const MAX_STATE_SYNC_SERVES: u32 = 20;
 
 /// Get messages and determine if need to synchronize or broadcast the current node status
 pub struct Synchronizer {
@@ -40,6 +50,8 @@ pub struct Synchronizer {
     is_synchronizing: bool,
     latest_status_lists: BTreeMap<u64, VecDeque<u32>>,
     block_lists: BTreeMap<u64, Block>,
+    /// Heights requested, with the node they are requested from
+    requested_heights: BTreeSet<(u64, u32)>,
     rand: ThreadRng,
     // Timer for each height processing
     remote_sync_time_out: Instant,
@@ -47,13 +59,27 @@ pub struct Synchronizer {
     local_sync_count: u8,
     sync_client: SynchronizerClient,
     msg_receiver: Receiver<SynchronizerMessage>,
+    /// Sync the state of a recent block from the other nodes instead of all the blocks,
+    /// when starting from genesis
+    state_sync: bool,
+    /// The state sync request of the executor not answered yet
+    state_sync_request: Option<Vec<u8>>,
+    /// When a state sync request was sent or answered last
+    state_sync_time: Instant,
+    state_sync_retries: u8,
+    /// Start of the current interval and the state sync requests served in it, of each node
+    state_sync_serves: HashMap<u32, (Instant, u32)>,
 }
 
 unsafe impl Sync for Synchronizer {}
 unsafe impl Send for Synchronizer {}
 
 impl Synchronizer {
-    pub fn new(mq_client: MqAgentClient, nodes_mgr_client: NodesManagerClient) -> Self {
+    pub fn new(
+        mq_client: MqAgentClient,
+        nodes_mgr_client: NodesManagerClient,
+        state_sync: bool,
+    ) -> Self {
         let (tx, rx) = unbounded();
         let client = SynchronizerClient::new(tx);
         Synchronizer {
@@ -65,11 +91,17 @@ impl Synchronizer {
             sync_end_height: 0,
             is_synchronizing: false,
             block_lists: BTreeMap::new(),
+            requested_heights: BTreeSet::new(),
             rand: thread_rng(),
             remote_sync_time_out: (Instant::now() - Duration::from_secs(SYNC_TIME_OUT)),
             local_sync_count: 0,
             sync_client: client,
             msg_receiver: rx,
+            state_sync,
+            state_sync_request: None,
+            state_sync_time: (Instant::now() - Duration::from_secs(SYNC_TIME_OUT)),
+            state_sync_retries: 0,
+            state_sync_serves: HashMap::new(),
         }
     }
 
@@ -123,6 +155,9 @@ impl Synchronizer {
         self.latest_status_lists = self
             .latest_status_lists
             .split_off(&(latest_status.get_height() + 1));
+        if new_height > 0 {
+            self.state_sync_request = None;
+        }
         self.current_status = latest_status;
         self.broadcast_status();
         self.prune_block_list_cache(new_height + 1);
@@ -200,6 +235,13 @@ impl Synchronizer {
         let current_height = self.current_status.get_height();
         if self.global_status.get_height() < status.get_height() {
             self.global_status = status.clone();
+            self.pub_global_status();
+        }
+
+        if self.is_state_syncing() {
+            self.add_latest_sync_lists(status.get_height(), origin);
+            self.request_state();
+            return;
         }
 
         if status.get_height() < current_height + 1 {
@@ -229,21 +271,160 @@ impl Synchronizer {
         self.is_synchronizing
     }
 
-    pub fn process_sync(&mut self, mut blocks: SyncResponse) {
+    pub fn process_sync(&mut self, mut blocks: SyncResponse, origin: u32) {
         let blocks = blocks.take_blocks();
         debug!("sync: process_sync: blocks len = {}", blocks.len());
 
+        // Blocks must have a header, a right transactions root, and be requested from
+        // this node, but the last one which carries the proof of the others only.
+        // Blocks the chain already has may be late answers to a repeated request.
+        let current_height = self.current_status.get_height();
+        let is_valid = blocks.iter().all(|block| {
+            let height = block.get_header().get_height();
+            block.has_header()
+                && (height == ::std::u64::MAX
+                    || (block.check_hash()
+                        && (height <= current_height
+                            || self.requested_heights.contains(&(height, origin)))))
+        });
+        if !is_valid {
+            warn!("sync: process_sync: invalid blocks from node {}", origin);
+            self.nodes_mgr_client.misbehave(MisbehaveReq::new(
+                SessionId::from(origin as usize),
+                Misbehavior::InvalidSyncBlocks,
+            ));
+            return;
+        }
+
         let mut heights = vec![];
         for block in blocks.into_iter() {
-            heights.push(block.get_header().get_height());
-            self.block_lists
-                .insert(block.get_header().get_height(), block);
+            let height = block.get_header().get_height();
+            heights.push(height);
+            self.block_lists.insert(height, block);
         }
 
         debug!("sync: process_sync: heights = {:?}", heights);
         self.submit_blocks();
     }
 
+    /// Send the state sync messages of the executor. Answers go back to the node asking,
+    /// requests go to one of the highest nodes.
+    pub fn process_local_state_sync(&mut self, origin: u32, data: Vec<u8>) {
+        match ext::Message::from_bytes(&data) {
+            Ok(ext::Message::StateHeaders(_)) | Ok(ext::Message::TrieNodes(_)) => {
+                let msg = Message::init(OperateType::Single, origin, MsgClass::RawBytes(data));
+                self.nodes_mgr_client.send_message(SingleTxReq::new(
+                    SessionId::from(origin as usize),
+                    routing_key!(Synchronizer >> RawBytes).into(),
+                    msg,
+                ));
+            }
+            Ok(ext::Message::GetTrieNodes(_)) if self.is_state_syncing() => {
+                self.state_sync_retries = 0;
+                self.send_state_request(data);
+            }
+            _ => {}
+        }
+    }
+
+    /// Pass the state sync messages of other nodes on to the executor.
+    /// Answers are only passed during a state sync.
+    pub fn process_remote_state_sync(&mut self, origin: u32, data: &[u8], msg_data: Vec<u8>) {
+        match ext::Message::from_bytes(data) {
+            Ok(ext::Message::StateHeaders(_)) | Ok(ext::Message::TrieNodes(_)) => {
+                if !self.is_state_syncing() {
+                    return;
+                }
+                self.state_sync_request = None;
+                self.state_sync_time = Instant::now();
+            }
+            Ok(ext::Message::GetStateHeaders) | Ok(ext::Message::GetTrieNodes(_)) => {
+                if !self.serve_state_sync(origin) {
+                    return;
+                }
+            }
+            _ => {
+                warn!("sync: unexpected state sync message from node {}", origin);
+                return;
+            }
+        }
+        self.mq_client.pub_state_sync(PubMessage::new(
+            routing_key!(Synchronizer >> RawBytes).into(),
+            msg_data,
+        ));
+    }
+
+    /// Count a state sync request of a node, return false if it asks too often.
+    fn serve_state_sync(&mut self, origin: u32) -> bool {
+        let now = Instant::now();
+        self.state_sync_serves
+            .retain(|_, (start, _)| now.duration_since(*start) < STATE_SYNC_SERVE_INTERVAL);
+        let (_, count) = self.state_sync_serves.entry(origin).or_insert((now, 0));
+        *count += 1;
+        // Only warn once in an interval.
+        if *count == MAX_STATE_SYNC_SERVES + 1 {
+            warn!("sync: node {} asks for the state too often", origin);
+        }
+        *count <= MAX_STATE_SYNC_SERVES
+    }
+
+    /// Whether to sync the state before the blocks: the chain is at genesis, and the
+    /// other nodes are far enough ahead.
+    fn is_state_syncing(&self) -> bool {
+        self.state_sync
+            && self.current_status.get_height() == 0
+            && !self.current_status.get_hash().is_empty()
+            && self.global_status.get_height() > STATE_SYNC_MIN_HEIGHT
+    }
+
+    /// Ask another node again when the request of the executor is not answered in time.
+    /// If nothing is asked for a while, the executor does not take the headers, or it
+    /// restarted, so ask for the headers again, and sync the blocks after some tries.
+    fn request_state(&mut self) {
+        if self.state_sync_time.elapsed().as_secs() <= SYNC_TIME_OUT {
+            return;
+        }
+        match self.state_sync_request.take() {
+            Some(data) => self.send_state_request(data),
+            None if self.state_sync_retries < STATE_SYNC_RETRIES => {
+                self.state_sync_retries += 1;
+                self.send_state_request(ext::Message::GetStateHeaders.to_bytes());
+            }
+            None => {
+                warn!("sync: no state to sync from other nodes, sync the blocks instead");
+                self.state_sync = false;
+                self.start_sync_req(self.current_status.get_height() + 1);
+            }
+        }
+    }
+
+    fn send_state_request(&mut self, data: Vec<u8>) {
+        if let Some(origin) = self.random_highest_node() {
+            debug!("sync: send state sync request to node {}", origin);
+            let msg = Message::init(
+                OperateType::Single,
+                origin,
+                MsgClass::RawBytes(data.clone()),
+            );
+            self.nodes_mgr_client.send_message(SingleTxReq::new(
+                SessionId::from(origin as usize),
+                routing_key!(Synchronizer >> RawBytes).into(),
+                msg,
+            ));
+        }
+        self.state_sync_request = Some(data);
+        self.state_sync_time = Instant::now();
+    }
+
+    fn random_highest_node(&mut self) -> Option<u32> {
+        let origins = self
+            .latest_status_lists
+            .values()
+            .rev()
+            .find(|origins| !origins.is_empty())?;
+        Some(origins[self.rand.gen_range(0, origins.len())])
+    }
+
     // Initiate a sync request
     fn start_sync_req(&mut self, start_height: u64) {
         debug!(
@@ -279,7 +460,7 @@ impl Synchronizer {
         }
     }
 
-    fn sync_strategy(&self, start_height: u64, end_height: u64, origin: u32) {
+    fn sync_strategy(&mut self, start_height: u64, end_height: u64, origin: u32) {
         //current height = 155,start_height = 156, end height = 160, to origin = 1
         debug!(
             "sync: sync_strategy: current height = {}, \
@@ -311,7 +492,7 @@ impl Synchronizer {
         }
     }
 
-    fn send_sync_req(&self, heights: Vec<u64>, origin: u32) {
+    fn send_sync_req(&mut self, heights: Vec<u64>, origin: u32) {
         if !heights.is_empty() {
             debug!(
                 "sync: send_sync_req:current height = {}, \
@@ -323,6 +504,9 @@ impl Synchronizer {
                 origin,
                 OperateType::Single
             );
+            for height in heights.iter() {
+                self.requested_heights.insert((*height, origin));
+            }
             let mut sync_req = SyncRequest::new();
             sync_req.set_heights(heights);
             let msg = Message::init(OperateType::Single, origin, sync_req.into());
@@ -335,6 +519,15 @@ impl Synchronizer {
         }
     }
 
+    // Tell the other services how high the other nodes are, much lighter than the sync blocks.
+    fn pub_global_status(&self) {
+        let msg: Message = self.global_status.clone().into();
+        self.mq_client.pub_global_status(PubMessage::new(
+            routing_key!(Net >> Status).into(),
+            msg.try_into().unwrap(),
+        ));
+    }
+
     fn broadcast_status(&mut self) {
         debug!(
             "sync: broadcast status {:?}, {:?} to other nodes",
@@ -447,6 +640,7 @@ impl Synchronizer {
     /// Prune block on btreemap
     fn prune_block_list_cache(&mut self, height: u64) {
         self.block_lists = self.block_lists.split_off(&height);
+        self.requested_heights = self.requested_heights.split_off(&(height, 0));
     }
 }
 
@@ -472,6 +666,14 @@ impl SynchronizerClient {
         self.send_msg(msg);
     }
 
+    pub fn handle_local_state_sync(&self, msg: SynchronizerMessage) {
+        self.send_msg(msg);
+    }
+
+    pub fn handle_remote_state_sync(&self, msg: SynchronizerMessage) {
+        self.send_msg(msg);
+    }
+
     fn send_msg(&self, msg: SynchronizerMessage) {
         match self.sender.try_send(msg) {
             Ok(_) => {
@@ -511,7 +713,17 @@ impl SynchronizerMessage {
             }
             routing_key!(Synchronizer >> SyncResponse) => {
                 if let Some(blocks) = msg.take_sync_response() {
-                    service.process_sync(blocks);
+                    service.process_sync(blocks, origin);
+                };
+            }
+            routing_key!(Executor >> SyncResponse) => {
+                if let Some(data) = msg.take_raw_bytes() {
+                    service.process_local_state_sync(origin, data);
+                };
+            }
+            routing_key!(Synchronizer >> RawBytes) => {
+                if let Some(data) = msg.take_raw_bytes() {
+                    service.process_remote_state_sync(origin, &data, self.data);
                 };
             }
             _ => {
@@ -0,0 +1,398 @@
+// Copyright Rivtower Technologies LLC.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Mutual TLS of the connections between nodes.
+//!
+//! The p2p service only speaks plain TCP, so the TLS runs in tunnels in front of it.
+//! The public port takes the TLS connections of the peers, and passes them on to the
+//! service listening on loopback. A dial goes to a local forwarder of the peer, which
+//! opens a TLS connection to it.
+//!
+//! Both sides present a certificate signed by the CA in `tls.ca_file`. A peer dialed
+//! must present the `common_name` configured for it if any, and a peer connecting the
+//! `common_name` of one of the peers, unless no peer has one configured.
+//!
+//! The service sees the tunnels as connections from loopback, `Tls::peer_addr` gives
+//! the address of the peer behind one.
+//!
+//! The loopback ports take plain TCP from any local process: the service one without a
+//! certificate, and a forwarder reaches its peer with the certificate of the node. Only
+//! run the node on a host without untrusted users, or enable `enable_auth`, so such a
+//! connection also has to sign the handshake with the key of an allowed node.
+
+use crate::config::NetConfig;
+use futures::future::{self, Either};
+use futures::prelude::*;
+use openssl::nid::Nid;
+use openssl::ssl::{
+    SslAcceptor, SslConnector, SslContextBuilder, SslFiletype, SslMethod, SslVerifyMode,
+};
+use openssl::x509::store::{X509Store, X509StoreBuilder};
+use openssl::x509::X509;
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::fs;
+use std::io;
+use std::net::{SocketAddr, TcpListener as StdTcpListener, ToSocketAddrs};
+use std::path::Path;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+use tokio::io::{copy, AsyncRead, AsyncWrite};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::reactor::Handle;
+use tokio::runtime::TaskExecutor;
+use tokio::timer::{Delay, Timeout};
+use tokio_openssl::{ConnectConfigurationExt, SslAcceptorExt, SslStream};
+
+/// Time a peer has to finish the TLS handshake
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+/// Time to wait before accepting again after an error, e.g. too many open files
+const ACCEPT_ERROR_DELAY: Duration = Duration::from_millis(100);
+
+/// The certificates, and the common names expected of the peers.
+struct Context {
+    acceptor: SslAcceptor,
+    connector: SslConnector,
+    // Common names of the configured peers, by their address
+    names: HashMap<SocketAddr, String>,
+    // Certificate, key and CA files
+    files: Vec<String>,
+}
+
+impl Context {
+    fn load(config: &NetConfig) -> io::Result<Self> {
+        let tls = config.tls.as_ref().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::NotFound, "no tls certificates in the config")
+        })?;
+
+        let mut acceptor = SslAcceptor::mozilla_intermediate(SslMethod::tls())?;
+        set_certificates(&mut acceptor, &tls.cert_file, &tls.key_file, &tls.ca_file)?;
+        acceptor.set_verify(SslVerifyMode::PEER | SslVerifyMode::FAIL_IF_NO_PEER_CERT);
+
+        let mut connector = SslConnector::builder(SslMethod::tls())?;
+        set_certificates(&mut connector, &tls.cert_file, &tls.key_file, &tls.ca_file)?;
+
+        let mut names = HashMap::new();
+        for peer in config.peers.iter().flatten() {
+            if let (Some(ip), Some(port), Some(name)) = (&peer.ip, peer.port, &peer.common_name) {
+                match format!("{}:{}", ip, port).to_socket_addrs() {
+                    Ok(mut addrs) => {
+                        if let Some(addr) = addrs.next() {
+                            names.insert(addr, name.clone());
+                        }
+                    }
+                    Err(e) => warn!("[Tls] Can not resolve peer {}:{}: {}", ip, port, e),
+                }
+            }
+        }
+
+        Ok(Context {
+            acceptor: acceptor.build(),
+            connector: connector.build(),
+            names,
+            files: vec![
+                tls.cert_file.clone(),
+                tls.key_file.clone(),
+                tls.ca_file.clone(),
+            ],
+        })
+    }
+
+    /// Whether a peer connecting with a certificate of `name` is allowed.
+    fn accepts(&self, name: Option<&String>) -> bool {
+        self.names.is_empty() || name.map_or(false, |name| self.names.values().any(|n| n == name))
+    }
+}
+
+/// The TLS tunnels of the p2p service.
+#[derive(Clone)]
+pub struct Tls {
+    context: Arc<RwLock<Context>>,
+    // Address the service sees for a tunnel -> address of the peer
+    peers: Arc<Mutex<HashMap<SocketAddr, SocketAddr>>>,
+    // Address of a dialed peer -> its local forwarder
+    forwarders: Arc<Mutex<HashMap<SocketAddr, SocketAddr>>>,
+    executor: TaskExecutor,
+}
+
+impl Tls {
+    /// Load the certificates.
+    pub fn start(config: &NetConfig, executor: TaskExecutor) -> io::Result<Self> {
+        let context = Context::load(config)?;
+        Ok(Tls {
+            context: Arc::new(RwLock::new(context)),
+            peers: Arc::new(Mutex::new(HashMap::new())),
+            forwarders: Arc::new(Mutex::new(HashMap::new())),
+            executor,
+        })
+    }
+
+    /// Take the TLS connections of the peers on `port`, and pass them on to the p2p
+    /// service at `service_addr`. The service listens on loopback, so the peers only
+    /// reach it through the tunnels.
+    pub fn listen(&self, port: usize, service_addr: SocketAddr) -> io::Result<()> {
+        let listener = StdTcpListener::bind(format!("0.0.0.0:{}", port))?;
+        let listener = TcpListener::from_std(listener, &Handle::default())?;
+        let acceptor = self.clone();
+        self.executor
+            .spawn(incoming(listener).for_each(move |stream| {
+                acceptor
+                    .executor
+                    .spawn(acceptor.accept(stream, service_addr));
+                Ok(())
+            }));
+        Ok(())
+    }
+
+    /// The address of the peer behind a tunnel the service sees as `addr`.
+    pub fn peer_addr(&self, addr: SocketAddr) -> SocketAddr {
+        self.peers
+            .lock()
+            .unwrap()
+            .get(&addr)
+            .cloned()
+            .unwrap_or(addr)
+    }
+
+    /// The local address to dial instead of `remote`, its forwarder is started on
+    /// the first dial.
+    pub fn forward(&self, remote: SocketAddr) -> io::Result<SocketAddr> {
+        let mut forwarders = self.forwarders.lock().unwrap();
+        if let Some(addr) = forwarders.get(&remote) {
+            return Ok(*addr);
+        }
+        let listener = StdTcpListener::bind("127.0.0.1:0")?;
+        let addr = listener.local_addr()?;
+        let listener = TcpListener::from_std(listener, &Handle::default())?;
+
+        let tls = self.clone();
+        self.executor
+            .spawn(incoming(listener).for_each(move |stream| {
+                tls.executor.spawn(tls.connect(stream, remote));
+                Ok(())
+            }));
+        forwarders.insert(remote, addr);
+        self.peers.lock().unwrap().insert(addr, remote);
+        Ok(addr)
+    }
+
+    /// Load the certificates again, e.g. after they are renewed. The tunnels already
+    /// open keep the certificates they are opened with.
+    pub fn reload(&self, config: &NetConfig) {
+        match Context::load(config) {
+            Ok(context) => {
+                *self.context.write().unwrap() = context;
+                info!("[Tls] Certificates reloaded.");
+            }
+            Err(e) => warn!("[Tls] Reload certificates failed, keep the old ones: {}", e),
+        }
+    }
+
+    /// Whether `file_name` is the certificate, the key or the CA file.
+    pub fn is_cert_file(&self, file_name: &str) -> bool {
+        self.context
+            .read()
+            .unwrap()
+            .files
+            .iter()
+            .any(|path| Path::new(path).file_name() == Some(OsStr::new(file_name)))
+    }
+
+    // Check the certificate of a peer connecting, and pass its connection on to the service.
+    fn accept(
+        &self,
+        stream: TcpStream,
+        service_addr: SocketAddr,
+    ) -> impl Future<Item = (), Error = ()> {
+        let remote_addr = match stream.peer_addr() {
+            Ok(addr) => addr,
+            Err(_) => return Either::A(future::ok(())),
+        };
+        let acceptor = self.context.read().unwrap().acceptor.clone();
+        let tls = self.clone();
+        let handshake = Timeout::new(acceptor.accept_async(stream), HANDSHAKE_TIMEOUT)
+            .map_err(move |e| info!("[Tls] Handshake of {:?} failed: {:?}", remote_addr, e))
+            .and_then(move |stream| {
+                let name = common_name(&stream);
+                if !tls.context.read().unwrap().accepts(name.as_ref()) {
+                    warn!(
+                        "[Tls] Refuse {:?} with an unknown common name {:?}.",
+                        remote_addr, name
+                    );
+                    return Either::A(future::ok(()));
+                }
+                let peers = Arc::clone(&tls.peers);
+                let relayed = TcpStream::connect(&service_addr)
+                    .map_err(|e| warn!("[Tls] Connect to the p2p service failed: {}", e))
+                    .and_then(move |service| {
+                        let addr = service.local_addr().ok();
+                        if let Some(addr) = addr {
+                            peers.lock().unwrap().insert(addr, remote_addr);
+                        }
+                        relay(stream, service).then(move |_| {
+                            if let Some(addr) = addr {
+                                peers.lock().unwrap().remove(&addr);
+                            }
+                            Ok::<_, ()>(())
+                        })
+                    });
+                Either::B(relayed)
+            });
+        Either::B(handshake)
+    }
+
+    // Open a TLS connection to `remote` for a dial of the service, and check its certificate.
+    fn connect(&self, stream: TcpStream, remote: SocketAddr) -> impl Future<Item = (), Error = ()> {
+        let (connector, expected) = {
+            let context = self.context.read().unwrap();
+            (
+                context.connector.clone(),
+                context.names.get(&remote).cloned(),
+            )
+        };
+        TcpStream::connect(&remote)
+            .map_err(move |e| info!("[Tls] Connect to {:?} failed: {}", remote, e))
+            .and_then(move |plain| {
+                future::result(connector.configure())
+                    .map_err(|e| warn!("[Tls] Configure the connection failed: {}", e))
+                    .and_then(move |mut config| {
+                        // The common name is checked below instead of the host name
+                        config.set_verify_hostname(false);
+                        config.set_use_server_name_indication(false);
+                        let domain = remote.ip().to_string();
+                        Timeout::new(config.connect_async(&domain, plain), HANDSHAKE_TIMEOUT)
+                            .map_err(move |e| {
+                                info!("[Tls] Handshake with {:?} failed: {:?}", remote, e)
+                            })
+                    })
+            })
+            .and_then(move |tls_stream| {
+                let name = common_name(&tls_stream);
+                if expected.is_some() && name != expected {
+                    warn!(
+                        "[Tls] Peer {:?} has the common name {:?}, not {:?}.",
+                        remote, name, expected
+                    );
+                    return Either::A(future::ok(()));
+                }
+                Either::B(relay(stream, tls_stream))
+            })
+    }
+}
+
+// Use only the certificates of the config, without the CAs of the system.
+fn set_certificates(
+    builder: &mut SslContextBuilder,
+    cert_file: &str,
+    key_file: &str,
+    ca_file: &str,
+) -> io::Result<()> {
+    builder.set_private_key_file(key_file, SslFiletype::PEM)?;
+    builder.set_certificate_chain_file(cert_file)?;
+    builder.check_private_key()?;
+    builder.set_cert_store(ca_store(ca_file)?);
+    Ok(())
+}
+
+fn ca_store(ca_file: &str) -> io::Result<X509Store> {
+    let mut store = X509StoreBuilder::new()?;
+    for cert in X509::stack_from_pem(&fs::read(ca_file)?)? {
+        store.add_cert(cert)?;
+    }
+    Ok(store.build())
+}
+
+fn common_name<S>(stream: &SslStream<S>) -> Option<String> {
+    let cert = stream.get_ref().ssl().peer_certificate()?;
+    let name = cert
+        .subject_name()
+        .entries_by_nid(Nid::COMMONNAME)
+        .next()?
+        .data()
+        .as_utf8()
+        .ok()?;
+    Some(name.to_string())
+}
+
+// The connections of a listener, an error only delays the next accept.
+fn incoming(listener: TcpListener) -> impl Stream<Item = TcpStream, Error = ()> {
+    listener
+        .incoming()
+        .then(|res| match res {
+            Ok(stream) => Either::A(future::ok::<_, ()>(Some(stream))),
+            Err(e) => {
+                warn!("[Tls] Accept failed: {}", e);
+                let delay = Delay::new(Instant::now() + ACCEPT_ERROR_DELAY);
+                Either::B(delay.then(|_| Ok::<_, ()>(None)))
+            }
+        })
+        .filter_map(|stream| stream)
+}
+
+// Pass the bytes both ways, until either side closes.
+fn relay<A, B>(a: A, b: B) -> impl Future<Item = (), Error = ()>
+where
+    A: AsyncRead + AsyncWrite,
+    B: AsyncRead + AsyncWrite,
+{
+    let (a_reader, a_writer) = a.split();
+    let (b_reader, b_writer) = b.split();
+    copy(a_reader, b_writer)
+        .map(|_| ())
+        .select(copy(b_reader, a_writer).map(|_| ()))
+        .then(|_| Ok::<_, ()>(()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Context;
+    use crate::config::NetConfig;
+    use openssl::ssl::{SslAcceptor, SslConnector, SslMethod};
+    use std::io::Write;
+    use tempfile::NamedTempFile;
+
+    fn context(names: &[(&str, &str)]) -> Context {
+        Context {
+            acceptor: SslAcceptor::mozilla_intermediate(SslMethod::tls())
+                .unwrap()
+                .build(),
+            connector: SslConnector::builder(SslMethod::tls()).unwrap().build(),
+            names: names
+                .iter()
+                .map(|(addr, name)| (addr.parse().unwrap(), name.to_string()))
+                .collect(),
+            files: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn accept_common_names() {
+        let any = context(&[]);
+        assert!(any.accepts(None));
+        assert!(any.accepts(Some(&"test2.cita".to_owned())));
+
+        let named = context(&[("127.0.0.1:4001", "test1.cita")]);
+        assert!(named.accepts(Some(&"test1.cita".to_owned())));
+        assert!(!named.accepts(Some(&"test2.cita".to_owned())));
+        assert!(!named.accepts(None));
+    }
+
+    #[test]
+    fn load_without_certificates() {
+        let mut tmp_file: NamedTempFile = NamedTempFile::new().unwrap();
+        tmp_file.write_all(b"port = 4000").unwrap();
+        let config = NetConfig::new(tmp_file.path().to_str().unwrap());
+        assert!(Context::load(&config).is_err());
+    }
+}
@@ -0,0 +1,204 @@
+// Copyright Rivtower Technologies LLC.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The `RESERVED` crypto type of transactions: SM2 signatures with SM3 digests.
+//!
+//! It is available whatever the crypto feature a service is built with, so every
+//! transaction may choose its own algorithm. As SM2 can not recover the public key
+//! from a signature, a signature is `r || s || pubkey`, and recovering verifies it
+//! against the embedded public key. The address of a public key is the last 20 bytes
+//! of its SM3 hash.
+
+use libsm::sm2::signature::{SigCtx, Signature};
+use libsm::sm3::hash::Sm3Hash;
+use std::fmt;
+
+pub const PRIVKEY_BYTES_LEN: usize = 32;
+pub const PUBKEY_BYTES_LEN: usize = 64;
+pub const SIGNATURE_BYTES_LEN: usize = 128;
+pub const ADDRESS_BYTES_LEN: usize = 20;
+
+const SCALAR_BYTES_LEN: usize = 32;
+/// Prefix of an uncompressed point.
+const UNCOMPRESSED_PREFIX: u8 = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Error {
+    InvalidPrivKey,
+    InvalidPubKey,
+    InvalidSignature,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let msg = match self {
+            Error::InvalidPrivKey => "Invalid SM2 private key",
+            Error::InvalidPubKey => "Invalid SM2 public key",
+            Error::InvalidSignature => "Invalid SM2 signature",
+        };
+        f.write_str(msg)
+    }
+}
+
+impl std::error::Error for Error {}
+
+pub struct KeyPair {
+    privkey: [u8; PRIVKEY_BYTES_LEN],
+    pubkey: [u8; PUBKEY_BYTES_LEN],
+}
+
+impl KeyPair {
+    pub fn gen_keypair() -> Self {
+        let ctx = SigCtx::new();
+        let (pk, sk) = ctx.new_keypair();
+        let mut keypair = KeyPair {
+            privkey: [0; PRIVKEY_BYTES_LEN],
+            pubkey: [0; PUBKEY_BYTES_LEN],
+        };
+        copy_aligned(&mut keypair.privkey, &ctx.serialize_seckey(&sk));
+        copy_aligned(&mut keypair.pubkey, &ctx.serialize_pubkey(&pk, false)[1..]);
+        keypair
+    }
+
+    pub fn from_privkey(privkey: &[u8]) -> Result<Self, Error> {
+        if privkey.len() != PRIVKEY_BYTES_LEN {
+            return Err(Error::InvalidPrivKey);
+        }
+        let ctx = SigCtx::new();
+        let sk = ctx
+            .load_seckey(privkey)
+            .map_err(|_| Error::InvalidPrivKey)?;
+        let pk = ctx.pk_from_sk(&sk);
+        let mut keypair = KeyPair {
+            privkey: [0; PRIVKEY_BYTES_LEN],
+            pubkey: [0; PUBKEY_BYTES_LEN],
+        };
+        copy_aligned(&mut keypair.privkey, privkey);
+        copy_aligned(&mut keypair.pubkey, &ctx.serialize_pubkey(&pk, false)[1..]);
+        Ok(keypair)
+    }
+
+    pub fn privkey(&self) -> &[u8; PRIVKEY_BYTES_LEN] {
+        &self.privkey
+    }
+
+    pub fn pubkey(&self) -> &[u8; PUBKEY_BYTES_LEN] {
+        &self.pubkey
+    }
+
+    pub fn address(&self) -> [u8; ADDRESS_BYTES_LEN] {
+        pubkey_to_address(&self.pubkey)
+    }
+
+    /// Sign the message, usually a transaction hash.
+    pub fn sign(&self, message: &[u8]) -> Result<Vec<u8>, Error> {
+        let ctx = SigCtx::new();
+        let sk = ctx
+            .load_seckey(&self.privkey)
+            .map_err(|_| Error::InvalidPrivKey)?;
+        let pk = load_pubkey(&ctx, &self.pubkey)?;
+        let sig = ctx.sign(message, &sk, &pk);
+
+        let mut signature = vec![0; SIGNATURE_BYTES_LEN];
+        copy_aligned(
+            &mut signature[..SCALAR_BYTES_LEN],
+            &sig.get_r().to_bytes_be(),
+        );
+        copy_aligned(
+            &mut signature[SCALAR_BYTES_LEN..2 * SCALAR_BYTES_LEN],
+            &sig.get_s().to_bytes_be(),
+        );
+        signature[2 * SCALAR_BYTES_LEN..].copy_from_slice(&self.pubkey);
+        Ok(signature)
+    }
+}
+
+/// Verify the signature of the message, returns the public key of the signer.
+pub fn recover(signature: &[u8], message: &[u8]) -> Result<[u8; PUBKEY_BYTES_LEN], Error> {
+    if signature.len() != SIGNATURE_BYTES_LEN {
+        return Err(Error::InvalidSignature);
+    }
+    let (r, rest) = signature.split_at(SCALAR_BYTES_LEN);
+    let (s, pubkey) = rest.split_at(SCALAR_BYTES_LEN);
+
+    let ctx = SigCtx::new();
+    let pk = load_pubkey(&ctx, pubkey)?;
+    if ctx.verify(message, &pk, &Signature::new(r, s)) {
+        let mut recovered = [0; PUBKEY_BYTES_LEN];
+        recovered.copy_from_slice(pubkey);
+        Ok(recovered)
+    } else {
+        Err(Error::InvalidSignature)
+    }
+}
+
+pub fn sm3(data: &[u8]) -> [u8; 32] {
+    Sm3Hash::new(data).get_hash()
+}
+
+pub fn pubkey_to_address(pubkey: &[u8]) -> [u8; ADDRESS_BYTES_LEN] {
+    let mut address = [0; ADDRESS_BYTES_LEN];
+    copy_aligned(&mut address, &sm3(pubkey));
+    address
+}
+
+fn load_pubkey(ctx: &SigCtx, pubkey: &[u8]) -> Result<libsm::sm2::ecc::Point, Error> {
+    if pubkey.len() != PUBKEY_BYTES_LEN {
+        return Err(Error::InvalidPubKey);
+    }
+    let mut point = Vec::with_capacity(PUBKEY_BYTES_LEN + 1);
+    point.push(UNCOMPRESSED_PREFIX);
+    point.extend_from_slice(pubkey);
+    ctx.load_pubkey(&point).map_err(|_| Error::InvalidPubKey)
+}
+
+/// Copy the last bytes of `src` to the end of `dst`, as big endian integers are aligned.
+fn copy_aligned(dst: &mut [u8], src: &[u8]) {
+    let len = src.len().min(dst.len());
+    let offset = dst.len() - len;
+    dst[offset..].copy_from_slice(&src[src.len() - len..]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{pubkey_to_address, recover, Error, KeyPair, SIGNATURE_BYTES_LEN};
+
+    #[test]
+    fn test_sign_and_recover() {
+        let keypair = KeyPair::gen_keypair();
+        let message = [7u8; 32];
+        let signature = keypair.sign(&message).unwrap();
+        assert_eq!(signature.len(), SIGNATURE_BYTES_LEN);
+        assert_eq!(
+            &recover(&signature, &message).unwrap()[..],
+            &keypair.pubkey()[..]
+        );
+        assert_eq!(
+            recover(&signature, &[8u8; 32]),
+            Err(Error::InvalidSignature)
+        );
+        assert_eq!(
+            recover(&signature[1..], &message),
+            Err(Error::InvalidSignature)
+        );
+    }
+
+    #[test]
+    fn test_from_privkey() {
+        let keypair = KeyPair::gen_keypair();
+        let restored = KeyPair::from_privkey(keypair.privkey()).unwrap();
+        assert_eq!(&restored.pubkey()[..], &keypair.pubkey()[..]);
+        assert_eq!(restored.address(), pubkey_to_address(keypair.pubkey()));
+    }
+}
@@ -0,0 +1,305 @@
+// Copyright Rivtower Technologies LLC.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! JSON-RPC methods which `jsonrpc-types` and `libproto` know nothing about.
+//!
+//! Calls of them travel as JSON in `RawBytes` messages. jsonrpc publishes a
+//! [`Message::Request`] with `Jsonrpc >> RawBytes`, and the service serving the method,
+//! see [`service_of`], replies a [`Message::Response`] with `<Service> >> RawBytes`.
+//!
+//! Chain passes the calls served by the executor on with `Chain >> RawBytes`, as it does
+//! with `Jsonrpc >> Request`, so requests and responses both travel on `Chain >> RawBytes`.
+//!
+//! The executor also tells auth about the manual black list with a
+//! [`Message::ManualBlackList`] on `Executor >> RawBytes`.
+//!
+//! State sync travels between the executors of two nodes. network passes the messages
+//! from peers on with `Synchronizer >> RawBytes`, and sends the ones the executor
+//! publishes with `Executor >> SyncResponse` to the peers, a key of their own so the
+//! services consuming `Executor >> RawBytes` never see them. A message with a zero
+//! origin asks a peer, network picks which one; otherwise it answers the origin.
+//!
+//! [`Message::Request`]: ./enum.Message.html
+//! [`Message::Response`]: ./enum.Message.html
+//! [`Message::ManualBlackList`]: ./enum.Message.html
+//! [`service_of`]: ./fn.service_of.html
+
+#[macro_use]
+extern crate serde_derive;
+
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use std::fmt;
+
+/// `getLogs` with a cursor, `null` for the first page, as the second param.
+/// It returns the logs and the `nextCursor`, which is `null` on the last page.
+pub const GET_LOGS: &str = "getLogs";
+
+/// `getTransactionsByAddress` with the address, then the optional `fromBlock`, `toBlock`,
+/// cursor and limit. It returns the transactions which the address sent, received or
+/// created as a contract, and the `nextCursor`. Needs the address index of chain.
+pub const GET_TRANSACTIONS_BY_ADDRESS: &str = "getTransactionsByAddress";
+
+/// `getBlockNumberByTimestamp` with a timestamp in milliseconds. It returns the number
+/// of the last block at or before it, `null` before genesis, so the state queries can be
+/// pinned to a time through the block number.
+pub const GET_BLOCK_NUMBER_BY_TIMESTAMP: &str = "getBlockNumberByTimestamp";
+
+/// `traceTransaction` with the transaction hash, returns its call tree.
+///
+/// Chain finds the transaction and passes the call on to the executor, with the
+/// `TracePosition` of `common-types` as the second param. Only chain builds that form,
+/// it is not a method for the clients, see [`Request::is_chain_trace`].
+///
+/// [`Request::is_chain_trace`]: ./struct.Request.html#method.is_chain_trace
+pub const TRACE_TRANSACTION: &str = "traceTransaction";
+
+/// The most transactions the executor replays to trace one, so a transaction further
+/// into its block can not be traced.
+pub const MAX_TRACE_TRANSACTIONS: usize = 10_000;
+
+/// `getProof` with the address, the storage keys and the optional block, `latest` by
+/// default. It returns the account and storage proofs in the shape of EIP-1186.
+pub const GET_PROOF: &str = "getProof";
+
+/// `addBlackList` with the address and the optional number of blocks it stays on the list.
+/// It returns the entry. An admin method.
+pub const ADD_BLACK_LIST: &str = "addBlackList";
+
+/// `removeBlackList` with the address, returns whether it was added by an admin.
+/// An admin method.
+pub const REMOVE_BLACK_LIST: &str = "removeBlackList";
+
+/// `getBlackList` returns the automatic and the manual entries of the black list.
+pub const GET_BLACK_LIST: &str = "getBlackList";
+
+/// `getPoolStatus` returns the number of pending transactions and of their senders.
+pub const GET_POOL_STATUS: &str = "getPoolStatus";
+
+/// `getPendingTransactions` with the optional limit, returns summaries of the
+/// transactions in the order they are packaged, all of them without a limit.
+pub const GET_PENDING_TRANSACTIONS: &str = "getPendingTransactions";
+
+/// `getPendingTransactionsByAccount` with the address of the sender.
+pub const GET_PENDING_TRANSACTIONS_BY_ACCOUNT: &str = "getPendingTransactionsByAccount";
+
+pub const INVALID_PARAMS_CODE: i64 = -32602;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Service {
+    Auth,
+    Chain,
+    Executor,
+}
+
+/// The service serving the method, `None` if it is not an extension method.
+/// `params` tells the extended `getLogs` apart from the plain one.
+pub fn service_of(method: &str, params: &[Value]) -> Option<Service> {
+    match method {
+        GET_LOGS if params.len() == 2 => Some(Service::Chain),
+        GET_TRANSACTIONS_BY_ADDRESS | GET_BLOCK_NUMBER_BY_TIMESTAMP => Some(Service::Chain),
+        TRACE_TRANSACTION if params.len() == 1 => Some(Service::Chain),
+        GET_PROOF | ADD_BLACK_LIST | REMOVE_BLACK_LIST | GET_BLACK_LIST => Some(Service::Executor),
+        GET_POOL_STATUS | GET_PENDING_TRANSACTIONS | GET_PENDING_TRANSACTIONS_BY_ACCOUNT => {
+            Some(Service::Auth)
+        }
+        _ => None,
+    }
+}
+
+/// Whether the method changes how the node treats others, so only admins may call it.
+pub fn is_admin(method: &str) -> bool {
+    method == ADD_BLACK_LIST || method == REMOVE_BLACK_LIST
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Request {
+    /// Id of the request in jsonrpc, the response carries it back
+    pub id: Vec<u8>,
+    pub method: String,
+    pub params: Vec<Value>,
+}
+
+impl Request {
+    pub fn service(&self) -> Option<Service> {
+        service_of(&self.method, &self.params)
+    }
+
+    /// Whether it is the `traceTransaction` chain passes on to the executor, with the
+    /// position of the transaction. The executor takes it only from chain.
+    pub fn is_chain_trace(&self) -> bool {
+        self.method == TRACE_TRANSACTION && self.params.len() == 2
+    }
+
+    /// The param at `index`, a missing one is read as `null`.
+    pub fn param<T: DeserializeOwned>(&self, index: usize) -> Result<T, Error> {
+        let param = self.params.get(index).cloned().unwrap_or(Value::Null);
+        serde_json::from_value(param).map_err(|err| {
+            Error::invalid_params(format!(
+                "invalid param {} of {}: {}",
+                index, self.method, err
+            ))
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Error {
+    pub code: i64,
+    pub message: String,
+}
+
+impl Error {
+    pub fn new<T: fmt::Display>(code: i64, message: T) -> Self {
+        Error {
+            code,
+            message: message.to_string(),
+        }
+    }
+
+    pub fn invalid_params<T: fmt::Display>(message: T) -> Self {
+        Error::new(INVALID_PARAMS_CODE, message)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Response {
+    pub id: Vec<u8>,
+    pub result: Result<Value, Error>,
+}
+
+impl Response {
+    pub fn new(id: Vec<u8>, result: Result<Value, Error>) -> Self {
+        Response { id, result }
+    }
+}
+
+/// Changes of the black list made by an admin, addresses are `0x` prefixed hex.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct ManualBlackList {
+    /// Forbidden at once, unlike the automatic entries
+    pub forbid: Vec<String>,
+    pub allow: Vec<String>,
+}
+
+/// Bytes are `0x` prefixed hex in the state sync messages.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Message {
+    Request(Request),
+    Response(Response),
+    ManualBlackList(ManualBlackList),
+    /// Ask a peer for a recent block to sync the state at.
+    GetStateHeaders,
+    /// The rlp encoded headers of the block to sync the state at, and of the two blocks
+    /// after it, which carry the proofs of the block and of its hash.
+    StateHeaders(Vec<String>),
+    /// Ask a peer for the state trie nodes of the hashes.
+    GetTrieNodes(Vec<String>),
+    /// The nodes a peer has of the hashes asked for.
+    TrieNodes(Vec<String>),
+    /// The executor synced the state of the rlp encoded header and continues from it,
+    /// so chain does too.
+    StateSynced(String),
+}
+
+impl Message {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        serde_json::to_vec(self).expect("serialize rpc extension message")
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<Self, serde_json::Error> {
+        serde_json::from_slice(data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        is_admin, service_of, Error, ManualBlackList, Message, Request, Response, Service,
+        ADD_BLACK_LIST, GET_BLACK_LIST, GET_BLOCK_NUMBER_BY_TIMESTAMP, GET_LOGS, GET_POOL_STATUS,
+        GET_TRANSACTIONS_BY_ADDRESS, TRACE_TRANSACTION,
+    };
+    use serde_json::{json, Value};
+
+    #[test]
+    fn test_service_of() {
+        assert_eq!(service_of(GET_LOGS, &[json!({})]), None);
+        assert_eq!(
+            service_of(GET_LOGS, &[json!({}), Value::Null]),
+            Some(Service::Chain)
+        );
+        assert_eq!(
+            service_of(GET_TRANSACTIONS_BY_ADDRESS, &[json!("0x01")]),
+            Some(Service::Chain)
+        );
+        assert_eq!(
+            service_of(GET_BLOCK_NUMBER_BY_TIMESTAMP, &[json!(1000)]),
+            Some(Service::Chain)
+        );
+        assert_eq!(service_of(GET_BLACK_LIST, &[]), Some(Service::Executor));
+        assert_eq!(service_of(GET_POOL_STATUS, &[]), Some(Service::Auth));
+        assert_eq!(
+            service_of(TRACE_TRANSACTION, &[json!("0x01")]),
+            Some(Service::Chain)
+        );
+        assert_eq!(
+            service_of(TRACE_TRANSACTION, &[json!("0x01"), json!({})]),
+            None
+        );
+        assert_eq!(service_of("blockNumber", &[]), None);
+    }
+
+    #[test]
+    fn test_is_admin() {
+        assert!(is_admin(ADD_BLACK_LIST));
+        assert!(!is_admin(GET_BLACK_LIST));
+        assert!(!is_admin(GET_LOGS));
+    }
+
+    #[test]
+    fn test_param() {
+        let req = Request {
+            id: vec![1, 2],
+            method: GET_LOGS.to_owned(),
+            params: vec![json!({}), json!(3)],
+        };
+        assert_eq!(req.param::<u64>(1), Ok(3));
+        assert_eq!(req.param::<Option<u64>>(2), Ok(None));
+        assert_eq!(req.param::<u64>(0).unwrap_err().code, -32602);
+    }
+
+    #[test]
+    fn test_message_bytes() {
+        let messages = vec![
+            Message::Request(Request {
+                id: vec![1, 2],
+                method: GET_LOGS.to_owned(),
+                params: vec![json!({}), Value::Null],
+            }),
+            Message::Response(Response::new(vec![1, 2], Ok(json!({"logs": []})))),
+            Message::Response(Response::new(vec![3], Err(Error::new(-32003, "failed")))),
+            Message::ManualBlackList(ManualBlackList {
+                forbid: vec!["0x01".to_owned()],
+                allow: vec![],
+            }),
+            Message::GetStateHeaders,
+            Message::GetTrieNodes(vec!["0x01".to_owned(), "0x02".to_owned()]),
+            Message::StateSynced("0xc0".to_owned()),
+        ];
+        for msg in messages {
+            assert_eq!(Message::from_bytes(&msg.to_bytes()).unwrap(), msg);
+        }
+    }
+}
@@ -0,0 +1,54 @@
+// Copyright Rivtower Technologies LLC.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::file::{Record, Writer};
+use chain_core::libchain::chain::Chain;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+
+/// Write blocks `from..=to` of `chain` with their proofs into `output`.
+pub fn export(chain: &Chain, from: u64, to: u64, output: &Path) -> Result<u64, String> {
+    let current_height = chain.get_current_height();
+    if from == 0 || from > to || to > current_height {
+        return Err(format!(
+            "invalid range {}..={}, the chain is at height {}",
+            from, to, current_height
+        ));
+    }
+
+    let file = File::create(output).map_err(|err| format!("create {:?}: {}", output, err))?;
+    let mut writer = Writer::new(BufWriter::new(file)).map_err(|err| err.to_string())?;
+    for height in from..=to {
+        let block = chain
+            .block_by_height(height)
+            .ok_or_else(|| format!("block {} not found", height))?;
+        // The proof of the latest block is not in any header yet.
+        let proof = if height == current_height {
+            chain.current_block_poof()
+        } else {
+            chain.get_block_proof_by_height(height)
+        }
+        .unwrap_or_default();
+
+        writer
+            .write(&Record { block, proof })
+            .map_err(|err| err.to_string())?;
+        if height % 1000 == 0 {
+            info!("exported block {}", height);
+        }
+    }
+    writer.finish().map_err(|err| err.to_string())?;
+    Ok(to - from + 1)
+}
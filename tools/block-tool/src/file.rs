@@ -0,0 +1,213 @@
+// Copyright Rivtower Technologies LLC.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The export file.
+//!
+//! It starts with `MAGIC` and the format version as a big-endian u32, followed
+//! by one record per block: the length of the record as a big-endian u32, then
+//! the record as RLP.
+
+use common_types::block::Block;
+use libproto::Proof;
+use rlp::{Decodable, DecoderError, Encodable, RlpStream, UntrustedRlp};
+use std::fmt;
+use std::io::{self, Read, Write};
+
+pub const MAGIC: &[u8; 8] = b"CITABLKS";
+pub const VERSION: u32 = 1;
+
+/// A block and the proof that committed it.
+///
+/// The block header carries the proof of the previous block, so the proof of
+/// the last exported block is only found in the record.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Record {
+    pub block: Block,
+    pub proof: Proof,
+}
+
+impl Encodable for Record {
+    fn rlp_append(&self, s: &mut RlpStream) {
+        s.begin_list(2);
+        s.append(&self.block);
+        s.append(&self.proof);
+    }
+}
+
+impl Decodable for Record {
+    fn decode(r: &UntrustedRlp) -> Result<Self, DecoderError> {
+        if r.item_count()? != 2 {
+            return Err(DecoderError::RlpIncorrectListLen);
+        }
+        Ok(Record {
+            block: r.val_at(0)?,
+            proof: r.val_at(1)?,
+        })
+    }
+}
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    BadMagic,
+    UnsupportedVersion(u32),
+    BadRecord(DecoderError),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io(err) => write!(f, "{}", err),
+            Error::BadMagic => write!(f, "not a block export file"),
+            Error::UnsupportedVersion(version) => {
+                write!(f, "unsupported export file version {}", version)
+            }
+            Error::BadRecord(err) => write!(f, "bad record: {}", err),
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        Error::Io(err)
+    }
+}
+
+pub struct Writer<W: Write> {
+    inner: W,
+}
+
+impl<W: Write> Writer<W> {
+    pub fn new(mut inner: W) -> Result<Self, Error> {
+        inner.write_all(MAGIC)?;
+        inner.write_all(&VERSION.to_be_bytes())?;
+        Ok(Writer { inner })
+    }
+
+    pub fn write(&mut self, record: &Record) -> Result<(), Error> {
+        let bytes = rlp::encode(record);
+        self.inner.write_all(&(bytes.len() as u32).to_be_bytes())?;
+        self.inner.write_all(&bytes)?;
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> Result<W, Error> {
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+}
+
+pub struct Reader<R: Read> {
+    inner: R,
+}
+
+impl<R: Read> Reader<R> {
+    pub fn new(mut inner: R) -> Result<Self, Error> {
+        let mut magic = [0u8; 8];
+        inner.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(Error::BadMagic);
+        }
+        let version = read_u32(&mut inner)?.ok_or(Error::BadMagic)?;
+        if version != VERSION {
+            return Err(Error::UnsupportedVersion(version));
+        }
+        Ok(Reader { inner })
+    }
+}
+
+impl<R: Read> Iterator for Reader<R> {
+    type Item = Result<Record, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let len = match read_u32(&mut self.inner) {
+            Ok(Some(len)) => len,
+            Ok(None) => return None,
+            Err(err) => return Some(Err(err.into())),
+        };
+        let mut bytes = vec![0u8; len as usize];
+        if let Err(err) = self.inner.read_exact(&mut bytes) {
+            return Some(Err(err.into()));
+        }
+        Some(UntrustedRlp::new(&bytes).as_val().map_err(Error::BadRecord))
+    }
+}
+
+// None at a clean end of file.
+fn read_u32<R: Read>(r: &mut R) -> io::Result<Option<u32>> {
+    let mut buf = [0u8; 4];
+    let mut read = 0;
+    while read < buf.len() {
+        match r.read(&mut buf[read..])? {
+            0 if read == 0 => return Ok(None),
+            0 => return Err(io::ErrorKind::UnexpectedEof.into()),
+            n => read += n,
+        }
+    }
+    Ok(Some(u32::from_be_bytes(buf)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn record(height: u64) -> Record {
+        let mut block = Block::default();
+        block.set_number(height);
+        Record {
+            block,
+            proof: Proof::new(),
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut writer = Writer::new(Vec::new()).unwrap();
+        for height in 1..4 {
+            writer.write(&record(height)).unwrap();
+        }
+        let bytes = writer.finish().unwrap();
+
+        let reader = Reader::new(Cursor::new(bytes)).unwrap();
+        let records: Vec<Record> = reader.map(Result::unwrap).collect();
+        assert_eq!(records, vec![record(1), record(2), record(3)]);
+    }
+
+    #[test]
+    fn test_bad_header() {
+        match Reader::new(Cursor::new(b"NOTBLKS\0\0\0\0\x01".to_vec())) {
+            Err(Error::BadMagic) => {}
+            _ => panic!("magic should be checked"),
+        }
+
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&2u32.to_be_bytes());
+        match Reader::new(Cursor::new(bytes)) {
+            Err(Error::UnsupportedVersion(2)) => {}
+            _ => panic!("version should be checked"),
+        }
+    }
+
+    #[test]
+    fn test_truncated_record() {
+        let mut writer = Writer::new(Vec::new()).unwrap();
+        writer.write(&record(1)).unwrap();
+        let mut bytes = writer.finish().unwrap();
+        bytes.pop();
+
+        let mut reader = Reader::new(Cursor::new(bytes)).unwrap();
+        assert!(reader.next().unwrap().is_err());
+    }
+}
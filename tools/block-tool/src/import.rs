@@ -0,0 +1,176 @@
+// Copyright Rivtower Technologies LLC.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Import exported blocks into a fresh or lagging node.
+//!
+//! Blocks are executed the way the executor's backlogs do: each one must
+//! follow the current block, its proof must be signed by the current
+//! validators, and executing it must give the state root it was exported with.
+//!
+//! A block is written to the executor first, then to chain. An import stopped in
+//! between is resumed: chain catches up by importing the blocks it lacks again,
+//! from the state of the executor at the height of chain.
+
+use crate::file::{Reader, Record};
+use chain_core::libchain::chain::{get_chain, Chain};
+use cita_types::Address;
+use common_types::block::OpenBlock;
+use common_types::block_number::{BlockTag, Tag};
+use core_executor::libexecutor::command::Commander;
+use core_executor::libexecutor::executor::Executor;
+use core_executor::libexecutor::fsm::FSM;
+use core_executor::libexecutor::sys_config::GlobalSysConfig;
+use libproto::ProofType;
+use proof::BftProof;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+/// Import the blocks of `input` above the current height. Returns how many were imported.
+pub fn import(
+    input: &Path,
+    chain: &Chain,
+    db: &cita_database::RocksDB,
+    executor: &mut Executor,
+) -> Result<u64, String> {
+    // A fresh chain database gets its genesis block from the executor.
+    if get_chain(db).is_none() {
+        let genesis = executor.executed_result_by_height(0);
+        chain.set_db_result(&genesis, &OpenBlock::default());
+    }
+    resume(chain, executor)?;
+    let proof_type = chain.get_chain_prooftype();
+
+    let file = File::open(input).map_err(|err| format!("open {:?}: {}", input, err))?;
+    let reader = Reader::new(BufReader::new(file)).map_err(|err| err.to_string())?;
+    let mut imported = 0;
+    let mut last_proof = None;
+    for record in reader {
+        let record = record.map_err(|err| err.to_string())?;
+        let height = record.block.number();
+        if height <= executor.get_current_height() {
+            continue;
+        }
+        import_block(&record, proof_type, chain, executor)?;
+        last_proof = Some(record.proof);
+        imported += 1;
+        if height % 1000 == 0 {
+            info!("imported block {}", height);
+        }
+    }
+
+    if let Some(proof) = last_proof {
+        chain.save_current_block_poof(&proof);
+    }
+    Ok(imported)
+}
+
+/// Go back to the height of chain if the executor is ahead of it, so the blocks
+/// chain lacks are imported again.
+fn resume(chain: &Chain, executor: &mut Executor) -> Result<(), String> {
+    let chain_height = chain.get_current_height();
+    let executor_height = executor.get_current_height();
+    if chain_height == executor_height {
+        return Ok(());
+    }
+    if chain_height > executor_height || executor.is_state_pruned(chain_height) {
+        return Err(format!(
+            "chain is at height {} but executor is at height {}",
+            chain_height, executor_height
+        ));
+    }
+    warn!(
+        "executor is at height {} ahead of chain, resume from height {}",
+        executor_height, chain_height
+    );
+    executor.rollback_current_height(BlockTag::Height(chain_height));
+    executor.sys_config = GlobalSysConfig::load(executor, BlockTag::Tag(Tag::Pending));
+    Ok(())
+}
+
+fn import_block(
+    record: &Record,
+    proof_type: Option<ProofType>,
+    chain: &Chain,
+    executor: &mut Executor,
+) -> Result<(), String> {
+    let block = &record.block;
+    let height = block.number();
+    if height != executor.get_current_height() + 1
+        || *block.parent_hash() != executor.get_current_hash()
+    {
+        return Err(format!(
+            "block {} does not follow block {}",
+            height,
+            executor.get_current_height()
+        ));
+    }
+    verify_proof(record, proof_type, &executor.sys_config.validators)?;
+
+    let open_block = OpenBlock::from(block.protobuf());
+    let mut closed_block = executor.into_fsm(open_block.clone());
+    closed_block.set_proof(block.proof().clone());
+    closed_block.rehash();
+    if closed_block.state_root() != block.state_root() {
+        return Err(format!(
+            "block {} executed to state root {:?}, expected {:?}",
+            height,
+            closed_block.state_root(),
+            block.state_root()
+        ));
+    }
+    if closed_block.hash() != block.hash() {
+        return Err(format!(
+            "block {} executed to hash {:?}, expected {:?}",
+            height,
+            closed_block.hash(),
+            block.hash()
+        ));
+    }
+
+    let executed_result = executor.grow(&closed_block);
+    closed_block.clear_cache();
+    chain.set_block_body(height, &open_block);
+    chain.set_db_result(&executed_result, &open_block);
+    Ok(())
+}
+
+// Only BFT proofs can be checked. Whether to is up to the proof type of the node,
+// not of the file, which could leave the proofs out.
+fn verify_proof(
+    record: &Record,
+    proof_type: Option<ProofType>,
+    validators: &[Address],
+) -> Result<(), String> {
+    let height = record.block.number();
+    if proof_type != Some(ProofType::Bft) {
+        return Ok(());
+    }
+    if record.block.proof_type() != Some(ProofType::Bft)
+        || record.proof.get_field_type() != ProofType::Bft
+    {
+        return Err(format!("block {} has no BFT proof", height));
+    }
+    if validators.is_empty() {
+        return Err(format!(
+            "no validators to check the proof of block {}",
+            height
+        ));
+    }
+    let proof = BftProof::from(record.proof.clone());
+    if proof.height as u64 != height || !proof.check(height as usize, validators) {
+        return Err(format!("block {} has an invalid proof", height));
+    }
+    Ok(())
+}
@@ -0,0 +1,154 @@
+// Copyright Rivtower Technologies LLC.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Export blocks with their proofs from a node's databases to a file,
+//! and import them into another node without networking.
+//!
+//! Run it from the node's directory, with the node stopped:
+//!
+//! ```shell
+//! block-tool export --output blocks.bin --from 1 --to 100000
+//! block-tool import --input blocks.bin
+//! ```
+
+#[macro_use]
+extern crate cita_logger as logger;
+#[macro_use]
+extern crate serde_derive;
+#[macro_use]
+extern crate util;
+
+use chain_core::libchain::chain::{Chain, Config as ChainConfig};
+use cita_database::{Config as DatabaseConfig, RocksDB, NUM_COLUMNS};
+use cita_directories::DataPath;
+use clap::{App, ArgMatches, SubCommand};
+use core_executor::libexecutor::executor::Executor;
+use core_executor::{JournalDBType, Pruning};
+use std::path::Path;
+use std::process;
+use std::sync::Arc;
+use util::set_panic_handler;
+
+mod export;
+mod file;
+mod import;
+
+/// The part of executor.toml the import needs.
+#[derive(Debug, Deserialize)]
+struct ExecutorOptions {
+    journaldb_type: JournalDBType,
+    #[serde(default = "default_state_history")]
+    state_history: u64,
+    genesis_path: String,
+    eth_compatibility: bool,
+}
+
+fn default_state_history() -> u64 {
+    1000
+}
+
+fn main() {
+    micro_service_init!("cita-block-tool", "CITA:block-tool", true);
+
+    let matches = App::new("block-tool")
+        .version("0.1")
+        .author("Rivtower")
+        .about("Export and import blocks of a CITA node")
+        .subcommand(SubCommand::with_name("export").args_from_usage(
+            "-o, --output=<FILE> 'File to write the blocks into'
+             -f, --from=[HEIGHT] 'First block to export, 1 by default'
+             -t, --to=[HEIGHT] 'Last block to export, the latest by default'
+             -c, --config=[FILE] 'Chain config file, chain.toml by default'",
+        ))
+        .subcommand(SubCommand::with_name("import").args_from_usage(
+            "-i, --input=<FILE> 'File to read the blocks from'
+             -c, --config=[FILE] 'Chain config file, chain.toml by default'
+             -e, --executor-config=[FILE] 'Executor config file, executor.toml by default'",
+        ))
+        .get_matches();
+
+    let result = match matches.subcommand() {
+        ("export", Some(matches)) => run_export(matches),
+        ("import", Some(matches)) => run_import(matches),
+        _ => Err(matches.usage().to_owned()),
+    };
+    if let Err(err) = result {
+        error!("{}", err);
+        process::exit(1);
+    }
+}
+
+fn open_chain(matches: &ArgMatches) -> (Arc<RocksDB>, Chain) {
+    let config_path = matches.value_of("config").unwrap_or("chain.toml");
+    let db_config = DatabaseConfig::with_category_num(NUM_COLUMNS);
+    let db = RocksDB::open(&DataPath::nosql_path(), &db_config).expect("Open chain DB failed.");
+    let db = Arc::new(db);
+    let chain = Chain::init_chain(Arc::clone(&db), ChainConfig::new(config_path));
+    (db, chain)
+}
+
+fn parse_height(matches: &ArgMatches, name: &str) -> Result<Option<u64>, String> {
+    matches
+        .value_of(name)
+        .map(|height| {
+            height
+                .parse()
+                .map_err(|_| format!("invalid --{} {}", name, height))
+        })
+        .transpose()
+}
+
+fn run_export(matches: &ArgMatches) -> Result<(), String> {
+    let (_db, chain) = open_chain(matches);
+    let from = parse_height(matches, "from")?.unwrap_or(1);
+    let to = parse_height(matches, "to")?.unwrap_or_else(|| chain.get_current_height());
+    let output = Path::new(matches.value_of("output").unwrap());
+    let exported = export::export(&chain, from, to, output)?;
+    info!("exported {} blocks into {:?}", exported, output);
+    Ok(())
+}
+
+fn run_import(matches: &ArgMatches) -> Result<(), String> {
+    let options_path = matches
+        .value_of("executor-config")
+        .unwrap_or("executor.toml");
+    let options: ExecutorOptions = parse_config!(ExecutorOptions, options_path);
+    let (db, chain) = open_chain(matches);
+
+    // The executor is driven directly, so its channels are never used.
+    let (_fsm_req_sender, fsm_req_receiver) = crossbeam_channel::unbounded();
+    let (fsm_resp_sender, _fsm_resp_receiver) = crossbeam_channel::unbounded();
+    let (_command_req_sender, command_req_receiver) = crossbeam_channel::unbounded();
+    let (command_resp_sender, _command_resp_receiver) = crossbeam_channel::unbounded();
+    let mut executor = Executor::init(
+        &options.genesis_path,
+        DataPath::root_node_path(),
+        fsm_req_receiver,
+        fsm_resp_sender,
+        command_req_receiver,
+        command_resp_sender,
+        options.eth_compatibility,
+        Pruning::from_config(options.journaldb_type, options.state_history),
+    );
+
+    let input = Path::new(matches.value_of("input").unwrap());
+    let imported = import::import(input, &chain, &db, &mut executor)?;
+    info!(
+        "imported {} blocks from {:?}, now at height {}",
+        imported,
+        input,
+        chain.get_current_height()
+    );
+    Ok(())
+}
@@ -12,52 +12,227 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+//! Create the key pair of a node, as a plain hex key:
+//!
+//! ```shell
+//! create-key-addr privkey address [reserved]
+//! ```
+//!
+//! or as an encrypted keystore, which the password is read from a file or from the
+//! `CITA_KEYSTORE_PASSWORD_FILE` or `CITA_KEYSTORE_PASSWORD` environment variable:
+//!
+//! ```shell
+//! create-key-addr new --keystore privkey --address address --password-file password
+//! create-key-addr import --input plain_privkey --keystore privkey --password-file password
+//! create-key-addr export --keystore privkey --output plain_privkey --password-file password
+//! create-key-addr passwd --keystore privkey --password-file password --new-password-file new
+//! ```
+
 extern crate cita_crypto as crypto;
+extern crate cita_reserved_crypto as reserved_crypto;
 
-use crate::crypto::{CreateKey, KeyPair, PubKey};
+use crate::crypto::{CreateKey, KeyPair, PrivKey, PubKey};
+use cita_keystore::{Kdf, Keystore};
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
 use hashable::Hashable;
-use std::env;
-use std::fs::{File, OpenOptions};
+use std::fs::{self, OpenOptions};
 use std::io::Write;
+use std::path::Path;
+use std::process;
 
 fn to_hex_string(data: &[u8]) -> String {
     let strs: Vec<String> = data.iter().map(|a| format!("{:02x}", a)).collect();
     strs.join("")
 }
 
-fn write_to_file(path: String, data: &str, append: bool) {
-    let mut file = if append {
-        OpenOptions::new()
-            .create(true)
-            .append(true)
-            .open(path)
-            .unwrap()
-    } else {
-        File::create(path).unwrap()
-    };
+fn append_to_file(path: String, data: &str) {
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .unwrap();
     write!(&mut file, "{}", data).unwrap();
 }
 
+fn write_privkey(path: &str, privkey: &[u8]) {
+    let hex_str_with_0x = String::from("0x") + &to_hex_string(privkey) + "\n";
+    exit_on_error(cita_keystore::write_secret(
+        path,
+        hex_str_with_0x.as_bytes(),
+    ));
+}
+
 fn create_key(path: String) -> PubKey {
     let keypair = KeyPair::gen_keypair();
-    let privkey = *keypair.privkey();
-    let hex_str = to_hex_string(&privkey);
-    let hex_str_with_0x = String::from("0x") + &hex_str + "\n";
-    write_to_file(path, &hex_str_with_0x, false);
+    write_privkey(&path, keypair.privkey());
     *keypair.pubkey()
 }
 
+fn address_of(pubkey: &PubKey) -> Vec<u8> {
+    pubkey.crypt_hash().0[12..].to_vec()
+}
+
 fn create_addr(path: String, pubkey: PubKey) {
-    let hash = pubkey.crypt_hash();
-    let addr = &hash.0[12..];
-    let hex_str = to_hex_string(addr);
+    let hex_str = to_hex_string(&address_of(&pubkey));
     let hex_str_with_0x = String::from("0x") + &hex_str + "\n";
-    write_to_file(path, &hex_str_with_0x, true);
+    append_to_file(path, &hex_str_with_0x);
+}
+
+// Key pair for transactions signed with the RESERVED (SM2/SM3) crypto.
+fn create_reserved_key_addr(key_path: String, addr_path: String) {
+    let keypair = reserved_crypto::KeyPair::gen_keypair();
+    write_privkey(&key_path, keypair.privkey());
+    let addr = String::from("0x") + &to_hex_string(&keypair.address()) + "\n";
+    append_to_file(addr_path, &addr);
+}
+
+fn exit_on_error<T, E: std::fmt::Display>(result: Result<T, E>) -> T {
+    result.unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(1);
+    })
+}
+
+fn password(m: &ArgMatches) -> String {
+    exit_on_error(cita_keystore::password(
+        m.value_of("password-file").map(Path::new),
+    ))
+}
+
+fn kdf(m: &ArgMatches) -> Kdf {
+    match m.value_of("kdf") {
+        Some("pbkdf2") => Kdf::Pbkdf2 { c: 262_144 },
+        _ => Kdf::default(),
+    }
+}
+
+fn save_keystore(m: &ArgMatches, keypair: &KeyPair) {
+    let address = address_of(keypair.pubkey());
+    let keystore = exit_on_error(Keystore::encrypt(
+        &keypair.privkey()[..],
+        Some(&address),
+        &password(m),
+        kdf(m),
+    ));
+    exit_on_error(keystore.save(m.value_of("keystore").unwrap()));
+    if let Some(path) = m.value_of("address") {
+        let hex_str_with_0x = String::from("0x") + &to_hex_string(&address) + "\n";
+        append_to_file(path.to_owned(), &hex_str_with_0x);
+    }
+}
+
+fn new_keystore(m: &ArgMatches) {
+    save_keystore(m, &KeyPair::gen_keypair());
+}
+
+fn import_keystore(m: &ArgMatches) {
+    let privkey = exit_on_error(cita_keystore::load_privkey(
+        m.value_of("input").unwrap(),
+        None,
+    ));
+    if privkey.len() != std::mem::size_of::<PrivKey>() {
+        eprintln!("Invalid private key length {}", privkey.len());
+        process::exit(1);
+    }
+    let keypair = exit_on_error(
+        KeyPair::from_privkey(PrivKey::from(&privkey[..]))
+            .map_err(|e| format!("Invalid private key: {:?}", e)),
+    );
+    save_keystore(m, &keypair);
+}
+
+fn export_keystore(m: &ArgMatches) {
+    let keystore = exit_on_error(Keystore::load(m.value_of("keystore").unwrap()));
+    let privkey = exit_on_error(keystore.decrypt(&password(m)));
+    write_privkey(m.value_of("output").unwrap(), &privkey);
+}
+
+fn change_password(m: &ArgMatches) {
+    let path = m.value_of("keystore").unwrap();
+    let new_password = exit_on_error(cita_keystore::read_password_file(
+        m.value_of("new-password-file").unwrap(),
+    ));
+    let keystore = exit_on_error(Keystore::load(path));
+    let keystore = exit_on_error(keystore.change_password(&password(m), &new_password));
+    // Write a new file and rename it, so the old keystore is not lost half written.
+    let tmp_path = format!("{}.tmp", path);
+    exit_on_error(keystore.save(&tmp_path));
+    exit_on_error(fs::rename(&tmp_path, path));
 }
 
 fn main() {
-    let mut args = env::args();
-    let _ = args.next().unwrap();
-    let pubkey = create_key(args.next().unwrap());
-    create_addr(args.next().unwrap(), pubkey);
+    let password_file = Arg::from_usage(
+        "-p, --password-file=[FILE] 'File holding the password, else read from the environment'",
+    );
+    let kdf = Arg::from_usage("--kdf=[KDF] 'Key derivation function'")
+        .possible_values(&["scrypt", "pbkdf2"]);
+    let matches = App::new("create-key-addr")
+        .version("0.1")
+        .author("Rivtower")
+        .about("Create the private key and address of a node")
+        .setting(AppSettings::ArgsNegateSubcommands)
+        .setting(AppSettings::SubcommandsNegateReqs)
+        .args_from_usage(
+            "<KEY_PATH> 'File to write the plain private key into'
+             <ADDR_PATH> 'File to append the address to'
+             [CRYPTO] 'reserved or sm2 for a key of the RESERVED crypto'",
+        )
+        .subcommand(
+            SubCommand::with_name("new")
+                .about("Create a key pair into a keystore")
+                .args_from_usage(
+                    "-k, --keystore=<FILE> 'File to write the keystore into'
+                     -a, --address=[FILE] 'File to append the address to'",
+                )
+                .arg(password_file.clone())
+                .arg(kdf.clone()),
+        )
+        .subcommand(
+            SubCommand::with_name("import")
+                .about("Encrypt a plain private key into a keystore")
+                .args_from_usage(
+                    "-i, --input=<FILE> 'File holding the plain private key'
+                     -k, --keystore=<FILE> 'File to write the keystore into'
+                     -a, --address=[FILE] 'File to append the address to'",
+                )
+                .arg(password_file.clone())
+                .arg(kdf),
+        )
+        .subcommand(
+            SubCommand::with_name("export")
+                .about("Decrypt the private key of a keystore")
+                .args_from_usage(
+                    "-k, --keystore=<FILE> 'Keystore file'
+                     -o, --output=<FILE> 'File to write the plain private key into'",
+                )
+                .arg(password_file.clone()),
+        )
+        .subcommand(
+            SubCommand::with_name("passwd")
+                .about("Change the password of a keystore")
+                .args_from_usage(
+                    "-k, --keystore=<FILE> 'Keystore file'
+                     -n, --new-password-file=<FILE> 'File holding the new password'",
+                )
+                .arg(password_file),
+        )
+        .get_matches();
+
+    match matches.subcommand() {
+        ("new", Some(m)) => new_keystore(m),
+        ("import", Some(m)) => import_keystore(m),
+        ("export", Some(m)) => export_keystore(m),
+        ("passwd", Some(m)) => change_password(m),
+        _ => {
+            let key_path = matches.value_of("KEY_PATH").unwrap().to_owned();
+            let addr_path = matches.value_of("ADDR_PATH").unwrap().to_owned();
+            match matches.value_of("CRYPTO") {
+                Some("reserved") | Some("sm2") => create_reserved_key_addr(key_path, addr_path),
+                _ => {
+                    let pubkey = create_key(key_path);
+                    create_addr(addr_path, pubkey);
+                }
+            }
+        }
+    }
 }